@@ -17,46 +17,6 @@ fn print_link_search_path() {
 #[cfg(not(windows))]
 fn print_link_search_path() {}
 
-#[cfg(feature = "with-syntex")]
-mod macros {
-    extern crate syntex;
-    extern crate pnet_macros;
-
-    use std::env;
-    use std::path::Path;
-
-    const FILES: &'static [&'static str] = &["ethernet.rs",
-                                             "gre.rs",
-                                             "ipv4.rs",
-                                             "ipv6.rs",
-                                             "icmp.rs",
-                                             "udp.rs",
-                                             "tcp.rs",
-                                             "arp.rs",
-                                             "vlan.rs"];
-
-    pub fn expand() {
-        let out_dir = env::var_os("OUT_DIR").unwrap();
-
-        for file in FILES {
-            let src_file = format!("src/packet/{}.in", file);
-            let src = Path::new(&src_file);
-            let dst = Path::new(&out_dir).join(file);
-
-            let mut registry = syntex::Registry::new();
-            pnet_macros::register(&mut registry);
-
-            registry.expand("", &src, &dst).unwrap();
-        }
-    }
-}
-
-#[cfg(not(feature = "with-syntex"))]
-mod macros {
-    pub fn expand() {}
-}
-
 fn main() {
-    macros::expand();
     print_link_search_path();
 }
@@ -0,0 +1,58 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/// This example lists the hosts on a local network, using ARP for IPv4 and NDP for IPv6
+
+extern crate pnet;
+
+use pnet::datalink::{self, NetworkInterface};
+use pnet::scan::Subnet;
+use pnet::scan::neighbor::{Config, Scanner};
+
+use std::env;
+use std::process;
+
+// Invoke as neighbor_scan <interface name> <subnet> [requests per second]
+fn main() {
+    let mut args = env::args().skip(1);
+    let (iface_name, subnet) = match (args.next(), args.next()) {
+        (Some(iface_name), Some(subnet)) => (iface_name, subnet),
+        _ => {
+            println!("USAGE: neighbor_scan <NETWORK INTERFACE> <SUBNET> [RATE]");
+            process::exit(1);
+        }
+    };
+    let subnet: Subnet = match subnet.parse() {
+        Ok(subnet) => subnet,
+        Err(e) => panic!("neighbor_scan: invalid subnet {}: {:?}", subnet, e),
+    };
+    let mut config = Config::default();
+    if let Some(rate) = args.next() {
+        config.rate = rate.parse().expect("neighbor_scan: invalid rate");
+    }
+
+    let interface_names_match = |iface: &NetworkInterface| iface.name == iface_name;
+    let interfaces = datalink::interfaces();
+    let interface = match interfaces.into_iter().filter(interface_names_match).next() {
+        Some(interface) => interface,
+        None => panic!("neighbor_scan: no interface named {}", iface_name),
+    };
+
+    let scanner = Scanner::new(&interface, config);
+    let result = scanner.scan_with(&subnet, |neighbor| {
+        println!("{:<40} {}  {:.3} ms",
+                 neighbor.ip,
+                 neighbor.mac,
+                 neighbor.rtt.as_secs() as f64 * 1000.0 +
+                 neighbor.rtt.subsec_nanos() as f64 / 1_000_000.0);
+    });
+    match result {
+        Ok(found) => println!("{} hosts found in {}", found.len(), subnet),
+        Err(e) => panic!("neighbor_scan: unable to scan {}: {}", subnet, e),
+    }
+}
@@ -6,23 +6,20 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-//! Implements the #[packet] decorator
+//! Implements #[derive(Packet)]
 
+use proc_macro2::{Delimiter, Group, Spacing, Span, TokenStream, TokenTree};
+use quote::ToTokens;
 use regex::Regex;
-use std::rc::Rc;
-
-use syntax::ast;
-use syntax::codemap::Span;
-use syntax::ext::base::{Annotatable, ExtCtxt};
-use syntax::ext::quote::rt::ExtParseUtils;
-use syntax::parse::token;
-use syntax::print::pprust::{tts_to_string, ty_to_string};
-use syntax::ptr::P;
-use syntax::tokenstream::Delimited;
-use syntax::tokenstream::TokenTree::{self, Sequence, Token};
+use syn;
+use syn::spanned::Spanned;
 
 use util::{Endianness, GetOperation, SetOperation, operations, to_little_endian, to_mutator};
 
+/// The attributes which may be used on the fields of a packet
+const FIELD_ATTRIBUTES: &'static [&'static str] = &["construct_with", "length", "length_fn",
+                                                    "payload", "present_if"];
+
 /// Lower and upper bounds of a payload
 /// Represented as strings since they may involve functions.
 struct PayloadBounds {
@@ -36,6 +33,10 @@ enum Type {
     Primitive(String, usize, Endianness),
     /// Any type of the form Vec<T>
     Vector(Box<Type>),
+    /// A fixed size array of bytes, [u8; N]
+    Array(usize),
+    /// Any type of the form Option<T>, for fields which are only sometimes present
+    Optional(Box<Type>),
     /// Any type which isn't a primitive or a vector
     Misc(String),
 }
@@ -49,12 +50,14 @@ struct Field {
     struct_length: Option<String>,
     is_payload: bool,
     construct_with: Option<Vec<Type>>,
+    present_if: Option<String>,
 }
 
 #[derive(Clone)]
 struct Packet {
     base_name: String,
     fields: Vec<Field>,
+    validate_fn: Option<String>,
 }
 
 impl Packet {
@@ -66,9 +69,16 @@ impl Packet {
     }
 }
 
+/// Print a type or path the way it was written, without the spaces between tokens
+fn tokens_to_string<T: ToTokens>(tokens: &T) -> String {
+    tokens.to_token_stream().to_string().replace(" ", "")
+}
+
 fn make_type(ty_str: String, endianness_important: bool) -> Result<Type, String> {
     if let Some((size, endianness, spec)) = parse_ty(&ty_str[..]) {
-        if !endianness_important || size <= 8 || spec == EndiannessSpecified::Yes {
+        if size == 0 || size > 128 {
+            Err(format!("unsupported integer size: {}", ty_str))
+        } else if !endianness_important || size <= 8 || spec == EndiannessSpecified::Yes {
             Ok(Type::Primitive(ty_str, size, endianness))
         } else {
             Err("endianness must be specified for types of size >= 8".to_owned())
@@ -80,6 +90,20 @@ fn make_type(ty_str: String, endianness_important: bool) -> Result<Type, String>
             Ok(ty) => Ok(Type::Vector(Box::new(ty))),
             Err(e) => Err(e),
         }
+    } else if ty_str.starts_with("Option<") {
+        let ty = make_type(String::from(&ty_str[7..ty_str.len() - 1]),
+                           endianness_important);
+        match ty {
+            Ok(ty) => Ok(Type::Optional(Box::new(ty))),
+            Err(e) => Err(e),
+        }
+    } else if ty_str.starts_with("[") && ty_str.ends_with("]") {
+        let mut parts = ty_str[1..ty_str.len() - 1].splitn(2, ';');
+        match (parts.next(), parts.next().map(|len| len.parse())) {
+            (Some("u8"), Some(Ok(len))) => Ok(Type::Array(len)),
+            (Some("u8"), _) => Err("array lengths must be an integer literal".to_owned()),
+            _ => Err("only arrays of u8 are supported".to_owned()),
+        }
     } else if ty_str.starts_with("&") {
         Err(format!("invalid type: {}", ty_str))
     } else {
@@ -87,150 +111,183 @@ fn make_type(ty_str: String, endianness_important: bool) -> Result<Type, String>
     }
 }
 
-fn multiple_payload_error(ecx: &mut ExtCtxt, field_span: Span, payload_span: Span) {
-    ecx.struct_span_err(field_span, "packet may not have multiple payloads")
-        .span_note(payload_span, "first payload defined here")
-        .emit();
+fn multiple_payload_error(cx: &mut GenContext, field_span: Span, payload_span: Span) {
+    cx.span_err(field_span, "packet may not have multiple payloads");
+    cx.span_err(payload_span, "first payload defined here");
 }
 
-fn make_packet(ecx: &mut ExtCtxt,
-               span: Span,
-               name: String,
-               vd: &ast::VariantData)
-    -> Option<Packet> {
-    let mut payload_span = None;
-    let mut fields = Vec::new();
-
-    // FIXME This is an ugly hack to match the old behaviour
-    let sfields = match *vd {
-        ast::VariantData::Struct(ref fields, _) => fields,
-        ast::VariantData::Tuple(ref fields, _) => {
-            for ref field in fields {
-                if let None = field.ident {
-                    ecx.span_err(field.span, "all fields in a packet must be named");
-                    return None;
-                }
-            }
+fn make_packet(cx: &mut GenContext, item: &syn::DeriveInput) -> Option<Packet> {
+    let sfields: Vec<&syn::Field> = match item.data {
+        syn::Data::Struct(ref data) => data.fields.iter().collect(),
+        _ => {
+            cx.span_err(item.ident.span(),
+                        "#[derive(Packet)] may only be used with structs");
             return None;
         }
-        _ => return None,
     };
+    if let syn::Visibility::Public(_) = item.vis {
+    } else {
+        cx.span_err(item.ident.span(), "#[derive(Packet)] structs must be public");
+        return None;
+    }
+    let validate_fn = match make_validate_fn(cx, &item.attrs) {
+        Ok(validate_fn) => validate_fn,
+        Err(()) => return None,
+    };
+
+    let mut payload_span = None;
+    let mut fields = Vec::new();
 
-    for ref field in sfields {
-        let field_name = match field.ident {
-            Some(name) => name.to_string(),
+    for field in &sfields {
+        let (field_name, field_span) = match field.ident {
+            Some(ref name) => (name.to_string(), name.span()),
             None => {
-                panic!("This shouldn't happen");
+                cx.span_err(field.ty.span(), "all fields in a packet must be named");
+                return None;
             }
         };
         let mut is_payload = false;
         let mut packet_length = None;
         let mut struct_length = None;
         let mut construct_with = Vec::new();
+        let mut present_if = None;
         let mut seen = Vec::new();
+        // The other fields, which #[length] and #[present_if] expressions may refer to
+        let field_names: Vec<String> = sfields.iter()
+            .filter_map(|field| {
+                field.ident
+                    .as_ref()
+                    .map(|name| name.to_string())
+                    .and_then(|name| if name == field_name { None } else { Some(name) })
+            })
+            .collect();
         for attr in &field.attrs {
-            let node = &attr.node.value.node;
-            match *node {
-                ast::MetaItemKind::Word(ref s) => {
-                    seen.push(s.to_owned());
-                    if &s[..] == "payload" {
-                        if payload_span.is_some() {
-                            multiple_payload_error(ecx, field.span, payload_span.unwrap());
-                            return None;
-                        }
-                        is_payload = true;
-                        payload_span = Some(field.span);
-                    } else {
-                        ecx.span_err(field.span, &format!("unknown attribute: {}", s)[..]);
+            // Attributes belonging to anything else, such as doc comments, are left alone
+            let name = match attr.path.get_ident() {
+                Some(ident) if FIELD_ATTRIBUTES.contains(&&ident.to_string()[..]) => {
+                    ident.to_string()
+                }
+                _ => continue,
+            };
+            let attr_span = attr.path.span();
+            let meta = match attr.parse_meta() {
+                Ok(meta) => meta,
+                Err(e) => {
+                    cx.errors.push(e);
+                    return None;
+                }
+            };
+            seen.push(name.clone());
+            match meta {
+                syn::Meta::Path(_) if name == "payload" => {
+                    if let Some(payload_span) = payload_span {
+                        multiple_payload_error(cx, field_span, payload_span);
                         return None;
                     }
+                    is_payload = true;
+                    payload_span = Some(field_span);
                 }
-                ast::MetaItemKind::List(ref s, ref items) => {
-                    seen.push(s.to_owned());
-                    if &s[..] == "construct_with" {
-                        if items.iter().len() == 0 {
-                            ecx.span_err(field.span,
-                                         "#[construct_with] must have at least one argument");
-                            return None;
-                        }
-                        for ty in items.iter() {
-                            if let ast::MetaItemKind::Word(ref s) = ty.node {
-                                match make_type(s.to_string(), false) {
-                                    Ok(ty) => construct_with.push(ty),
-                                    Err(e) => {
-                                        ecx.span_err(field.span, &e);
-                                        return None;
-                                    }
+                syn::Meta::List(ref list) if name == "construct_with" => {
+                    if list.nested.is_empty() {
+                        cx.span_err(attr_span,
+                                    "#[construct_with] must have at least one argument");
+                        return None;
+                    }
+                    for ty in &list.nested {
+                        if let syn::NestedMeta::Meta(syn::Meta::Path(ref path)) = *ty {
+                            match make_type(tokens_to_string(path), false) {
+                                Ok(ty) => construct_with.push(ty),
+                                Err(e) => {
+                                    cx.span_err(path.span(), &e);
+                                    return None;
                                 }
-                            } else {
-                                ecx.span_err(field.span,
-                                             "#[construct_with] should be of the form \
-                                              #[construct_with(<types>)]");
-                                return None;
                             }
+                        } else {
+                            cx.span_err(attr_span,
+                                        "#[construct_with] should be of the form \
+                                         #[construct_with(<types>)]");
+                            return None;
                         }
+                    }
+                }
+                syn::Meta::NameValue(ref nv) if name == "length_fn" => {
+                    if let syn::Lit::Str(ref s) = nv.lit {
+                        packet_length = Some(s.value() + "(&_self.to_immutable())");
                     } else {
-                        ecx.span_err(field.span, &format!("unknown attribute: {}", s)[..]);
+                        cx.span_err(attr_span,
+                                    "#[length_fn] should be used as #[length_fn = \
+                                     \"name_of_function\"]");
                         return None;
                     }
                 }
-                ast::MetaItemKind::NameValue(ref s, ref lit) => {
-                    seen.push(s.to_owned());
-                    match &s[..] {
-                        "length_fn" => {
-                            let node = &lit.node;
-                            if let ast::LitKind::Str(ref s, _) = *node {
-                                packet_length = Some(s.to_string() + "(&_self.to_immutable())");
-                            } else {
-                                ecx.span_err(field.span,
-                                             "#[length_fn] should be used as #[length_fn = \
-                                              \"name_of_function\"]");
+                syn::Meta::NameValue(ref nv) if name == "length" => {
+                    if let syn::Lit::Str(ref s) = nv.lit {
+                        let parsed = s.value()
+                            .parse::<TokenStream>()
+                            .map_err(|_| {
+                                syn::Error::new(s.span(),
+                                                "#[length] should contain a valid arithmetic \
+                                                 expression")
+                            })
+                            .and_then(|tts| parse_length_expr(tts, &field_names, s.span()));
+                        match parsed {
+                            Ok(tokens) => packet_length = Some(tokens.to_string()),
+                            Err(e) => {
+                                cx.errors.push(e);
                                 return None;
                             }
                         }
-                        "length" => {
-                            let node = &lit.node;
-                            if let ast::LitKind::Str(ref s, _) = *node {
-                                let field_names: Vec<String> = sfields.iter()
-                                    .filter_map(|field| {
-                                        field.ident
-                                            .map(|name| name.to_string())
-                                            .and_then(|name| {
-                                                if name == field_name { None } else { Some(name) }
-                                            })
-                                    })
-                                    .collect();
-                                let tt_tokens = ecx.parse_tts(s.to_string());
-                                let tokens_packet =
-                                    parse_length_expr(ecx, &tt_tokens, &field_names);
-                                let parsed = tts_to_string(&tokens_packet[..]);
-                                packet_length = Some(parsed);
-                            } else {
-                                ecx.span_err(field.span,
-                                             "#[length] should be used as #[length = \
-                                              \"field_name and/or arithmetic expression\"]");
+                    } else {
+                        cx.span_err(attr_span,
+                                    "#[length] should be used as #[length = \
+                                     \"field_name and/or arithmetic expression\"]");
+                        return None;
+                    }
+                }
+                syn::Meta::NameValue(ref nv) if name == "present_if" => {
+                    if let syn::Lit::Str(ref s) = nv.lit {
+                        let parsed = s.value()
+                            .parse::<TokenStream>()
+                            .map_err(|_| {
+                                syn::Error::new(s.span(),
+                                                "#[present_if] should contain a valid boolean \
+                                                 expression")
+                            })
+                            .and_then(|tts| {
+                                parse_present_if_expr(tts, &field_names, s.span())
+                            });
+                        match parsed {
+                            Ok(tokens) => present_if = Some(tokens.to_string()),
+                            Err(e) => {
+                                cx.errors.push(e);
                                 return None;
                             }
                         }
-                        _ => {
-                            ecx.span_err(field.span, &format!("unknown attribute: {}", s)[..]);
-                            return None;
-                        }
+                    } else {
+                        cx.span_err(attr_span,
+                                    "#[present_if] should be used as #[present_if = \
+                                     \"boolean expression\"]");
+                        return None;
                     }
                 }
+                _ => {
+                    cx.span_err(attr_span, &format!("unknown attribute: {}", name)[..]);
+                    return None;
+                }
             }
         }
         let old_len = seen.len();
+        seen.sort();
         seen.dedup();
         if seen.len() != old_len {
-            ecx.span_err(field.span, "cannot have two attributes with the same name");
+            cx.span_err(field_span, "cannot have two attributes with the same name");
             return None;
         }
 
-        let ty = match make_type(ty_to_string(&*field.ty), true) {
+        let ty = match make_type(tokens_to_string(&field.ty), true) {
             Ok(ty) => ty,
             Err(e) => {
-                ecx.span_err(field.span, &e);
+                cx.span_err(field.ty.span(), &e);
                 return None;
             }
         };
@@ -239,198 +296,287 @@ fn make_packet(ecx: &mut ExtCtxt,
             Type::Vector(_) => {
                 struct_length = Some(format!("_packet.{}.len()", field_name).to_owned());
                 if !is_payload && packet_length.is_none() {
-                    ecx.span_err(field.span,
-                                 "variable length field must have #[length = \"\"] or \
-                                  #[length_fn = \"\"] attribute");
+                    cx.span_err(field_span,
+                                "variable length field must have #[length = \"\"] or \
+                                 #[length_fn = \"\"] attribute");
                     return None;
                 }
             }
             Type::Misc(_) => {
                 if construct_with.is_empty() {
-                    ecx.span_err(field.span,
-                                 "non-primitive field types must specify #[construct_with]");
+                    cx.span_err(field_span,
+                                "non-primitive field types must specify #[construct_with]");
+                    return None;
+                }
+            }
+            Type::Array(_) => {
+                if is_payload || packet_length.is_some() {
+                    cx.span_err(field_span,
+                                "array fields have a fixed length, and may not be a #[payload] \
+                                 or specify a length");
                     return None;
                 }
             }
+            Type::Optional(ref inner_ty) => {
+                let size = match **inner_ty {
+                    Type::Primitive(_, size, _) if size % 8 == 0 => size / 8,
+                    _ => {
+                        cx.span_err(field.ty.span(),
+                                    "optional fields must be primitives of a whole number of \
+                                     bytes");
+                        return None;
+                    }
+                };
+                if is_payload || packet_length.is_some() {
+                    cx.span_err(field_span,
+                                "optional fields may not be a #[payload] or specify a length");
+                    return None;
+                }
+                // Optional fields are variable length fields, which are either empty, or
+                // the size of the value
+                match present_if {
+                    Some(ref cond) => {
+                        packet_length = Some(format!("(if {} {{ {} }} else {{ 0 }})", cond, size));
+                        struct_length = Some(format!("(if _packet.{}.is_some() {{ {} }} else \
+                                                      {{ 0 }})",
+                                                     field_name,
+                                                     size));
+                    }
+                    None => {
+                        cx.span_err(field_span,
+                                    "optional fields must have a #[present_if = \"\"] \
+                                     attribute");
+                        return None;
+                    }
+                }
+            }
             _ => {}
         }
+        if present_if.is_some() {
+            if let Type::Optional(_) = ty {
+            } else {
+                cx.span_err(field_span, "#[present_if] may only be used with Option<T> fields");
+                return None;
+            }
+        }
 
         fields.push(Field {
             name: field_name,
-            span: field.span,
+            span: field_span,
             ty: ty,
             packet_length: packet_length,
             struct_length: struct_length,
             is_payload: is_payload,
             construct_with: Some(construct_with),
+            present_if: present_if,
         });
     }
 
     if payload_span.is_none() {
-        ecx.span_err(span, "#[packet]'s must contain a payload");
+        cx.span_err(item.ident.span(), "#[derive(Packet)] structs must contain a payload");
         return None;
     }
 
     Some(Packet {
-        base_name: name,
+        base_name: item.ident.to_string(),
         fields: fields,
+        validate_fn: validate_fn,
     })
-
 }
 
-fn make_packets(ecx: &mut ExtCtxt, span: Span, item: &Annotatable) -> Option<Vec<Packet>> {
-    if let Annotatable::Item(ref item) = *item {
-        match item.node {
-            ast::ItemKind::Enum(ref ed, ref _gs) => {
-                if item.vis != ast::Visibility::Public {
-                    ecx.span_err(item.span, "#[packet] enums must be public");
-                    return None;
-                }
-                let mut vec = vec![];
-                for ref variant in &ed.variants {
-                    if variant.node.data.is_struct() {
-                        let name = variant.node.name.to_string();
-                        if let Some(packet) = make_packet(ecx, span, name, &variant.node.data) {
-                            vec.push(packet);
-                        } else {
-                            return None;
-                        }
-                    } else {
-                        ecx.span_err(variant.span, "");
-                        return None;
-                    }
-                }
-
-                Some(vec)
-            }
-            ast::ItemKind::Struct(ref sd, ref _gs) => {
-                if item.vis != ast::Visibility::Public {
-                    ecx.span_err(item.span, "#[packet] structs must be public");
-                    return None;
-                }
-                let name = item.ident.to_string();
-                if let Some(packet) = make_packet(ecx, span, name, sd) {
-                    Some(vec![packet])
-                } else {
-                    None
-                }
-            }
-            _ => {
-                ecx.span_err(span, "#[packet] may only be used with enums and structs");
-
-                None
+/// Find the function named by a #[validate_fn = "name"] attribute on a packet
+fn make_validate_fn(cx: &mut GenContext, attrs: &[syn::Attribute]) -> Result<Option<String>, ()> {
+    for attr in attrs {
+        if attr.path.is_ident("validate_fn") {
+            if let Ok(syn::Meta::NameValue(syn::MetaNameValue { lit: syn::Lit::Str(ref s), .. })) =
+                attr.parse_meta() {
+                return Ok(Some(s.value()));
             }
+            cx.span_err(attr.path.span(),
+                        "#[validate_fn] should be used as #[validate_fn = \
+                         \"name_of_function\"]");
+            return Err(());
         }
-    } else {
-        ecx.span_err(span, "#[packet] may only be used with enums and structs");
-
-        None
     }
+
+    Ok(None)
 }
 
 /// Return the processed length expression for the packet
-fn parse_length_expr(ecx: &mut ExtCtxt,
-                     tts: &[TokenTree],
-                     field_names: &[String])
-    -> Vec<TokenTree> {
-    let error_msg = "Only field names, constants, integers, basic arithmetic expressions \
-                     (+ - * / %) and parentheses are allowed in the \"length\" attribute";
-    let tokens_packet = tts.iter().fold(Vec::new(), |mut acc_packet, tt_token| {
-        match *tt_token {
-            Token(span, token::Ident(name)) => {
-                if name.to_string().chars().any(|c| c.is_lowercase()) {
-                    if field_names.contains(&name.to_string()) {
-                        let mut modified_packet_tokens =
-                            ecx.parse_tts(format!("_self.get_{}() as usize", name).to_owned());
-                        acc_packet.append(&mut modified_packet_tokens);
-                    } else {
-                        ecx.span_err(span,
-                                     "Field name must be a member of the struct and not the \
-                                      field itself");
+///
+/// `span` is the span of the attribute's string, which is where any errors are reported.
+fn parse_length_expr(tts: TokenStream,
+                     field_names: &[String],
+                     span: Span)
+    -> Result<TokenStream, syn::Error> {
+    parse_expr(tts, field_names, span, ExprKind::Length)
+}
+
+/// Return the processed #[present_if] condition for a field
+///
+/// `span` is the span of the attribute's string, which is where any errors are reported.
+fn parse_present_if_expr(tts: TokenStream,
+                         field_names: &[String],
+                         span: Span)
+    -> Result<TokenStream, syn::Error> {
+    parse_expr(tts, field_names, span, ExprKind::Condition)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ExprKind {
+    /// An arithmetic expression, from #[length]
+    Length,
+    /// A boolean expression, from #[present_if]
+    Condition,
+}
+
+fn parse_expr(tts: TokenStream,
+              field_names: &[String],
+              span: Span,
+              kind: ExprKind)
+    -> Result<TokenStream, syn::Error> {
+    let error_msg = match kind {
+        ExprKind::Length => {
+            "Only field names, constants, integers, basic arithmetic expressions (+ - * / %) and \
+             parentheses are allowed in the \"length\" attribute"
+        }
+        ExprKind::Condition => {
+            "Only field names, constants, integers, arithmetic (+ - * / %), comparisons \
+             (== != < <= > >=), && and || and parentheses are allowed in the \"present_if\" \
+             attribute"
+        }
+    };
+    let mut tokens_packet = TokenStream::new();
+    let mut tts = tts.into_iter().peekable();
+    while let Some(tt_token) = tts.next() {
+        match tt_token {
+            TokenTree::Ident(name) => {
+                // Paths, such as std::u32::MAX, are joined up, and treated as constants
+                let mut path = name.to_string();
+                while let Some(TokenTree::Punct(ref punct)) = tts.peek().cloned() {
+                    if punct.as_char() != ':' {
+                        break;
+                    }
+                    tts.next();
+                    match (tts.next(), tts.next()) {
+                        (Some(TokenTree::Punct(ref colon)), Some(TokenTree::Ident(ref segment)))
+                            if colon.as_char() == ':' => {
+                            path = path + "::" + &segment.to_string()[..];
+                        }
+                        _ => return Err(syn::Error::new(span, error_msg)),
                     }
                 }
-                // Constants are only recongized if they are all uppercase
-                else {
-                    let mut modified_packet_tokens =
-                        ecx.parse_tts(format!("{} as usize", name).to_owned());
-                    acc_packet.append(&mut modified_packet_tokens);
-                }
-            }
-            Token(_, token::ModSep) => {
-                acc_packet.push(tt_token.clone());
+                let replacement = if path.contains("::") ||
+                                     !path.chars().any(|c| c.is_lowercase()) {
+                    // Constants are only recognized if they are all uppercase, or part of a path
+                    format!("{} as usize", path)
+                } else if field_names.contains(&path) {
+                    format!("_self.get_{}() as usize", path)
+                } else {
+                    return Err(syn::Error::new(span,
+                                               "Field name must be a member of the struct and \
+                                                not the field itself"));
+                };
+                let replacement = if kind == ExprKind::Condition {
+                    // Casts followed by < would be parsed as the start of generic arguments
+                    format!("({})", replacement)
+                } else {
+                    replacement
+                };
+                tokens_packet.extend(replacement.parse::<TokenStream>().unwrap());
             }
-            Token(span, token::BinOp(binop)) => {
-                match binop {
-                    token::Plus | token::Minus | token::Star | token::Slash | token::Percent => {
-                        acc_packet.push(tt_token.clone());
+            TokenTree::Punct(punct) => {
+                match punct.as_char() {
+                    '+' | '-' | '*' | '/' | '%' => {
+                        tokens_packet.extend(Some(TokenTree::Punct(punct)));
                     }
-                    _ => {
-                        ecx.span_err(span, error_msg);
+                    // Each of these is only allowed as part of a comparison, && or ||, since
+                    // alone they would be bitwise operations on the fields
+                    '=' | '!' | '<' | '>' | '&' | '|' if kind == ExprKind::Condition => {
+                        let operator = match (punct.spacing(), tts.peek()) {
+                            (Spacing::Joint, Some(&TokenTree::Punct(ref next))) => {
+                                format!("{}{}", punct.as_char(), next.as_char())
+                            }
+                            _ => punct.as_char().to_string(),
+                        };
+                        match &operator[..] {
+                            "==" | "!=" | "<=" | ">=" | "&&" | "||" => {
+                                tts.next();
+                            }
+                            _ if operator.starts_with('<') || operator.starts_with('>') => {
+                                tokens_packet.extend(Some(TokenTree::Punct(punct)));
+                                continue;
+                            }
+                            _ => return Err(syn::Error::new(span, error_msg)),
+                        }
+                        tokens_packet.extend(operator.parse::<TokenStream>().unwrap());
                     }
-                };
-            }
-            Token(_, token::Literal(token::Integer(_), None)) => {
-                acc_packet.push(tt_token.clone());
-            }
-            Token(span, _) => {
-                ecx.span_err(span, error_msg);
+                    _ => return Err(syn::Error::new(span, error_msg)),
+                }
             }
-            TokenTree::Delimited(span, ref delimited) => {
-                let tts = parse_length_expr(ecx, &delimited.tts, &field_names);
-                let tt_delimited = Delimited {
-                    delim: delimited.delim,
-                    open_span: delimited.open_span,
-                    tts: tts,
-                    close_span: delimited.close_span,
-                };
-                acc_packet.push(TokenTree::Delimited(span, Rc::new(tt_delimited)));
+            TokenTree::Literal(literal) => {
+                if let syn::Lit::Int(_) = syn::Lit::new(literal.clone()) {
+                    tokens_packet.extend(Some(TokenTree::Literal(literal)));
+                } else {
+                    return Err(syn::Error::new(span, error_msg));
+                }
             }
-            Sequence(span, _) => {
-                ecx.span_err(span, error_msg);
+            TokenTree::Group(group) => {
+                if group.delimiter() != Delimiter::Parenthesis {
+                    return Err(syn::Error::new(span, error_msg));
+                }
+                let tts = parse_expr(group.stream(), field_names, span, kind)?;
+                tokens_packet.extend(Some(TokenTree::Group(Group::new(Delimiter::Parenthesis,
+                                                                      tts))));
             }
-        };
-        acc_packet
-    });
+        }
+    }
 
-    tokens_packet
+    Ok(tokens_packet)
 }
 
-
-struct GenContext<'a, 'b: 'a, 'c> {
-    ecx: &'a mut ExtCtxt<'b>,
-    push: &'c mut FnMut(P<ast::Item>),
+/// Collects the generated items, and any errors found while generating them
+struct GenContext {
+    items: TokenStream,
+    errors: Vec<syn::Error>,
 }
 
-impl<'a, 'b, 'c> GenContext<'a, 'b, 'c> {
+impl GenContext {
     fn push_item_from_string(&mut self, item: String) {
-        (*self.push)(self.ecx.parse_item(item));
+        self.items.extend(item.parse::<TokenStream>().expect("generated an invalid item"));
+    }
+
+    fn span_err(&mut self, span: Span, msg: &str) {
+        self.errors.push(syn::Error::new(span, msg));
     }
 }
 
-pub fn generate_packet(ecx: &mut ExtCtxt,
-                       span: Span,
-                       _meta_item: &ast::MetaItem,
-                       item: &Annotatable,
-                       push: &mut FnMut(Annotatable)) {
-    if let Some(packets) = make_packets(ecx, span, item) {
-        let mut cx = GenContext {
-            ecx: ecx,
-            push: &mut |item| push(Annotatable::Item(item)),
-        };
+pub fn generate_packet(item: &syn::DeriveInput) -> TokenStream {
+    let mut cx = GenContext {
+        items: TokenStream::new(),
+        errors: Vec::new(),
+    };
 
-        for packet in &packets {
-            generate_packet_structs(&mut cx, &packet);
+    if let Some(packet) = make_packet(&mut cx, item) {
+        generate_packet_structs(&mut cx, &packet);
 
-            if let Some((payload_bounds, packet_size)) = generate_packet_impls(&mut cx, &packet) {
-                generate_packet_size_impls(&mut cx, &packet, &packet_size[..]);
+        if let Some((payload_bounds, packet_size)) = generate_packet_impls(&mut cx, &packet) {
+            generate_packet_size_impls(&mut cx, &packet, &packet_size[..]);
 
-                generate_packet_trait_impls(&mut cx, &packet, &payload_bounds);
-                generate_iterables(&mut cx, &packet);
-                generate_converters(&mut cx, &packet);
-                generate_debug_impls(&mut cx, &packet);
-            }
+            generate_packet_trait_impls(&mut cx, &packet, &payload_bounds);
+            generate_iterables(&mut cx, &packet);
+            generate_from_bytes_impl(&mut cx, &packet);
+            generate_converters(&mut cx, &packet);
+            generate_debug_impls(&mut cx, &packet);
         }
     }
+
+    let mut tokens = cx.items;
+    for error in cx.errors {
+        tokens.extend(error.to_compile_error());
+    }
+
+    tokens
 }
 
 fn generate_packet_structs(cx: &mut GenContext, packet: &Packet) {
@@ -470,6 +616,7 @@ fn handle_misc_field(cx: &mut GenContext,
                 inner_accessors +
                 &generate_accessor_str(&arg_name[..],
                                        &ty_str[..],
+                                       size,
                                        &co[..],
                                        &ops[..],
                                        Some(&name[..]))[..];
@@ -477,6 +624,7 @@ fn handle_misc_field(cx: &mut GenContext,
                 inner_mutators +
                 &generate_mutator_str(&arg_name[..],
                                       &ty_str[..],
+                                      size,
                                       &co[..],
                                       &to_mutator(&ops[..])[..],
                                       Some(&name[..]))[..];
@@ -486,7 +634,7 @@ fn handle_misc_field(cx: &mut GenContext,
             // Current offset needs to be recalculated for each arg
             *co = current_offset(*bit_offset, offset_fns);
         } else {
-            cx.ecx.span_err(field.span,
+            cx.span_err(field.span,
                             "arguments to #[construct_with] must be primitives");
             *error = true;
         }
@@ -548,8 +696,16 @@ fn handle_vec_primitive(cx: &mut GenContext,
                         co: &mut String) {
     if inner_ty_str == "u8" || (size % 8) == 0 {
         let ops = operations(0, size).unwrap();
+        // Only whole bytes are supported, so signed values need no sign extension or range check
+        let (bits_ty, signed) = bits_ty(inner_ty_str, size);
         if !field.is_payload {
-            let op_strings = generate_accessor_op_str("packet", inner_ty_str, &ops);
+            let op_strings = if signed {
+                format!("({{ {} }}) as {}",
+                        generate_accessor_op_str("packet", &bits_ty[..], &ops),
+                        inner_ty_str)
+            } else {
+                generate_accessor_op_str("packet", inner_ty_str, &ops)
+            };
             *accessors = format!("{accessors}
                                     /// Get the value of the {name} field (copies contents)
                                     #[inline]
@@ -558,14 +714,15 @@ fn handle_vec_primitive(cx: &mut GenContext,
                                     pub fn get_{name}(&self) -> Vec<{inner_ty_str}> {{
                                         use std::cmp::min;
                                         let _self = self;
-                                        let current_offset = {co};
                                         let pkt_len = self.packet.len();
+                                        let current_offset = min({co}, pkt_len);
                                         let end = min(current_offset + {packet_length}, pkt_len);
 
                                         let packet = &_self.packet[current_offset..end];
-                                        let mut vec: Vec<{inner_ty_str}> = Vec::with_capacity(packet.len());
+                                        let num_vals = packet.len() / {size};
+                                        let mut vec: Vec<{inner_ty_str}> = Vec::with_capacity(num_vals);
                                         let mut co = 0;
-                                        for _ in 0..vec.capacity() {{
+                                        for _ in 0..num_vals {{
                                             vec.push({{
                                                 {ops}
                                             }});
@@ -584,8 +741,9 @@ fn handle_vec_primitive(cx: &mut GenContext,
         }
         let check_len = if field.packet_length.is_some() {
             format!("let len = {packet_length};
-                                             assert!(vals.len() <= len);",
-                    packet_length = field.packet_length.as_ref().unwrap())
+                                             assert!(vals.len() * {size} <= len);",
+                    packet_length = field.packet_length.as_ref().unwrap(),
+                    size = size / 8)
         } else {
             String::new()
         };
@@ -603,13 +761,15 @@ fn handle_vec_primitive(cx: &mut GenContext,
         } else {
             // e.g. Vec<u16> -> Vec<u8>
             let sop_strings = generate_sop_strings(&to_mutator(&ops));
+            let cast = if signed { format!(" as {}", bits_ty) } else { String::new() };
             format!("
                                 let mut co = current_offset;
                                 for i in 0..vals.len() {{
-                                    let val = vals[i];
+                                    let val = vals[i]{cast};
                                     {sop}
                                     co += {size};
                                 }}",
+                    cast = cast,
                     sop = sop_strings,
                     size = size / 8)
         };
@@ -637,11 +797,126 @@ fn handle_vec_primitive(cx: &mut GenContext,
 
     } else {
 
-        cx.ecx.span_err(field.span, "unimplemented variable length field");
+        cx.span_err(field.span, "unimplemented variable length field");
         *error = true;
     }
 }
 
+fn handle_array_field(field: &Field,
+                      len: usize,
+                      co: &str,
+                      accessors: &mut String,
+                      mutators: &mut String) {
+    *accessors = format!("{accessors}
+                            /// Get the value of the {name} field, without copying
+                            #[inline]
+                            #[cfg_attr(feature = \"clippy\", allow(used_underscore_binding))]
+                            pub fn get_{name}(&self) -> &[u8; {len}] {{
+                                use std::convert::TryFrom;
+                                let _self = self;
+                                let co = {co};
+
+                                <&[u8; {len}]>::try_from(&_self.packet[co..co + {len}]).unwrap()
+                            }}
+                            ",
+                         accessors = accessors,
+                         name = field.name,
+                         len = len,
+                         co = co);
+    *mutators = format!("{mutators}
+                            /// Set the value of the {name} field
+                            #[inline]
+                            #[cfg_attr(feature = \"clippy\", allow(used_underscore_binding))]
+                            pub fn set_{name}(&mut self, vals: &[u8; {len}]) {{
+                                let _self = self;
+                                let co = {co};
+
+                                _self.packet[co..co + {len}].copy_from_slice(&vals[..]);
+                            }}
+                            ",
+                        mutators = mutators,
+                        name = field.name,
+                        len = len,
+                        co = co);
+}
+
+fn handle_optional_field(field: &Field,
+                         inner_ty: &Type,
+                         co: &str,
+                         name: &str,
+                         accessors: &mut String,
+                         mutators: &mut String) {
+    let (ty_str, size, endianness) = match *inner_ty {
+        Type::Primitive(ref ty_str, size, endianness) => (ty_str, size, endianness),
+        _ => unreachable!(),
+    };
+    let mut ops = operations(0, size).unwrap();
+    if endianness == Endianness::Little {
+        ops = to_little_endian(ops);
+    }
+    let present_if = field.present_if.as_ref().unwrap();
+    // The packet may be too short to hold a field which the flags say is present
+    let fits = format!("{} + {} <= _self.packet.len()", co, (size + 7) / 8);
+
+    // The value itself is accessed in the same way as any other primitive field, once it's known
+    // to be present
+    let get_value = generate_accessor_str("value", &ty_str[..], size, co, &ops[..], Some(name));
+    let set_value = generate_mutator_str("value",
+                                         &ty_str[..],
+                                         size,
+                                         co,
+                                         &to_mutator(&ops[..])[..],
+                                         Some(name));
+
+    *accessors = format!("{accessors}
+                            /// Get the value of the {name} field, or None if it isn't present or
+                            /// the packet is too short to hold it
+                            #[inline]
+                            #[allow(trivial_numeric_casts)]
+                            #[cfg_attr(feature = \"clippy\", allow(used_underscore_binding))]
+                            pub fn get_{name}(&self) -> Option<{ty_str}> {{
+                                {get_value}
+
+                                let _self = self;
+                                if ({present_if}) && {fits} {{
+                                    Some(get_value(_self))
+                                }} else {{
+                                    None
+                                }}
+                            }}
+                            ",
+                         accessors = accessors,
+                         name = field.name,
+                         ty_str = ty_str,
+                         get_value = get_value,
+                         present_if = present_if,
+                         fits = fits);
+    *mutators = format!("{mutators}
+                            /// Set the value of the {name} field. Setting it to None does nothing,
+                            /// and it may only be set to a value if it is present and fits in the
+                            /// packet.
+                            #[inline]
+                            #[allow(trivial_numeric_casts)]
+                            #[cfg_attr(feature = \"clippy\", allow(used_underscore_binding))]
+                            pub fn set_{name}(&mut self, val: Option<{ty_str}>) {{
+                                {set_value}
+
+                                let _self = self;
+                                if let Some(val) = val {{
+                                    assert!({present_if}, \"{name} is not present\");
+                                    assert!({fits}, \"{name} does not fit in the packet\");
+                                    set_value(_self, val);
+                                }}
+                            }}
+                            ",
+                        mutators = mutators,
+                        name = field.name,
+                        ty_str = ty_str,
+                        set_value = set_value,
+                        present_if = present_if,
+                        fits = fits);
+}
+
 fn handle_vector_field(cx: &mut GenContext,
                        error: &mut bool,
                        field: &Field,
@@ -650,7 +925,7 @@ fn handle_vector_field(cx: &mut GenContext,
                        inner_ty: &Box<Type>,
                        co: &mut String) {
     if !field.is_payload && !field.packet_length.is_some() {
-        cx.ecx.span_err(field.span,
+        cx.span_err(field.span,
                         "variable length field must have #[length_fn = \"\"] attribute");
         *error = true;
     }
@@ -663,7 +938,7 @@ fn handle_vector_field(cx: &mut GenContext,
                                 pub fn get_{name}_raw(&self) -> &[u8] {{
                                     use std::cmp::min;
                                     let _self = self;
-                                    let current_offset = {co};
+                                    let current_offset = min({co}, _self.packet.len());
                                     let end = min(current_offset + {packet_length}, _self.packet.len());
 
                                     &_self.packet[current_offset..end]
@@ -681,7 +956,7 @@ fn handle_vector_field(cx: &mut GenContext,
                                 pub fn get_{name}_raw_mut(&mut self) -> &mut [u8] {{
                                     use std::cmp::min;
                                     let _self = self;
-                                    let current_offset = {co};
+                                    let current_offset = min({co}, _self.packet.len());
                                     let end = min(current_offset + {packet_length}, _self.packet.len());
 
                                     &mut _self.packet[current_offset..end]
@@ -704,7 +979,15 @@ fn handle_vector_field(cx: &mut GenContext,
                                  co)
         }
         Type::Vector(_) => {
-            cx.ecx.span_err(field.span, "variable length fields may not contain vectors");
+            cx.span_err(field.span, "variable length fields may not contain vectors");
+            *error = true;
+        }
+        Type::Array(_) => {
+            cx.span_err(field.span, "variable length fields may not contain arrays");
+            *error = true;
+        }
+        Type::Optional(_) => {
+            cx.span_err(field.span, "variable length fields may not contain optional fields");
             *error = true;
         }
         Type::Misc(ref inner_ty_str) => {
@@ -717,7 +1000,7 @@ fn handle_vector_field(cx: &mut GenContext,
                                     use pnet::packet::FromPacket;
                                     use std::cmp::min;
                                     let _self = self;
-                                    let current_offset = {co};
+                                    let current_offset = min({co}, _self.packet.len());
                                     let end = min(current_offset + {packet_length}, _self.packet.len());
 
                                     {inner_ty_str}Iterable {{
@@ -733,7 +1016,7 @@ fn handle_vector_field(cx: &mut GenContext,
                                 pub fn get_{name}_iter(&self) -> {inner_ty_str}Iterable {{
                                     use std::cmp::min;
                                     let _self = self;
-                                    let current_offset = {co};
+                                    let current_offset = min({co}, _self.packet.len());
                                     let end = min(current_offset + {packet_length}, _self.packet.len());
 
                                     {inner_ty_str}Iterable {{
@@ -785,8 +1068,15 @@ fn generate_packet_impl(cx: &mut GenContext,
     let mut mutators = "".to_owned();
     let mut error = false;
     let mut payload_bounds = None;
+    // Checks that each field fits in the buffer, for new_checked. Those which depend on variable
+    // length fields have to come after the validate_fn, which makes the length functions safe to
+    // call.
+    let mut fixed_checks = String::new();
+    let mut fixed_end = 0;
+    let mut variable_checks = String::new();
     for (idx, ref field) in packet.fields.iter().enumerate() {
         let mut co = current_offset(bit_offset, &offset_fns_packet[..]);
+        let field_start = co.clone();
 
         if field.is_payload {
             let mut upper_bound_str = "".to_owned();
@@ -795,7 +1085,7 @@ fn generate_packet_impl(cx: &mut GenContext,
                     format!("{} + {}", co.clone(), field.packet_length.as_ref().unwrap());
             } else {
                 if idx != packet.fields.len() - 1 {
-                    cx.ecx.span_err(field.span,
+                    cx.span_err(field.span,
                                     "#[payload] must specify a #[length_fn], unless it is the \
                                      last field of a packet");
                     error = true;
@@ -816,12 +1106,14 @@ fn generate_packet_impl(cx: &mut GenContext,
                 mutators = mutators +
                            &generate_mutator_str(&field.name[..],
                                                  &ty_str[..],
+                                                 size,
                                                  &co[..],
                                                  &to_mutator(&ops[..])[..],
                                                  None)[..];
                 accessors = accessors +
                             &generate_accessor_str(&field.name[..],
                                                    &ty_str[..],
+                                                   size,
                                                    &co[..],
                                                    &ops[..],
                                                    None)[..];
@@ -836,6 +1128,21 @@ fn generate_packet_impl(cx: &mut GenContext,
                                     inner_ty,
                                     &mut co)
             }
+            Type::Array(len) => {
+                if bit_offset % 8 != 0 {
+                    cx.span_err(field.span, "array fields must start on a byte boundary");
+                    error = true;
+                }
+                handle_array_field(field, len, &co, &mut accessors, &mut mutators);
+                bit_offset += len * 8;
+            }
+            Type::Optional(ref inner_ty) => {
+                if bit_offset % 8 != 0 {
+                    cx.span_err(field.span, "optional fields must start on a byte boundary");
+                    error = true;
+                }
+                handle_optional_field(field, inner_ty, &co, &name, &mut accessors, &mut mutators);
+            }
             Type::Misc(ref ty_str) => {
                 handle_misc_field(cx,
                                   &mut error,
@@ -849,6 +1156,31 @@ fn generate_packet_impl(cx: &mut GenContext,
                                   &ty_str)
             }
         }
+        let field_end = match field.packet_length {
+            Some(ref packet_length) => format!("{} + {}", field_start, packet_length),
+            None if field.is_payload => field_start,
+            None => current_offset((bit_offset + 7) / 8 * 8, &offset_fns_packet[..]),
+        };
+        let check = format!("let end = {end};
+                             if end > available {{
+                                 return Err(PacketError::Truncated {{
+                                     field: \"{name}\",
+                                     expected: end,
+                                     available: available,
+                                 }});
+                             }}
+                             ",
+                            end = field_end,
+                            name = field.name);
+        if offset_fns_packet.is_empty() && field.packet_length.is_none() {
+            // Fields sharing a byte with the previous field don't need another check
+            if (bit_offset + 7) / 8 > fixed_end {
+                fixed_checks = fixed_checks + &check[..];
+                fixed_end = (bit_offset + 7) / 8;
+            }
+        } else {
+            variable_checks = variable_checks + &check[..];
+        }
         if field.packet_length.is_some() {
             offset_fns_packet.push(field.packet_length.as_ref().unwrap().clone());
         }
@@ -865,7 +1197,7 @@ fn generate_packet_impl(cx: &mut GenContext,
         let mut set_fields = String::new();
         for field in &packet.fields {
             match field.ty {
-                Type::Vector(_) => {
+                Type::Vector(_) | Type::Array(_) => {
                     set_fields = set_fields +
                                  &format!("_self.set_{field}(&packet.{field});\n",
                                           field = field.name)[..];
@@ -929,6 +1261,26 @@ fn generate_packet_impl(cx: &mut GenContext,
             }}
         }}
 
+        /// Constructs a new {name}, checking that every field, including variable length ones,
+        /// fits in the provided buffer and has a valid value. The accessors of a {name}
+        /// constructed this way will not panic.
+        #[inline]
+        #[cfg_attr(feature = \"clippy\", allow(used_underscore_binding))]
+        pub fn new_checked<'p>(packet: &'p {mut} [u8])
+            -> Result<{name}<'p>, ::pnet::packet::PacketError> {{
+            use ::pnet::packet::{{{cap_mut}PacketData, PacketError}};
+            let available = packet.len();
+            let checked = {name} {{ packet: {cap_mut}PacketData::Borrowed(packet) }};
+            {{
+                let _self = &checked;
+                {fixed_checks}
+                {validate}
+                {variable_checks}
+            }}
+
+            Ok(checked)
+        }}
+
         /// Constructs a new {name}. If the provided buffer is less than the minimum required
         /// packet size, this will return None. With this constructor the {name} will
         /// own its own data and the underlying buffer will be dropped when the {name} is.
@@ -973,6 +1325,12 @@ fn generate_packet_impl(cx: &mut GenContext,
     mut = if mutable { "mut" } else { "" },
     cap_mut = if mutable { "Mut" } else { "" },
     byte_size = byte_size,
+    fixed_checks = fixed_checks,
+    validate = match packet.validate_fn {
+        Some(ref validate_fn) => format!("{}(&_self.to_immutable())?;", validate_fn),
+        None => String::new(),
+    },
+    variable_checks = variable_checks,
     accessors = accessors,
     mutators = if mutable { &mutators[..] } else { "" },
     populate = populate,
@@ -1082,6 +1440,17 @@ fn generate_iterables(cx: &mut GenContext, packet: &Packet) {
     ", name = name));
 }
 
+fn generate_from_bytes_impl(cx: &mut GenContext, packet: &Packet) {
+    cx.push_item_from_string(format!("
+    impl<'a, 'p> ::pnet::packet::FromBytes<'p> for {name}<'a> {{
+        type T = {name}<'p>;
+        #[inline]
+        fn from_bytes(bytes: &'p [u8]) -> Option<{name}<'p>> {{
+            {name}::new(bytes)
+        }}
+    }}", name = packet.packet_name()));
+}
+
 fn generate_converters(cx: &mut GenContext, packet: &Packet) {
     let get_fields = generate_get_fields(packet);
 
@@ -1134,11 +1503,11 @@ enum EndiannessSpecified {
     Yes,
 }
 
-/// Given a type in the form `u([0-9]+)(be|le)?`, return a tuple of it's size and endianness
+/// Given a type in the form `[ui]([0-9]+)(be|le)?`, return a tuple of it's size and endianness
 ///
 /// If 1 <= size <= 8, Endianness will be Big.
 fn parse_ty(ty: &str) -> Option<(usize, Endianness, EndiannessSpecified)> {
-    let re = Regex::new(r"^u([0-9]+)(be|le)?$").unwrap();
+    let re = Regex::new(r"^[ui]([0-9]+)(be|le)?$").unwrap();
     let iter = match re.captures_iter(ty).next() {
         Some(c) => c,
         None => return None,
@@ -1176,7 +1545,43 @@ fn test_parse_ty() {
     assert_eq!(parse_ty("u16"), Some((16, Endianness::Big, EndiannessSpecified::No)));
     assert_eq!(parse_ty("uable"), None);
     assert_eq!(parse_ty("u21re"), None);
-    assert_eq!(parse_ty("i21be"), None);
+    assert_eq!(parse_ty("i8"), Some((8, Endianness::Big, EndiannessSpecified::No)));
+    assert_eq!(parse_ty("i21be"), Some((21, Endianness::Big, EndiannessSpecified::Yes)));
+    assert_eq!(parse_ty("i21le"), Some((21, Endianness::Little, EndiannessSpecified::Yes)));
+    assert_eq!(parse_ty("s21be"), None);
+}
+
+/// Given a primitive type and its size, return the unsigned type used to manipulate its bits, and
+/// whether it is signed. eg. `i12be` is manipulated as a `u16`.
+fn bits_ty(ty: &str, size: usize) -> (String, bool) {
+    let bits = storage_bits(size);
+
+    (format!("u{}", bits), ty.starts_with('i'))
+}
+
+/// The number of bits in the integer type which a primitive of the given size is stored in
+fn storage_bits(size: usize) -> usize {
+    if size <= 8 {
+        8
+    } else if size <= 16 {
+        16
+    } else if size <= 32 {
+        32
+    } else if size <= 64 {
+        64
+    } else {
+        128
+    }
+}
+
+#[test]
+fn test_bits_ty() {
+    assert_eq!(bits_ty("u8", 8), ("u8".to_owned(), false));
+    assert_eq!(bits_ty("i3", 3), ("u8".to_owned(), true));
+    assert_eq!(bits_ty("i12be", 12), ("u16".to_owned(), true));
+    assert_eq!(bits_ty("u24le", 24), ("u32".to_owned(), false));
+    assert_eq!(bits_ty("i64be", 64), ("u64".to_owned(), true));
+    assert_eq!(bits_ty("u128le", 128), ("u128".to_owned(), false));
 }
 
 fn generate_sop_strings(operations: &[SetOperation]) -> String {
@@ -1196,30 +1601,60 @@ fn generate_sop_strings(operations: &[SetOperation]) -> String {
 /// the Rust code required to set the field
 fn generate_mutator_str(name: &str,
                         ty: &str,
+                        size: usize,
                         offset: &str,
                         operations: &[SetOperation],
                         inner: Option<&str>)
     -> String {
     let op_strings = generate_sop_strings(operations);
 
+    // Signed values are checked to fit, then set using their two's complement bits
+    let (bits_ty, signed) = bits_ty(ty, size);
+    let convert = if !signed {
+        String::new()
+    } else if size == storage_bits(size) {
+        format!("let val = val as {};", bits_ty)
+    } else {
+        let max = (1i64 << (size - 1)) - 1;
+        format!("assert!(({min}..={max}).contains(&val), \"value out of range for {name}\");
+        let val = val as {bits_ty};",
+                min = -max - 1,
+                max = max,
+                name = name,
+                bits_ty = bits_ty)
+    };
+
     let mutator = if let Some(struct_name) = inner {
         format!("#[inline]
     #[allow(trivial_numeric_casts)]
     #[cfg_attr(feature = \"clippy\", allow(used_underscore_binding))]
     fn set_{name}(_self: &mut {struct_name}, val: {ty}) {{
+        {convert}
         let co = {co};
         {operations}
-    }}", struct_name = struct_name, name = name, ty = ty, co = offset, operations = op_strings)
+    }}",
+                struct_name = struct_name,
+                name = name,
+                ty = ty,
+                convert = convert,
+                co = offset,
+                operations = op_strings)
     } else {
         format!("/// Set the {name} field
     #[inline]
     #[allow(trivial_numeric_casts)]
     #[cfg_attr(feature = \"clippy\", allow(used_underscore_binding))]
     pub fn set_{name}(&mut self, val: {ty}) {{
+        {convert}
         let _self = self;
         let co = {co};
         {operations}
-    }}", name = name, ty = ty, co = offset, operations = op_strings)
+    }}",
+                name = name,
+                ty = ty,
+                convert = convert,
+                co = offset,
+                operations = op_strings)
     };
 
     mutator
@@ -1295,12 +1730,28 @@ fn test_generate_accessor_op_str() {
 /// return the Rust code required to get the field.
 fn generate_accessor_str(name: &str,
                          ty: &str,
+                         size: usize,
                          offset: &str,
                          operations: &[GetOperation],
                          inner: Option<&str>)
     -> String {
 
-    let op_strings = generate_accessor_op_str("_self.packet", ty, operations);
+    // Signed values are read as unsigned, then sign extended
+    let (bits_ty, signed) = bits_ty(ty, size);
+    let op_strings = if !signed {
+        generate_accessor_op_str("_self.packet", ty, operations)
+    } else {
+        let raw = generate_accessor_op_str("_self.packet", &bits_ty[..], operations);
+        let extend = storage_bits(size) - size;
+        if extend == 0 {
+            format!("let raw = {{ {} }};\nraw as {}", raw, ty)
+        } else {
+            format!("let raw = {{ {raw} }};\n((raw << {extend}) as {ty}) >> {extend}",
+                    raw = raw,
+                    extend = extend,
+                    ty = ty)
+        }
+    };
 
     let accessor = if let Some(struct_name) = inner {
         format!("#[inline]
@@ -1344,6 +1795,8 @@ fn generate_get_fields(packet: &Packet) -> String {
 
                                                 vec
                                             }},\n", field = field.name)[..]
+        } else if let Type::Array(_) = field.ty {
+            gets = gets + &format!("{field} : *_self.get_{field}(),\n", field = field.name)[..]
         } else {
             gets = gets + &format!("{field} : _self.get_{field}(),\n", field = field.name)[..]
         }
@@ -1355,26 +1808,24 @@ fn generate_get_fields(packet: &Packet) -> String {
 
 #[cfg(test)]
 mod tests {
-    use syntax::ast::CrateConfig;
-    use syntax::ext::base::ExtCtxt;
-    use syntax::ext::expand::ExpansionConfig;
-    use syntax::ext::quote::rt::ExtParseUtils;
-    use syntax::parse::ParseSess;
-    use syntax::print::pprust::tts_to_string;
+    use proc_macro2::{Span, TokenStream};
 
     fn assert_parse_length_expr(expr: &str, field_names: &[&str], expected: &str) {
-        let sess = ParseSess::new();
-        let mut feature_gated_cfgs = Vec::new();
-        let mut ecx = ExtCtxt::new(&sess,
-                                   CrateConfig::default(),
-                                   ExpansionConfig::default("parse_length_expr".to_owned()),
-                                   &mut feature_gated_cfgs);
-        let expr_tokens = ecx.parse_tts(expr.to_owned());
+        let expr_tokens = expr.parse::<TokenStream>().unwrap();
         let field_names_vec: Vec<String> =
             field_names.iter().map(|field_name| (*field_name).to_owned()).collect();
-        let parsed = super::parse_length_expr(&mut ecx, &expr_tokens, &field_names_vec);
-        let expected_tokens = ecx.parse_tts(expected.to_owned());
-        assert_eq!(tts_to_string(&parsed), tts_to_string(&expected_tokens));
+        let parsed = super::parse_length_expr(expr_tokens, &field_names_vec, Span::call_site())
+            .unwrap();
+        let expected_tokens = expected.parse::<TokenStream>().unwrap();
+        assert_eq!(parsed.to_string(), expected_tokens.to_string());
+    }
+
+    fn assert_parse_length_expr_err(expr: &str, field_names: &[&str]) {
+        let expr_tokens = expr.parse::<TokenStream>().unwrap();
+        let field_names_vec: Vec<String> =
+            field_names.iter().map(|field_name| (*field_name).to_owned()).collect();
+        assert!(super::parse_length_expr(expr_tokens, &field_names_vec, Span::call_site())
+            .is_err());
     }
 
     #[test]
@@ -1430,4 +1881,52 @@ mod tests {
                                  &["key"],
                                  "_self.get_key() as usize * (4 + std::u32::MIN as usize)");
     }
+
+    #[test]
+    fn test_parse_expr_invalid() {
+        assert_parse_length_expr_err("tomato", &["banana"]);
+        assert_parse_length_expr_err("banana + 7.5", &["banana"]);
+        assert_parse_length_expr_err("banana << 2", &["banana"]);
+        assert_parse_length_expr_err("[banana]", &["banana"]);
+        assert_parse_length_expr_err("banana == 1", &["banana"]);
+    }
+
+    fn assert_parse_present_if_expr(expr: &str, field_names: &[&str], expected: &str) {
+        let expr_tokens = expr.parse::<TokenStream>().unwrap();
+        let field_names_vec: Vec<String> =
+            field_names.iter().map(|field_name| (*field_name).to_owned()).collect();
+        let parsed =
+            super::parse_present_if_expr(expr_tokens, &field_names_vec, Span::call_site())
+                .unwrap();
+        let expected_tokens = expected.parse::<TokenStream>().unwrap();
+        assert_eq!(parsed.to_string(), expected_tokens.to_string());
+    }
+
+    #[test]
+    fn test_parse_present_if_expr() {
+        assert_parse_present_if_expr("banana == 1",
+                                     &["banana"],
+                                     "(_self.get_banana() as usize) == 1");
+        assert_parse_present_if_expr("banana != 0 && apple < LIMIT",
+                                     &["apple", "banana"],
+                                     "(_self.get_banana() as usize) != 0 && \
+                                      (_self.get_apple() as usize) < (LIMIT as usize)");
+        assert_parse_present_if_expr("(banana + 1) >= 2 || apple <= 3 || apple > 4",
+                                     &["apple", "banana"],
+                                     "((_self.get_banana() as usize) + 1) >= 2 || \
+                                      (_self.get_apple() as usize) <= 3 || \
+                                      (_self.get_apple() as usize) > 4");
+    }
+
+    #[test]
+    fn test_parse_present_if_expr_invalid() {
+        for expr in &["!banana", "banana = 1", "banana & 1 == 1", "banana | apple", "tomato == 1"] {
+            let expr_tokens = expr.parse::<TokenStream>().unwrap();
+            let field_names = vec!["apple".to_owned(), "banana".to_owned()];
+            assert!(super::parse_present_if_expr(expr_tokens, &field_names, Span::call_site())
+                        .is_err(),
+                    "{}",
+                    expr);
+        }
+    }
 }
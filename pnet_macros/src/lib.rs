@@ -6,33 +6,39 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-//! The pnet_macros crate provides the `#[packet]` macro and compiler plugin, which is used to
+//! The pnet_macros crate provides the `#[derive(Packet)]` procedural macro, which is used to
 //! specify the format of on-the-wire packets, and automatically generate zero-copy accessors and
 //! mutators for the fields. It is used as follows:
 //!
-//! ```no_run
-//! #![feature(core, collections, custom_attribute, plugin)]
-//! #![plugin(pnet_macros_plugin)]
-//!
+//! ```
+//! #[macro_use]
+//! extern crate pnet_macros;
 //! extern crate pnet;
 //! extern crate pnet_macros_support;
 //!
 //! /// This module contains a list of type aliases which may be used
 //! use pnet_macros_support::types::{u4, u12be};
 //!
-//! /// Packets are specified in the same way as normal Rust structs, but with a `#[packet]`
-//! /// attribute.
-//! #[packet]
+//! /// Packets are specified in the same way as normal Rust structs, but with
+//! /// `#[derive(Packet)]`.
+//! #[derive(Packet, Clone, Debug)]
 //! pub struct Example {
 //!     // This is a simple field which contains a 4-bit, unsigned integer.
 //!     // Note that `u4` is simply an alias for `u8` - the name is a hint
-//!     // to the compiler plugin, it is NOT a usable 4 bit type!
+//!     // to the macro, it is NOT a usable 4 bit type!
 //!     simple_field1: u4,
 //!
 //!     // This specifies that `simple_field2` should be a 12-bit field,
 //!     // with bits stored in big endian
 //!     simple_field2: u12be,
 //!
+//!     // Signed, two's complement fields are specified in the same way
+//!     signed_field: i8,
+//!
+//!     // Fixed size byte arrays must start on a byte boundary. Their
+//!     // accessors return a reference into the packet, rather than a copy
+//!     address: [u8; 6],
+//!
 //!     // All packets must specify a `#[payload]`, which should be a
 //!     // `Vec<u8>`. This represents the packet's payload, for example in
 //!     // an IPv4 packet, the payload could be a UDP packet, or in a UDP
@@ -55,6 +61,10 @@
 //!      - A method, `pub fn new<'p>(packet: &'p [u8]) -> ExamplePacket<'p>`, used for the
 //!        construction of an `ExamplePacket`, given a buffer to store it. The buffer should be
 //!        long enough to contain all the fields in the packet.
+//!      - A method, `pub fn new_checked<'p>(packet: &'p [u8]) -> Result<ExamplePacket<'p>,
+//!        PacketError>`, which also checks that variable length fields fit in the buffer, and
+//!        calls any `#[validate_fn]`. A `pnet::packet::PacketError` names the first field which
+//!        is truncated or invalid.
 //!      - A method, `pub fn to_immutable<'p>(&'p self) -> ExamplePacket<'p>`, which is simply an
 //!        identity function. It exists for consistency with `MutableExamplePacket`.
 //!      - A number of accessor methods, of the form `pub get_{field_name}(&self) -> {field_type}`,
@@ -100,8 +110,8 @@
 //!    value should be a number of bytes that the field uses.
 //!
 //!    The type contained in the vector may either be one of the primitive types specified in
-//!    `pnet_macros::types`, or another structure marked with #[packet], for example
-//!    `Vec<Example>`.
+//!    `pnet_macros::types`, or another structure deriving `Packet`, for example
+//!    `Vec<Example>`. Such structures must also implement `Debug`.
 //!
 //!  * \#[length = "arithmetic expression"]
 //!
@@ -112,8 +122,21 @@
 //!    in the expression. An example would be `#[length = "field_name + CONSTANT - 4]`.
 //!
 //!    The type contained in the vector may either be one of the primitive types specified in
-//!    `pnet_macros::types`, or another structure marked with #[packet], for example
-//!    `Vec<Example>`.
+//!    `pnet_macros::types`, or another structure deriving `Packet`, for example
+//!    `Vec<Example>`. Such structures must also implement `Debug`.
+//!
+//!  * \#[present_if = "boolean expression"]
+//!
+//!    This attribute is used for fields which are only in the packet some of the time, usually
+//!    depending on a flag earlier in the packet. Such fields should have the type `Option<T>`,
+//!    where `T` is a primitive type of a whole number of bytes, and must start on a byte boundary.
+//!    The expression may use the same names, constants and arithmetic as `#[length]`, along with
+//!    comparisons (== != < <= > >=), && and ||. An example would be
+//!    `#[present_if = "key_present == 1"]`.
+//!
+//!    The getter returns `None` when the expression is false, and the field then takes no space
+//!    in the packet. The setter takes an `Option<T>`; setting `None` does nothing, and setting a
+//!    value when the field isn't present panics.
 //!
 //!  * \#[payload]
 //!
@@ -126,10 +149,19 @@
 //!    If the packet has no payload, you must still specify this attribute, but you can provide a
 //!    `#[length_fn]` attribute returning zero.
 //!
+//!  * \#[validate_fn = "function_name"]
+//!
+//!    This attribute goes on the packet structure itself, after `#[derive(Packet)]`, and names a
+//!    function which `new_checked` uses to check the values of fields, for example that a header
+//!    length isn't shorter than the fixed part of the header. Its signature should be
+//!    `fn {function_name}(example_packet: &ExamplePacket) -> Result<(), PacketError>`. It is
+//!    called once the fixed size fields before the first variable length field are known to be
+//!    in the buffer, and before any length functions are called, so they may rely on the checks
+//!    it makes.
+//!
 //!  * \#[construct_with(<primitive type>, ...)]
 //!
-//!    Unfortunately, compiler plugins do not currently have access to type information during the
-//!    decoration stage (where all of the above is generated), so this attribute is required. This
+//!    Procedural macros do not have access to type information, so this attribute is required. It
 //!    must be used for all fields which are neither primitive types, nor vectors of primitive
 //!    types. Three things are required when using `#[construct_with]`:
 //!      - The field type must have a method `new`, which takes one or more parameters of primitive
@@ -142,162 +174,32 @@
 
 #![deny(missing_docs)]
 
-#![cfg_attr(not(feature = "with-syntex"), feature(plugin_registrar, quote, rustc_private))]
 #![cfg_attr(feature="clippy", feature(plugin))]
 #![cfg_attr(feature="clippy", plugin(clippy))]
 #![cfg_attr(feature="clippy", allow(let_and_return))]
 
-#[cfg(feature = "with-syntex")]
-extern crate syntex;
-
-#[cfg(feature = "with-syntex")]
-extern crate syntex_syntax as syntax;
-
-#[cfg(not(feature = "with-syntex"))]
-extern crate syntax;
-
+extern crate proc_macro;
+extern crate proc_macro2;
+extern crate quote;
 extern crate regex;
+#[macro_use]
+extern crate syn;
 
-#[cfg(not(feature = "with-syntex"))]
-extern crate rustc_plugin;
-
-use syntax::ast;
-use syntax::codemap::Span;
-use syntax::ext::base::{Annotatable, ExtCtxt};
-
-#[cfg(not(feature = "with-syntex"))]
-use syntax::ext::base::{MultiDecorator, MultiModifier};
-use syntax::ext::build::AstBuilder;
-use syntax::parse::token;
-use syntax::ptr::P;
+use proc_macro::TokenStream;
 
 mod decorator;
 mod util;
 
-/// Helper for creating meta words
-fn mw(ecx: &mut ExtCtxt, span: Span, word: &'static str) -> P<ast::MetaItem> {
-    ecx.meta_word(span, token::InternedString::new(word))
-}
-
-/// Replace the #[packet] attribute with internal attributes
-///
-/// The #[packet] attribute is consumed, so we replace it with two internal attributes,
-/// #[packet_generator], which is used to generate the packet implementations, and
-fn packet_modifier(ecx: &mut ExtCtxt,
-                   span: Span,
-                   _meta_item: &ast::MetaItem,
-                   item: Annotatable)
-    -> Annotatable {
-    let item = item.expect_item();
-    let mut new_item = (*item).clone();
-
-    let packet_generator = mw(ecx, span, "packet_generator");
-    let clone = mw(ecx, span, "Clone");
-    let debug = mw(ecx, span, "Debug");
-    let unused_attrs = mw(ecx, span, "unused_attributes");
-
-    let a1 = ecx.attribute(span, packet_generator);
-    let a2 = ecx.attribute(span,
-                           ecx.meta_list(span,
-                                         token::InternedString::new("derive"),
-                                         vec![clone, debug]));
-    let a3 = ecx.attribute(span,
-                           ecx.meta_list(span,
-                                         token::InternedString::new("allow"),
-                                         vec![unused_attrs]));
-
-    new_item.attrs.push(a1);
-    new_item.attrs.push(a2);
-    new_item.attrs.push(a3);
-
-    Annotatable::Item(P(new_item))
-}
-
-/// Helper function to get mutable access to the fields in a struct/enum
-#[cfg(feature = "with-syntex")]
-fn variant_data_fields(vd: &mut ast::VariantData) -> &mut [ast::StructField] {
-    match *vd {
-        ast::VariantData::Struct(ref mut fields, _) => fields,
-        ast::VariantData::Tuple(ref mut fields, _) => fields,
-        _ => &mut [],
-    }
-}
-
-/// Removes the attributes we've introduced from a particular struct/enum
-#[cfg(feature = "with-syntex")]
-fn remove_attributes_struct(vd: &mut ast::VariantData) {
-    for field in variant_data_fields(vd) {
-        let mut attrs = &mut field.attrs;
-        attrs.retain(|attr| {
-            match attr.node.value.node {
-                ast::MetaItemKind::Word(ref s) => *s != "payload",
-                ast::MetaItemKind::List(ref s, _) => *s != "construct_with",
-                ast::MetaItemKind::NameValue(ref s, _) => !(*s == "length_fn" || *s == "length"),
-            }
-        });
-    }
-}
-
-#[cfg(feature = "with-syntex")]
-fn remove_attributes_mod(module: &mut ast::Mod) {
-    let mut new_items = Vec::with_capacity(module.items.len());
-    for item in &module.items {
-        let new_item = item.clone().map(|mut item| {
-            match item.node {
-                ast::ItemKind::Enum(ref mut ed, ref _gs) => {
-                    let mut new_variants = Vec::with_capacity(ed.variants.len());
-                    for variant in &ed.variants {
-                        let mut new_variant = variant.clone();
-                        if new_variant.node.data.is_struct() {
-                            remove_attributes_struct(&mut new_variant.node.data);
-                        }
-                        new_variants.push(new_variant);
-                    }
-                    ed.variants = new_variants;
-                }
-                ast::ItemKind::Struct(ref mut sd, ref _gs) => {
-                    remove_attributes_struct(sd);
-                }
-                ast::ItemKind::Mod(ref mut m) => {
-                    remove_attributes_mod(m);
-                }
-                _ => {}
-            }
-
-            item
-        });
-        new_items.push(new_item);
-    }
-
-    module.items = new_items;
-}
-
-/// This iterates through a crate and removes any references to attributes
-/// which we introduce but aren't already consumed
-#[cfg(feature = "with-syntex")]
-fn remove_attributes(mut krate: ast::Crate) -> ast::Crate {
-    remove_attributes_mod(&mut krate.module);
-
-    krate
-}
-
-/// The entry point for the plugin when using syntex
-#[cfg(feature = "with-syntex")]
-pub fn register(registry: &mut syntex::Registry) {
-    registry.add_modifier("packet", packet_modifier);
-    registry.add_decorator("packet_generator", decorator::generate_packet);
-    registry.add_post_expansion_pass(remove_attributes);
-}
-
-/// The entry point for the syntax extension
+/// The entry point for `#[derive(Packet)]`
 ///
-/// This registers each part of the plugin with the compiler. There are two parts: a modifier, to
-/// add additional attributes to the original structure; and a decorator, to generate the various
-/// required structures and method.
-#[cfg(not(feature = "with-syntex"))]
-pub fn register(registry: &mut rustc_plugin::Registry) {
-    registry.register_syntax_extension(token::intern("packet"),
-                                       MultiModifier(Box::new(packet_modifier)));
-    registry.register_syntax_extension(token::intern("packet_generator"),
-                                       MultiDecorator(Box::new(decorator::generate_packet)));
+/// This generates the packet structures and their implementations alongside the annotated
+/// structure, which is left as it is. The attributes used to describe fields are declared here,
+/// so that the compiler accepts them without any feature gates.
+#[proc_macro_derive(Packet,
+                    attributes(construct_with, length, length_fn, payload, present_if,
+                               validate_fn))]
+pub fn derive_packet(input: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(input as syn::DeriveInput);
+
+    decorator::generate_packet(&item).into()
 }
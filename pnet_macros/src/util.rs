@@ -90,7 +90,7 @@ pub struct SetOperation {
     /// Bits to save from old byte
     save_mask: u8,
     /// Bits to mask out of value we're setting
-    value_mask: u64,
+    value_mask: u128,
     /// Number of places to left shift the value we're setting
     shiftl: u8,
     /// Number of places to right shift the value we're setting
@@ -123,7 +123,7 @@ macro_rules! radix_fn {
 }
 
 radix_fn!(radix16_u8, u8);
-radix_fn!(radix16_u64, u64);
+radix_fn!(radix16_u128, u128);
 
 impl fmt::Display for SetOperation {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
@@ -138,7 +138,7 @@ impl fmt::Display for SetOperation {
         };
 
         let mask_str = if should_mask {
-            format!("({{val}} & 0x{})", radix16_u64(self.value_mask))
+            format!("({{val}} & 0x{})", radix16_u128(self.value_mask))
         } else {
             "{val}".to_owned()
         };
@@ -353,16 +353,12 @@ fn test_get_shiftr() {
 /// Assumes big endian, and that each byte will be masked, then cast to the next power of two
 /// greater than or equal to size bits before shifting. offset should be in the range [0, 7]
 pub fn operations(offset: usize, size: usize) -> Option<Vec<GetOperation>> {
-    if offset > 7 || size == 0 || size > 64 {
+    if offset > 7 || size == 0 || size > 128 {
         return None;
     }
 
-    let num_full_bytes = size / 8;
-    let num_bytes = if offset > 0 || size % 8 != 0 {
-        num_full_bytes + 1
-    } else {
-        num_full_bytes
-    };
+    // Number of bytes the field touches, including partial bytes at either end
+    let num_bytes = (offset + size + 7) / 8;
 
     let mut current_offset = offset;
     let mut num_bits_remaining = size;
@@ -524,7 +520,7 @@ fn operations_test() {
 
     assert_eq!(operations(8, 1), None);
     assert_eq!(operations(3, 0), None);
-    assert_eq!(operations(3, 65), None);
+    assert_eq!(operations(3, 129), None);
 
     assert_eq!(operations(3, 33).unwrap(),
                vec![Op {
@@ -552,10 +548,36 @@ fn operations_test() {
                         shiftl: 0,
                         shiftr: 4,
                     }]);
+    // The widest supported fields
+    let ops = operations(0, 128).unwrap();
+    assert_eq!(ops.len(), 16);
+    assert_eq!(ops[0],
+               Op {
+                   mask: 0b11111111,
+                   shiftl: 120,
+                   shiftr: 0,
+               });
+    // Only ten bits, but spread over three bytes
+    assert_eq!(operations(7, 10).unwrap(),
+               vec![Op {
+                        mask: 0b00000001,
+                        shiftl: 9,
+                        shiftr: 0,
+                    },
+                    Op {
+                        mask: 0b11111111,
+                        shiftl: 1,
+                        shiftr: 0,
+                    },
+                    Op {
+                        mask: 0b10000000,
+                        shiftl: 0,
+                        shiftr: 7,
+                    }]);
 }
 
 /// Mask `bits` bits of a byte. eg. mask_high_bits(2) == 0b00000011
-fn mask_high_bits(mut bits: u64) -> u64 {
+fn mask_high_bits(mut bits: u128) -> u128 {
     let mut mask = 0;
     while bits > 0 {
         mask = mask | (1 << (bits - 1));
@@ -570,7 +592,7 @@ fn mask_high_bits(mut bits: u64) -> u64 {
 ///
 /// In the form of (bits to get, bits to set)
 pub fn to_mutator(ops: &[GetOperation]) -> Vec<SetOperation> {
-    fn num_bits_set(n: u8) -> u64 {
+    fn num_bits_set(n: u8) -> u128 {
         let mut count = 0;
         for i in 0..8 {
             if n & (1 << i) > 0 {
@@ -910,14 +932,129 @@ fn test_to_mutator() {
                         shiftl: 4,
                         shiftr: 0,
                     }]);
+    assert_eq!(to_mutator(&operations(0, 128).unwrap())[0],
+               Sop {
+                   save_mask: 0b00000000,
+                   value_mask: 0xFF << 120,
+                   shiftl: 0,
+                   shiftr: 120,
+               });
 }
 
 /// Takes a set of operations to get a field in big endian, and converts them to get the field in
 /// little endian.
-pub fn to_little_endian(_ops: Vec<GetOperation>) -> Vec<GetOperation> {
-    let mut ops = _ops.clone();
-    for (op, be_op) in ops.iter_mut().zip(_ops.iter().rev()) {
-	op.shiftl = be_op.shiftl;
+///
+/// The bits of the field within each byte keep the same position, but the byte at the lowest
+/// address holds the least significant bits of the value, the next byte the bits above those, and
+/// so on. For fields which start and end on a byte boundary this is simply the reverse byte order;
+/// for other fields, the bits in the first byte are the least significant bits of the value.
+pub fn to_little_endian(ops: Vec<GetOperation>) -> Vec<GetOperation> {
+    let mut value_offset = 0;
+    let mut le_ops = Vec::with_capacity(ops.len());
+    for op in ops {
+        // Bits from this byte are moved from their position in the byte to value_offset
+        le_ops.push(GetOperation {
+            mask: op.mask,
+            shiftl: value_offset,
+            shiftr: op.mask.trailing_zeros() as u8,
+        });
+        value_offset += op.mask.count_ones() as u8;
     }
-    ops
+
+    le_ops
+}
+
+#[test]
+fn test_to_little_endian() {
+    type Op = GetOperation;
+    type Sop = SetOperation;
+
+    // u16le
+    assert_eq!(to_little_endian(operations(0, 16).unwrap()),
+               vec![Op {
+                        mask: 0b11111111,
+                        shiftl: 0,
+                        shiftr: 0,
+                    },
+                    Op {
+                        mask: 0b11111111,
+                        shiftl: 8,
+                        shiftr: 0,
+                    }]);
+    // u24le, one bit into the first byte
+    assert_eq!(to_little_endian(operations(1, 24).unwrap()),
+               vec![Op {
+                        mask: 0b01111111,
+                        shiftl: 0,
+                        shiftr: 0,
+                    },
+                    Op {
+                        mask: 0b11111111,
+                        shiftl: 7,
+                        shiftr: 0,
+                    },
+                    Op {
+                        mask: 0b11111111,
+                        shiftl: 15,
+                        shiftr: 0,
+                    },
+                    Op {
+                        mask: 0b10000000,
+                        shiftl: 23,
+                        shiftr: 7,
+                    }]);
+    // u12le
+    let ops = to_little_endian(operations(0, 12).unwrap());
+    assert_eq!(ops,
+               vec![Op {
+                        mask: 0b11111111,
+                        shiftl: 0,
+                        shiftr: 0,
+                    },
+                    Op {
+                        mask: 0b11110000,
+                        shiftl: 8,
+                        shiftr: 4,
+                    }]);
+    assert_eq!(to_mutator(&ops[..]),
+               vec![Sop {
+                        save_mask: 0b00000000,
+                        value_mask: 0x0FF,
+                        shiftl: 0,
+                        shiftr: 0,
+                    },
+                    Sop {
+                        save_mask: 0b00001111,
+                        value_mask: 0xF00,
+                        shiftl: 4,
+                        shiftr: 8,
+                    }]);
+    // u12le, four bits into the first byte
+    let ops = to_little_endian(operations(4, 12).unwrap());
+    assert_eq!(ops,
+               vec![Op {
+                        mask: 0b00001111,
+                        shiftl: 0,
+                        shiftr: 0,
+                    },
+                    Op {
+                        mask: 0b11111111,
+                        shiftl: 4,
+                        shiftr: 0,
+                    }]);
+    assert_eq!(to_mutator(&ops[..]),
+               vec![Sop {
+                        save_mask: 0b11110000,
+                        value_mask: 0x00F,
+                        shiftl: 0,
+                        shiftr: 0,
+                    },
+                    Sop {
+                        save_mask: 0b00000000,
+                        value_mask: 0xFF0,
+                        shiftl: 0,
+                        shiftr: 4,
+                    }]);
+    // Single byte fields are the same in either endianness
+    assert_eq!(to_little_endian(operations(3, 4).unwrap()), operations(3, 4).unwrap());
 }
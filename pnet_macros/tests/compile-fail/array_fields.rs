@@ -0,0 +1,56 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
+extern crate pnet_macros_support;
+
+use pnet_macros_support::types::*;
+
+const LEN: usize = 4;
+
+#[derive(Packet)]
+pub struct NotBytes {
+    words: [u16; 4],
+    #[payload]
+    payload: Vec<u8>,
+}
+
+#[derive(Packet)]
+pub struct NotLiteral {
+    bytes: [u8; LEN],
+    #[payload]
+    payload: Vec<u8>,
+}
+
+#[derive(Packet)]
+pub struct WithLength {
+    len: u8,
+    #[length = "len"]
+    bytes: [u8; 4],
+    #[payload]
+    payload: Vec<u8>,
+}
+
+#[derive(Packet)]
+pub struct Unaligned {
+    flag: u1,
+    bytes: [u8; 4],
+    #[payload]
+    payload: Vec<u8>,
+}
+
+#[derive(Packet)]
+pub struct TooWide {
+    wide: u129be,
+    #[payload]
+    payload: Vec<u8>,
+}
+
+fn main() {}
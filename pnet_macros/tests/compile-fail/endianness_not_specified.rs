@@ -6,14 +6,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-// error-pattern: endianness must be specified for types of size >= 8
-
-#![feature(custom_attribute, plugin)]
-#![plugin(pnet_macros_plugin)]
-
 extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
 
-#[packet]
+#[derive(Packet)]
 pub struct PacketU16 {
     banana: u16,
     #[payload]
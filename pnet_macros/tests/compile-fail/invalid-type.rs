@@ -6,14 +6,13 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-#![feature(custom_attribute, plugin)]
-#![plugin(pnet_macros_plugin)]
-
 extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
 
-#[packet]
+#[derive(Packet)]
 pub struct InvalidType {
-    pub field: String, // ~ ERROR: non-primitive field types must specify #[construct_with]
+    pub field: String,
     #[payload]
     pub payload: Vec<u8>,
 }
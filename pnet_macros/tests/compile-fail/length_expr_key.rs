@@ -6,14 +6,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-// error-pattern: error: Field name must be a member of the struct and not the field itself
-
-#![feature(custom_attribute, plugin)]
-#![plugin(pnet_macros_plugin)]
-
 extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
 
-#[packet]
+#[derive(Packet)]
 pub struct PacketWithPayload {
     banana: u8,
     #[length = "tomato"]
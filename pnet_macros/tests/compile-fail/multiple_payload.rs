@@ -6,16 +6,17 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-#![feature(custom_attribute, plugin)]
-#![plugin(pnet_macros_plugin)]
-
 extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
 
-#[packet]
+#[derive(Packet)]
 pub struct PacketWithPayload {
     #[length_fn = ""]
     #[payload]
-    payload1: Vec<u8>, // ~ NOTE first payload defined here
+    payload1: Vec<u8>,
     #[payload]
-    payload2: Vec<u8>, // ~ ERROR packet may not have multiple payloads
+    payload2: Vec<u8>,
 }
+
+fn main() {}
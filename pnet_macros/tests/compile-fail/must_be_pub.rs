@@ -6,13 +6,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-#![feature(custom_attribute, plugin)]
-#![plugin(pnet_macros_plugin)]
-
 extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
 
-// ~ ERROR #[packet] structs must be public
-#[packet]
+#[derive(Packet)]
 struct MustBePub {
     banana: u8,
     #[payload]
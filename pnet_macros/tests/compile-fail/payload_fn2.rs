@@ -6,16 +6,15 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-#![feature(custom_attribute, plugin)]
-#![plugin(pnet_macros_plugin)]
-
 extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
 
-#[packet]
+#[derive(Packet)]
 pub struct PacketWithPayload2 {
     banana: u8,
     #[payload(length_fn = "length_of_payload")]
-    payload: Vec<u8>, // ~ ERROR: unknown attribute: payload
+    payload: Vec<u8>,
 }
 
 fn length_of_payload(_: &PacketWithPayload2Packet) -> usize {
@@ -0,0 +1,60 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
+extern crate pnet_macros_support;
+
+use pnet_macros_support::types::*;
+
+#[derive(Packet)]
+pub struct NoCondition {
+    flag: u8,
+    value: Option<u16be>,
+    #[payload]
+    payload: Vec<u8>,
+}
+
+#[derive(Packet)]
+pub struct NotOptional {
+    flag: u8,
+    #[present_if = "flag == 1"]
+    value: u16be,
+    #[payload]
+    payload: Vec<u8>,
+}
+
+#[derive(Packet)]
+pub struct PartialByte {
+    flag: u8,
+    #[present_if = "flag == 1"]
+    value: Option<u12be>,
+    #[payload]
+    payload: Vec<u8>,
+}
+
+#[derive(Packet)]
+pub struct Unaligned {
+    flag: u4,
+    #[present_if = "flag == 1"]
+    value: Option<u16be>,
+    #[payload]
+    payload: Vec<u8>,
+}
+
+#[derive(Packet)]
+pub struct BitwiseCondition {
+    flag: u8,
+    #[present_if = "flag & 1 == 1"]
+    value: Option<u16be>,
+    #[payload]
+    payload: Vec<u8>,
+}
+
+fn main() {}
@@ -6,13 +6,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-#![feature(custom_attribute, plugin)]
-#![plugin(pnet_macros_plugin)]
-
 extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
 
-// ~ ERROR all fields in a packet must be named
-#[packet]
+#[derive(Packet)]
 pub struct Foo(#[payload]
                pub u8);
 
@@ -6,15 +6,14 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-#![feature(custom_attribute, plugin)]
-#![plugin(pnet_macros_plugin)]
-
 extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
 
-#[packet]
+#[derive(Packet)]
 pub struct PacketWithPayload {
     banana: u8,
-    var_length: Vec<u8>, /* ~ ERROR: variable length field must have #[length = ""] or #[length_fn = ""] attribute */
+    var_length: Vec<u8>,
     #[payload]
     payload: Vec<u8>,
 }
@@ -6,19 +6,15 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-// ignore-test
-// ignored as this error message isn't implemented yet
-
-#![feature(custom_attribute, plugin)]
-#![plugin(pnet_macros_plugin)]
-
 extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
 
-#[packet]
+#[derive(Packet)]
 pub struct PacketWithPayload {
     banana: u8,
     #[length_fn = "length_fn"]
-    var_length: Vec<u8>, // ~ ERROR: length_fn must be of type &PacketWithPayloadHeader -> usize
+    var_length: Vec<u8>,
     #[payload]
     payload: Vec<u8>,
 }
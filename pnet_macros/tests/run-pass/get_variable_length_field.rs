@@ -6,15 +6,14 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-#![feature(custom_attribute, plugin, slice_bytes, vec_push_all)]
-#![plugin(pnet_macros_plugin)]
-
 extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
 extern crate pnet_macros_support;
 
 use pnet_macros_support::types::*;
 
-#[packet]
+#[derive(Clone, Debug, Packet)]
 pub struct WithVariableLengthField {
     banana: u32be,
     #[length = "3"]
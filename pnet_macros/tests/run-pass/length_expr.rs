@@ -6,12 +6,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-#![feature(custom_attribute, plugin, slice_bytes, vec_push_all)]
-#![plugin(pnet_macros_plugin)]
-
 extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
 
-#[packet]
+#[derive(Clone, Debug, Packet)]
 pub struct Key {
     banana: u8,
     #[length = "banana"]
@@ -19,7 +18,7 @@ pub struct Key {
     payload: Vec<u8>,
 }
 
-#[packet]
+#[derive(Clone, Debug, Packet)]
 pub struct AnotherKey {
     banana: u8,
     #[length = "banana + 7"]
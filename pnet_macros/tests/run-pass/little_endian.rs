@@ -0,0 +1,302 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Round trips every little endian field width, at every bit offset, through its getter and
+//! setter, and checks the bytes written against a reference implementation.
+
+extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
+extern crate pnet_macros_support;
+
+/// Read a little endian field of `size` bits, starting `bit` bits into `buf`. The bits in the
+/// lowest addressed byte are the least significant bits of the value.
+fn read_le(buf: &[u8], bit: usize, size: usize) -> u64 {
+    let mut byte = bit / 8;
+    let mut offset = bit % 8;
+    let mut remaining = size;
+    let mut shift = 0;
+    let mut val = 0u64;
+    while remaining > 0 {
+        let n = if 8 - offset < remaining { 8 - offset } else { remaining };
+        let piece = (buf[byte] as u64 >> (8 - offset - n)) & ((1 << n) - 1);
+        val |= piece << shift;
+        shift += n;
+        remaining -= n;
+        byte += 1;
+        offset = 0;
+    }
+
+    val
+}
+
+fn max_value(bits: usize) -> u64 {
+    if bits == 64 { !0 } else { (1 << bits) - 1 }
+}
+
+/// Values to round trip through a field of the given width
+fn test_values(bits: usize) -> Vec<u64> {
+    let max = max_value(bits);
+    let mut vals = vec![0,
+                        1,
+                        max,
+                        max - 1,
+                        max >> 1,
+                        0x5555555555555555 & max,
+                        0xaaaaaaaaaaaaaaaa & max,
+                        0x0123456789abcdef & max];
+    for i in 0..bits {
+        vals.push(1 << i);
+        vals.push(max ^ (1 << i));
+    }
+    let mut x = 0x9e3779b97f4a7c15u64;
+    for _ in 0..64 {
+        x = x.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        vals.push(x & max);
+    }
+
+    vals
+}
+
+/// Declares a packet with eight little endian fields, one starting at each bit offset 0-7, each
+/// followed by a padding field, along with a function which tests them.
+macro_rules! le_packet {
+    ($module:ident, $name:ident, $mutable:ident, $ty:ident, $pad:ident,
+     $bits:expr, $pad_bits:expr) => {
+        mod $module {
+            use pnet::packet::Packet;
+            use pnet_macros_support::types::*;
+            use super::{max_value, read_le, test_values};
+
+            #[derive(Clone, Debug, Packet)]
+            pub struct $name {
+                a0: $ty,
+                p0: $pad,
+                a1: $ty,
+                p1: $pad,
+                a2: $ty,
+                p2: $pad,
+                a3: $ty,
+                p3: $pad,
+                a4: $ty,
+                p4: $pad,
+                a5: $ty,
+                p5: $pad,
+                a6: $ty,
+                p6: $pad,
+                a7: $ty,
+                p7: $pad,
+                #[payload]
+                payload: Vec<u8>,
+            }
+
+            fn get_a(packet: &$mutable, i: usize) -> u64 {
+                (match i {
+                    0 => packet.get_a0(),
+                    1 => packet.get_a1(),
+                    2 => packet.get_a2(),
+                    3 => packet.get_a3(),
+                    4 => packet.get_a4(),
+                    5 => packet.get_a5(),
+                    6 => packet.get_a6(),
+                    _ => packet.get_a7(),
+                }) as u64
+            }
+
+            fn set_a(packet: &mut $mutable, i: usize, val: u64) {
+                let val = val as $ty;
+                match i {
+                    0 => packet.set_a0(val),
+                    1 => packet.set_a1(val),
+                    2 => packet.set_a2(val),
+                    3 => packet.set_a3(val),
+                    4 => packet.set_a4(val),
+                    5 => packet.set_a5(val),
+                    6 => packet.set_a6(val),
+                    _ => packet.set_a7(val),
+                }
+            }
+
+            fn get_p(packet: &$mutable, i: usize) -> u64 {
+                (match i {
+                    0 => packet.get_p0(),
+                    1 => packet.get_p1(),
+                    2 => packet.get_p2(),
+                    3 => packet.get_p3(),
+                    4 => packet.get_p4(),
+                    5 => packet.get_p5(),
+                    6 => packet.get_p6(),
+                    _ => packet.get_p7(),
+                }) as u64
+            }
+
+            fn set_p(packet: &mut $mutable, i: usize, val: u64) {
+                let val = val as $pad;
+                match i {
+                    0 => packet.set_p0(val),
+                    1 => packet.set_p1(val),
+                    2 => packet.set_p2(val),
+                    3 => packet.set_p3(val),
+                    4 => packet.set_p4(val),
+                    5 => packet.set_p5(val),
+                    6 => packet.set_p6(val),
+                    _ => packet.set_p7(val),
+                }
+            }
+
+            pub fn check() {
+                // Each field and its padding take 8n + 1 bits, so field i starts at bit offset i
+                let stride = $bits + $pad_bits;
+                let values = test_values($bits);
+                for field in 0..8 {
+                    // Surrounding fields are filled with zeroes, then ones, to check that
+                    // setting a field leaves its neighbours alone
+                    for &(others, pads) in &[(0, 0), (max_value($bits), max_value($pad_bits))] {
+                        let mut buf = vec![0u8; stride];
+                        let mut packet = $mutable::new(&mut buf[..]).unwrap();
+                        for i in 0..8 {
+                            set_a(&mut packet, i, others);
+                            set_p(&mut packet, i, pads);
+                        }
+                        for &val in &values {
+                            set_a(&mut packet, field, val);
+                            assert_eq!(get_a(&packet, field), val, "a{} = {:#x}", field, val);
+                            assert_eq!(read_le(packet.packet(), field * stride, $bits), val);
+                            if field == 0 && $bits % 8 == 0 {
+                                let bytes = val.to_le_bytes();
+                                assert_eq!(&packet.packet()[..$bits / 8], &bytes[..$bits / 8]);
+                            }
+                            for i in 0..8 {
+                                if i != field {
+                                    assert_eq!(get_a(&packet, i), others);
+                                }
+                                assert_eq!(get_p(&packet, i), pads);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+le_packet!(le9, U9Le, MutableU9LePacket, u9le, u8, 9, 8);
+le_packet!(le10, U10Le, MutableU10LePacket, u10le, u7, 10, 7);
+le_packet!(le11, U11Le, MutableU11LePacket, u11le, u6, 11, 6);
+le_packet!(le12, U12Le, MutableU12LePacket, u12le, u5, 12, 5);
+le_packet!(le13, U13Le, MutableU13LePacket, u13le, u4, 13, 4);
+le_packet!(le14, U14Le, MutableU14LePacket, u14le, u3, 14, 3);
+le_packet!(le15, U15Le, MutableU15LePacket, u15le, u2, 15, 2);
+le_packet!(le16, U16Le, MutableU16LePacket, u16le, u1, 16, 1);
+le_packet!(le17, U17Le, MutableU17LePacket, u17le, u8, 17, 8);
+le_packet!(le18, U18Le, MutableU18LePacket, u18le, u7, 18, 7);
+le_packet!(le19, U19Le, MutableU19LePacket, u19le, u6, 19, 6);
+le_packet!(le20, U20Le, MutableU20LePacket, u20le, u5, 20, 5);
+le_packet!(le21, U21Le, MutableU21LePacket, u21le, u4, 21, 4);
+le_packet!(le22, U22Le, MutableU22LePacket, u22le, u3, 22, 3);
+le_packet!(le23, U23Le, MutableU23LePacket, u23le, u2, 23, 2);
+le_packet!(le24, U24Le, MutableU24LePacket, u24le, u1, 24, 1);
+le_packet!(le25, U25Le, MutableU25LePacket, u25le, u8, 25, 8);
+le_packet!(le26, U26Le, MutableU26LePacket, u26le, u7, 26, 7);
+le_packet!(le27, U27Le, MutableU27LePacket, u27le, u6, 27, 6);
+le_packet!(le28, U28Le, MutableU28LePacket, u28le, u5, 28, 5);
+le_packet!(le29, U29Le, MutableU29LePacket, u29le, u4, 29, 4);
+le_packet!(le30, U30Le, MutableU30LePacket, u30le, u3, 30, 3);
+le_packet!(le31, U31Le, MutableU31LePacket, u31le, u2, 31, 2);
+le_packet!(le32, U32Le, MutableU32LePacket, u32le, u1, 32, 1);
+le_packet!(le33, U33Le, MutableU33LePacket, u33le, u8, 33, 8);
+le_packet!(le34, U34Le, MutableU34LePacket, u34le, u7, 34, 7);
+le_packet!(le35, U35Le, MutableU35LePacket, u35le, u6, 35, 6);
+le_packet!(le36, U36Le, MutableU36LePacket, u36le, u5, 36, 5);
+le_packet!(le37, U37Le, MutableU37LePacket, u37le, u4, 37, 4);
+le_packet!(le38, U38Le, MutableU38LePacket, u38le, u3, 38, 3);
+le_packet!(le39, U39Le, MutableU39LePacket, u39le, u2, 39, 2);
+le_packet!(le40, U40Le, MutableU40LePacket, u40le, u1, 40, 1);
+le_packet!(le41, U41Le, MutableU41LePacket, u41le, u8, 41, 8);
+le_packet!(le42, U42Le, MutableU42LePacket, u42le, u7, 42, 7);
+le_packet!(le43, U43Le, MutableU43LePacket, u43le, u6, 43, 6);
+le_packet!(le44, U44Le, MutableU44LePacket, u44le, u5, 44, 5);
+le_packet!(le45, U45Le, MutableU45LePacket, u45le, u4, 45, 4);
+le_packet!(le46, U46Le, MutableU46LePacket, u46le, u3, 46, 3);
+le_packet!(le47, U47Le, MutableU47LePacket, u47le, u2, 47, 2);
+le_packet!(le48, U48Le, MutableU48LePacket, u48le, u1, 48, 1);
+le_packet!(le49, U49Le, MutableU49LePacket, u49le, u8, 49, 8);
+le_packet!(le50, U50Le, MutableU50LePacket, u50le, u7, 50, 7);
+le_packet!(le51, U51Le, MutableU51LePacket, u51le, u6, 51, 6);
+le_packet!(le52, U52Le, MutableU52LePacket, u52le, u5, 52, 5);
+le_packet!(le53, U53Le, MutableU53LePacket, u53le, u4, 53, 4);
+le_packet!(le54, U54Le, MutableU54LePacket, u54le, u3, 54, 3);
+le_packet!(le55, U55Le, MutableU55LePacket, u55le, u2, 55, 2);
+le_packet!(le56, U56Le, MutableU56LePacket, u56le, u1, 56, 1);
+le_packet!(le57, U57Le, MutableU57LePacket, u57le, u8, 57, 8);
+le_packet!(le58, U58Le, MutableU58LePacket, u58le, u7, 58, 7);
+le_packet!(le59, U59Le, MutableU59LePacket, u59le, u6, 59, 6);
+le_packet!(le60, U60Le, MutableU60LePacket, u60le, u5, 60, 5);
+le_packet!(le61, U61Le, MutableU61LePacket, u61le, u4, 61, 4);
+le_packet!(le62, U62Le, MutableU62LePacket, u62le, u3, 62, 3);
+le_packet!(le63, U63Le, MutableU63LePacket, u63le, u2, 63, 2);
+le_packet!(le64, U64Le, MutableU64LePacket, u64le, u1, 64, 1);
+
+fn main() {
+    le9::check();
+    le10::check();
+    le11::check();
+    le12::check();
+    le13::check();
+    le14::check();
+    le15::check();
+    le16::check();
+    le17::check();
+    le18::check();
+    le19::check();
+    le20::check();
+    le21::check();
+    le22::check();
+    le23::check();
+    le24::check();
+    le25::check();
+    le26::check();
+    le27::check();
+    le28::check();
+    le29::check();
+    le30::check();
+    le31::check();
+    le32::check();
+    le33::check();
+    le34::check();
+    le35::check();
+    le36::check();
+    le37::check();
+    le38::check();
+    le39::check();
+    le40::check();
+    le41::check();
+    le42::check();
+    le43::check();
+    le44::check();
+    le45::check();
+    le46::check();
+    le47::check();
+    le48::check();
+    le49::check();
+    le50::check();
+    le51::check();
+    le52::check();
+    le53::check();
+    le54::check();
+    le55::check();
+    le56::check();
+    le57::check();
+    le58::check();
+    le59::check();
+    le60::check();
+    le61::check();
+    le62::check();
+    le63::check();
+    le64::check();
+}
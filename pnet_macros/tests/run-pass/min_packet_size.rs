@@ -6,23 +6,21 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-#![feature(custom_attribute, plugin, slice_bytes, vec_push_all)]
-#![plugin(pnet_macros_plugin)]
-
 extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
 extern crate pnet_macros_support;
 
 use pnet_macros_support::types::*;
 
-#[packet]
+#[derive(Clone, Debug, Packet)]
 pub struct ByteAligned {
     banana: u8,
     #[payload]
     payload: Vec<u8>,
 }
 
-
-#[packet]
+#[derive(Clone, Debug, Packet)]
 pub struct ByteAlignedWithVariableLength {
     banana: u16be,
     #[length_fn = "length_fn1"]
@@ -34,8 +32,7 @@ fn length_fn1(_: &ByteAlignedWithVariableLengthPacket) -> usize {
     unimplemented!()
 }
 
-
-#[packet]
+#[derive(Clone, Debug, Packet)]
 pub struct ByteAlignedWithVariableLengthAndPayload {
     banana: u32be,
     #[length_fn = "length_fn2"]
@@ -48,8 +45,7 @@ fn length_fn2(_: &ByteAlignedWithVariableLengthAndPayloadPacket) -> usize {
     unimplemented!()
 }
 
-
-#[packet]
+#[derive(Clone, Debug, Packet)]
 pub struct NonByteAligned {
     banana: u3,
     tomato: u5,
@@ -57,8 +53,7 @@ pub struct NonByteAligned {
     payload: Vec<u8>,
 }
 
-
-#[packet]
+#[derive(Clone, Debug, Packet)]
 pub struct NonByteAlignedWithVariableLength {
     banana: u11be,
     tomato: u21be,
@@ -71,8 +66,7 @@ fn length_fn3(_: &NonByteAlignedWithVariableLengthPacket) -> usize {
     unimplemented!()
 }
 
-
-#[packet]
+#[derive(Clone, Debug, Packet)]
 pub struct NonByteAlignedWithVariableLengthAndPayload {
     banana: u7,
     tomato: u9be,
@@ -86,7 +80,6 @@ fn length_fn4(_: &NonByteAlignedWithVariableLengthAndPayloadPacket) -> usize {
     unimplemented!()
 }
 
-
 fn main() {
     assert_eq!(ByteAlignedPacket::minimum_packet_size(), 1);
     assert_eq!(ByteAlignedWithVariableLengthPacket::minimum_packet_size(),
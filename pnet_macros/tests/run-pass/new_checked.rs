@@ -0,0 +1,66 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
+extern crate pnet_macros_support;
+
+use pnet::packet::PacketError;
+use pnet_macros_support::types::*;
+
+#[derive(Clone, Debug, Packet)]
+#[validate_fn = "validate"]
+pub struct Checked {
+    length: u8,
+    #[length_fn = "options_length"]
+    options: Vec<u8>,
+    trailer: u16be,
+    #[payload]
+    payload: Vec<u8>,
+}
+
+fn options_length(packet: &CheckedPacket) -> usize {
+    packet.get_length() as usize - 1
+}
+
+fn validate(packet: &CheckedPacket) -> Result<(), PacketError> {
+    if packet.get_length() == 0 {
+        Err(PacketError::InvalidValue { field: "length" })
+    } else {
+        Ok(())
+    }
+}
+
+fn main() {
+    assert_eq!(CheckedPacket::new_checked(&[]).err(),
+               Some(PacketError::Truncated {
+                   field: "length",
+                   expected: 1,
+                   available: 0,
+               }));
+    assert_eq!(CheckedPacket::new_checked(&[0, 0, 0]).err(),
+               Some(PacketError::InvalidValue { field: "length" }));
+    assert_eq!(CheckedPacket::new_checked(&[5, 0, 0]).err(),
+               Some(PacketError::Truncated {
+                   field: "options",
+                   expected: 5,
+                   available: 3,
+               }));
+    assert_eq!(CheckedPacket::new_checked(&[2, 0, 0]).err(),
+               Some(PacketError::Truncated {
+                   field: "trailer",
+                   expected: 4,
+                   available: 3,
+               }));
+
+    let mut buffer = [2, 7, 0, 1, 9];
+    let packet = MutableCheckedPacket::new_checked(&mut buffer[..]).unwrap();
+    assert_eq!(packet.get_options(), vec![7]);
+    assert_eq!(packet.get_trailer(), 1);
+}
@@ -0,0 +1,138 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
+extern crate pnet_macros_support;
+
+use pnet::packet::{FromPacket, Packet, PacketError};
+use pnet_macros_support::types::*;
+use std::panic::{self, AssertUnwindSafe};
+
+const EXTENDED: u8 = 2;
+
+#[derive(Clone, Debug, Packet)]
+pub struct Optional {
+    has_id: u1,
+    has_timestamp: u1,
+    reserved: u2,
+    version: u4,
+    #[present_if = "has_id == 1"]
+    id: Option<u16be>,
+    #[present_if = "has_timestamp == 1 && version >= EXTENDED"]
+    timestamp: Option<u32le>,
+    #[present_if = "has_id == 1 || has_timestamp == 1"]
+    offset: Option<i16be>,
+    length: u8,
+    #[length = "length"]
+    data: Vec<u8>,
+    #[payload]
+    payload: Vec<u8>,
+}
+
+fn main() {
+    // No optional fields, so length directly follows the flags
+    let data = [0x01, 2, 0xaa, 0xbb, 0xcc];
+    let packet = OptionalPacket::new(&data[..]).unwrap();
+    assert_eq!(packet.get_id(), None);
+    assert_eq!(packet.get_timestamp(), None);
+    assert_eq!(packet.get_offset(), None);
+    assert_eq!(packet.get_length(), 2);
+    assert_eq!(packet.get_data(), vec![0xaa, 0xbb]);
+    assert_eq!(packet.payload(), &[0xcc]);
+    assert_eq!(OptionalPacket::minimum_packet_size(), 2);
+
+    // Every optional field present
+    let data = [0xc2, 0x12, 0x34, 0x04, 0x03, 0x02, 0x01, 0xff, 0xfe, 1, 0xaa, 0xcc];
+    let packet = OptionalPacket::new(&data[..]).unwrap();
+    assert_eq!(packet.get_id(), Some(0x1234));
+    assert_eq!(packet.get_timestamp(), Some(0x01020304));
+    assert_eq!(packet.get_offset(), Some(-2));
+    assert_eq!(packet.get_data(), vec![0xaa]);
+    assert_eq!(packet.payload(), &[0xcc]);
+
+    // The timestamp depends on the version, as well as its flag
+    let data = [0x41, 0xff, 0xfe, 0];
+    let packet = OptionalPacket::new(&data[..]).unwrap();
+    assert_eq!(packet.get_timestamp(), None);
+    assert_eq!(packet.get_offset(), Some(-2));
+    assert_eq!(packet.get_length(), 0);
+
+    // Round trip through the struct
+    let optional = packet.from_packet();
+    assert_eq!(optional.id, None);
+    assert_eq!(optional.offset, Some(-2));
+    assert_eq!(OptionalPacket::packet_size(&optional), 4);
+    let mut buf = [0u8; 4];
+    {
+        let mut packet = MutableOptionalPacket::new(&mut buf[..]).unwrap();
+        packet.populate(&optional);
+    }
+    assert_eq!(&buf, &data);
+
+    let optional = Optional {
+        has_id: 1,
+        has_timestamp: 1,
+        reserved: 0,
+        version: 2,
+        id: Some(7),
+        timestamp: Some(0xdeadbeef),
+        offset: Some(100),
+        length: 1,
+        data: vec![9],
+        payload: vec![],
+    };
+    assert_eq!(OptionalPacket::packet_size(&optional), 11);
+    let mut buf = [0u8; 11];
+    {
+        let mut packet = MutableOptionalPacket::new(&mut buf[..]).unwrap();
+        packet.populate(&optional);
+        assert_eq!(packet.get_timestamp(), Some(0xdeadbeef));
+    }
+    assert_eq!(&buf, &[0xc2, 0, 7, 0xef, 0xbe, 0xad, 0xde, 0, 100, 1, 9]);
+
+    // new_checked reports optional fields which don't fit
+    assert_eq!(OptionalPacket::new_checked(&[0x80, 0x00]).err(),
+               Some(PacketError::Truncated {
+                   field: "id",
+                   expected: 3,
+                   available: 2,
+               }));
+    assert!(OptionalPacket::new_checked(&[0x80, 0x00, 0x01, 0x00, 0x00, 0x00]).is_ok());
+    assert_eq!(OptionalPacket::new_checked(&[0xc2, 0x00, 0x01, 0x02, 0x03, 0x04]).err(),
+               Some(PacketError::Truncated {
+                   field: "timestamp",
+                   expected: 7,
+                   available: 6,
+               }));
+
+    // Present fields which don't fit in the packet can't be read
+    let data = [0xc2, 0x12, 0x34, 0x04, 0x03];
+    let packet = OptionalPacket::new(&data[..]).unwrap();
+    assert_eq!(packet.get_id(), Some(0x1234));
+    assert_eq!(packet.get_timestamp(), None);
+    assert_eq!(packet.get_offset(), None);
+
+    // Values may only be set when the field is present
+    let mut buf = [0u8; 4];
+    let mut packet = MutableOptionalPacket::new(&mut buf[..]).unwrap();
+    packet.set_id(None);
+    assert_eq!(packet.packet(), &[0, 0, 0, 0]);
+    panic::set_hook(Box::new(|_| {}));
+    let result = panic::catch_unwind(AssertUnwindSafe(|| packet.set_id(Some(1))));
+    assert_eq!(result.unwrap_err().downcast_ref::<&str>(), Some(&"id is not present"));
+
+    // Nor when they don't fit
+    let mut buf = [0x80, 0];
+    let mut packet = MutableOptionalPacket::new(&mut buf[..]).unwrap();
+    let result = panic::catch_unwind(AssertUnwindSafe(|| packet.set_id(Some(1))));
+    let _ = panic::take_hook();
+    assert_eq!(result.unwrap_err().downcast_ref::<&str>(),
+               Some(&"id does not fit in the packet"));
+}
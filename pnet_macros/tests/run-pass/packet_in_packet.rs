@@ -6,12 +6,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-#![feature(custom_attribute, plugin, slice_bytes, vec_push_all)]
-#![plugin(pnet_macros_plugin)]
-
 extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
 
-#[packet]
+#[derive(Clone, Debug, Packet)]
 pub struct PacketWithPayload {
     banana: u8,
     length: u8,
@@ -22,7 +21,7 @@ pub struct PacketWithPayload {
     payload: Vec<u8>,
 }
 
-#[packet]
+#[derive(Clone, Debug, Packet)]
 pub struct PacketOption {
     pineapple: u8,
     length: u8,
@@ -0,0 +1,157 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
+extern crate pnet_macros_support;
+
+use pnet::packet::{Packet, PrimitiveValues};
+use pnet_macros_support::types::*;
+use std::panic::{self, AssertUnwindSafe};
+
+/// A field made up of two signed nibbles
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nibbles(pub i8, pub i8);
+
+impl Nibbles {
+    pub fn new(high: i4, low: i4) -> Nibbles {
+        Nibbles(high, low)
+    }
+}
+
+impl PrimitiveValues for Nibbles {
+    type T = (i4, i4);
+    fn to_primitive_values(&self) -> (i4, i4) {
+        (self.0, self.1)
+    }
+}
+
+/// Loosely based on the start of an NTP header, followed by odd sized fields
+#[derive(Clone, Debug, Packet)]
+pub struct Signed {
+    stratum: u8,
+    poll: i8,
+    precision: i8,
+    correction: i64be,
+    one: i1,
+    three: i3,
+    twelve: i12be,
+    twelve_le: i12le,
+    #[construct_with(i4, i4)]
+    nibbles: Nibbles,
+    reserved: u4,
+    twenty_four_le: i24le,
+    thirty_three: i33be,
+    seven: i7,
+    sixteen_le: i16le,
+    #[length = "8"]
+    words: Vec<i16be>,
+    #[payload]
+    payload: Vec<u8>,
+}
+
+fn main() {
+    let mut buf = [0u8; 38];
+    {
+        let mut packet = MutableSignedPacket::new(&mut buf[..]).unwrap();
+        packet.set_stratum(2);
+        packet.set_poll(-6);
+        packet.set_precision(-20);
+        packet.set_correction(-1);
+        packet.set_one(-1);
+        packet.set_three(-4);
+        packet.set_twelve(-2048);
+        packet.set_twelve_le(-2);
+        packet.set_nibbles(Nibbles(-8, 7));
+        packet.set_twenty_four_le(-8388608);
+        packet.set_thirty_three(-4294967296);
+        packet.set_seven(-64);
+        packet.set_sixteen_le(-32768);
+        packet.set_words(&[-1, 1, -32768, 32767]);
+
+        assert_eq!(packet.get_stratum(), 2);
+        assert_eq!(packet.get_poll(), -6);
+        assert_eq!(packet.get_precision(), -20);
+        assert_eq!(packet.get_correction(), -1);
+        assert_eq!(packet.get_one(), -1);
+        assert_eq!(packet.get_three(), -4);
+        assert_eq!(packet.get_twelve(), -2048);
+        assert_eq!(packet.get_twelve_le(), -2);
+        assert_eq!(packet.get_nibbles(), Nibbles(-8, 7));
+        assert_eq!(packet.get_twenty_four_le(), -8388608);
+        assert_eq!(packet.get_thirty_three(), -4294967296);
+        assert_eq!(packet.get_seven(), -64);
+        assert_eq!(packet.get_sixteen_le(), -32768);
+        assert_eq!(packet.get_words(), vec![-1, 1, -32768, 32767]);
+    }
+
+    assert_eq!(&buf[..11], &[2, 0xfa, 0xec, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    // one, three and the first four bits of twelve
+    assert_eq!(buf[11], 0b1100_1000);
+    // The rest of twelve, then the least significant byte of twelve_le
+    assert_eq!(&buf[12..14], &[0b0000_0000, 0b1111_1110]);
+    // The most significant nibble of twelve_le, the nibbles, then reserved
+    assert_eq!(&buf[14..16], &[0b1111_1000, 0b0111_0000]);
+    // Little endian fields store their least significant byte first
+    assert_eq!(&buf[16..19], &[0x00, 0x00, 0x80]);
+    // thirty_three, then seven
+    assert_eq!(&buf[19..24], &[0x80, 0x00, 0x00, 0x00, 0b0100_0000]);
+    assert_eq!(&buf[24..26], &[0x00, 0x80]);
+    assert_eq!(&buf[26..34], &[0xff, 0xff, 0x00, 0x01, 0x80, 0x00, 0x7f, 0xff]);
+
+    // Every value of a small field survives a round trip, without disturbing its neighbours
+    {
+        let mut packet = MutableSignedPacket::new(&mut buf[..]).unwrap();
+        for val in -4..4 {
+            packet.set_three(val);
+            assert_eq!(packet.get_three(), val);
+            assert_eq!(packet.get_one(), -1);
+            assert_eq!(packet.get_twelve(), -2048);
+        }
+        for val in -2048..2048 {
+            packet.set_twelve(val);
+            packet.set_twelve_le(val);
+            assert_eq!(packet.get_twelve(), val);
+            assert_eq!(packet.get_twelve_le(), val);
+            assert_eq!(packet.get_three(), 3);
+            assert_eq!(packet.get_nibbles(), Nibbles(-8, 7));
+        }
+        packet.set_one(0);
+        assert_eq!(packet.get_one(), 0);
+        packet.set_thirty_three(4294967295);
+        assert_eq!(packet.get_thirty_three(), 4294967295);
+        assert_eq!(packet.get_seven(), -64);
+        packet.set_seven(63);
+        assert_eq!(packet.get_seven(), 63);
+        assert_eq!(packet.get_thirty_three(), 4294967295);
+        assert_eq!(packet.get_sixteen_le(), -32768);
+    }
+
+    // Values which don't fit in the field are rejected
+    let mut packet = MutableSignedPacket::new(&mut buf[..]).unwrap();
+    let out_of_range: Vec<Box<dyn Fn(&mut MutableSignedPacket)>> =
+        vec![Box::new(|p| p.set_one(1)),
+             Box::new(|p| p.set_three(4)),
+             Box::new(|p| p.set_three(-5)),
+             Box::new(|p| p.set_twelve(2048)),
+             Box::new(|p| p.set_twelve_le(-2049)),
+             Box::new(|p| p.set_nibbles(Nibbles(8, 0))),
+             Box::new(|p| p.set_thirty_three(-4294967297)),
+             Box::new(|p| p.set_seven(64))];
+    panic::set_hook(Box::new(|_| {}));
+    for set in &out_of_range {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| set(&mut packet)));
+        assert!(result.is_err());
+    }
+    let _ = panic::take_hook();
+
+    let packet = SignedPacket::new(&buf[..]).unwrap();
+    assert_eq!(packet.get_twelve(), 2047);
+    assert_eq!(packet.packet().len(), 38);
+}
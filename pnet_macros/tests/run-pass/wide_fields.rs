@@ -0,0 +1,106 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+extern crate pnet;
+#[macro_use]
+extern crate pnet_macros;
+extern crate pnet_macros_support;
+
+use pnet::packet::{FromPacket, Packet};
+use pnet_macros_support::types::*;
+
+#[derive(Clone, Debug, Packet)]
+pub struct Wide {
+    flags: u4,
+    counter: u128be,
+    reserved: u4,
+    identifier: [u8; 16],
+    counter_le: u128le,
+    delta: i128be,
+    mac: [u8; 6],
+    #[payload]
+    payload: Vec<u8>,
+}
+
+fn main() {
+    assert_eq!(WidePacket::minimum_packet_size(), 71);
+
+    let counter = 0x0102030405060708090a0b0c0d0e0f10;
+    let identifier = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+    let mac = [0xde, 0xad, 0xbe, 0xef, 0x00, 0x01];
+
+    let mut buf = [0u8; 90];
+    {
+        let mut packet = MutableWidePacket::new(&mut buf[..]).unwrap();
+        packet.set_flags(0xa);
+        packet.set_counter(counter);
+        packet.set_reserved(0x5);
+        packet.set_identifier(&identifier);
+        packet.set_counter_le(counter);
+        packet.set_delta(-2);
+        packet.set_mac(&mac);
+        packet.set_payload(&[1, 2, 3]);
+
+        assert_eq!(packet.get_flags(), 0xa);
+        assert_eq!(packet.get_counter(), counter);
+        assert_eq!(packet.get_reserved(), 0x5);
+        assert_eq!(packet.get_identifier(), &identifier);
+        assert_eq!(packet.get_counter_le(), counter);
+        assert_eq!(packet.get_delta(), -2);
+        assert_eq!(packet.get_mac(), &mac);
+    }
+
+    // counter isn't byte aligned, so it is shifted by four bits
+    assert_eq!(&buf[..18],
+               &[0xa0, 0x10, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0x80, 0x90, 0xa0, 0xb0, 0xc0,
+                 0xd0, 0xe0, 0xf1, 0x05, 0x20]);
+    assert_eq!(&buf[17..33], &identifier);
+    let mut counter_le = [0x10, 0x0f, 0x0e, 0x0d, 0x0c, 0x0b, 0x0a, 0x09, 0x08, 0x07, 0x06, 0x05,
+                          0x04, 0x03, 0x02, 0x01];
+    assert_eq!(&buf[33..49], &counter_le);
+    let mut delta = [0xff; 16];
+    delta[15] = 0xfe;
+    assert_eq!(&buf[49..65], &delta);
+    assert_eq!(&buf[65..71], &mac);
+
+    // The getters borrow from the packet's buffer
+    {
+        let packet = WidePacket::new(&buf[..]).unwrap();
+        let identifier_ptr = packet.get_identifier().as_ptr();
+        assert_eq!(identifier_ptr, packet.packet()[17..].as_ptr());
+
+        let wide = packet.from_packet();
+        assert_eq!(wide.counter, counter);
+        assert_eq!(wide.identifier, identifier);
+        assert_eq!(wide.mac, mac);
+    }
+
+    // Populating a packet copies the arrays in
+    counter_le.reverse();
+    let wide = Wide {
+        flags: 0,
+        counter: 1,
+        reserved: 0,
+        identifier: counter_le,
+        counter_le: !0,
+        delta: i128::min_value(),
+        mac: [0; 6],
+        payload: vec![],
+    };
+    let mut buf = [0u8; 71];
+    {
+        let mut packet = MutableWidePacket::new(&mut buf[..]).unwrap();
+        packet.populate(&wide);
+    }
+    let packet = WidePacket::new(&buf[..]).unwrap();
+    assert_eq!(packet.get_counter(), 1);
+    assert_eq!(packet.get_identifier(), &counter_le);
+    assert_eq!(packet.get_counter_le(), u128::max_value());
+    assert_eq!(packet.get_delta(), i128::min_value());
+    assert_eq!(packet.get_mac(), &[0; 6]);
+}
@@ -1,21 +1,8 @@
-extern crate compiletest_rs as compiletest;
-
-use std::path::PathBuf;
-
-fn run_mode(mode: &'static str) {
-
-    let mut config = compiletest::default_config();
-    let cfg_mode = mode.parse().ok().expect("Invalid mode");
-
-    config.mode = cfg_mode;
-    config.src_base = PathBuf::from(format!("tests/{}", mode));
-    config.target_rustcflags = Some("-L target/debug -L target/debug/deps".to_string());
-
-    compiletest::run_tests(&config);
-}
+extern crate trybuild;
 
 #[test]
 fn compile_test() {
-    run_mode("compile-fail");
-    run_mode("run-pass");
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/compile-fail/*.rs");
+    t.pass("tests/run-pass/*.rs");
 }
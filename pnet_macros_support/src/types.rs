@@ -8,20 +8,26 @@
 
 //! Provides type aliases for various primitive integer types
 //!
-//! These types are aliased to the next largest of [`u8`, `u16`, `u32`, `u64`], and purely serve as
-//! hints for the `#[packet]` macro to enable the generation of the correct bit manipulations to
-//! get the value out of a packet.
+//! These types are aliased to the next largest of [`u8`, `u16`, `u32`, `u64`], or, for the signed
+//! `i*` types, [`i8`, `i16`, `i32`, `i64`], and purely serve as hints for `#[derive(Packet)]` to
+//! enable the generation of the correct bit manipulations to get the value out of a packet.
+//! `u128be`, `u128le`, `i128be` and `i128le` are also provided, for wider fields.
 //!
-//! They should NOT be used outside of data types marked as `#[packet]`.
+//! They should NOT be used outside of data types marked as `#[derive(Packet)]`.
 //!
-//! All aliases for types larger than `u8` contain a `be` or `le` suffix. These specify whether the
+//! Signed types are stored as two's complement. Getters sign extend the value, and setters panic
+//! if given a value which does not fit in the field.
+//!
+//! All aliases for types larger than `u8` or `i8` contain a `be` or `le` suffix. These specify whether the
 //! value is big or little endian, respectively. When using `set_*()` and `get_*()` methods, host
 //! endianness should be used - the methods will convert as appropriate.
+//!
+//! Little endian fields which do not start or end on a byte boundary, such as a `u12le`, are laid
+//! out byte by byte: the bits of the field in the lowest addressed byte are the least significant
+//! bits of the value, those in the next byte are the next most significant, and so on.
 
 #![allow(non_camel_case_types)]
 
-// TODO signed equivalents?
-
 /// Represents an unsigned, 1-bit integer
 pub type u1 = u8;
 
@@ -212,6 +218,9 @@ pub type u63be = u64;
 /// Represents an unsigned, 64-bit, big endian integer
 pub type u64be = u64;
 
+/// Represents an unsigned, 128-bit, big endian integer
+pub type u128be = u128;
+
 
 /// Represents an unsigned, 9-bit, little endian integer
 pub type u9le = u16;
@@ -380,3 +389,371 @@ pub type u63le = u64;
 
 /// Represents an unsigned, 64-bit, little endian integer
 pub type u64le = u64;
+
+/// Represents an unsigned, 128-bit, little endian integer
+pub type u128le = u128;
+
+/// Represents a signed, 1-bit integer
+pub type i1 = i8;
+
+/// Represents a signed, 2-bit integer
+pub type i2 = i8;
+
+/// Represents a signed, 3-bit integer
+pub type i3 = i8;
+
+/// Represents a signed, 4-bit integer
+pub type i4 = i8;
+
+/// Represents a signed, 5-bit integer
+pub type i5 = i8;
+
+/// Represents a signed, 6-bit integer
+pub type i6 = i8;
+
+/// Represents a signed, 7-bit integer
+pub type i7 = i8;
+
+
+/// Represents a signed, 9-bit, big endian integer
+pub type i9be = i16;
+
+/// Represents a signed, 10-bit, big endian integer
+pub type i10be = i16;
+
+/// Represents a signed, 11-bit, big endian integer
+pub type i11be = i16;
+
+/// Represents a signed, 12-bit, big endian integer
+pub type i12be = i16;
+
+/// Represents a signed, 13-bit, big endian integer
+pub type i13be = i16;
+
+/// Represents a signed, 14-bit, big endian integer
+pub type i14be = i16;
+
+/// Represents a signed, 15-bit, big endian integer
+pub type i15be = i16;
+
+/// Represents a signed, 16-bit, big endian integer
+pub type i16be = i16;
+
+/// Represents a signed, 17-bit, big endian integer
+pub type i17be = i32;
+
+/// Represents a signed, 18-bit, big endian integer
+pub type i18be = i32;
+
+/// Represents a signed, 19-bit, big endian integer
+pub type i19be = i32;
+
+/// Represents a signed, 20-bit, big endian integer
+pub type i20be = i32;
+
+/// Represents a signed, 21-bit, big endian integer
+pub type i21be = i32;
+
+/// Represents a signed, 22-bit, big endian integer
+pub type i22be = i32;
+
+/// Represents a signed, 23-bit, big endian integer
+pub type i23be = i32;
+
+/// Represents a signed, 24-bit, big endian integer
+pub type i24be = i32;
+
+/// Represents a signed, 25-bit, big endian integer
+pub type i25be = i32;
+
+/// Represents a signed, 26-bit, big endian integer
+pub type i26be = i32;
+
+/// Represents a signed, 27-bit, big endian integer
+pub type i27be = i32;
+
+/// Represents a signed, 28-bit, big endian integer
+pub type i28be = i32;
+
+/// Represents a signed, 29-bit, big endian integer
+pub type i29be = i32;
+
+/// Represents a signed, 30-bit, big endian integer
+pub type i30be = i32;
+
+/// Represents a signed, 31-bit, big endian integer
+pub type i31be = i32;
+
+/// Represents a signed, 32-bit, big endian integer
+pub type i32be = i32;
+
+/// Represents a signed, 33-bit, big endian integer
+pub type i33be = i64;
+
+/// Represents a signed, 34-bit, big endian integer
+pub type i34be = i64;
+
+/// Represents a signed, 35-bit, big endian integer
+pub type i35be = i64;
+
+/// Represents a signed, 36-bit, big endian integer
+pub type i36be = i64;
+
+/// Represents a signed, 37-bit, big endian integer
+pub type i37be = i64;
+
+/// Represents a signed, 38-bit, big endian integer
+pub type i38be = i64;
+
+/// Represents a signed, 39-bit, big endian integer
+pub type i39be = i64;
+
+/// Represents a signed, 40-bit, big endian integer
+pub type i40be = i64;
+
+/// Represents a signed, 41-bit, big endian integer
+pub type i41be = i64;
+
+/// Represents a signed, 42-bit, big endian integer
+pub type i42be = i64;
+
+/// Represents a signed, 43-bit, big endian integer
+pub type i43be = i64;
+
+/// Represents a signed, 44-bit, big endian integer
+pub type i44be = i64;
+
+/// Represents a signed, 45-bit, big endian integer
+pub type i45be = i64;
+
+/// Represents a signed, 46-bit, big endian integer
+pub type i46be = i64;
+
+/// Represents a signed, 47-bit, big endian integer
+pub type i47be = i64;
+
+/// Represents a signed, 48-bit, big endian integer
+pub type i48be = i64;
+
+/// Represents a signed, 49-bit, big endian integer
+pub type i49be = i64;
+
+/// Represents a signed, 50-bit, big endian integer
+pub type i50be = i64;
+
+/// Represents a signed, 51-bit, big endian integer
+pub type i51be = i64;
+
+/// Represents a signed, 52-bit, big endian integer
+pub type i52be = i64;
+
+/// Represents a signed, 53-bit, big endian integer
+pub type i53be = i64;
+
+/// Represents a signed, 54-bit, big endian integer
+pub type i54be = i64;
+
+/// Represents a signed, 55-bit, big endian integer
+pub type i55be = i64;
+
+/// Represents a signed, 56-bit, big endian integer
+pub type i56be = i64;
+
+/// Represents a signed, 57-bit, big endian integer
+pub type i57be = i64;
+
+/// Represents a signed, 58-bit, big endian integer
+pub type i58be = i64;
+
+/// Represents a signed, 59-bit, big endian integer
+pub type i59be = i64;
+
+/// Represents a signed, 60-bit, big endian integer
+pub type i60be = i64;
+
+/// Represents a signed, 61-bit, big endian integer
+pub type i61be = i64;
+
+/// Represents a signed, 62-bit, big endian integer
+pub type i62be = i64;
+
+/// Represents a signed, 63-bit, big endian integer
+pub type i63be = i64;
+
+/// Represents a signed, 64-bit, big endian integer
+pub type i64be = i64;
+
+/// Represents a signed, 128-bit, big endian integer
+pub type i128be = i128;
+
+
+/// Represents a signed, 9-bit, little endian integer
+pub type i9le = i16;
+
+/// Represents a signed, 10-bit, little endian integer
+pub type i10le = i16;
+
+/// Represents a signed, 11-bit, little endian integer
+pub type i11le = i16;
+
+/// Represents a signed, 12-bit, little endian integer
+pub type i12le = i16;
+
+/// Represents a signed, 13-bit, little endian integer
+pub type i13le = i16;
+
+/// Represents a signed, 14-bit, little endian integer
+pub type i14le = i16;
+
+/// Represents a signed, 15-bit, little endian integer
+pub type i15le = i16;
+
+/// Represents a signed, 16-bit, little endian integer
+pub type i16le = i16;
+
+/// Represents a signed, 17-bit, little endian integer
+pub type i17le = i32;
+
+/// Represents a signed, 18-bit, little endian integer
+pub type i18le = i32;
+
+/// Represents a signed, 19-bit, little endian integer
+pub type i19le = i32;
+
+/// Represents a signed, 20-bit, little endian integer
+pub type i20le = i32;
+
+/// Represents a signed, 21-bit, little endian integer
+pub type i21le = i32;
+
+/// Represents a signed, 22-bit, little endian integer
+pub type i22le = i32;
+
+/// Represents a signed, 23-bit, little endian integer
+pub type i23le = i32;
+
+/// Represents a signed, 24-bit, little endian integer
+pub type i24le = i32;
+
+/// Represents a signed, 25-bit, little endian integer
+pub type i25le = i32;
+
+/// Represents a signed, 26-bit, little endian integer
+pub type i26le = i32;
+
+/// Represents a signed, 27-bit, little endian integer
+pub type i27le = i32;
+
+/// Represents a signed, 28-bit, little endian integer
+pub type i28le = i32;
+
+/// Represents a signed, 29-bit, little endian integer
+pub type i29le = i32;
+
+/// Represents a signed, 30-bit, little endian integer
+pub type i30le = i32;
+
+/// Represents a signed, 31-bit, little endian integer
+pub type i31le = i32;
+
+/// Represents a signed, 32-bit, little endian integer
+pub type i32le = i32;
+
+/// Represents a signed, 33-bit, little endian integer
+pub type i33le = i64;
+
+/// Represents a signed, 34-bit, little endian integer
+pub type i34le = i64;
+
+/// Represents a signed, 35-bit, little endian integer
+pub type i35le = i64;
+
+/// Represents a signed, 36-bit, little endian integer
+pub type i36le = i64;
+
+/// Represents a signed, 37-bit, little endian integer
+pub type i37le = i64;
+
+/// Represents a signed, 38-bit, little endian integer
+pub type i38le = i64;
+
+/// Represents a signed, 39-bit, little endian integer
+pub type i39le = i64;
+
+/// Represents a signed, 40-bit, little endian integer
+pub type i40le = i64;
+
+/// Represents a signed, 41-bit, little endian integer
+pub type i41le = i64;
+
+/// Represents a signed, 42-bit, little endian integer
+pub type i42le = i64;
+
+/// Represents a signed, 43-bit, little endian integer
+pub type i43le = i64;
+
+/// Represents a signed, 44-bit, little endian integer
+pub type i44le = i64;
+
+/// Represents a signed, 45-bit, little endian integer
+pub type i45le = i64;
+
+/// Represents a signed, 46-bit, little endian integer
+pub type i46le = i64;
+
+/// Represents a signed, 47-bit, little endian integer
+pub type i47le = i64;
+
+/// Represents a signed, 48-bit, little endian integer
+pub type i48le = i64;
+
+/// Represents a signed, 49-bit, little endian integer
+pub type i49le = i64;
+
+/// Represents a signed, 50-bit, little endian integer
+pub type i50le = i64;
+
+/// Represents a signed, 51-bit, little endian integer
+pub type i51le = i64;
+
+/// Represents a signed, 52-bit, little endian integer
+pub type i52le = i64;
+
+/// Represents a signed, 53-bit, little endian integer
+pub type i53le = i64;
+
+/// Represents a signed, 54-bit, little endian integer
+pub type i54le = i64;
+
+/// Represents a signed, 55-bit, little endian integer
+pub type i55le = i64;
+
+/// Represents a signed, 56-bit, little endian integer
+pub type i56le = i64;
+
+/// Represents a signed, 57-bit, little endian integer
+pub type i57le = i64;
+
+/// Represents a signed, 58-bit, little endian integer
+pub type i58le = i64;
+
+/// Represents a signed, 59-bit, little endian integer
+pub type i59le = i64;
+
+/// Represents a signed, 60-bit, little endian integer
+pub type i60le = i64;
+
+/// Represents a signed, 61-bit, little endian integer
+pub type i61le = i64;
+
+/// Represents a signed, 62-bit, little endian integer
+pub type i62le = i64;
+
+/// Represents a signed, 63-bit, little endian integer
+pub type i63le = i64;
+
+/// Represents a signed, 64-bit, little endian integer
+pub type i64le = i64;
+
+/// Represents a signed, 128-bit, little endian integer
+pub type i128le = i128;
@@ -0,0 +1,565 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for resolving IPv4 addresses to MAC addresses using ARP
+//!
+//! An `ArpResolver` takes over a datalink channel, and handles the ARP packets received on it in
+//! a background thread: replies are remembered in a `NeighborCache`, and requests for any
+//! addresses it has been configured with are answered.
+
+use datalink::{EthernetDataLinkReceiver, EthernetDataLinkSender};
+use internal::is_transient;
+use packet::{MutablePacket, Packet};
+use packet::arp::{ArpHardwareTypes, ArpOperation, ArpOperations, ArpPacket, MutableArpPacket};
+use packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use util::MacAddr;
+
+use std::collections::HashMap;
+use std::io;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The length of an Ethernet frame carrying an ARP packet for IPv4
+pub(crate) const ARP_FRAME_LEN: usize = 14 + 28;
+
+/// The Ethernet broadcast address
+fn broadcast() -> MacAddr {
+    MacAddr(0xff, 0xff, 0xff, 0xff, 0xff, 0xff)
+}
+
+fn unspecified() -> MacAddr {
+    MacAddr(0, 0, 0, 0, 0, 0)
+}
+
+/// A cache mapping IPv4 addresses to MAC addresses, whose entries expire after a fixed time
+#[derive(Clone, Debug)]
+pub struct NeighborCache {
+    entries: HashMap<Ipv4Addr, (MacAddr, Instant)>,
+    timeout: Duration,
+}
+
+impl NeighborCache {
+    /// Create an empty cache, whose entries expire `timeout` after they were last updated
+    pub fn new(timeout: Duration) -> NeighborCache {
+        NeighborCache {
+            entries: HashMap::new(),
+            timeout: timeout,
+        }
+    }
+
+    /// Add or refresh the entry for `ip`
+    pub fn insert(&mut self, ip: Ipv4Addr, mac: MacAddr) {
+        self.entries.insert(ip, (mac, Instant::now()));
+    }
+
+    /// Look up the MAC address of `ip`, if it has an entry which hasn't expired
+    pub fn get(&self, ip: &Ipv4Addr) -> Option<MacAddr> {
+        match self.entries.get(ip) {
+            Some(&(mac, updated)) if updated.elapsed() < self.timeout => Some(mac),
+            _ => None,
+        }
+    }
+
+    /// Whether there is an entry for `ip`, even an expired one
+    pub fn contains(&self, ip: &Ipv4Addr) -> bool {
+        self.entries.contains_key(ip)
+    }
+
+    /// Remove the entry for `ip`, returning its MAC address if it hadn't expired
+    pub fn remove(&mut self, ip: &Ipv4Addr) -> Option<MacAddr> {
+        let mac = self.get(ip);
+        self.entries.remove(ip);
+
+        mac
+    }
+
+    /// Remove every expired entry
+    pub fn expire(&mut self) {
+        let timeout = self.timeout;
+        let expired: Vec<Ipv4Addr> = self.entries
+            .iter()
+            .filter(|&(_, &(_, updated))| updated.elapsed() >= timeout)
+            .map(|(ip, _)| *ip)
+            .collect();
+        for ip in expired {
+            self.entries.remove(&ip);
+        }
+    }
+
+    /// The entries which haven't expired
+    pub fn entries(&self) -> Vec<(Ipv4Addr, MacAddr)> {
+        self.entries
+            .iter()
+            .filter(|&(_, &(_, updated))| updated.elapsed() < self.timeout)
+            .map(|(ip, &(mac, _))| (*ip, mac))
+            .collect()
+    }
+}
+
+/// Options for an `ArpResolver`
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Config {
+    /// How long learned addresses are cached for. Defaults to 60 seconds.
+    pub cache_timeout: Duration,
+
+    /// How long to wait for a reply to each request. Defaults to one second.
+    pub request_timeout: Duration,
+
+    /// The number of requests sent by `resolve()` before giving up. Defaults to 3.
+    pub attempts: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            cache_timeout: Duration::from_secs(60),
+            request_timeout: Duration::from_secs(1),
+            attempts: 3,
+        }
+    }
+}
+
+/// The parts of an ARP packet which vary between the packets an `ArpResolver` or neighbor scan
+/// sends
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ArpFrame {
+    destination: MacAddr,
+    operation: ArpOperation,
+    sender_hw_addr: MacAddr,
+    sender_proto_addr: Ipv4Addr,
+    target_hw_addr: MacAddr,
+    target_proto_addr: Ipv4Addr,
+}
+
+impl ArpFrame {
+    /// A broadcast request from `sender_hw_addr` at `sender_proto_addr`, asking who has
+    /// `target_proto_addr`
+    pub(crate) fn request(sender_hw_addr: MacAddr,
+                          sender_proto_addr: Ipv4Addr,
+                          target_proto_addr: Ipv4Addr)
+        -> ArpFrame {
+        ArpFrame {
+            destination: broadcast(),
+            operation: ArpOperations::Request,
+            sender_hw_addr: sender_hw_addr,
+            sender_proto_addr: sender_proto_addr,
+            target_hw_addr: unspecified(),
+            target_proto_addr: target_proto_addr,
+        }
+    }
+
+    pub(crate) fn send(&self, tx: &mut Box<EthernetDataLinkSender>) -> io::Result<()> {
+        let frame = *self;
+        let result = tx.build_and_send(1, ARP_FRAME_LEN, &mut |mut ethernet| {
+            frame.fill(&mut ethernet);
+        });
+
+        match result {
+            Some(result) => result,
+            None => Err(io::Error::new(io::ErrorKind::Other, "Unable to build ARP frame")),
+        }
+    }
+
+    fn fill(&self, ethernet: &mut MutableEthernetPacket) {
+        ethernet.set_destination(self.destination);
+        ethernet.set_source(self.sender_hw_addr);
+        ethernet.set_ethertype(EtherTypes::Arp);
+
+        let mut arp = MutableArpPacket::new(ethernet.payload_mut()).unwrap();
+        arp.set_hardware_type(ArpHardwareTypes::Ethernet);
+        arp.set_protocol_type(EtherTypes::Ipv4);
+        arp.set_hw_addr_len(6);
+        arp.set_proto_addr_len(4);
+        arp.set_operation(self.operation);
+        arp.set_sender_hw_addr(self.sender_hw_addr);
+        arp.set_sender_proto_addr(self.sender_proto_addr);
+        arp.set_target_hw_addr(self.target_hw_addr);
+        arp.set_target_proto_addr(self.target_proto_addr);
+    }
+}
+
+/// State shared between an `ArpResolver` and its receiving thread
+struct State {
+    cache: NeighborCache,
+    /// Addresses to answer requests for, and the MAC address to answer with
+    addresses: HashMap<Ipv4Addr, MacAddr>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Signalled whenever the cache is updated
+    updated: Condvar,
+    tx: Mutex<Box<EthernetDataLinkSender>>,
+    stop: AtomicBool,
+}
+
+/// Resolves IPv4 addresses to MAC addresses over a datalink channel, and answers ARP requests
+///
+/// The receiving half of the channel is read by a background thread until the resolver is
+/// dropped. The thread notices it should stop when the next packet or read timeout arrives, so
+/// the channel should be created with a read timeout where the backend supports one.
+pub struct ArpResolver {
+    shared: Arc<Shared>,
+    mac: MacAddr,
+    ip: Ipv4Addr,
+    config: Config,
+}
+
+impl ArpResolver {
+    /// Start handling ARP on the given datalink channel
+    ///
+    /// `mac` and `ip` are the addresses of this host on the channel's interface, which requests
+    /// are sent from.
+    pub fn new(tx: Box<EthernetDataLinkSender>,
+               rx: Box<EthernetDataLinkReceiver>,
+               mac: MacAddr,
+               ip: Ipv4Addr,
+               config: Config)
+        -> ArpResolver {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                cache: NeighborCache::new(config.cache_timeout),
+                addresses: HashMap::new(),
+            }),
+            updated: Condvar::new(),
+            tx: Mutex::new(tx),
+            stop: AtomicBool::new(false),
+        });
+
+        let thread_shared = shared.clone();
+        thread::spawn(move || receive(rx, thread_shared));
+
+        ArpResolver {
+            shared: shared,
+            mac: mac,
+            ip: ip,
+            config: config,
+        }
+    }
+
+    /// Find the MAC address of `ip`, from the cache or by sending ARP requests
+    ///
+    /// Fails with `ErrorKind::TimedOut` if no reply is received to any of the requests.
+    pub fn resolve(&self, ip: Ipv4Addr) -> io::Result<MacAddr> {
+        for _ in 0..self.config.attempts {
+            if let Some(mac) = self.lookup(ip) {
+                return Ok(mac);
+            }
+            self.request(ip)?;
+
+            let deadline = Instant::now() + self.config.request_timeout;
+            let mut state = self.shared.state.lock().unwrap();
+            loop {
+                if let Some(mac) = state.cache.get(&ip) {
+                    return Ok(mac);
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                state = self.shared.updated.wait_timeout(state, deadline - now).unwrap().0;
+            }
+        }
+
+        match self.lookup(ip) {
+            Some(mac) => Ok(mac),
+            None => Err(io::Error::new(io::ErrorKind::TimedOut, "No reply to ARP request")),
+        }
+    }
+
+    /// Look up the MAC address of `ip` in the cache, without sending any requests
+    pub fn lookup(&self, ip: Ipv4Addr) -> Option<MacAddr> {
+        self.shared.state.lock().unwrap().cache.get(&ip)
+    }
+
+    /// Get a copy of the cache
+    pub fn cache(&self) -> NeighborCache {
+        self.shared.state.lock().unwrap().cache.clone()
+    }
+
+    /// Send a single ARP request for `ip`, without waiting for a reply
+    pub fn request(&self, ip: Ipv4Addr) -> io::Result<()> {
+        let frame = ArpFrame::request(self.mac, self.ip, ip);
+
+        frame.send(&mut self.shared.tx.lock().unwrap())
+    }
+
+    /// Answer ARP requests for `ip` with `mac`
+    pub fn add_address(&self, ip: Ipv4Addr, mac: MacAddr) {
+        self.shared.state.lock().unwrap().addresses.insert(ip, mac);
+    }
+
+    /// Stop answering ARP requests for `ip`
+    pub fn remove_address(&self, ip: Ipv4Addr) {
+        self.shared.state.lock().unwrap().addresses.remove(&ip);
+    }
+
+    /// Send a gratuitous ARP request, announcing that `ip` is at `mac`
+    ///
+    /// This updates the caches of other hosts on the network, for example after an address moves
+    /// to another host.
+    pub fn announce(&self, ip: Ipv4Addr, mac: MacAddr) -> io::Result<()> {
+        let frame = ArpFrame::request(mac, ip, ip);
+
+        frame.send(&mut self.shared.tx.lock().unwrap())
+    }
+}
+
+impl Drop for ArpResolver {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Handle the ARP packets received on `rx` until the resolver is dropped
+fn receive(mut rx: Box<EthernetDataLinkReceiver>, shared: Arc<Shared>) {
+    let mut iter = rx.iter();
+    while !shared.stop.load(Ordering::SeqCst) {
+        match iter.next() {
+            // Failing to send a reply is no reason to stop resolving addresses
+            Ok(packet) => {
+                let _ = handle_packet(&packet, &shared);
+            }
+            Err(ref e) if is_transient(e) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+/// Learn from an ARP packet, and answer it if it's a request for one of our addresses
+fn handle_packet(ethernet: &EthernetPacket, shared: &Shared) -> io::Result<()> {
+    if ethernet.get_ethertype() != EtherTypes::Arp {
+        return Ok(());
+    }
+    let arp = match ArpPacket::new(ethernet.payload()) {
+        Some(arp) => arp,
+        None => return Ok(()),
+    };
+    if arp.get_hardware_type() != ArpHardwareTypes::Ethernet ||
+       arp.get_protocol_type() != EtherTypes::Ipv4 ||
+       arp.get_hw_addr_len() != 6 || arp.get_proto_addr_len() != 4 {
+        return Ok(());
+    }
+
+    let sender_ip = arp.get_sender_proto_addr();
+    let sender_mac = arp.get_sender_hw_addr();
+    let target_ip = arp.get_target_proto_addr();
+    let answer = {
+        let mut state = shared.state.lock().unwrap();
+        let answer = match arp.get_operation() {
+            ArpOperations::Request => state.addresses.get(&target_ip).cloned(),
+            _ => None,
+        };
+        // As in RFC 826, entries are only created for replies and for requests to us, but any
+        // packet can update an existing entry
+        let learn = arp.get_operation() == ArpOperations::Reply || answer.is_some() ||
+                    state.cache.contains(&sender_ip);
+        if learn && sender_ip != Ipv4Addr::new(0, 0, 0, 0) {
+            state.cache.insert(sender_ip, sender_mac);
+            shared.updated.notify_all();
+        }
+
+        answer
+    };
+
+    match answer {
+        Some(mac) => {
+            let frame = ArpFrame {
+                destination: sender_mac,
+                operation: ArpOperations::Reply,
+                sender_hw_addr: mac,
+                sender_proto_addr: target_ip,
+                target_hw_addr: sender_mac,
+                target_proto_addr: sender_ip,
+            };
+            frame.send(&mut shared.tx.lock().unwrap())
+        }
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use datalink::Channel::Ethernet;
+    use datalink::dummy;
+    use packet::Packet;
+    use packet::arp::{ArpOperations, ArpPacket};
+    use packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+    use std::io;
+    use std::net::Ipv4Addr;
+    use std::sync::mpsc::{Receiver, Sender};
+    use std::thread;
+    use std::time::Duration;
+    use super::{ARP_FRAME_LEN, ArpFrame, ArpResolver, Config, NeighborCache, broadcast,
+                unspecified};
+    use util::MacAddr;
+
+    fn our_mac() -> MacAddr {
+        MacAddr::new(1, 2, 3, 4, 5, 6)
+    }
+
+    fn our_ip() -> Ipv4Addr {
+        Ipv4Addr::new(10, 0, 0, 1)
+    }
+
+    fn peer_mac() -> MacAddr {
+        MacAddr::new(6, 5, 4, 3, 2, 1)
+    }
+
+    fn peer_ip() -> Ipv4Addr {
+        Ipv4Addr::new(10, 0, 0, 2)
+    }
+
+    fn create_resolver(config: Config)
+        -> (ArpResolver, Sender<io::Result<Box<[u8]>>>, Receiver<Box<[u8]>>) {
+        let mut dummy_config = dummy::Config::default();
+        let inject_handle = dummy_config.inject_handle().unwrap();
+        let read_handle = dummy_config.read_handle().unwrap();
+        let (tx, rx) = match dummy::channel(&dummy::dummy_interface(0), dummy_config) {
+            Ok(Ethernet(tx, rx)) => (tx, rx),
+            _ => panic!("Not a valid channel returned"),
+        };
+
+        (ArpResolver::new(tx, rx, our_mac(), our_ip(), config), inject_handle, read_handle)
+    }
+
+    fn frame(frame: ArpFrame) -> Box<[u8]> {
+        let mut buffer = vec![0u8; ARP_FRAME_LEN];
+        frame.fill(&mut MutableEthernetPacket::new(&mut buffer[..]).unwrap());
+
+        buffer.into_boxed_slice()
+    }
+
+    fn read_arp(read_handle: &Receiver<Box<[u8]>>) -> ArpFrame {
+        let buffer = read_handle.recv_timeout(Duration::from_secs(1)).expect("Expected a packet");
+        let ethernet = EthernetPacket::new(&buffer[..]).unwrap();
+        assert_eq!(ethernet.get_ethertype(), EtherTypes::Arp);
+        let arp = ArpPacket::new(ethernet.payload()).unwrap();
+
+        ArpFrame {
+            destination: ethernet.get_destination(),
+            operation: arp.get_operation(),
+            sender_hw_addr: arp.get_sender_hw_addr(),
+            sender_proto_addr: arp.get_sender_proto_addr(),
+            target_hw_addr: arp.get_target_hw_addr(),
+            target_proto_addr: arp.get_target_proto_addr(),
+        }
+    }
+
+    #[test]
+    fn cache_expiry() {
+        let mut cache = NeighborCache::new(Duration::from_millis(50));
+        cache.insert(peer_ip(), peer_mac());
+        assert_eq!(cache.get(&peer_ip()), Some(peer_mac()));
+        assert_eq!(cache.entries(), vec![(peer_ip(), peer_mac())]);
+
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(cache.get(&peer_ip()), None);
+        assert!(cache.contains(&peer_ip()));
+        cache.expire();
+        assert!(!cache.contains(&peer_ip()));
+    }
+
+    #[test]
+    fn resolve() {
+        let (resolver, inject_handle, read_handle) = create_resolver(Config::default());
+
+        // Play the part of the peer, answering the request
+        let peer = thread::spawn(move || {
+            let request = read_arp(&read_handle);
+            assert_eq!(request.destination, broadcast());
+            assert_eq!(request.operation, ArpOperations::Request);
+            assert_eq!(request.sender_hw_addr, our_mac());
+            assert_eq!(request.sender_proto_addr, our_ip());
+            assert_eq!(request.target_proto_addr, peer_ip());
+            let reply = ArpFrame {
+                destination: our_mac(),
+                operation: ArpOperations::Reply,
+                sender_hw_addr: peer_mac(),
+                sender_proto_addr: peer_ip(),
+                target_hw_addr: our_mac(),
+                target_proto_addr: our_ip(),
+            };
+            inject_handle.send(Ok(frame(reply))).unwrap();
+
+            (inject_handle, read_handle)
+        });
+
+        assert_eq!(resolver.resolve(peer_ip()).unwrap(), peer_mac());
+        let (_inject_handle, read_handle) = peer.join().unwrap();
+
+        // Now it's cached, so no more requests are sent
+        assert_eq!(resolver.lookup(peer_ip()), Some(peer_mac()));
+        assert_eq!(resolver.resolve(peer_ip()).unwrap(), peer_mac());
+        assert!(read_handle.try_recv().is_err());
+    }
+
+    #[test]
+    fn resolve_timeout() {
+        let config = Config {
+            request_timeout: Duration::from_millis(20),
+            attempts: 2,
+            ..Default::default()
+        };
+        let (resolver, _inject_handle, read_handle) = create_resolver(config);
+
+        let err = resolver.resolve(peer_ip()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(read_handle.try_iter().count(), 2);
+    }
+
+    #[test]
+    fn answer_requests() {
+        let (resolver, inject_handle, read_handle) = create_resolver(Config::default());
+        let proxy_ip = Ipv4Addr::new(10, 0, 0, 5);
+        let proxy_mac = MacAddr::new(2, 0, 0, 0, 0, 5);
+        resolver.add_address(proxy_ip, proxy_mac);
+
+        let request = ArpFrame {
+            destination: broadcast(),
+            operation: ArpOperations::Request,
+            sender_hw_addr: peer_mac(),
+            sender_proto_addr: peer_ip(),
+            target_hw_addr: unspecified(),
+            target_proto_addr: proxy_ip,
+        };
+        inject_handle.send(Ok(frame(request))).unwrap();
+
+        let reply = read_arp(&read_handle);
+        assert_eq!(reply.destination, peer_mac());
+        assert_eq!(reply.operation, ArpOperations::Reply);
+        assert_eq!(reply.sender_hw_addr, proxy_mac);
+        assert_eq!(reply.sender_proto_addr, proxy_ip);
+        assert_eq!(reply.target_hw_addr, peer_mac());
+        assert_eq!(reply.target_proto_addr, peer_ip());
+        // The peer is learned from its request
+        assert_eq!(resolver.lookup(peer_ip()), Some(peer_mac()));
+
+        // Requests for other addresses are left alone
+        resolver.remove_address(proxy_ip);
+        inject_handle.send(Ok(frame(request))).unwrap();
+        assert!(read_handle.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+
+    #[test]
+    fn announce() {
+        let (resolver, _inject_handle, read_handle) = create_resolver(Config::default());
+        resolver.announce(our_ip(), our_mac()).unwrap();
+
+        let announcement = read_arp(&read_handle);
+        assert_eq!(announcement.destination, broadcast());
+        assert_eq!(announcement.operation, ArpOperations::Request);
+        assert_eq!(announcement.sender_hw_addr, our_mac());
+        assert_eq!(announcement.sender_proto_addr, our_ip());
+        assert_eq!(announcement.target_hw_addr, unspecified());
+        assert_eq!(announcement.target_proto_addr, our_ip());
+    }
+}
@@ -22,3 +22,9 @@ pub struct packet_mreq {
     pub mr_alen: libc::c_ushort,
     pub mr_address: [libc::c_uchar; 8],
 }
+
+// Not exported by all versions of libc; see ipv6(7)
+pub const IPV6_HDRINCL: libc::c_int = 36;
+
+// Values of sll_pkttype, from <linux/if_packet.h>
+pub const PACKET_OUTGOING: libc::c_uchar = 4;
@@ -0,0 +1,787 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for building packets a layer at a time
+//!
+//! A `PacketBuilder` is given each layer of a packet, outermost first, and fills in every length,
+//! next protocol and checksum field when the packet is built:
+//!
+//! ```
+//! use pnet::builder::PacketBuilder;
+//! use pnet::util::MacAddr;
+//! use std::net::Ipv4Addr;
+//!
+//! let packet = PacketBuilder::new()
+//!     .ethernet(MacAddr::new(2, 0, 0, 0, 0, 1), MacAddr::new(2, 0, 0, 0, 0, 2))
+//!     .ipv4(Ipv4Addr::new(192, 168, 0, 1), Ipv4Addr::new(192, 168, 0, 2))
+//!     .udp(1234, 1234)
+//!     .payload(b"hello")
+//!     .build()
+//!     .unwrap();
+//! assert_eq!(packet.len(), 14 + 20 + 8 + 5);
+//! ```
+//!
+//! Fields which aren't given to the builder's methods can be set by pushing a layer directly:
+//!
+//! ```
+//! use pnet::builder::{PacketBuilder, Tcp};
+//! use pnet::packet::tcp::TcpFlags;
+//! use std::net::Ipv6Addr;
+//!
+//! let source = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+//! let destination = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 2);
+//! let syn = PacketBuilder::new()
+//!     .ipv6(source, destination)
+//!     .push(Tcp { sequence: 1000, flags: TcpFlags::SYN, ..Tcp::new(50000, 80) })
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! To build a packet in place in a datalink channel's write buffer, use `build_into` from the
+//! closure passed to `build_and_send`:
+//!
+//! ```rust,no_run
+//! # use pnet::builder::PacketBuilder;
+//! # use pnet::datalink::{self, Channel};
+//! # use pnet::packet::MutablePacket;
+//! # let interface = datalink::interfaces().into_iter().next().unwrap();
+//! # let builder = PacketBuilder::new();
+//! # let mut tx = match datalink::channel(&interface, Default::default()) {
+//! #     Ok(Channel::Ethernet(tx, _)) => tx,
+//! #     _ => panic!(),
+//! # };
+//! tx.build_and_send(1, builder.packet_size(), &mut |mut packet| {
+//!     builder.build_into(packet.packet_mut()).unwrap();
+//! });
+//! ```
+
+use packet::ethernet::{EtherType, EtherTypes, MutableEthernetPacket};
+use packet::icmp::{self, IcmpCode, IcmpType, MutableIcmpPacket};
+use packet::icmpv6::{self, Icmpv6Code, Icmpv6Type, MutableIcmpv6Packet};
+use packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use packet::ipv4::{self, Ipv4Flags, MutableIpv4Packet};
+use packet::ipv6::MutableIpv6Packet;
+use packet::tcp::{self, MutableTcpPacket};
+use packet::udp::{self, MutableUdpPacket};
+use packet::vlan::{ClassOfService, ClassesOfService, MutableVlanPacket};
+use util::MacAddr;
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// Represents an error which occurred whilst building a packet
+#[derive(Copy, Debug, PartialEq, Eq, Clone)]
+pub enum BuildErr {
+    /// The next protocol of a layer couldn't be worked out from the layer after it, and wasn't
+    /// given explicitly
+    UnknownNextProtocol,
+    /// A TCP or UDP layer isn't inside an IP layer, or an ICMPv6 layer isn't inside an IPv6 layer,
+    /// so its checksum can't be calculated
+    NoIpLayer,
+    /// A layer is too long for its length field, or has too many TCP options
+    TooLong,
+    /// The buffer passed to `build_into` is smaller than the packet
+    BufferTooSmall,
+}
+
+/// An Ethernet header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ethernet {
+    /// The source MAC address
+    pub source: MacAddr,
+    /// The destination MAC address
+    pub destination: MacAddr,
+    /// The ethertype, if it can't be worked out from the next layer. Defaults to `None`.
+    pub ethertype: Option<EtherType>,
+}
+
+impl Ethernet {
+    /// An Ethernet header with the given addresses
+    pub fn new(source: MacAddr, destination: MacAddr) -> Ethernet {
+        Ethernet {
+            source: source,
+            destination: destination,
+            ethertype: None,
+        }
+    }
+}
+
+/// An IEEE 802.1Q VLAN tag
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Vlan {
+    /// The priority code point. Defaults to `ClassesOfService::BE`.
+    pub priority: ClassOfService,
+    /// Whether the frame may be dropped when there is congestion. Defaults to `false`.
+    pub drop_eligible: bool,
+    /// The VLAN ID
+    pub vlan_identifier: u16,
+    /// The ethertype, if it can't be worked out from the next layer. Defaults to `None`.
+    pub ethertype: Option<EtherType>,
+}
+
+impl Vlan {
+    /// A VLAN tag with the given VLAN ID
+    pub fn new(vlan_identifier: u16) -> Vlan {
+        Vlan {
+            priority: ClassesOfService::BE,
+            drop_eligible: false,
+            vlan_identifier: vlan_identifier,
+            ethertype: None,
+        }
+    }
+}
+
+/// An IPv4 header, with no options
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ipv4 {
+    /// The source address
+    pub source: Ipv4Addr,
+    /// The destination address
+    pub destination: Ipv4Addr,
+    /// The Differentiated Services Code Point. Defaults to 0.
+    pub dscp: u8,
+    /// The Explicit Congestion Notification bits. Defaults to 0.
+    pub ecn: u8,
+    /// The identification used when reassembling fragments. Defaults to 0.
+    pub identification: u16,
+    /// Whether the Don't Fragment flag is set. Defaults to `false`.
+    pub dont_fragment: bool,
+    /// The time to live. Defaults to 64.
+    pub ttl: u8,
+    /// The next level protocol, if it can't be worked out from the next layer. Defaults to
+    /// `None`.
+    pub next_level_protocol: Option<IpNextHeaderProtocol>,
+}
+
+impl Ipv4 {
+    /// An IPv4 header with the given addresses
+    pub fn new(source: Ipv4Addr, destination: Ipv4Addr) -> Ipv4 {
+        Ipv4 {
+            source: source,
+            destination: destination,
+            dscp: 0,
+            ecn: 0,
+            identification: 0,
+            dont_fragment: false,
+            ttl: 64,
+            next_level_protocol: None,
+        }
+    }
+}
+
+/// An IPv6 header, with no extension headers
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Ipv6 {
+    /// The source address
+    pub source: Ipv6Addr,
+    /// The destination address
+    pub destination: Ipv6Addr,
+    /// The traffic class. Defaults to 0.
+    pub traffic_class: u8,
+    /// The flow label, of which only the lower 20 bits are used. Defaults to 0.
+    pub flow_label: u32,
+    /// The hop limit. Defaults to 64.
+    pub hop_limit: u8,
+    /// The next header, if it can't be worked out from the next layer. Defaults to `None`.
+    pub next_header: Option<IpNextHeaderProtocol>,
+}
+
+impl Ipv6 {
+    /// An IPv6 header with the given addresses
+    pub fn new(source: Ipv6Addr, destination: Ipv6Addr) -> Ipv6 {
+        Ipv6 {
+            source: source,
+            destination: destination,
+            traffic_class: 0,
+            flow_label: 0,
+            hop_limit: 64,
+            next_header: None,
+        }
+    }
+}
+
+/// A TCP header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Tcp {
+    /// The source port
+    pub source: u16,
+    /// The destination port
+    pub destination: u16,
+    /// The sequence number. Defaults to 0.
+    pub sequence: u32,
+    /// The acknowledgement number. Defaults to 0.
+    pub acknowledgement: u32,
+    /// The flags, from `packet::tcp::TcpFlags`. Defaults to 0.
+    pub flags: u16,
+    /// The receive window. Defaults to 65535.
+    pub window: u16,
+    /// The urgent pointer. Defaults to 0.
+    pub urgent_ptr: u16,
+    /// The encoded options, at most 40 bytes. They are padded to a multiple of 4 bytes with
+    /// End of Options List. Defaults to no options.
+    pub options: Vec<u8>,
+}
+
+impl Tcp {
+    /// A TCP header with the given ports
+    pub fn new(source: u16, destination: u16) -> Tcp {
+        Tcp {
+            source: source,
+            destination: destination,
+            sequence: 0,
+            acknowledgement: 0,
+            flags: 0,
+            window: 65535,
+            urgent_ptr: 0,
+            options: Vec::new(),
+        }
+    }
+
+    fn header_length(&self) -> usize {
+        20 + (self.options.len() + 3) / 4 * 4
+    }
+}
+
+/// A UDP header
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Udp {
+    /// The source port
+    pub source: u16,
+    /// The destination port
+    pub destination: u16,
+}
+
+impl Udp {
+    /// A UDP header with the given ports
+    pub fn new(source: u16, destination: u16) -> Udp {
+        Udp {
+            source: source,
+            destination: destination,
+        }
+    }
+}
+
+/// An ICMP header. The rest of the header, which depends on the type, is part of the payload.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Icmp {
+    /// The message type
+    pub icmp_type: IcmpType,
+    /// The message code
+    pub icmp_code: IcmpCode,
+}
+
+impl Icmp {
+    /// An ICMP header with the given type and code
+    pub fn new(icmp_type: IcmpType, icmp_code: IcmpCode) -> Icmp {
+        Icmp {
+            icmp_type: icmp_type,
+            icmp_code: icmp_code,
+        }
+    }
+}
+
+/// An ICMPv6 header. The rest of the header, which depends on the type, is part of the payload.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Icmpv6 {
+    /// The message type
+    pub icmpv6_type: Icmpv6Type,
+    /// The message code
+    pub icmpv6_code: Icmpv6Code,
+}
+
+impl Icmpv6 {
+    /// An ICMPv6 header with the given type and code
+    pub fn new(icmpv6_type: Icmpv6Type, icmpv6_code: Icmpv6Code) -> Icmpv6 {
+        Icmpv6 {
+            icmpv6_type: icmpv6_type,
+            icmpv6_code: icmpv6_code,
+        }
+    }
+}
+
+/// A layer of a packet being built
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Layer {
+    /// An Ethernet header
+    Ethernet(Ethernet),
+    /// A VLAN tag
+    Vlan(Vlan),
+    /// An IPv4 header
+    Ipv4(Ipv4),
+    /// An IPv6 header
+    Ipv6(Ipv6),
+    /// A TCP header
+    Tcp(Tcp),
+    /// A UDP header
+    Udp(Udp),
+    /// An ICMP header
+    Icmp(Icmp),
+    /// An ICMPv6 header
+    Icmpv6(Icmpv6),
+    /// Data copied into the packet as is
+    Payload(Vec<u8>),
+}
+
+macro_rules! impl_from_layer {
+    ($($layer:ident),*) => {
+        $(
+            impl From<$layer> for Layer {
+                fn from(layer: $layer) -> Layer {
+                    Layer::$layer(layer)
+                }
+            }
+        )*
+    }
+}
+
+impl_from_layer!(Ethernet, Vlan, Ipv4, Ipv6, Tcp, Udp, Icmp, Icmpv6);
+
+impl Layer {
+    fn size(&self) -> usize {
+        match *self {
+            Layer::Ethernet(..) => 14,
+            Layer::Vlan(..) => 4,
+            Layer::Ipv4(..) => 20,
+            Layer::Ipv6(..) => 40,
+            Layer::Tcp(ref tcp) => tcp.header_length(),
+            Layer::Udp(..) => 8,
+            Layer::Icmp(..) | Layer::Icmpv6(..) => 4,
+            Layer::Payload(ref payload) => payload.len(),
+        }
+    }
+
+    /// The ethertype of a packet starting with this layer
+    fn ethertype(&self) -> Option<EtherType> {
+        match *self {
+            Layer::Vlan(..) => Some(EtherTypes::Vlan),
+            Layer::Ipv4(..) => Some(EtherTypes::Ipv4),
+            Layer::Ipv6(..) => Some(EtherTypes::Ipv6),
+            _ => None,
+        }
+    }
+
+    /// The IP protocol number of this layer
+    fn ip_protocol(&self) -> Option<IpNextHeaderProtocol> {
+        match *self {
+            Layer::Ipv4(..) => Some(IpNextHeaderProtocols::Ipv4),
+            Layer::Ipv6(..) => Some(IpNextHeaderProtocols::Ipv6),
+            Layer::Tcp(..) => Some(IpNextHeaderProtocols::Tcp),
+            Layer::Udp(..) => Some(IpNextHeaderProtocols::Udp),
+            Layer::Icmp(..) => Some(IpNextHeaderProtocols::Icmp),
+            Layer::Icmpv6(..) => Some(IpNextHeaderProtocols::Ipv6Icmp),
+            _ => None,
+        }
+    }
+}
+
+/// Builds packets from a list of layers, outermost first
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PacketBuilder {
+    layers: Vec<Layer>,
+}
+
+impl PacketBuilder {
+    /// Create a builder with no layers
+    pub fn new() -> PacketBuilder {
+        Default::default()
+    }
+
+    /// Add a layer inside the previous one
+    pub fn push<L: Into<Layer>>(mut self, layer: L) -> PacketBuilder {
+        self.layers.push(layer.into());
+        self
+    }
+
+    /// Add an Ethernet header
+    pub fn ethernet(self, source: MacAddr, destination: MacAddr) -> PacketBuilder {
+        self.push(Ethernet::new(source, destination))
+    }
+
+    /// Add a VLAN tag
+    pub fn vlan(self, vlan_identifier: u16) -> PacketBuilder {
+        self.push(Vlan::new(vlan_identifier))
+    }
+
+    /// Add an IPv4 header
+    pub fn ipv4(self, source: Ipv4Addr, destination: Ipv4Addr) -> PacketBuilder {
+        self.push(Ipv4::new(source, destination))
+    }
+
+    /// Add an IPv6 header
+    pub fn ipv6(self, source: Ipv6Addr, destination: Ipv6Addr) -> PacketBuilder {
+        self.push(Ipv6::new(source, destination))
+    }
+
+    /// Add a TCP header with no flags set
+    pub fn tcp(self, source: u16, destination: u16) -> PacketBuilder {
+        self.push(Tcp::new(source, destination))
+    }
+
+    /// Add a UDP header
+    pub fn udp(self, source: u16, destination: u16) -> PacketBuilder {
+        self.push(Udp::new(source, destination))
+    }
+
+    /// Add an ICMP header
+    pub fn icmp(self, icmp_type: IcmpType, icmp_code: IcmpCode) -> PacketBuilder {
+        self.push(Icmp::new(icmp_type, icmp_code))
+    }
+
+    /// Add an ICMPv6 header
+    pub fn icmpv6(self, icmpv6_type: Icmpv6Type, icmpv6_code: Icmpv6Code) -> PacketBuilder {
+        self.push(Icmpv6::new(icmpv6_type, icmpv6_code))
+    }
+
+    /// Add some data
+    pub fn payload(self, payload: &[u8]) -> PacketBuilder {
+        self.push(Layer::Payload(payload.to_vec()))
+    }
+
+    /// The size of the built packet, in bytes
+    pub fn packet_size(&self) -> usize {
+        self.layers.iter().map(Layer::size).sum()
+    }
+
+    /// Build the packet
+    pub fn build(&self) -> Result<Vec<u8>, BuildErr> {
+        let mut packet = vec![0; self.packet_size()];
+        self.build_into(&mut packet)?;
+
+        Ok(packet)
+    }
+
+    /// Build the packet at the start of `buffer`, returning its size. The buffer is left
+    /// unchanged if an error is returned.
+    pub fn build_into(&self, buffer: &mut [u8]) -> Result<usize, BuildErr> {
+        let size = self.packet_size();
+        if buffer.len() < size {
+            return Err(BuildErr::BufferTooSmall);
+        }
+        let offsets = self.check(size)?;
+        let buffer = &mut buffer[..size];
+        for byte in buffer.iter_mut() {
+            *byte = 0;
+        }
+
+        for (i, layer) in self.layers.iter().enumerate() {
+            let next = self.layers.get(i + 1);
+            write_layer(layer, next, &mut buffer[offsets[i]..]);
+        }
+        // Checksums cover the layers inside them, so are filled in from the innermost layer out
+        for (i, layer) in self.layers.iter().enumerate().rev() {
+            let data = &mut buffer[offsets[i]..];
+            match *layer {
+                Layer::Ipv4(..) => {
+                    let mut ip = MutableIpv4Packet::new(data).unwrap();
+                    let checksum = ipv4::checksum(&ip.to_immutable());
+                    ip.set_checksum(checksum);
+                }
+                Layer::Tcp(..) => {
+                    let mut tcp = MutableTcpPacket::new(data).unwrap();
+                    let checksum = match self.enclosing_ip(i) {
+                        Some(Layer::Ipv4(ip)) => {
+                            tcp::ipv4_checksum(&tcp.to_immutable(), ip.source, ip.destination)
+                        }
+                        Some(Layer::Ipv6(ip)) => {
+                            tcp::ipv6_checksum(&tcp.to_immutable(), ip.source, ip.destination)
+                        }
+                        _ => unreachable!(),
+                    };
+                    tcp.set_checksum(checksum);
+                }
+                Layer::Udp(..) => {
+                    let mut udp = MutableUdpPacket::new(data).unwrap();
+                    let checksum = match self.enclosing_ip(i) {
+                        Some(Layer::Ipv4(ip)) => {
+                            udp::ipv4_checksum(&udp.to_immutable(), ip.source, ip.destination)
+                        }
+                        Some(Layer::Ipv6(ip)) => {
+                            udp::ipv6_checksum(&udp.to_immutable(), ip.source, ip.destination)
+                        }
+                        _ => unreachable!(),
+                    };
+                    // Zero means no checksum, so one's complement's other zero is sent instead
+                    udp.set_checksum(if checksum == 0 { 0xffff } else { checksum });
+                }
+                Layer::Icmp(..) => {
+                    let mut icmp = MutableIcmpPacket::new(data).unwrap();
+                    let checksum = icmp::checksum(&icmp.to_immutable());
+                    icmp.set_checksum(checksum);
+                }
+                Layer::Icmpv6(..) => {
+                    let mut icmp = MutableIcmpv6Packet::new(data).unwrap();
+                    if let Some(Layer::Ipv6(ip)) = self.enclosing_ip(i) {
+                        let checksum =
+                            icmpv6::checksum(&icmp.to_immutable(), ip.source, ip.destination);
+                        icmp.set_checksum(checksum);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(size)
+    }
+
+    /// Check the layers can be built into a packet of the given size, returning the offset of
+    /// each layer
+    fn check(&self, size: usize) -> Result<Vec<usize>, BuildErr> {
+        let mut offsets = Vec::with_capacity(self.layers.len());
+        let mut offset = 0;
+        for (i, layer) in self.layers.iter().enumerate() {
+            let next = self.layers.get(i + 1);
+            let length = size - offset;
+            let known = match *layer {
+                Layer::Ethernet(Ethernet { ethertype, .. }) |
+                Layer::Vlan(Vlan { ethertype, .. }) => {
+                    ethertype.or_else(|| next.and_then(Layer::ethertype)).is_some()
+                }
+                Layer::Ipv4(Ipv4 { next_level_protocol: protocol, .. }) |
+                Layer::Ipv6(Ipv6 { next_header: protocol, .. }) => {
+                    protocol.or_else(|| next.and_then(Layer::ip_protocol)).is_some()
+                }
+                _ => true,
+            };
+            if !known {
+                return Err(BuildErr::UnknownNextProtocol);
+            }
+            let too_long = match *layer {
+                Layer::Ipv4(..) | Layer::Udp(..) => length > 65535,
+                Layer::Ipv6(..) => length - 40 > 65535,
+                Layer::Tcp(ref tcp) => tcp.options.len() > 40,
+                _ => false,
+            };
+            if too_long {
+                return Err(BuildErr::TooLong);
+            }
+            let has_ip = match *layer {
+                Layer::Tcp(..) | Layer::Udp(..) => self.enclosing_ip(i).is_some(),
+                Layer::Icmpv6(..) => {
+                    self.enclosing_ip(i).and_then(Layer::ethertype) == Some(EtherTypes::Ipv6)
+                }
+                _ => true,
+            };
+            if !has_ip {
+                return Err(BuildErr::NoIpLayer);
+            }
+            offsets.push(offset);
+            offset += layer.size();
+        }
+
+        Ok(offsets)
+    }
+
+    /// The closest IP layer outside the given layer
+    fn enclosing_ip(&self, index: usize) -> Option<&Layer> {
+        for layer in self.layers[..index].iter().rev() {
+            if let Layer::Ipv4(..) | Layer::Ipv6(..) = *layer {
+                return Some(layer);
+            }
+        }
+
+        None
+    }
+}
+
+/// Write a layer's header, other than its checksum
+fn write_layer(layer: &Layer, next: Option<&Layer>, data: &mut [u8]) {
+    let length = data.len();
+    match *layer {
+        Layer::Ethernet(ref ethernet) => {
+            let mut packet = MutableEthernetPacket::new(data).unwrap();
+            packet.set_destination(ethernet.destination);
+            packet.set_source(ethernet.source);
+            packet.set_ethertype(ethernet.ethertype
+                .or_else(|| next.and_then(Layer::ethertype))
+                .unwrap());
+        }
+        Layer::Vlan(ref vlan) => {
+            let mut packet = MutableVlanPacket::new(data).unwrap();
+            packet.set_priority_code_point(vlan.priority);
+            packet.set_drop_eligible_indicator(vlan.drop_eligible as u8);
+            packet.set_vlan_identifier(vlan.vlan_identifier);
+            packet.set_ethertype(vlan.ethertype
+                .or_else(|| next.and_then(Layer::ethertype))
+                .unwrap());
+        }
+        Layer::Ipv4(ref ip) => {
+            let mut packet = MutableIpv4Packet::new(data).unwrap();
+            packet.set_version(4);
+            packet.set_header_length(5);
+            packet.set_dscp(ip.dscp);
+            packet.set_ecn(ip.ecn);
+            packet.set_total_length(length as u16);
+            packet.set_identification(ip.identification);
+            if ip.dont_fragment {
+                packet.set_flags(Ipv4Flags::DontFragment);
+            }
+            packet.set_ttl(ip.ttl);
+            packet.set_next_level_protocol(ip.next_level_protocol
+                .or_else(|| next.and_then(Layer::ip_protocol))
+                .unwrap());
+            packet.set_source(ip.source);
+            packet.set_destination(ip.destination);
+        }
+        Layer::Ipv6(ref ip) => {
+            let mut packet = MutableIpv6Packet::new(data).unwrap();
+            packet.set_version(6);
+            packet.set_traffic_class(ip.traffic_class);
+            packet.set_flow_label(ip.flow_label & 0xfffff);
+            packet.set_payload_length((length - 40) as u16);
+            packet.set_next_header(ip.next_header
+                .or_else(|| next.and_then(Layer::ip_protocol))
+                .unwrap());
+            packet.set_hop_limit(ip.hop_limit);
+            packet.set_source(ip.source);
+            packet.set_destination(ip.destination);
+        }
+        Layer::Tcp(ref tcp) => {
+            data[20..20 + tcp.options.len()].copy_from_slice(&tcp.options);
+            let mut packet = MutableTcpPacket::new(data).unwrap();
+            packet.set_source(tcp.source);
+            packet.set_destination(tcp.destination);
+            packet.set_sequence(tcp.sequence);
+            packet.set_acknowledgement(tcp.acknowledgement);
+            packet.set_data_offset((tcp.header_length() / 4) as u8);
+            packet.set_flags(tcp.flags);
+            packet.set_window(tcp.window);
+            packet.set_urgent_ptr(tcp.urgent_ptr);
+        }
+        Layer::Udp(ref udp) => {
+            let mut packet = MutableUdpPacket::new(data).unwrap();
+            packet.set_source(udp.source);
+            packet.set_destination(udp.destination);
+            packet.set_length(length as u16);
+        }
+        Layer::Icmp(ref icmp) => {
+            let mut packet = MutableIcmpPacket::new(data).unwrap();
+            packet.set_icmp_type(icmp.icmp_type);
+            packet.set_icmp_code(icmp.icmp_code);
+        }
+        Layer::Icmpv6(ref icmp) => {
+            let mut packet = MutableIcmpv6Packet::new(data).unwrap();
+            packet.set_icmpv6_type(icmp.icmpv6_type);
+            packet.set_icmpv6_code(icmp.icmpv6_code);
+        }
+        Layer::Payload(ref payload) => data[..payload.len()].copy_from_slice(payload),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use packet::Packet;
+    use packet::ethernet::EthernetPacket;
+    use packet::icmpv6::Icmpv6Types;
+    use packet::ipv4::Ipv4Packet;
+    use packet::ipv6::Ipv6Packet;
+    use packet::tcp::{TcpFlags, TcpPacket};
+    use packet::udp::UdpPacket;
+    use packet::vlan::VlanPacket;
+
+    #[test]
+    fn build_udp() {
+        let source = Ipv4Addr::new(192, 168, 0, 1);
+        let destination = Ipv4Addr::new(192, 168, 0, 2);
+        let builder = PacketBuilder::new()
+            .ethernet(MacAddr::new(2, 0, 0, 0, 0, 1), MacAddr::new(2, 0, 0, 0, 0, 2))
+            .vlan(100)
+            .ipv4(source, destination)
+            .udp(1234, 53)
+            .payload(b"query");
+        let packet = builder.build().unwrap();
+        assert_eq!(packet.len(), 14 + 4 + 20 + 8 + 5);
+
+        let ethernet = EthernetPacket::new(&packet).unwrap();
+        assert_eq!(ethernet.get_ethertype(), EtherTypes::Vlan);
+        let vlan = VlanPacket::new(ethernet.payload()).unwrap();
+        assert_eq!(vlan.get_vlan_identifier(), 100);
+        assert_eq!(vlan.get_ethertype(), EtherTypes::Ipv4);
+        let ip = Ipv4Packet::new(vlan.payload()).unwrap();
+        assert_eq!(ip.get_total_length(), 33);
+        assert_eq!(ip.get_next_level_protocol(), IpNextHeaderProtocols::Udp);
+        assert_eq!(ip.get_checksum(), ipv4::checksum(&ip));
+        let udp = UdpPacket::new(ip.payload()).unwrap();
+        assert_eq!(udp.get_length(), 13);
+        assert_eq!(udp.get_checksum(), udp::ipv4_checksum(&udp, source, destination));
+        assert_eq!(udp.payload(), b"query");
+
+        // Building into a buffer which has been used before gives the same packet
+        let mut buffer = vec![0xff; 64];
+        assert_eq!(builder.build_into(&mut buffer), Ok(packet.len()));
+        assert_eq!(&buffer[..packet.len()], &packet[..]);
+        assert_eq!(builder.build_into(&mut buffer[..10]), Err(BuildErr::BufferTooSmall));
+    }
+
+    #[test]
+    fn build_udp_zero_checksum() {
+        let source = Ipv4Addr::new(192, 168, 0, 1);
+        let destination = Ipv4Addr::new(192, 168, 0, 2);
+        let build = |payload: &[u8]| {
+            PacketBuilder::new()
+                .ipv4(source, destination)
+                .udp(1234, 53)
+                .payload(payload)
+                .build()
+                .unwrap()
+        };
+
+        // Adding the checksum of a zeroed payload as the payload sums to zero
+        let packet = build(&[0, 0]);
+        let checksum = UdpPacket::new(&packet[20..]).unwrap().get_checksum();
+        let packet = build(&[(checksum >> 8) as u8, checksum as u8]);
+        let udp = UdpPacket::new(&packet[20..]).unwrap();
+        assert_eq!(udp::ipv4_checksum(&udp, source, destination), 0);
+        assert_eq!(udp.get_checksum(), 0xffff);
+    }
+
+    #[test]
+    fn build_tcp_ipv6() {
+        let source = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let destination = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 2);
+        let tcp = Tcp {
+            sequence: 1000,
+            flags: TcpFlags::SYN,
+            // Maximum segment size
+            options: vec![2, 4, 0x05, 0xb4, 1, 1],
+            ..Tcp::new(50000, 80)
+        };
+        let packet = PacketBuilder::new().ipv6(source, destination).push(tcp).build().unwrap();
+
+        let ip = Ipv6Packet::new(&packet).unwrap();
+        assert_eq!(ip.get_payload_length(), 28);
+        assert_eq!(ip.get_next_header(), IpNextHeaderProtocols::Tcp);
+        let tcp = TcpPacket::new(ip.payload()).unwrap();
+        assert_eq!(tcp.get_data_offset(), 7);
+        assert_eq!(tcp.get_flags(), TcpFlags::SYN);
+        assert_eq!(tcp.get_sequence(), 1000);
+        assert_eq!(&tcp.packet()[20..], &[2, 4, 0x05, 0xb4, 1, 1, 0, 0]);
+        assert_eq!(tcp.get_checksum(), tcp::ipv6_checksum(&tcp, source, destination));
+    }
+
+    #[test]
+    fn build_errors() {
+        let v4 = Ipv4Addr::new(10, 0, 0, 1);
+        let v6 = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        let mac = MacAddr::new(2, 0, 0, 0, 0, 1);
+        assert_eq!(PacketBuilder::new().ethernet(mac, mac).payload(b"x").build(),
+                   Err(BuildErr::UnknownNextProtocol));
+        assert_eq!(PacketBuilder::new().udp(1, 2).build(), Err(BuildErr::NoIpLayer));
+        assert_eq!(PacketBuilder::new()
+                       .ipv4(v4, v4)
+                       .icmpv6(Icmpv6Types::EchoRequest, Icmpv6Code(0))
+                       .build(),
+                   Err(BuildErr::NoIpLayer));
+        assert_eq!(PacketBuilder::new().ipv6(v6, v6).udp(1, 2).payload(&[0; 65536]).build(),
+                   Err(BuildErr::TooLong));
+
+        // An explicit next protocol is used when the next layer doesn't give one
+        let ethernet = Ethernet { ethertype: Some(EtherType(0x88b5)), ..Ethernet::new(mac, mac) };
+        let packet = PacketBuilder::new().push(ethernet).payload(b"x").build().unwrap();
+        assert_eq!(&packet[12..], &[0x88, 0xb5, b'x']);
+    }
+}
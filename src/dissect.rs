@@ -0,0 +1,637 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for decoding every layer of a captured packet in one go
+//!
+//! Rather than chaining `EthernetPacket::new`, `Ipv4Packet::new` and so on by hand, a `Dissector`
+//! walks the packet and returns each layer it finds as a view over the original bytes:
+//!
+//! ```
+//! use pnet::dissect::{Dissector, Layer, LinkType};
+//!
+//! let dissector = Dissector::new();
+//! # let captured = vec![0u8; 14];
+//! for layer in dissector.dissect(LinkType::Ethernet, &captured) {
+//!     match layer {
+//!         Layer::Ethernet(ethernet) => println!("{} > {}", ethernet.get_source(),
+//!                                               ethernet.get_destination()),
+//!         Layer::Tcp(tcp) => println!("TCP port {}", tcp.get_destination()),
+//!         _ => (),
+//!     }
+//! }
+//! ```
+//!
+//! Dissection stops at the first layer which is too short or otherwise invalid, which is
+//! returned as a `Layer::Malformed`, so truncated or corrupt packets never cause a panic. Decoders
+//! for other protocols can be registered against an ethertype, IP protocol or port.
+
+use packet::arp::ArpPacket;
+use packet::ethernet::{EtherType, EtherTypes, EthernetPacket};
+use packet::gre::GrePacket;
+use packet::icmp::IcmpPacket;
+use packet::icmpv6::Icmpv6Packet;
+use packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use packet::ipv4::Ipv4Packet;
+use packet::ipv6::{self, FragmentPacket, Ipv6Packet};
+use packet::tcp::TcpPacket;
+use packet::udp::UdpPacket;
+use packet::vlan::VlanPacket;
+
+use std::cmp;
+use std::collections::HashMap;
+
+/// The maximum number of layers decoded from a single packet, so that decoders which don't
+/// consume any data can't loop forever
+const MAX_LAYERS: usize = 32;
+
+/// The type of header a captured packet starts with
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LinkType {
+    /// An Ethernet frame
+    Ethernet,
+    /// An IPv4 or IPv6 packet with no link layer header, as returned by the layer 3 channels of
+    /// `pnet::transport`
+    RawIp,
+}
+
+/// A decoded layer of a packet
+#[derive(Debug)]
+pub enum Layer<'p> {
+    /// An Ethernet header
+    Ethernet(EthernetPacket<'p>),
+    /// An IEEE 802.1Q VLAN tag
+    Vlan(VlanPacket<'p>),
+    /// An ARP packet
+    Arp(ArpPacket<'p>),
+    /// An IPv4 header. Its payload excludes any link layer padding.
+    Ipv4(Ipv4Packet<'p>),
+    /// An IPv6 header. Any extension headers are left in its payload, and are skipped over to find
+    /// the next layer.
+    Ipv6(Ipv6Packet<'p>),
+    /// A GRE header
+    Gre(GrePacket<'p>),
+    /// An ICMP packet
+    Icmp(IcmpPacket<'p>),
+    /// An ICMPv6 packet
+    Icmpv6(Icmpv6Packet<'p>),
+    /// A TCP header
+    Tcp(TcpPacket<'p>),
+    /// A UDP header
+    Udp(UdpPacket<'p>),
+    /// A header decoded by a decoder registered with the `Dissector`
+    Custom {
+        /// The name the decoder was registered with
+        name: &'static str,
+        /// The bytes of the header
+        header: &'p [u8],
+    },
+    /// Bytes which should have been a header of the named protocol, but were too short or
+    /// otherwise invalid. This is always the last layer.
+    Malformed {
+        /// The protocol which couldn't be decoded, eg. `"IPv4"`
+        protocol: &'static str,
+        /// The remaining bytes of the packet
+        data: &'p [u8],
+    },
+    /// Bytes which no decoder handles, such as application data. This is always the last
+    /// layer.
+    Payload(&'p [u8]),
+}
+
+/// What follows a header decoded by a registered decoder
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Next {
+    /// An Ethernet frame
+    Ethernet,
+    /// A packet of the given ethertype
+    EtherType(EtherType),
+    /// A packet of the given IP protocol
+    IpProtocol(IpNextHeaderProtocol),
+    /// Data which isn't decoded any further
+    Payload,
+}
+
+/// A decoder for a protocol which isn't built in. Given the data from the start of its header, it
+/// returns the length of the header and what follows it, or `None` if the header is invalid.
+pub type Decoder = dyn Fn(&[u8]) -> Option<(usize, Next)> + Send + Sync;
+
+struct Registered {
+    name: &'static str,
+    decoder: Box<Decoder>,
+}
+
+/// What the dissector should try to decode next
+#[derive(Copy, Clone)]
+enum Protocol<'d> {
+    Ethernet,
+    EtherType(EtherType),
+    IpProtocol(IpNextHeaderProtocol),
+    Custom(&'d Registered),
+    Payload,
+}
+
+/// The result of decoding one layer
+enum Step<'p, 'd> {
+    Decoded(Layer<'p>, &'p [u8], Protocol<'d>),
+    Malformed(&'static str),
+    Unknown,
+}
+
+/// Decodes packets into their layers, using the built in decoders and any which have been
+/// registered
+#[derive(Default)]
+pub struct Dissector {
+    ethertypes: HashMap<EtherType, Registered>,
+    ip_protocols: HashMap<IpNextHeaderProtocol, Registered>,
+    tcp_ports: HashMap<u16, Registered>,
+    udp_ports: HashMap<u16, Registered>,
+}
+
+impl Dissector {
+    /// Create a dissector which only knows about the built in protocols
+    pub fn new() -> Dissector {
+        Default::default()
+    }
+
+    /// Decode packets with the given ethertype using `decoder`. This takes precedence over the
+    /// built in decoder for the ethertype, if there is one.
+    pub fn register_ethertype<F>(&mut self, ethertype: EtherType, name: &'static str, decoder: F)
+        where F: Fn(&[u8]) -> Option<(usize, Next)> + Send + Sync + 'static
+    {
+        self.ethertypes.insert(ethertype, Registered::new(name, decoder));
+    }
+
+    /// Decode the payload of IP packets with the given protocol using `decoder`. This takes
+    /// precedence over the built in decoder for the protocol, if there is one.
+    pub fn register_ip_protocol<F>(&mut self,
+                                   protocol: IpNextHeaderProtocol,
+                                   name: &'static str,
+                                   decoder: F)
+        where F: Fn(&[u8]) -> Option<(usize, Next)> + Send + Sync + 'static
+    {
+        self.ip_protocols.insert(protocol, Registered::new(name, decoder));
+    }
+
+    /// Decode the payload of TCP segments to or from the given port using `decoder`. When both
+    /// ports have decoders, the destination port's is used.
+    pub fn register_tcp_port<F>(&mut self, port: u16, name: &'static str, decoder: F)
+        where F: Fn(&[u8]) -> Option<(usize, Next)> + Send + Sync + 'static
+    {
+        self.tcp_ports.insert(port, Registered::new(name, decoder));
+    }
+
+    /// Decode the payload of UDP datagrams to or from the given port using `decoder`. When both
+    /// ports have decoders, the destination port's is used.
+    pub fn register_udp_port<F>(&mut self, port: u16, name: &'static str, decoder: F)
+        where F: Fn(&[u8]) -> Option<(usize, Next)> + Send + Sync + 'static
+    {
+        self.udp_ports.insert(port, Registered::new(name, decoder));
+    }
+
+    /// Decode the layers of a packet, outermost first
+    pub fn dissect<'p>(&self, link_type: LinkType, packet: &'p [u8]) -> Vec<Layer<'p>> {
+        let mut protocol = match link_type {
+            LinkType::Ethernet => Protocol::Ethernet,
+            LinkType::RawIp => {
+                match packet.first().map(|b| b >> 4) {
+                    Some(4) => Protocol::EtherType(EtherTypes::Ipv4),
+                    Some(6) => Protocol::EtherType(EtherTypes::Ipv6),
+                    _ => Protocol::Payload,
+                }
+            }
+        };
+        let mut layers = Vec::new();
+        let mut data = packet;
+        while !data.is_empty() && layers.len() < MAX_LAYERS {
+            match self.decode(protocol, data) {
+                Step::Decoded(layer, payload, next) => {
+                    layers.push(layer);
+                    data = payload;
+                    protocol = next;
+                }
+                Step::Malformed(name) => {
+                    layers.push(Layer::Malformed {
+                        protocol: name,
+                        data: data,
+                    });
+                    return layers;
+                }
+                Step::Unknown => break,
+            }
+        }
+        if !data.is_empty() {
+            layers.push(Layer::Payload(data));
+        }
+
+        layers
+    }
+
+    fn decode<'p, 'd>(&'d self, protocol: Protocol<'d>, data: &'p [u8]) -> Step<'p, 'd> {
+        match protocol {
+            Protocol::Ethernet => {
+                match EthernetPacket::new(data) {
+                    Some(ethernet) => {
+                        let next = Protocol::EtherType(ethernet.get_ethertype());
+                        Step::Decoded(Layer::Ethernet(ethernet), &data[14..], next)
+                    }
+                    None => Step::Malformed("Ethernet"),
+                }
+            }
+            Protocol::EtherType(ethertype) => {
+                if let Some(registered) = self.ethertypes.get(&ethertype) {
+                    return registered.decode(data);
+                }
+                match ethertype {
+                    EtherTypes::Vlan => self.decode_vlan(data),
+                    EtherTypes::Arp => self.decode_arp(data),
+                    EtherTypes::Ipv4 => self.decode_ipv4(data),
+                    EtherTypes::Ipv6 => self.decode_ipv6(data),
+                    _ => Step::Unknown,
+                }
+            }
+            Protocol::IpProtocol(ip_protocol) => {
+                if let Some(registered) = self.ip_protocols.get(&ip_protocol) {
+                    return registered.decode(data);
+                }
+                match ip_protocol {
+                    IpNextHeaderProtocols::Gre => self.decode_gre(data),
+                    IpNextHeaderProtocols::Icmp => self.decode_icmp(data),
+                    IpNextHeaderProtocols::Ipv6Icmp => self.decode_icmpv6(data),
+                    IpNextHeaderProtocols::Tcp => self.decode_tcp(data),
+                    IpNextHeaderProtocols::Udp => self.decode_udp(data),
+                    _ => Step::Unknown,
+                }
+            }
+            Protocol::Custom(registered) => registered.decode(data),
+            Protocol::Payload => Step::Unknown,
+        }
+    }
+
+    fn port(ports: &HashMap<u16, Registered>, source: u16, destination: u16) -> Protocol {
+        match ports.get(&destination).or_else(|| ports.get(&source)) {
+            Some(registered) => Protocol::Custom(registered),
+            None => Protocol::Payload,
+        }
+    }
+
+    fn decode_vlan<'p, 'd>(&'d self, data: &'p [u8]) -> Step<'p, 'd> {
+        match VlanPacket::new(data) {
+            Some(vlan) => {
+                let next = Protocol::EtherType(vlan.get_ethertype());
+                Step::Decoded(Layer::Vlan(vlan), &data[4..], next)
+            }
+            None => Step::Malformed("802.1Q"),
+        }
+    }
+
+    fn decode_arp<'p, 'd>(&'d self, data: &'p [u8]) -> Step<'p, 'd> {
+        match ArpPacket::new(data) {
+            Some(arp) => Step::Decoded(Layer::Arp(arp), &data[28..], Protocol::Payload),
+            None => Step::Malformed("ARP"),
+        }
+    }
+
+    fn decode_ipv4<'p, 'd>(&'d self, data: &'p [u8]) -> Step<'p, 'd> {
+        let (header_length, total_length) = match Ipv4Packet::new(data) {
+            Some(ref ip) if ip.get_version() == 4 => {
+                (ip.get_header_length() as usize * 4, ip.get_total_length() as usize)
+            }
+            _ => return Step::Malformed("IPv4"),
+        };
+        if header_length < 20 || header_length > data.len() || total_length < header_length {
+            return Step::Malformed("IPv4");
+        }
+        // Drop any Ethernet padding, but keep going if the capture was truncated
+        let data = &data[..cmp::min(total_length, data.len())];
+        let ip = Ipv4Packet::new(data).unwrap();
+        // Only the first fragment of a datagram starts with the next header
+        let next = if ip.get_fragment_offset() == 0 {
+            Protocol::IpProtocol(ip.get_next_level_protocol())
+        } else {
+            Protocol::Payload
+        };
+
+        Step::Decoded(Layer::Ipv4(ip), &data[header_length..], next)
+    }
+
+    fn decode_ipv6<'p, 'd>(&'d self, data: &'p [u8]) -> Step<'p, 'd> {
+        let payload_length = match Ipv6Packet::new(data) {
+            Some(ref ip) if ip.get_version() == 6 => ip.get_payload_length() as usize,
+            _ => return Step::Malformed("IPv6"),
+        };
+        // A payload length of zero is used by jumbograms, whose length is in an extension header
+        let data = if payload_length == 0 {
+            data
+        } else {
+            &data[..cmp::min(40 + payload_length, data.len())]
+        };
+        let ip = Ipv6Packet::new(data).unwrap();
+        let (next_header, offset) = match ipv6::upper_layer(&ip) {
+            Some(upper) => upper,
+            None => return Step::Malformed("IPv6"),
+        };
+        // Only the first fragment of a packet starts with the next header
+        let (next, offset) = if next_header == IpNextHeaderProtocols::Ipv6Frag {
+            match FragmentPacket::new(&data[offset..]) {
+                Some(ref fragment) if fragment.get_fragment_offset() == 0 => {
+                    (Protocol::IpProtocol(fragment.get_next_header()), offset + 8)
+                }
+                Some(..) => (Protocol::Payload, offset + 8),
+                None => return Step::Malformed("IPv6"),
+            }
+        } else {
+            (Protocol::IpProtocol(next_header), offset)
+        };
+
+        Step::Decoded(Layer::Ipv6(ip), &data[offset..], next)
+    }
+
+    fn decode_gre<'p, 'd>(&'d self, data: &'p [u8]) -> Step<'p, 'd> {
+        let gre = match GrePacket::new(data) {
+            Some(gre) => gre,
+            None => return Step::Malformed("GRE"),
+        };
+        // Source routed GRE isn't supported by GrePacket
+        if gre.get_routing_present() != 0 {
+            return Step::Unknown;
+        }
+        let length = 4 + gre.get_checksum_present() as usize * 4 +
+                     gre.get_key_present() as usize * 4 +
+                     gre.get_sequence_present() as usize * 4;
+        if length > data.len() {
+            return Step::Malformed("GRE");
+        }
+        let next = Protocol::EtherType(EtherType::new(gre.get_protocol_type()));
+
+        Step::Decoded(Layer::Gre(gre), &data[length..], next)
+    }
+
+    fn decode_icmp<'p, 'd>(&'d self, data: &'p [u8]) -> Step<'p, 'd> {
+        match IcmpPacket::new(data) {
+            Some(icmp) => Step::Decoded(Layer::Icmp(icmp), &data[4..], Protocol::Payload),
+            None => Step::Malformed("ICMP"),
+        }
+    }
+
+    fn decode_icmpv6<'p, 'd>(&'d self, data: &'p [u8]) -> Step<'p, 'd> {
+        match Icmpv6Packet::new(data) {
+            Some(icmp) => Step::Decoded(Layer::Icmpv6(icmp), &data[4..], Protocol::Payload),
+            None => Step::Malformed("ICMPv6"),
+        }
+    }
+
+    fn decode_tcp<'p, 'd>(&'d self, data: &'p [u8]) -> Step<'p, 'd> {
+        let tcp = match TcpPacket::new(data) {
+            Some(tcp) => tcp,
+            None => return Step::Malformed("TCP"),
+        };
+        let header_length = tcp.get_data_offset() as usize * 4;
+        if header_length < 20 || header_length > data.len() {
+            return Step::Malformed("TCP");
+        }
+        let next = Dissector::port(&self.tcp_ports, tcp.get_source(), tcp.get_destination());
+
+        Step::Decoded(Layer::Tcp(tcp), &data[header_length..], next)
+    }
+
+    fn decode_udp<'p, 'd>(&'d self, data: &'p [u8]) -> Step<'p, 'd> {
+        let length = match UdpPacket::new(data) {
+            Some(udp) => udp.get_length() as usize,
+            None => return Step::Malformed("UDP"),
+        };
+        if length < 8 {
+            return Step::Malformed("UDP");
+        }
+        let data = &data[..cmp::min(length, data.len())];
+        let udp = UdpPacket::new(data).unwrap();
+        let next = Dissector::port(&self.udp_ports, udp.get_source(), udp.get_destination());
+
+        Step::Decoded(Layer::Udp(udp), &data[8..], next)
+    }
+}
+
+impl Registered {
+    fn new<F>(name: &'static str, decoder: F) -> Registered
+        where F: Fn(&[u8]) -> Option<(usize, Next)> + Send + Sync + 'static
+    {
+        Registered {
+            name: name,
+            decoder: Box::new(decoder),
+        }
+    }
+
+    fn decode<'p, 'd>(&'d self, data: &'p [u8]) -> Step<'p, 'd> {
+        match (self.decoder)(data) {
+            Some((length, next)) if length <= data.len() => {
+                let layer = Layer::Custom {
+                    name: self.name,
+                    header: &data[..length],
+                };
+                let next = match next {
+                    Next::Ethernet => Protocol::Ethernet,
+                    Next::EtherType(ethertype) => Protocol::EtherType(ethertype),
+                    Next::IpProtocol(protocol) => Protocol::IpProtocol(protocol),
+                    Next::Payload => Protocol::Payload,
+                };
+                Step::Decoded(layer, &data[length..], next)
+            }
+            _ => Step::Malformed(self.name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use packet::{MutablePacket, Packet};
+    use packet::ethernet::MutableEthernetPacket;
+    use packet::ipv4::MutableIpv4Packet;
+    use packet::ipv6::{MutableFragmentPacket, MutableIpv6Packet};
+    use packet::udp::MutableUdpPacket;
+    use std::net::Ipv4Addr;
+
+    /// An Ethernet frame holding an IPv4 UDP datagram with the given payload, followed by two
+    /// bytes of padding
+    fn udp_frame(destination: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0u8; 14 + 20 + 8 + payload.len() + 2];
+        {
+            let mut ethernet = MutableEthernetPacket::new(&mut frame).unwrap();
+            ethernet.set_ethertype(EtherTypes::Ipv4);
+            let mut ip = MutableIpv4Packet::new(ethernet.payload_mut()).unwrap();
+            ip.set_version(4);
+            ip.set_header_length(5);
+            ip.set_total_length(20 + 8 + payload.len() as u16);
+            ip.set_next_level_protocol(IpNextHeaderProtocols::Udp);
+            ip.set_source(Ipv4Addr::new(10, 0, 0, 1));
+            ip.set_destination(Ipv4Addr::new(10, 0, 0, 2));
+            let mut udp = MutableUdpPacket::new(ip.payload_mut()).unwrap();
+            udp.set_source(1234);
+            udp.set_destination(destination);
+            udp.set_length(8 + payload.len() as u16);
+            udp.payload_mut()[..payload.len()].copy_from_slice(payload);
+        }
+
+        frame
+    }
+
+    #[test]
+    fn dissect_udp() {
+        let frame = udp_frame(53, b"query");
+        let layers = Dissector::new().dissect(LinkType::Ethernet, &frame);
+        assert_eq!(layers.len(), 4);
+        match layers[1] {
+            Layer::Ipv4(ref ip) => assert_eq!(ip.get_source(), Ipv4Addr::new(10, 0, 0, 1)),
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+        match layers[2] {
+            Layer::Udp(ref udp) => assert_eq!(udp.get_destination(), 53),
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+        // The padding after the IPv4 packet isn't part of the payload
+        match layers[3] {
+            Layer::Payload(payload) => assert_eq!(payload, b"query"),
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+
+        let layers = Dissector::new().dissect(LinkType::RawIp, &frame[14..]);
+        assert_eq!(layers.len(), 3);
+        match layers[0] {
+            Layer::Ipv4(..) => (),
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+    }
+
+    #[test]
+    fn dissect_malformed() {
+        let mut frame = udp_frame(53, b"query");
+        // A header length of 60 bytes, longer than the rest of the frame
+        frame[14] = 0x4f;
+        let layers = Dissector::new().dissect(LinkType::Ethernet, &frame[..40]);
+        assert_eq!(layers.len(), 2);
+        match layers[1] {
+            Layer::Malformed { protocol, data } => {
+                assert_eq!(protocol, "IPv4");
+                assert_eq!(data.len(), 26);
+            }
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+
+        // A truncated UDP header
+        let frame = udp_frame(53, b"");
+        let layers = Dissector::new().dissect(LinkType::Ethernet, &frame[..14 + 20 + 4]);
+        match layers[2] {
+            Layer::Malformed { protocol: "UDP", .. } => (),
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+
+        assert!(Dissector::new().dissect(LinkType::Ethernet, &[]).is_empty());
+        match Dissector::new().dissect(LinkType::Ethernet, &[0; 10])[0] {
+            Layer::Malformed { protocol: "Ethernet", .. } => (),
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+    }
+
+    #[test]
+    fn dissect_ipv6_extension_headers() {
+        // Hop-by-Hop Options, then Destination Options, then UDP
+        let mut packet = vec![0u8; 40 + 8 + 8 + 8 + 4];
+        {
+            let mut ip = MutableIpv6Packet::new(&mut packet).unwrap();
+            ip.set_version(6);
+            ip.set_payload_length(8 + 8 + 8 + 4);
+            ip.set_next_header(IpNextHeaderProtocols::Hopopt);
+        }
+        packet[40] = IpNextHeaderProtocols::Ipv6Opts.0;
+        packet[48] = IpNextHeaderProtocols::Udp.0;
+        {
+            let mut udp = MutableUdpPacket::new(&mut packet[56..]).unwrap();
+            udp.set_destination(53);
+            udp.set_length(8 + 4);
+        }
+        let layers = Dissector::new().dissect(LinkType::RawIp, &packet);
+        assert_eq!(layers.len(), 3);
+        match layers[0] {
+            Layer::Ipv6(ref ip) => assert_eq!(ip.payload().len(), 28),
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+        match layers[1] {
+            Layer::Udp(ref udp) => assert_eq!(udp.get_destination(), 53),
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+
+        // The first fragment of a packet is decoded, but not the others
+        packet[48] = IpNextHeaderProtocols::Ipv6Frag.0;
+        packet.splice(56..56, vec![IpNextHeaderProtocols::Udp.0, 0, 0, 0, 0, 0, 0, 1]);
+        MutableIpv6Packet::new(&mut packet).unwrap().set_payload_length(8 + 8 + 8 + 8 + 4);
+        match Dissector::new().dissect(LinkType::RawIp, &packet)[1] {
+            Layer::Udp(ref udp) => assert_eq!(udp.get_destination(), 53),
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+        MutableFragmentPacket::new(&mut packet[56..]).unwrap().set_fragment_offset(1);
+        match Dissector::new().dissect(LinkType::RawIp, &packet)[1] {
+            Layer::Payload(payload) => assert_eq!(payload.len(), 12),
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+
+        // An extension header running past the end of the packet
+        packet[41] = 8;
+        match Dissector::new().dissect(LinkType::RawIp, &packet)[0] {
+            Layer::Malformed { protocol: "IPv6", .. } => (),
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+    }
+
+    #[test]
+    fn dissect_registered() {
+        // VXLAN carries Ethernet frames after an 8 byte header
+        let inner = udp_frame(9999, b"inner");
+        let mut payload = vec![0x08, 0, 0, 0, 0, 0, 42, 0];
+        payload.extend_from_slice(&inner[..inner.len() - 2]);
+        let frame = udp_frame(4789, &payload);
+
+        let mut dissector = Dissector::new();
+        dissector.register_udp_port(4789, "VXLAN", |data| {
+            if data.len() >= 8 && data[0] & 0x08 != 0 {
+                Some((8, Next::Ethernet))
+            } else {
+                None
+            }
+        });
+        let layers = dissector.dissect(LinkType::Ethernet, &frame);
+        assert_eq!(layers.len(), 8);
+        match layers[3] {
+            Layer::Custom { name, header } => {
+                assert_eq!(name, "VXLAN");
+                assert_eq!(header[6], 42);
+            }
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+        match layers[6] {
+            Layer::Udp(ref udp) => assert_eq!(udp.get_destination(), 9999),
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+
+        // A registered decoder rejecting its header makes it malformed
+        let frame = udp_frame(4789, &[0; 8]);
+        match *dissector.dissect(LinkType::Ethernet, &frame).last().unwrap() {
+            Layer::Malformed { protocol: "VXLAN", .. } => (),
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+
+        // Registered decoders replace the built in ones
+        dissector.register_ip_protocol(IpNextHeaderProtocols::Udp, "Not UDP", |_| {
+            Some((0, Next::Payload))
+        });
+        let layers = dissector.dissect(LinkType::Ethernet, &frame);
+        assert_eq!(layers.len(), 4);
+        match layers[2] {
+            Layer::Custom { name: "Not UDP", header } => assert!(header.is_empty()),
+            ref layer => panic!("unexpected layer {:?}", layer),
+        }
+    }
+}
@@ -0,0 +1,566 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for decoding NetFlow v5, NetFlow v9 and IPFIX export packets
+//!
+//! NetFlow v9 and IPFIX data records can only be decoded once the template describing them has
+//! been seen, so a `Decoder` remembers the templates of the packets it is given. Templates are
+//! scoped to an exporter, so each exporter needs its own `Decoder`.
+//!
+//! Records of every version are decoded into a list of fields. NetFlow v5 records are given the
+//! fields of the equivalent IPFIX information elements, which NetFlow v9 also uses for its first
+//! 127 field types.
+
+use packet::Packet;
+use packet::ipfix::{self, InformationElements, IpfixPacket, SetPacket};
+use packet::netflow::{self, FlowSetPacket, NetflowV5Packet, NetflowV9Packet};
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Represents an error which occurred whilst decoding an export packet
+#[derive(Copy, Debug, PartialEq, Eq, Clone)]
+pub enum DecodeErr {
+    /// The packet is shorter than its header, or one of its sets, says it is
+    Truncated,
+    /// The packet's version is not 5 (NetFlow v5), 9 (NetFlow v9) or 10 (IPFIX)
+    UnsupportedVersion(u16),
+}
+
+/// A field of a data record
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    /// The enterprise which defined the field's information element, or zero for those assigned
+    /// by IANA
+    pub enterprise: u32,
+    /// The field's information element, see `packet::ipfix::InformationElements`
+    pub id: u16,
+    /// The field's value, as sent
+    pub value: Vec<u8>,
+}
+
+impl Field {
+    /// The value of an unsigned integer field, which may have been sent in fewer bytes than its
+    /// type needs
+    pub fn as_u64(&self) -> Option<u64> {
+        if self.value.is_empty() || self.value.len() > 8 {
+            return None;
+        }
+
+        Some(self.value.iter().fold(0, |value, byte| (value << 8) | *byte as u64))
+    }
+
+    /// The value of an IPv4 or IPv6 address field
+    pub fn as_ip_addr(&self) -> Option<IpAddr> {
+        let value = &self.value;
+        match value.len() {
+            4 => Some(IpAddr::V4(Ipv4Addr::new(value[0], value[1], value[2], value[3]))),
+            16 => {
+                let mut octets = [0u8; 16];
+                octets.copy_from_slice(value);
+                Some(IpAddr::V6(Ipv6Addr::from(octets)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A decoded data record
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DataRecord {
+    /// The ID of the template describing the record, or zero for NetFlow v5 records
+    pub template_id: u16,
+    /// The number of fields, at the start of `fields`, which are the scope of an options record
+    pub scope_field_count: usize,
+    /// The record's fields, in the order they were sent
+    pub fields: Vec<Field>,
+}
+
+impl DataRecord {
+    /// The first field holding the IANA assigned information element `id`
+    pub fn get(&self, id: u16) -> Option<&Field> {
+        self.fields.iter().find(|field| field.enterprise == 0 && field.id == id)
+    }
+}
+
+/// A decoded export packet
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Message {
+    /// 5 for NetFlow v5, 9 for NetFlow v9 or 10 for IPFIX
+    pub version: u16,
+    /// When the packet was sent, in seconds since the Unix epoch
+    pub export_time: u32,
+    /// The sequence number of the packet. NetFlow v9 counts packets, while NetFlow v5 and IPFIX
+    /// count the data records sent before this one.
+    pub sequence_number: u32,
+    /// The observation domain ID for IPFIX, or source ID for NetFlow v9. For NetFlow v5, the
+    /// engine type and engine ID.
+    pub domain_id: u32,
+    /// The data records of the packet
+    pub records: Vec<DataRecord>,
+    /// The number of data sets which were skipped, as their template hasn't been seen
+    pub unknown_sets: usize,
+}
+
+/// A field of a template: its enterprise number, information element and length
+type TemplateField = (u32, u16, u16);
+
+/// Describes the data records with its template ID
+#[derive(Clone, Debug)]
+struct Template {
+    scope_field_count: usize,
+    fields: Vec<TemplateField>,
+}
+
+impl Template {
+    /// Decode a data record from the start of `bytes`, returning it and its length, or `None` if
+    /// there aren't enough bytes left for one
+    fn decode(&self, template_id: u16, bytes: &[u8]) -> Option<(DataRecord, usize)> {
+        let mut position = 0;
+        let mut fields = Vec::with_capacity(self.fields.len());
+        for &(enterprise, id, length) in &self.fields {
+            let length = if length == ipfix::VARIABLE_LENGTH {
+                let short = *bytes.get(position)? as usize;
+                position += 1;
+                if short < 255 {
+                    short
+                } else {
+                    let long = bytes.get(position..position + 2)?;
+                    position += 2;
+                    (long[0] as usize) << 8 | long[1] as usize
+                }
+            } else {
+                length as usize
+            };
+            fields.push(Field {
+                enterprise: enterprise,
+                id: id,
+                value: bytes.get(position..position + length)?.to_vec(),
+            });
+            position += length;
+        }
+        let record = DataRecord {
+            template_id: template_id,
+            scope_field_count: self.scope_field_count,
+            fields: fields,
+        };
+
+        Some((record, position))
+    }
+}
+
+/// Identifies a template: the version of the packets it was sent in, the observation domain, and
+/// its template ID
+type TemplateKey = (u16, u32, u16);
+
+/// Decodes NetFlow and IPFIX export packets, remembering the templates they contain
+#[derive(Clone, Debug, Default)]
+pub struct Decoder {
+    templates: HashMap<TemplateKey, Template>,
+}
+
+impl Decoder {
+    /// Create a new Decoder, which knows no templates
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    /// Decode a NetFlow v5, NetFlow v9 or IPFIX export packet
+    ///
+    /// Any templates in the packet are remembered, and used to decode its data records and those
+    /// of later packets.
+    pub fn decode(&mut self, packet: &[u8]) -> Result<Message, DecodeErr> {
+        if packet.len() < 2 {
+            return Err(DecodeErr::Truncated);
+        }
+        match (packet[0] as u16) << 8 | packet[1] as u16 {
+            5 => decode_v5(packet),
+            9 => self.decode_v9(packet),
+            ipfix::VERSION => self.decode_ipfix(packet),
+            version => Err(DecodeErr::UnsupportedVersion(version)),
+        }
+    }
+
+    /// The number of templates which are known
+    pub fn template_count(&self) -> usize {
+        self.templates.len()
+    }
+
+    fn decode_v9(&mut self, packet: &[u8]) -> Result<Message, DecodeErr> {
+        let header = NetflowV9Packet::new(packet).ok_or(DecodeErr::Truncated)?;
+        let domain_id = header.get_source_id();
+        let mut message = Message {
+            version: 9,
+            export_time: header.get_unix_secs(),
+            sequence_number: header.get_sequence_number(),
+            domain_id: domain_id,
+            records: Vec::new(),
+            unknown_sets: 0,
+        };
+
+        let mut sets = header.payload();
+        while !sets.is_empty() {
+            let set = FlowSetPacket::new(sets).ok_or(DecodeErr::Truncated)?;
+            let length = set.get_length() as usize;
+            if length < 4 || length > sets.len() {
+                return Err(DecodeErr::Truncated);
+            }
+            match set.get_flowset_id() {
+                netflow::TEMPLATE_FLOWSET_ID => self.v9_templates(domain_id, set.payload()),
+                netflow::OPTIONS_TEMPLATE_FLOWSET_ID => {
+                    self.v9_options_templates(domain_id, set.payload())
+                }
+                id => self.data_set(&mut message, id, set.payload()),
+            }
+            sets = &sets[length..];
+        }
+
+        Ok(message)
+    }
+
+    fn v9_templates(&mut self, domain_id: u32, mut records: &[u8]) {
+        while let Some(record) = netflow::TemplateRecordPacket::new(records) {
+            let fields: Vec<TemplateField> = record.get_fields_iter()
+                .map(|field| (0, field.get_field_type(), field.get_field_length()))
+                .collect();
+            if record.get_template_id() < 256 || fields.len() != record.get_field_count() as usize {
+                break;
+            }
+            self.templates.insert((9, domain_id, record.get_template_id()),
+                                  Template {
+                                      scope_field_count: 0,
+                                      fields: fields,
+                                  });
+            records = &records[4 + record.get_fields_raw().len()..];
+        }
+    }
+
+    fn v9_options_templates(&mut self, domain_id: u32, mut records: &[u8]) {
+        while let Some(record) = netflow::OptionsTemplateRecordPacket::new(records) {
+            let scope_length = record.get_option_scope_length() as usize;
+            let option_length = record.get_option_length() as usize;
+            if record.get_template_id() < 256 || scope_length % 4 != 0 || option_length % 4 != 0 ||
+               6 + scope_length + option_length > records.len() {
+                break;
+            }
+            let fields = record.get_scope_fields_iter()
+                .chain(record.get_option_fields_iter())
+                .map(|field| (0, field.get_field_type(), field.get_field_length()))
+                .collect();
+            self.templates.insert((9, domain_id, record.get_template_id()),
+                                  Template {
+                                      scope_field_count: scope_length / 4,
+                                      fields: fields,
+                                  });
+            records = &records[6 + scope_length + option_length..];
+        }
+    }
+
+    fn decode_ipfix(&mut self, packet: &[u8]) -> Result<Message, DecodeErr> {
+        let header = IpfixPacket::new(packet).ok_or(DecodeErr::Truncated)?;
+        if (header.get_length() as usize) < 16 || header.get_length() as usize > packet.len() {
+            return Err(DecodeErr::Truncated);
+        }
+        let domain_id = header.get_observation_domain_id();
+        let mut message = Message {
+            version: ipfix::VERSION,
+            export_time: header.get_export_time(),
+            sequence_number: header.get_sequence_number(),
+            domain_id: domain_id,
+            records: Vec::new(),
+            unknown_sets: 0,
+        };
+
+        let mut sets = header.payload();
+        while !sets.is_empty() {
+            let set = SetPacket::new(sets).ok_or(DecodeErr::Truncated)?;
+            let length = set.get_length() as usize;
+            if length < 4 || length > sets.len() {
+                return Err(DecodeErr::Truncated);
+            }
+            match set.get_set_id() {
+                ipfix::TEMPLATE_SET_ID => self.ipfix_templates(domain_id, set.payload(), false),
+                ipfix::OPTIONS_TEMPLATE_SET_ID => {
+                    self.ipfix_templates(domain_id, set.payload(), true)
+                }
+                id => self.data_set(&mut message, id, set.payload()),
+            }
+            sets = &sets[length..];
+        }
+
+        Ok(message)
+    }
+
+    /// Read the template or options template records of a set, which only differ in the
+    /// options templates' extra scope field count
+    fn ipfix_templates(&mut self, domain_id: u32, mut records: &[u8], options: bool) {
+        let header_length = if options { 6 } else { 4 };
+        while records.len() >= header_length {
+            let (template_id, field_count, scope_field_count, fields) = if options {
+                let record = ipfix::OptionsTemplateRecordPacket::new(records).unwrap();
+                (record.get_template_id(),
+                 record.get_field_count(),
+                 record.get_scope_field_count(),
+                 ipfix_fields(record.get_fields_iter()))
+            } else {
+                let record = ipfix::TemplateRecordPacket::new(records).unwrap();
+                (record.get_template_id(),
+                 record.get_field_count(),
+                 0,
+                 ipfix_fields(record.get_fields_iter()))
+            };
+            if template_id == ipfix::TEMPLATE_SET_ID ||
+               template_id == ipfix::OPTIONS_TEMPLATE_SET_ID {
+                // Withdraw every template of the domain
+                self.templates.retain(|&(version, domain, _), _| {
+                    version != ipfix::VERSION || domain != domain_id
+                });
+                break;
+            }
+            if template_id < 256 {
+                // Padding
+                break;
+            }
+            let (fields, length) = fields;
+            if fields.len() != field_count as usize ||
+               header_length + length > records.len() {
+                break;
+            }
+            let key = (ipfix::VERSION, domain_id, template_id);
+            if field_count == 0 {
+                self.templates.remove(&key);
+            } else {
+                self.templates.insert(key,
+                                      Template {
+                                          scope_field_count: scope_field_count as usize,
+                                          fields: fields,
+                                      });
+            }
+            records = &records[header_length + length..];
+        }
+    }
+
+    fn data_set(&self, message: &mut Message, template_id: u16, mut records: &[u8]) {
+        let key = (message.version, message.domain_id, template_id);
+        let template = match self.templates.get(&key) {
+            Some(template) if template_id >= 256 => template,
+            _ => {
+                message.unknown_sets += 1;
+                return;
+            }
+        };
+        // Anything left which is too short for a record is padding
+        while let Some((record, length)) = template.decode(template_id, records) {
+            if length == 0 {
+                break;
+            }
+            message.records.push(record);
+            records = &records[length..];
+        }
+    }
+}
+
+/// The fields of an IPFIX template, and the number of bytes their specifiers should take up
+fn ipfix_fields(specifiers: ipfix::FieldSpecifierIterable) -> (Vec<TemplateField>, usize) {
+    let mut length = 0;
+    let fields = specifiers.map(|field| {
+            let enterprise = field.get_enterprise_number_raw();
+            length += 4 + field.get_enterprise() as usize * 4;
+            (enterprise.iter().fold(0, |n, byte| n << 8 | *byte as u32),
+             field.get_information_element_id(),
+             field.get_field_length())
+        })
+        .collect();
+
+    (fields, length)
+}
+
+/// Decode a NetFlow v5 packet, whose records always have the same fields
+fn decode_v5(packet: &[u8]) -> Result<Message, DecodeErr> {
+    let header = NetflowV5Packet::new(packet).ok_or(DecodeErr::Truncated)?;
+    if header.get_records_raw().len() < header.get_count() as usize * 48 {
+        return Err(DecodeErr::Truncated);
+    }
+    let field = |id, value: &[u8]| {
+        Field {
+            enterprise: 0,
+            id: id,
+            value: value.to_vec(),
+        }
+    };
+    let records = header.get_records_iter()
+        .map(|record| {
+            let bytes = record.packet();
+            DataRecord {
+                template_id: 0,
+                scope_field_count: 0,
+                fields: vec![field(InformationElements::SourceIpv4Address, &bytes[0..4]),
+                             field(InformationElements::DestinationIpv4Address, &bytes[4..8]),
+                             field(InformationElements::IpNextHopIpv4Address, &bytes[8..12]),
+                             field(InformationElements::IngressInterface, &bytes[12..14]),
+                             field(InformationElements::EgressInterface, &bytes[14..16]),
+                             field(InformationElements::PacketDeltaCount, &bytes[16..20]),
+                             field(InformationElements::OctetDeltaCount, &bytes[20..24]),
+                             field(InformationElements::FlowStartSysUpTime, &bytes[24..28]),
+                             field(InformationElements::FlowEndSysUpTime, &bytes[28..32]),
+                             field(InformationElements::SourceTransportPort, &bytes[32..34]),
+                             field(InformationElements::DestinationTransportPort, &bytes[34..36]),
+                             field(InformationElements::TcpControlBits, &bytes[37..38]),
+                             field(InformationElements::ProtocolIdentifier, &bytes[38..39]),
+                             field(InformationElements::IpClassOfService, &bytes[39..40]),
+                             field(InformationElements::BgpSourceAsNumber, &bytes[40..42]),
+                             field(InformationElements::BgpDestinationAsNumber, &bytes[42..44]),
+                             field(InformationElements::SourceIpv4PrefixLength, &bytes[44..45]),
+                             field(InformationElements::DestinationIpv4PrefixLength,
+                                   &bytes[45..46])],
+            }
+        })
+        .collect();
+
+    Ok(Message {
+        version: 5,
+        export_time: header.get_unix_secs(),
+        sequence_number: header.get_flow_sequence(),
+        domain_id: (header.get_engine_type() as u32) << 8 | header.get_engine_id() as u32,
+        records: records,
+        unknown_sets: 0,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use packet::ipfix::InformationElements;
+    use std::net::IpAddr;
+    use super::{DecodeErr, Decoder, Field};
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn netflow_v5() {
+        let mut packet = vec![0, 5, 0, 1, // version, count
+                              0, 0, 0, 100, // uptime
+                              0x59, 0x68, 0x2f, 0x00, 0, 0, 0, 0, // time
+                              0, 0, 0, 9, // sequence
+                              1, 2, 0, 0]; // engine, sampling
+        packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2, 0, 0, 0, 0, // addresses
+                                   0, 1, 0, 2, // interfaces
+                                   0, 0, 0, 3, 0, 0, 0, 180, // packets, octets
+                                   0, 0, 0, 10, 0, 0, 0, 90, // first, last
+                                   0x9c, 0x40, 0, 53, // ports
+                                   0, 0, 17, 0, // pad, flags, protocol, tos
+                                   0, 0, 0, 0, 32, 24, 0, 0]);
+
+        let message = Decoder::new().decode(&packet).unwrap();
+        assert_eq!(message.version, 5);
+        assert_eq!(message.export_time, 1500000000);
+        assert_eq!(message.sequence_number, 9);
+        assert_eq!(message.domain_id, 0x102);
+        assert_eq!(message.records.len(), 1);
+        let record = &message.records[0];
+        let get = |id| record.get(id).unwrap();
+        assert_eq!(get(InformationElements::SourceIpv4Address).as_ip_addr(),
+                   Some(ip("10.0.0.1")));
+        assert_eq!(get(InformationElements::OctetDeltaCount).as_u64(), Some(180));
+        assert_eq!(get(InformationElements::SourceTransportPort).as_u64(), Some(40000));
+        assert_eq!(get(InformationElements::ProtocolIdentifier).as_u64(), Some(17));
+        assert_eq!(get(InformationElements::DestinationIpv4PrefixLength).as_u64(), Some(24));
+
+        assert_eq!(Decoder::new().decode(&packet[..70]), Err(DecodeErr::Truncated));
+    }
+
+    #[test]
+    fn netflow_v9() {
+        let header = [0, 9, 0, 2, 0, 0, 0, 0, 0x59, 0x68, 0x2f, 0x00, 0, 0, 0, 1, 0, 0, 0, 7];
+        let data = [1, 0, 0, 12, // data FlowSet for template 256
+                    192, 0, 2, 1, 0, 0, 0, 42];
+        let mut decoder = Decoder::new();
+
+        // Data before its template can't be decoded
+        let mut packet = header.to_vec();
+        packet.extend_from_slice(&data);
+        let message = decoder.decode(&packet).unwrap();
+        assert_eq!(message.records.len(), 0);
+        assert_eq!(message.unknown_sets, 1);
+
+        let mut packet = header.to_vec();
+        packet.extend_from_slice(&[0, 0, 0, 16, 1, 0, 0, 2, 0, 8, 0, 4, 0, 1, 0, 4]);
+        // An options template for the sampling interval of an interface
+        packet.extend_from_slice(&[0, 1, 0, 16, 1, 1, 0, 4, 0, 4, 0, 2, 0, 4, 0, 34, 0, 4]);
+        packet.extend_from_slice(&[0, 0]);
+        packet.extend_from_slice(&data);
+        packet.extend_from_slice(&[1, 1, 0, 12, 0, 0, 0, 3, 0, 0, 0, 100]);
+        // The options template FlowSet's length counts its padding
+        packet[20 + 16 + 3] = 20;
+        let message = decoder.decode(&packet).unwrap();
+        assert_eq!(message.domain_id, 7);
+        assert_eq!(message.unknown_sets, 0);
+        assert_eq!(decoder.template_count(), 2);
+        assert_eq!(message.records.len(), 2);
+        assert_eq!(message.records[0].template_id, 256);
+        assert_eq!(message.records[0].fields,
+                   vec![Field {
+                            enterprise: 0,
+                            id: 8,
+                            value: vec![192, 0, 2, 1],
+                        },
+                        Field {
+                            enterprise: 0,
+                            id: 1,
+                            value: vec![0, 0, 0, 42],
+                        }]);
+        assert_eq!(message.records[1].scope_field_count, 1);
+        assert_eq!(message.records[1].fields[0].id, 2);
+        assert_eq!(message.records[1].get(34).unwrap().as_u64(), Some(100));
+    }
+
+    #[test]
+    fn ipfix() {
+        let mut packet = vec![0, 10, 0, 0, 0x59, 0x68, 0x2f, 0x00, 0, 0, 0, 5, 0, 0, 0, 1];
+        packet.extend_from_slice(&[0, 2, 0, 24, // template set
+                                   1, 0, 0, 3, // template ID, field count
+                                   0, 4, 0, 1, // protocol
+                                   0x80, 1, 0xff, 0xff, 0, 0, 0x72, 0x79, // enterprise, variable
+                                   0, 7, 0, 2]); // source port
+        packet.extend_from_slice(&[1, 0, 0, 16, // data set
+                                   6, 3, b'a', b'b', b'c', 0, 80,
+                                   17, 0, 0, 53, 0]); // empty variable field, then padding
+        let length = packet.len() as u16;
+        packet[2] = (length >> 8) as u8;
+        packet[3] = length as u8;
+
+        let mut decoder = Decoder::new();
+        let message = decoder.decode(&packet).unwrap();
+        assert_eq!(message.version, 10);
+        assert_eq!(message.sequence_number, 5);
+        assert_eq!(message.records.len(), 2);
+        let record = &message.records[0];
+        assert_eq!(record.get(InformationElements::ProtocolIdentifier).unwrap().as_u64(),
+                   Some(6));
+        assert_eq!(record.fields[1].enterprise, 29305);
+        assert_eq!(record.fields[1].value, b"abc");
+        // Enterprise fields aren't found by IANA ID
+        assert_eq!(record.get(1), None);
+        assert_eq!(record.get(InformationElements::SourceTransportPort).unwrap().as_u64(),
+                   Some(80));
+        assert_eq!(message.records[1].fields[1].value, vec![]);
+
+        // Templates are withdrawn by sending them without fields
+        let mut withdrawal = packet[..16].to_vec();
+        withdrawal.extend_from_slice(&[0, 2, 0, 8, 1, 0, 0, 0]);
+        withdrawal[3] = 24;
+        decoder.decode(&withdrawal).unwrap();
+        assert_eq!(decoder.template_count(), 0);
+
+        // A length longer than the packet
+        packet[3] += 1;
+        assert_eq!(decoder.decode(&packet), Err(DecodeErr::Truncated));
+        assert_eq!(decoder.decode(&[0, 1, 0, 0]), Err(DecodeErr::UnsupportedVersion(1)));
+    }
+}
@@ -0,0 +1,444 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for exporting flow records to an IPFIX collector over UDP
+//!
+//! Each direction of a `Flow` with packets is sent as a separate data record, using one template
+//! for IPv4 flows and another for IPv6. Collectors can't ask for templates over UDP, so they are
+//! sent with the first message, and again whenever the template interval has passed.
+//!
+//! ```no_run
+//! use pnet::flow::{Config, FlowTable};
+//! use pnet::flow::export::{self, IpfixExporter};
+//!
+//! let mut table = FlowTable::new(Config::default());
+//! let collector = "192.0.2.1:4739".parse().unwrap();
+//! let mut exporter = IpfixExporter::new(collector, export::Config::default()).unwrap();
+//!
+//! // After processing some packets:
+//! exporter.export(&table.expire()).unwrap();
+//! ```
+
+use flow::{Flow, FlowEnd};
+use packet::ip::IpNextHeaderProtocol;
+use packet::ipfix::{self, FieldSpecifier, InformationElements, MutableIpfixPacket,
+                    MutableSetPacket, MutableTemplateRecordPacket};
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// The template ID of records for IPv4 flows
+pub const IPV4_TEMPLATE_ID: u16 = 256;
+
+/// The template ID of records for IPv6 flows
+pub const IPV6_TEMPLATE_ID: u16 = 257;
+
+/// Options for encoding and exporting flow records
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The observation domain the records belong to. Defaults to 0.
+    pub observation_domain_id: u32,
+
+    /// How often templates are sent again. Defaults to 60 seconds.
+    pub template_interval: Duration,
+
+    /// The largest message to send, which should fit in a single packet to the collector.
+    /// Defaults to 1400 bytes.
+    pub max_message_size: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            observation_domain_id: 0,
+            template_interval: Duration::from_secs(60),
+            max_message_size: 1400,
+        }
+    }
+}
+
+/// The fields of the records for IPv4 and IPv6 flows, which only differ in their addresses
+fn template_fields(ipv6: bool) -> Vec<FieldSpecifier> {
+    let (source, destination, length) = if ipv6 {
+        (InformationElements::SourceIpv6Address, InformationElements::DestinationIpv6Address, 16)
+    } else {
+        (InformationElements::SourceIpv4Address, InformationElements::DestinationIpv4Address, 4)
+    };
+
+    vec![FieldSpecifier::new(source, length),
+         FieldSpecifier::new(destination, length),
+         FieldSpecifier::new(InformationElements::SourceTransportPort, 2),
+         FieldSpecifier::new(InformationElements::DestinationTransportPort, 2),
+         FieldSpecifier::new(InformationElements::ProtocolIdentifier, 1),
+         FieldSpecifier::new(InformationElements::TcpControlBits, 2),
+         FieldSpecifier::new(InformationElements::PacketDeltaCount, 8),
+         FieldSpecifier::new(InformationElements::OctetDeltaCount, 8),
+         FieldSpecifier::new(InformationElements::FlowStartMilliseconds, 8),
+         FieldSpecifier::new(InformationElements::FlowEndMilliseconds, 8),
+         FieldSpecifier::new(InformationElements::FlowEndReason, 1)]
+}
+
+/// Encodes flow records as IPFIX messages
+pub struct Encoder {
+    config: Config,
+    /// The number of data records encoded so far
+    sequence_number: u32,
+    templates_sent: Option<Instant>,
+}
+
+impl Encoder {
+    /// Create a new Encoder
+    pub fn new(config: Config) -> Encoder {
+        Encoder {
+            config: config,
+            sequence_number: 0,
+            templates_sent: None,
+        }
+    }
+
+    /// Encode the records of `flows` as IPFIX messages
+    ///
+    /// The records are split across as many messages as they need, and the templates are
+    /// included in the first if they are due. If there are no records and no templates are due,
+    /// there are no messages.
+    pub fn encode(&mut self, flows: &[Flow]) -> Vec<Vec<u8>> {
+        let templates_due = self.templates_sent
+            .map_or(true, |sent| sent.elapsed() >= self.config.template_interval);
+        let mut messages = Vec::new();
+        let mut message = Vec::new();
+        if templates_due {
+            self.templates_sent = Some(Instant::now());
+            message = self.start_message();
+            push_templates(&mut message);
+        }
+
+        // The template ID and start of the current data set
+        let mut set: Option<(u16, usize)> = None;
+        let mut records = 0;
+        let times = Times::new();
+        for (template_id, record) in flows.iter().flat_map(|flow| flow_records(flow, &times)) {
+            let set_header = match set {
+                Some((id, _)) if id == template_id => 0,
+                _ => 4,
+            };
+            if !message.is_empty() && records > 0 &&
+               message.len() + set_header + record.len() > self.config.max_message_size {
+                finish_set(&mut message, set.take());
+                messages.push(finish_message(message));
+                message = Vec::new();
+                records = 0;
+            }
+            if message.is_empty() {
+                message = self.start_message();
+            }
+            if set.map_or(true, |(id, _)| id != template_id) {
+                finish_set(&mut message, set.take());
+                set = Some((template_id, message.len()));
+                message.extend_from_slice(&[0; 4]);
+            }
+            message.extend_from_slice(&record);
+            records += 1;
+            self.sequence_number = self.sequence_number.wrapping_add(1);
+        }
+        if !message.is_empty() {
+            finish_set(&mut message, set);
+            messages.push(finish_message(message));
+        }
+
+        messages
+    }
+
+    /// A message header, without its length or export time, which are filled in once the
+    /// message is finished
+    fn start_message(&self) -> Vec<u8> {
+        let mut message = vec![0u8; 16];
+        {
+            let mut header = MutableIpfixPacket::new(&mut message[..]).unwrap();
+            header.set_version(ipfix::VERSION);
+            header.set_sequence_number(self.sequence_number);
+            header.set_observation_domain_id(self.config.observation_domain_id);
+        }
+
+        message
+    }
+}
+
+fn push_templates(message: &mut Vec<u8>) {
+    let start = message.len();
+    message.extend_from_slice(&[0; 4]);
+    for &(template_id, ipv6) in &[(IPV4_TEMPLATE_ID, false), (IPV6_TEMPLATE_ID, true)] {
+        let fields = template_fields(ipv6);
+        let position = message.len();
+        message.resize(position + 4 + fields.len() * 4, 0);
+        let mut record = MutableTemplateRecordPacket::new(&mut message[position..]).unwrap();
+        record.set_template_id(template_id);
+        record.set_field_count(fields.len() as u16);
+        record.set_fields(&fields);
+    }
+    let length = message.len() - start;
+    let mut set = MutableSetPacket::new(&mut message[start..]).unwrap();
+    set.set_set_id(ipfix::TEMPLATE_SET_ID);
+    set.set_length(length as u16);
+}
+
+/// Fill in the header of the data set starting at `set`, if there is one
+fn finish_set(message: &mut Vec<u8>, set: Option<(u16, usize)>) {
+    if let Some((template_id, start)) = set {
+        let length = message.len() - start;
+        let mut set = MutableSetPacket::new(&mut message[start..]).unwrap();
+        set.set_set_id(template_id);
+        set.set_length(length as u16);
+    }
+}
+
+fn finish_message(mut message: Vec<u8>) -> Vec<u8> {
+    let export_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0);
+    let length = message.len();
+    {
+        let mut header = MutableIpfixPacket::new(&mut message[..]).unwrap();
+        header.set_length(length as u16);
+        header.set_export_time(export_time as u32);
+    }
+
+    message
+}
+
+/// Converts `Instant`s to milliseconds since the Unix epoch
+struct Times {
+    now: Instant,
+    now_millis: u64,
+}
+
+impl Times {
+    fn new() -> Times {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Times {
+            now: Instant::now(),
+            now_millis: since_epoch.as_secs() * 1000 + since_epoch.subsec_millis() as u64,
+        }
+    }
+
+    fn millis(&self, instant: Instant) -> u64 {
+        let ago = self.now.duration_since(instant);
+        self.now_millis.saturating_sub(ago.as_secs() * 1000 + ago.subsec_millis() as u64)
+    }
+}
+
+/// The data records for each direction of a flow which has packets, with their template IDs
+fn flow_records(flow: &Flow, times: &Times) -> Vec<(u16, Vec<u8>)> {
+    // See the flowEndReason information element
+    let end_reason = match flow.end {
+        Some(FlowEnd::Idle) => 1,
+        Some(FlowEnd::Active) => 2,
+        Some(FlowEnd::Flushed) | None => 4,
+    };
+    let directions = [(flow.initiator, flow.responder, flow.forward),
+                      (flow.responder, flow.initiator, flow.reverse)];
+    directions.iter()
+        .filter(|&&(_, _, counters)| counters.packets > 0)
+        .map(|&(source, destination, counters)| {
+            let mut record = Vec::with_capacity(72);
+            let template_id = match (source.ip(), destination.ip()) {
+                (IpAddr::V4(source), IpAddr::V4(destination)) => {
+                    record.extend_from_slice(&source.octets());
+                    record.extend_from_slice(&destination.octets());
+                    IPV4_TEMPLATE_ID
+                }
+                (source, destination) => {
+                    record.extend_from_slice(&ipv6_octets(source));
+                    record.extend_from_slice(&ipv6_octets(destination));
+                    IPV6_TEMPLATE_ID
+                }
+            };
+            push_u16(&mut record, source.port());
+            push_u16(&mut record, destination.port());
+            let IpNextHeaderProtocol(protocol) = flow.key.protocol;
+            record.push(protocol);
+            push_u16(&mut record, counters.tcp_flags);
+            push_u64(&mut record, counters.packets);
+            push_u64(&mut record, counters.bytes);
+            push_u64(&mut record, times.millis(flow.first_seen));
+            push_u64(&mut record, times.millis(flow.last_seen));
+            record.push(end_reason);
+
+            (template_id, record)
+        })
+        .collect()
+}
+
+fn ipv6_octets(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
+
+fn push_u16(buffer: &mut Vec<u8>, value: u16) {
+    buffer.extend_from_slice(&[(value >> 8) as u8, value as u8]);
+}
+
+fn push_u64(buffer: &mut Vec<u8>, value: u64) {
+    for shift in (0..8).rev() {
+        buffer.push((value >> (shift * 8)) as u8);
+    }
+}
+
+/// Sends flow records to an IPFIX collector over UDP
+pub struct IpfixExporter {
+    socket: UdpSocket,
+    encoder: Encoder,
+}
+
+impl IpfixExporter {
+    /// Create an exporter which sends to the collector listening at `collector`
+    pub fn new(collector: SocketAddr, config: Config) -> io::Result<IpfixExporter> {
+        let local = match collector {
+            SocketAddr::V4(..) => IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+            SocketAddr::V6(..) => IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)),
+        };
+        let socket = UdpSocket::bind(SocketAddr::new(local, 0))?;
+        socket.connect(collector)?;
+
+        Ok(IpfixExporter {
+            socket: socket,
+            encoder: Encoder::new(config),
+        })
+    }
+
+    /// Send the records of `flows` to the collector
+    pub fn export(&mut self, flows: &[Flow]) -> io::Result<()> {
+        for message in self.encoder.encode(flows) {
+            self.socket.send(&message)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flow::{Counters, Flow, FlowEnd, FlowKey};
+    use flow::decode::Decoder;
+    use packet::ip::IpNextHeaderProtocols;
+    use packet::ipfix::InformationElements;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+    use std::time::{Duration, Instant};
+    use super::{Config, Encoder, IPV4_TEMPLATE_ID, IPV6_TEMPLATE_ID, IpfixExporter};
+
+    fn flow(initiator: &str, responder: &str, forward: u64, reverse: u64) -> Flow {
+        let initiator: SocketAddr = initiator.parse().unwrap();
+        let responder: SocketAddr = responder.parse().unwrap();
+        let counters = |packets| {
+            Counters {
+                packets: packets,
+                bytes: packets * 100,
+                tcp_flags: 0x12,
+            }
+        };
+
+        Flow {
+            key: FlowKey::new(IpNextHeaderProtocols::Tcp, initiator, responder),
+            initiator: initiator,
+            responder: responder,
+            forward: counters(forward),
+            reverse: counters(reverse),
+            first_seen: Instant::now() - Duration::from_secs(10),
+            last_seen: Instant::now(),
+            end: Some(FlowEnd::Idle),
+        }
+    }
+
+    #[test]
+    fn encode() {
+        let flows = vec![flow("10.0.0.2:40000", "10.0.0.1:80", 3, 2),
+                         flow("[2001:db8::2]:40000", "[2001:db8::1]:443", 1, 0)];
+        let mut encoder = Encoder::new(Config {
+            observation_domain_id: 5,
+            ..Default::default()
+        });
+        let messages = encoder.encode(&flows);
+        assert_eq!(messages.len(), 1);
+
+        let mut decoder = Decoder::new();
+        let message = decoder.decode(&messages[0]).unwrap();
+        assert_eq!(message.version, 10);
+        assert_eq!(message.domain_id, 5);
+        assert_eq!(message.sequence_number, 0);
+        assert_eq!(decoder.template_count(), 2);
+        let records = message.records;
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].template_id, IPV4_TEMPLATE_ID);
+        assert_eq!(records[2].template_id, IPV6_TEMPLATE_ID);
+
+        let get = |i: usize, id| records[i].get(id).unwrap();
+        assert_eq!(get(0, InformationElements::SourceIpv4Address).as_ip_addr(),
+                   Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))));
+        assert_eq!(get(0, InformationElements::PacketDeltaCount).as_u64(), Some(3));
+        assert_eq!(get(1, InformationElements::SourceTransportPort).as_u64(), Some(80));
+        assert_eq!(get(1, InformationElements::OctetDeltaCount).as_u64(), Some(200));
+        assert_eq!(get(1, InformationElements::ProtocolIdentifier).as_u64(), Some(6));
+        assert_eq!(get(1, InformationElements::TcpControlBits).as_u64(), Some(0x12));
+        assert_eq!(get(1, InformationElements::FlowEndReason).as_u64(), Some(1));
+        let start = get(2, InformationElements::FlowStartMilliseconds).as_u64().unwrap();
+        let end = get(2, InformationElements::FlowEndMilliseconds).as_u64().unwrap();
+        assert!(end - start >= 9990 && end - start <= 10010);
+        assert_eq!(get(2, InformationElements::DestinationIpv6Address).as_ip_addr(),
+                   Some("2001:db8::1".parse().unwrap()));
+
+        // Templates aren't sent again until the interval has passed
+        let messages = encoder.encode(&flows[1..]);
+        let message = Decoder::new().decode(&messages[0]).unwrap();
+        assert_eq!(message.unknown_sets, 1);
+        assert_eq!(message.sequence_number, 3);
+        assert!(encoder.encode(&[]).is_empty());
+    }
+
+    #[test]
+    fn split_messages() {
+        let flows: Vec<_> = (0..10)
+            .map(|i| flow(&format!("10.0.0.{}:1000", i + 2), "10.0.0.1:53", 1, 1))
+            .collect();
+        let mut encoder = Encoder::new(Config {
+            max_message_size: 400,
+            template_interval: Duration::from_secs(0),
+            ..Default::default()
+        });
+        let messages = encoder.encode(&flows);
+        let mut decoder = Decoder::new();
+        let mut records = 0;
+        for message in &messages {
+            assert!(message.len() <= 400);
+            let decoded = decoder.decode(message).unwrap();
+            assert_eq!(decoded.sequence_number, records);
+            records += decoded.records.len() as u32;
+        }
+        assert_eq!(records, 20);
+        assert!(messages.len() > 3);
+
+        // Templates are always due with no interval
+        assert_eq!(encoder.encode(&[]).len(), 1);
+    }
+
+    #[test]
+    fn export_udp() {
+        let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        collector.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut exporter = IpfixExporter::new(collector.local_addr().unwrap(), Config::default())
+            .unwrap();
+        exporter.export(&[flow("10.0.0.2:40000", "10.0.0.1:80", 3, 2)]).unwrap();
+
+        let mut buffer = [0u8; 1500];
+        let length = collector.recv(&mut buffer).unwrap();
+        let message = Decoder::new().decode(&buffer[..length]).unwrap();
+        assert_eq!(message.records.len(), 2);
+    }
+}
@@ -0,0 +1,648 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for grouping captured packets into flows
+//!
+//! A flow is all the packets of one protocol sent between two endpoints, in either direction. A
+//! `FlowTable` counts the packets of each flow, and hands back a `Flow` record for each one once
+//! it ends:
+//!
+//! ```
+//! use pnet::flow::{Config, FlowTable};
+//! use pnet::packet::ethernet::EthernetPacket;
+//!
+//! let mut table = FlowTable::new(Config::default());
+//! # let captured = vec![0u8; 14];
+//! # let packet = EthernetPacket::new(&captured[..]).unwrap();
+//! // For each captured packet:
+//! table.process_ethernet(&packet);
+//!
+//! // And every so often:
+//! for flow in table.expire() {
+//!     println!("{} <-> {}: {} bytes", flow.initiator, flow.responder, flow.bytes());
+//! }
+//! ```
+//!
+//! Non-first fragments carry no ports, so are ignored; `reassembly::ip` can put packets back
+//! together before they are counted.
+//!
+//! Flow records can be sent to a collector with `export`, and NetFlow and IPFIX packets from other
+//! exporters read with `decode`.
+
+use packet::Packet;
+use packet::ethernet::{EtherTypes, EthernetPacket};
+use packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use packet::ipv4::{Ipv4Flags, Ipv4Packet};
+use packet::ipv6::{FragmentPacket, Ipv6Packet};
+use packet::tcp::TcpPacket;
+use packet::udp::UdpPacket;
+use packet::vlan::VlanPacket;
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+pub mod decode;
+pub mod export;
+
+/// Identifies a flow, whichever direction its packets are sent in
+///
+/// The endpoints are ordered, so that packets in both directions have the same key. Protocols
+/// other than TCP and UDP have ports of zero.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    /// The protocol of the flow's packets
+    pub protocol: IpNextHeaderProtocol,
+    /// The lower of the two endpoints
+    pub lower: SocketAddr,
+    /// The higher of the two endpoints
+    pub upper: SocketAddr,
+}
+
+impl FlowKey {
+    /// Create the key for packets sent between `source` and `destination` in either direction
+    pub fn new(protocol: IpNextHeaderProtocol,
+               source: SocketAddr,
+               destination: SocketAddr)
+        -> FlowKey {
+        let (lower, upper) = if (source.ip(), source.port()) <=
+                                (destination.ip(), destination.port()) {
+            (source, destination)
+        } else {
+            (destination, source)
+        };
+
+        FlowKey {
+            protocol: protocol,
+            lower: lower,
+            upper: upper,
+        }
+    }
+
+    /// The key for an Ethernet frame containing an IPv4 or IPv6 packet, possibly VLAN tagged
+    pub fn from_ethernet(packet: &EthernetPacket) -> Option<FlowKey> {
+        Observation::from_ethernet(packet).map(|observation| observation.key())
+    }
+
+    /// The key for an IPv4 packet
+    pub fn from_ipv4(packet: &Ipv4Packet) -> Option<FlowKey> {
+        Observation::from_ipv4(packet).map(|observation| observation.key())
+    }
+
+    /// The key for an IPv6 packet
+    pub fn from_ipv6(packet: &Ipv6Packet) -> Option<FlowKey> {
+        Observation::from_ipv6(packet).map(|observation| observation.key())
+    }
+}
+
+/// What was seen of a packet
+struct Observation {
+    protocol: IpNextHeaderProtocol,
+    source: SocketAddr,
+    destination: SocketAddr,
+    /// The length of the IP packet
+    length: usize,
+    tcp_flags: u16,
+}
+
+impl Observation {
+    fn from_ethernet(packet: &EthernetPacket) -> Option<Observation> {
+        let mut ethertype = packet.get_ethertype();
+        let mut payload = packet.payload();
+        while ethertype == EtherTypes::Vlan {
+            let vlan = VlanPacket::new(payload)?;
+            ethertype = vlan.get_ethertype();
+            payload = &payload[VlanPacket::minimum_packet_size()..];
+        }
+        match ethertype {
+            EtherTypes::Ipv4 => Observation::from_ipv4(&Ipv4Packet::new(payload)?),
+            EtherTypes::Ipv6 => Observation::from_ipv6(&Ipv6Packet::new(payload)?),
+            _ => None,
+        }
+    }
+
+    fn from_ipv4(packet: &Ipv4Packet) -> Option<Observation> {
+        if packet.get_fragment_offset() != 0 {
+            return None;
+        }
+        let bytes = packet.packet();
+        let header_length = packet.get_header_length() as usize * 4;
+        let length = packet.get_total_length() as usize;
+        if header_length < 20 || length < header_length || length > bytes.len() {
+            return None;
+        }
+        // A first fragment may not hold the whole transport header
+        let more = packet.get_flags() & Ipv4Flags::MoreFragments != 0;
+
+        Observation::from_transport(packet.get_next_level_protocol(),
+                                    IpAddr::V4(packet.get_source()),
+                                    IpAddr::V4(packet.get_destination()),
+                                    &bytes[header_length..length],
+                                    length,
+                                    more)
+    }
+
+    fn from_ipv6(packet: &Ipv6Packet) -> Option<Observation> {
+        let bytes = packet.packet();
+        let length = 40 + packet.get_payload_length() as usize;
+        if length > bytes.len() {
+            return None;
+        }
+        let mut next_header = packet.get_next_header();
+        let mut position = 40;
+        let mut more = false;
+        loop {
+            if position + 8 > length {
+                break;
+            }
+            match next_header {
+                IpNextHeaderProtocols::Hopopt |
+                IpNextHeaderProtocols::Ipv6Route |
+                IpNextHeaderProtocols::Ipv6Opts => {
+                    next_header = IpNextHeaderProtocol(bytes[position]);
+                    position += (bytes[position + 1] as usize + 1) * 8;
+                }
+                IpNextHeaderProtocols::Ipv6Frag => {
+                    let fragment = FragmentPacket::new(&bytes[position..length])?;
+                    if fragment.get_fragment_offset() != 0 {
+                        return None;
+                    }
+                    more = fragment.get_more_fragments() != 0;
+                    next_header = fragment.get_next_header();
+                    position += 8;
+                }
+                _ => break,
+            }
+        }
+        if position > length {
+            return None;
+        }
+
+        Observation::from_transport(next_header,
+                                    IpAddr::V6(packet.get_source()),
+                                    IpAddr::V6(packet.get_destination()),
+                                    &bytes[position..length],
+                                    length,
+                                    more)
+    }
+
+    fn from_transport(protocol: IpNextHeaderProtocol,
+                      source: IpAddr,
+                      destination: IpAddr,
+                      payload: &[u8],
+                      length: usize,
+                      fragment: bool)
+        -> Option<Observation> {
+        let (source_port, destination_port, tcp_flags) = match protocol {
+            IpNextHeaderProtocols::Tcp => {
+                let tcp = TcpPacket::new(payload);
+                match tcp {
+                    Some(tcp) => (tcp.get_source(), tcp.get_destination(), tcp.get_flags()),
+                    None if fragment => return None,
+                    None => (0, 0, 0),
+                }
+            }
+            IpNextHeaderProtocols::Udp => {
+                match UdpPacket::new(payload) {
+                    Some(udp) => (udp.get_source(), udp.get_destination(), 0),
+                    None if fragment => return None,
+                    None => (0, 0, 0),
+                }
+            }
+            _ => (0, 0, 0),
+        };
+
+        Some(Observation {
+            protocol: protocol,
+            source: SocketAddr::new(source, source_port),
+            destination: SocketAddr::new(destination, destination_port),
+            length: length,
+            tcp_flags: tcp_flags,
+        })
+    }
+
+    fn key(&self) -> FlowKey {
+        FlowKey::new(self.protocol, self.source, self.destination)
+    }
+}
+
+/// Counts of the packets sent in one direction of a flow
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counters {
+    /// The number of packets
+    pub packets: u64,
+    /// The number of bytes, counting whole IP packets
+    pub bytes: u64,
+    /// Every TCP flag seen, combined, see `packet::tcp::TcpFlags`
+    pub tcp_flags: u16,
+}
+
+impl Counters {
+    fn add(&mut self, observation: &Observation) {
+        self.packets += 1;
+        self.bytes += observation.length as u64;
+        self.tcp_flags |= observation.tcp_flags;
+    }
+}
+
+/// Why a flow record was emitted
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FlowEnd {
+    /// No packets were seen for longer than the idle timeout
+    Idle,
+    /// The flow was active for longer than the active timeout. Later packets are counted in a
+    /// new record for the same flow.
+    Active,
+    /// The table was flushed
+    Flushed,
+}
+
+/// A record of the packets of one flow
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Flow {
+    /// The flow's key
+    pub key: FlowKey,
+    /// The endpoint which sent the first packet
+    pub initiator: SocketAddr,
+    /// The other endpoint
+    pub responder: SocketAddr,
+    /// Packets sent by the initiator
+    pub forward: Counters,
+    /// Packets sent by the responder
+    pub reverse: Counters,
+    /// When the first packet was seen
+    pub first_seen: Instant,
+    /// When the last packet was seen
+    pub last_seen: Instant,
+    /// Why the record was emitted, or `None` if the flow is still being tracked
+    pub end: Option<FlowEnd>,
+}
+
+impl Flow {
+    /// The number of packets sent in both directions
+    pub fn packets(&self) -> u64 {
+        self.forward.packets + self.reverse.packets
+    }
+
+    /// The number of bytes sent in both directions
+    pub fn bytes(&self) -> u64 {
+        self.forward.bytes + self.reverse.bytes
+    }
+
+    /// Every TCP flag seen in either direction, combined
+    pub fn tcp_flags(&self) -> u16 {
+        self.forward.tcp_flags | self.reverse.tcp_flags
+    }
+}
+
+/// Options for a `FlowTable`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// How long a flow can go without packets before it ends. Defaults to 15 seconds.
+    pub idle_timeout: Duration,
+
+    /// How long a flow can last before its record is emitted, so that long running flows are
+    /// reported while they are active. Defaults to 30 minutes.
+    pub active_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            idle_timeout: Duration::from_secs(15),
+            active_timeout: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+/// Tracks the flows of captured packets
+pub struct FlowTable {
+    flows: HashMap<FlowKey, Flow>,
+    config: Config,
+}
+
+impl FlowTable {
+    /// Create a new FlowTable
+    pub fn new(config: Config) -> FlowTable {
+        FlowTable {
+            flows: HashMap::new(),
+            config: config,
+        }
+    }
+
+    /// Count a captured Ethernet frame, returning the key of its flow, or `None` if it isn't part
+    /// of a flow
+    pub fn process_ethernet(&mut self, packet: &EthernetPacket) -> Option<FlowKey> {
+        Observation::from_ethernet(packet).map(|observation| self.add(observation))
+    }
+
+    /// Count a captured IPv4 packet, returning the key of its flow
+    pub fn process_ipv4(&mut self, packet: &Ipv4Packet) -> Option<FlowKey> {
+        Observation::from_ipv4(packet).map(|observation| self.add(observation))
+    }
+
+    /// Count a captured IPv6 packet, returning the key of its flow
+    pub fn process_ipv6(&mut self, packet: &Ipv6Packet) -> Option<FlowKey> {
+        Observation::from_ipv6(packet).map(|observation| self.add(observation))
+    }
+
+    fn add(&mut self, observation: Observation) -> FlowKey {
+        let key = observation.key();
+        let now = Instant::now();
+        let flow = self.flows.entry(key).or_insert_with(|| {
+            Flow {
+                key: key,
+                initiator: observation.source,
+                responder: observation.destination,
+                forward: Counters::default(),
+                reverse: Counters::default(),
+                first_seen: now,
+                last_seen: now,
+                end: None,
+            }
+        });
+        if observation.source == flow.initiator {
+            flow.forward.add(&observation);
+        } else {
+            flow.reverse.add(&observation);
+        }
+        flow.last_seen = now;
+
+        key
+    }
+
+    /// Emit the records of flows which have been idle for longer than the idle timeout, and of
+    /// those which have lasted longer than the active timeout
+    ///
+    /// Flows which reached the active timeout carry on being tracked in a new record.
+    pub fn expire(&mut self) -> Vec<Flow> {
+        let mut expired = Vec::new();
+        self.expire_with(|flow| expired.push(flow));
+
+        expired
+    }
+
+    /// Like `expire()`, but calls `f` with each record rather than collecting them
+    pub fn expire_with<F>(&mut self, mut f: F)
+        where F: FnMut(Flow)
+    {
+        let now = Instant::now();
+        let config = self.config;
+        let mut idle = Vec::new();
+        for (key, flow) in &mut self.flows {
+            if now.duration_since(flow.last_seen) >= config.idle_timeout {
+                idle.push(*key);
+            } else if now.duration_since(flow.first_seen) >= config.active_timeout {
+                let mut record = flow.clone();
+                record.end = Some(FlowEnd::Active);
+                flow.forward = Counters::default();
+                flow.reverse = Counters::default();
+                flow.first_seen = now;
+                f(record);
+            }
+        }
+        for key in idle {
+            if let Some(mut flow) = self.flows.remove(&key) {
+                flow.end = Some(FlowEnd::Idle);
+                f(flow);
+            }
+        }
+    }
+
+    /// Emit the records of every flow, and stop tracking them
+    pub fn flush(&mut self) -> Vec<Flow> {
+        self.flows
+            .drain()
+            .map(|(_, mut flow)| {
+                flow.end = Some(FlowEnd::Flushed);
+                flow
+            })
+            .collect()
+    }
+
+    /// The record of a flow which is being tracked
+    pub fn get(&self, key: &FlowKey) -> Option<&Flow> {
+        self.flows.get(key)
+    }
+
+    /// The number of flows being tracked
+    pub fn len(&self) -> usize {
+        self.flows.len()
+    }
+
+    /// Whether no flows are being tracked
+    pub fn is_empty(&self) -> bool {
+        self.flows.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use packet::ethernet::{EtherTypes, MutableEthernetPacket};
+    use packet::ip::IpNextHeaderProtocols;
+    use packet::ipv4::{Ipv4Packet, MutableIpv4Packet};
+    use packet::ipv6::{Ipv6Packet, MutableIpv6Packet};
+    use packet::tcp::{MutableTcpPacket, TcpFlags};
+    use packet::udp::MutableUdpPacket;
+    use packet::vlan::MutableVlanPacket;
+    use packet::ethernet::EthernetPacket;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+    use std::thread;
+    use std::time::Duration;
+    use super::{Config, FlowEnd, FlowKey, FlowTable};
+
+    fn socket(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    fn tcp(source: SocketAddr, destination: SocketAddr, flags: u16, payload: usize) -> Vec<u8> {
+        let mut buffer = vec![0u8; 20 + 20 + payload];
+        {
+            let mut ipv4 = MutableIpv4Packet::new(&mut buffer[..]).unwrap();
+            ipv4.set_version(4);
+            ipv4.set_header_length(5);
+            ipv4.set_total_length(40 + payload as u16);
+            ipv4.set_next_level_protocol(IpNextHeaderProtocols::Tcp);
+            match (source.ip(), destination.ip()) {
+                (IpAddr::V4(source), IpAddr::V4(destination)) => {
+                    ipv4.set_source(source);
+                    ipv4.set_destination(destination);
+                }
+                _ => unreachable!(),
+            }
+        }
+        {
+            let mut segment = MutableTcpPacket::new(&mut buffer[20..]).unwrap();
+            segment.set_source(source.port());
+            segment.set_destination(destination.port());
+            segment.set_data_offset(5);
+            segment.set_flags(flags);
+        }
+
+        buffer
+    }
+
+    #[test]
+    fn key_is_bidirectional() {
+        let client = socket("10.0.0.2:40000");
+        let server = socket("10.0.0.1:80");
+        let forward = tcp(client, server, TcpFlags::SYN, 0);
+        let reverse = tcp(server, client, TcpFlags::SYN | TcpFlags::ACK, 0);
+        let key = FlowKey::from_ipv4(&Ipv4Packet::new(&forward[..]).unwrap()).unwrap();
+        assert_eq!(key.protocol, IpNextHeaderProtocols::Tcp);
+        assert_eq!(key.lower, server);
+        assert_eq!(key.upper, client);
+        assert_eq!(FlowKey::from_ipv4(&Ipv4Packet::new(&reverse[..]).unwrap()), Some(key));
+    }
+
+    #[test]
+    fn ethernet_vlan_udp() {
+        let mut frame = vec![0u8; 14 + 4 + 40 + 8];
+        {
+            let mut ethernet = MutableEthernetPacket::new(&mut frame[..]).unwrap();
+            ethernet.set_ethertype(EtherTypes::Vlan);
+        }
+        {
+            let mut vlan = MutableVlanPacket::new(&mut frame[14..]).unwrap();
+            vlan.set_vlan_identifier(10);
+            vlan.set_ethertype(EtherTypes::Ipv6);
+        }
+        {
+            let mut ipv6 = MutableIpv6Packet::new(&mut frame[18..]).unwrap();
+            ipv6.set_version(6);
+            ipv6.set_payload_length(8);
+            ipv6.set_next_header(IpNextHeaderProtocols::Udp);
+            ipv6.set_source(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2));
+            ipv6.set_destination(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        }
+        {
+            let mut udp = MutableUdpPacket::new(&mut frame[58..]).unwrap();
+            udp.set_source(5353);
+            udp.set_destination(53);
+            udp.set_length(8);
+        }
+
+        let mut table = FlowTable::new(Config::default());
+        let key = table.process_ethernet(&EthernetPacket::new(&frame[..]).unwrap()).unwrap();
+        assert_eq!(key.protocol, IpNextHeaderProtocols::Udp);
+        assert_eq!(key.lower, socket("[2001:db8::1]:53"));
+        assert_eq!(key.upper, socket("[2001:db8::2]:5353"));
+        let flow = table.get(&key).unwrap();
+        assert_eq!(flow.initiator, key.upper);
+        assert_eq!(flow.forward.bytes, 48);
+
+        // Packets which aren't IP are ignored
+        let mut arp = vec![0u8; 42];
+        MutableEthernetPacket::new(&mut arp[..]).unwrap().set_ethertype(EtherTypes::Arp);
+        assert_eq!(table.process_ethernet(&EthernetPacket::new(&arp[..]).unwrap()), None);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn counters() {
+        let client = socket("10.0.0.2:40000");
+        let server = socket("10.0.0.1:80");
+        let mut table = FlowTable::new(Config::default());
+        for packet in &[tcp(client, server, TcpFlags::SYN, 0),
+                        tcp(server, client, TcpFlags::SYN | TcpFlags::ACK, 0),
+                        tcp(client, server, TcpFlags::ACK, 100),
+                        tcp(client, server, TcpFlags::FIN | TcpFlags::ACK, 0)] {
+            table.process_ipv4(&Ipv4Packet::new(&packet[..]).unwrap());
+        }
+        // A packet of another flow
+        let other = tcp(client, socket("10.0.0.1:443"), TcpFlags::SYN, 0);
+        table.process_ipv4(&Ipv4Packet::new(&other[..]).unwrap());
+        assert_eq!(table.len(), 2);
+
+        let flow = table.get(&FlowKey::new(IpNextHeaderProtocols::Tcp, client, server)).unwrap();
+        assert_eq!(flow.initiator, client);
+        assert_eq!(flow.responder, server);
+        assert_eq!(flow.forward.packets, 3);
+        assert_eq!(flow.forward.bytes, 40 + 140 + 40);
+        assert_eq!(flow.forward.tcp_flags, TcpFlags::SYN | TcpFlags::ACK | TcpFlags::FIN);
+        assert_eq!(flow.reverse.packets, 1);
+        assert_eq!(flow.packets(), 4);
+        assert_eq!(flow.bytes(), 260);
+        assert_eq!(flow.end, None);
+
+        let flushed = table.flush();
+        assert_eq!(flushed.len(), 2);
+        assert!(flushed.iter().all(|flow| flow.end == Some(FlowEnd::Flushed)));
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn timeouts() {
+        let mut table = FlowTable::new(Config {
+            idle_timeout: Duration::from_millis(100),
+            active_timeout: Duration::from_millis(150),
+        });
+        let first = tcp(socket("10.0.0.2:1"), socket("10.0.0.1:2"), TcpFlags::ACK, 0);
+        let second = tcp(socket("10.0.0.2:3"), socket("10.0.0.1:4"), TcpFlags::ACK, 0);
+        let first = Ipv4Packet::new(&first[..]).unwrap();
+        let second = Ipv4Packet::new(&second[..]).unwrap();
+        let first_key = table.process_ipv4(&first).unwrap();
+        table.process_ipv4(&second);
+        assert!(table.expire().is_empty());
+
+        // Only the first flow stays active
+        for _ in 0..3 {
+            thread::sleep(Duration::from_millis(60));
+            table.process_ipv4(&first);
+        }
+        let mut records = Vec::new();
+        table.expire_with(|flow| records.push(flow));
+        records.sort_by_key(|flow| flow.end == Some(FlowEnd::Idle));
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].key, first_key);
+        assert_eq!(records[0].end, Some(FlowEnd::Active));
+        assert_eq!(records[0].packets(), 4);
+        assert_eq!(records[1].end, Some(FlowEnd::Idle));
+
+        // The active flow carries on with fresh counters
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.get(&first_key).unwrap().packets(), 0);
+        table.process_ipv4(&first);
+        assert_eq!(table.get(&first_key).unwrap().packets(), 1);
+    }
+
+    #[test]
+    fn missing_ports() {
+        let mut packet = tcp(socket("10.0.0.2:1"), socket("10.0.0.1:2"), TcpFlags::ACK, 0);
+        MutableIpv4Packet::new(&mut packet[..]).unwrap().set_fragment_offset(10);
+        assert_eq!(FlowKey::from_ipv4(&Ipv4Packet::new(&packet[..]).unwrap()), None);
+
+        let mut packet = vec![0u8; 40 + 8 + 20];
+        {
+            let mut ipv6 = MutableIpv6Packet::new(&mut packet[..]).unwrap();
+            ipv6.set_version(6);
+            ipv6.set_payload_length(28);
+            ipv6.set_next_header(IpNextHeaderProtocols::Ipv6Frag);
+            ipv6.set_source(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2));
+            ipv6.set_destination(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+        }
+        packet[40] = IpNextHeaderProtocols::Tcp.0;
+        packet[43] = 1;
+        MutableTcpPacket::new(&mut packet[48..]).unwrap().set_destination(443);
+        let key = FlowKey::from_ipv6(&Ipv6Packet::new(&packet[..]).unwrap()).unwrap();
+        assert_eq!(key.protocol, IpNextHeaderProtocols::Tcp);
+        assert_eq!(key.lower.port(), 443);
+
+        // Later fragments are ignored
+        packet[42] = 1;
+        assert_eq!(FlowKey::from_ipv6(&Ipv6Packet::new(&packet[..]).unwrap()), None);
+
+        let mut table = FlowTable::new(Config::default());
+        let mut icmp = tcp(socket("10.0.0.2:1"), socket("10.0.0.1:2"), 0, 0);
+        MutableIpv4Packet::new(&mut icmp[..])
+            .unwrap()
+            .set_next_level_protocol(IpNextHeaderProtocols::Icmp);
+        let key = table.process_ipv4(&Ipv4Packet::new(&icmp[..]).unwrap()).unwrap();
+        assert_eq!(key.lower, SocketAddr::new(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), 0));
+    }
+}
@@ -0,0 +1,334 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for fragmenting IPv4 and IPv6 packets which are too large for a link's MTU
+//!
+//! Packets sent through a datalink channel don't pass through the operating system's network
+//! stack, so any which are larger than the MTU need to be split up before they are sent.
+//! `reassembly::ip` puts fragments back together.
+
+use packet::Packet;
+use packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use packet::ipv4::{self, Ipv4Flags, Ipv4OptionNumbers, Ipv4Packet, MutableIpv4Packet};
+use packet::ipv6::{Ipv6Packet, MutableFragmentPacket, MutableIpv6Packet};
+
+use std::cmp;
+
+/// Represents an error which occurred whilst fragmenting a packet
+#[derive(Copy, Debug, PartialEq, Eq, Clone)]
+pub enum FragmentErr {
+    /// The packet is larger than the MTU, but has the Don't Fragment flag set
+    DontFragment,
+    /// The MTU is too small to fit the packet's headers and at least 8 bytes of data
+    MtuTooSmall,
+    /// The packet's header or length fields are inconsistent with its size
+    InvalidPacket,
+}
+
+/// Split an IPv4 packet into fragments no larger than `mtu`
+///
+/// Each fragment has its offset, More Fragments flag, total length and checksum set. Only options
+/// with the "copied" flag set are included in the fragments after the first. Packets which fit
+/// the MTU are returned unchanged, as the only fragment. Fragments may be fragmented further.
+pub fn fragment_ipv4(packet: &Ipv4Packet,
+                     mtu: usize)
+    -> Result<Vec<Ipv4Packet<'static>>, FragmentErr> {
+    let bytes = packet.packet();
+    let header_length = packet.get_header_length() as usize * 4;
+    let total_length = packet.get_total_length() as usize;
+    if header_length < 20 || total_length < header_length || total_length > bytes.len() {
+        return Err(FragmentErr::InvalidPacket);
+    }
+    if total_length <= mtu {
+        return Ok(vec![Ipv4Packet::owned(bytes[..total_length].to_vec()).unwrap()]);
+    }
+    if packet.get_flags() & Ipv4Flags::DontFragment != 0 {
+        return Err(FragmentErr::DontFragment);
+    }
+
+    let header = &bytes[..header_length];
+    let mut later_header = bytes[..20].to_vec();
+    later_header.extend(copied_options(&header[20..])?);
+    while later_header.len() % 4 != 0 {
+        later_header.push(Ipv4OptionNumbers::EOL.0);
+    }
+    // Every fragment except the last must carry a multiple of 8 bytes
+    let first_size = mtu.saturating_sub(header.len()) & !7;
+    let later_size = mtu.saturating_sub(later_header.len()) & !7;
+    if first_size == 0 || later_size == 0 {
+        return Err(FragmentErr::MtuTooSmall);
+    }
+
+    let data = &bytes[header_length..total_length];
+    let base_offset = packet.get_fragment_offset() as usize * 8;
+    let more_after = packet.get_flags() & Ipv4Flags::MoreFragments != 0;
+    let mut fragments = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let (header, size) = if offset == 0 {
+            (header, first_size)
+        } else {
+            (&later_header[..], later_size)
+        };
+        let end = cmp::min(offset + size, data.len());
+        let mut buffer = header.to_vec();
+        buffer.extend_from_slice(&data[offset..end]);
+        let length = buffer.len();
+        {
+            let mut fragment = MutableIpv4Packet::new(&mut buffer[..]).unwrap();
+            fragment.set_header_length((header.len() / 4) as u8);
+            fragment.set_total_length(length as u16);
+            let more = end < data.len() || more_after;
+            let flags = fragment.get_flags() & !Ipv4Flags::MoreFragments;
+            fragment.set_flags(if more { flags | Ipv4Flags::MoreFragments } else { flags });
+            fragment.set_fragment_offset(((base_offset + offset) / 8) as u16);
+            let checksum = ipv4::checksum(&fragment.to_immutable());
+            fragment.set_checksum(checksum);
+        }
+        fragments.push(Ipv4Packet::owned(buffer).unwrap());
+        offset = end;
+    }
+
+    Ok(fragments)
+}
+
+/// The options which must be repeated in every fragment, i.e. those whose "copied" flag is set
+fn copied_options(options: &[u8]) -> Result<Vec<u8>, FragmentErr> {
+    let mut copied = Vec::new();
+    let mut position = 0;
+    while position < options.len() {
+        let option_type = options[position];
+        let length = match option_type {
+            0 => break,
+            1 => 1,
+            _ if position + 1 < options.len() => options[position + 1] as usize,
+            _ => return Err(FragmentErr::InvalidPacket),
+        };
+        if length < 1 || position + length > options.len() {
+            return Err(FragmentErr::InvalidPacket);
+        }
+        if option_type & 0x80 != 0 {
+            copied.extend_from_slice(&options[position..position + length]);
+        }
+        position += length;
+    }
+
+    Ok(copied)
+}
+
+/// Split an IPv6 packet into fragments no larger than `mtu`
+///
+/// The Hop-by-Hop Options and Routing headers, along with any Destination Options header before
+/// a Routing header, are repeated in each fragment, followed by a Fragment header with the given
+/// `identification`. Packets which fit the MTU are returned unchanged, as the only fragment.
+pub fn fragment_ipv6(packet: &Ipv6Packet,
+                     mtu: usize,
+                     identification: u32)
+    -> Result<Vec<Ipv6Packet<'static>>, FragmentErr> {
+    let bytes = packet.packet();
+    let end = 40 + packet.get_payload_length() as usize;
+    if end > bytes.len() {
+        return Err(FragmentErr::InvalidPacket);
+    }
+    if end <= mtu {
+        return Ok(vec![Ipv6Packet::owned(bytes[..end].to_vec()).unwrap()]);
+    }
+
+    // Find the end of the headers which every fragment must carry, and the next header field
+    // which points past them
+    let mut next_header = packet.get_next_header();
+    let mut next_header_position = 6;
+    let mut position = 40;
+    let mut unfragmentable = (40, next_header_position);
+    while next_header == IpNextHeaderProtocols::Hopopt ||
+          next_header == IpNextHeaderProtocols::Ipv6Route ||
+          next_header == IpNextHeaderProtocols::Ipv6Opts {
+        if position + 2 > end {
+            return Err(FragmentErr::InvalidPacket);
+        }
+        let header = next_header;
+        next_header = IpNextHeaderProtocol(bytes[position]);
+        next_header_position = position;
+        position += (bytes[position + 1] as usize + 1) * 8;
+        if position > end {
+            return Err(FragmentErr::InvalidPacket);
+        }
+        if header != IpNextHeaderProtocols::Ipv6Opts {
+            unfragmentable = (position, next_header_position);
+        }
+    }
+    let (header_length, next_header_position) = unfragmentable;
+    let first_header = IpNextHeaderProtocol(bytes[next_header_position]);
+
+    let size = mtu.saturating_sub(header_length + 8) & !7;
+    if size == 0 {
+        return Err(FragmentErr::MtuTooSmall);
+    }
+    let data = &bytes[header_length..end];
+    let mut fragments = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let end = cmp::min(offset + size, data.len());
+        let mut buffer = bytes[..header_length].to_vec();
+        buffer[next_header_position] = IpNextHeaderProtocols::Ipv6Frag.0;
+        buffer.extend_from_slice(&[0; 8]);
+        buffer.extend_from_slice(&data[offset..end]);
+        let length = buffer.len();
+        {
+            let mut fragment = MutableFragmentPacket::new(&mut buffer[header_length..]).unwrap();
+            fragment.set_next_header(first_header);
+            fragment.set_fragment_offset((offset / 8) as u16);
+            fragment.set_more_fragments((end < data.len()) as u8);
+            fragment.set_identification(identification);
+        }
+        MutableIpv6Packet::new(&mut buffer[..]).unwrap().set_payload_length((length - 40) as u16);
+        fragments.push(Ipv6Packet::owned(buffer).unwrap());
+        offset = end;
+    }
+
+    Ok(fragments)
+}
+
+#[cfg(test)]
+mod tests {
+    use packet::Packet;
+    use packet::ip::IpNextHeaderProtocols;
+    use packet::ipv4::{self, Ipv4Flags, Ipv4Packet, MutableIpv4Packet};
+    use packet::ipv6::{FragmentPacket, Ipv6Packet, MutableIpv6Packet};
+    use reassembly::ip::{Config, FragmentReassembler};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use super::{FragmentErr, fragment_ipv4, fragment_ipv6};
+
+    fn ipv4(options: &[u8], flags: u8, payload_length: usize) -> Vec<u8> {
+        let header_length = 20 + options.len();
+        let mut buffer = vec![0u8; header_length + payload_length];
+        {
+            let mut packet = MutableIpv4Packet::new(&mut buffer[..]).unwrap();
+            packet.set_version(4);
+            packet.set_header_length((header_length / 4) as u8);
+            packet.set_total_length((header_length + payload_length) as u16);
+            packet.set_identification(0x1234);
+            packet.set_flags(flags);
+            packet.set_ttl(64);
+            packet.set_next_level_protocol(IpNextHeaderProtocols::Udp);
+            packet.set_source(Ipv4Addr::new(10, 0, 0, 1));
+            packet.set_destination(Ipv4Addr::new(10, 0, 0, 2));
+        }
+        buffer[20..header_length].copy_from_slice(options);
+        for (i, byte) in buffer[header_length..].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let checksum = ipv4::checksum(&Ipv4Packet::new(&buffer[..]).unwrap());
+        MutableIpv4Packet::new(&mut buffer[..]).unwrap().set_checksum(checksum);
+
+        buffer
+    }
+
+    #[test]
+    fn ipv4_fragments() {
+        let whole = ipv4(&[], 0, 1000);
+        let fragments = fragment_ipv4(&Ipv4Packet::new(&whole[..]).unwrap(), 400).unwrap();
+        let lengths: Vec<_> = fragments.iter().map(|f| f.packet().len()).collect();
+        assert_eq!(lengths, vec![396, 396, 268]);
+        let offsets: Vec<_> = fragments.iter().map(|f| f.get_fragment_offset()).collect();
+        assert_eq!(offsets, vec![0, 47, 94]);
+        let flags: Vec<_> = fragments.iter().map(|f| f.get_flags()).collect();
+        assert_eq!(flags, vec![Ipv4Flags::MoreFragments, Ipv4Flags::MoreFragments, 0]);
+        for fragment in &fragments {
+            assert_eq!(fragment.get_checksum(), ipv4::checksum(fragment));
+            assert_eq!(fragment.get_identification(), 0x1234);
+        }
+
+        let mut reassembler = FragmentReassembler::new(Config::default());
+        let mut reassembled = None;
+        for fragment in fragments.iter().rev() {
+            reassembled = reassembler.process_ipv4(fragment);
+        }
+        assert_eq!(reassembled.unwrap().packet(), &whole[..]);
+    }
+
+    #[test]
+    fn ipv4_copied_options() {
+        // Record route (not copied), then a NOP and loose source route (copied)
+        let options = [7, 7, 4, 0, 0, 0, 0, 1, 0x83, 7, 4, 10, 0, 0, 3, 0];
+        let whole = ipv4(&options, 0, 100);
+        let fragments = fragment_ipv4(&Ipv4Packet::new(&whole[..]).unwrap(), 76).unwrap();
+        assert_eq!(fragments.len(), 3);
+        assert_eq!(fragments[0].get_header_length(), 9);
+        assert_eq!(&fragments[0].packet()[20..36], &options[..]);
+        for fragment in &fragments[1..] {
+            assert_eq!(fragment.get_header_length(), 7);
+            assert_eq!(&fragment.packet()[20..28], &[0x83, 7, 4, 10, 0, 0, 3, 0]);
+            assert_eq!(fragment.get_checksum(), ipv4::checksum(fragment));
+        }
+        assert_eq!(fragments[1].get_fragment_offset(), 5);
+        assert_eq!(fragments[2].get_fragment_offset(), 11);
+        assert_eq!(fragments[2].payload(), &whole[36 + 88..]);
+    }
+
+    #[test]
+    fn ipv4_errors() {
+        let small = ipv4(&[], Ipv4Flags::DontFragment, 100);
+        let packet = Ipv4Packet::new(&small[..]).unwrap();
+        assert_eq!(fragment_ipv4(&packet, 120).unwrap()[0].packet(), &small[..]);
+        assert_eq!(fragment_ipv4(&packet, 100).err(), Some(FragmentErr::DontFragment));
+
+        let large = ipv4(&[], 0, 100);
+        let packet = Ipv4Packet::new(&large[..]).unwrap();
+        assert_eq!(fragment_ipv4(&packet, 27).err(), Some(FragmentErr::MtuTooSmall));
+        assert_eq!(fragment_ipv4(&packet, 28).unwrap().len(), 13);
+
+        let truncated = Ipv4Packet::new(&large[..60]).unwrap();
+        assert_eq!(fragment_ipv4(&truncated, 40).err(), Some(FragmentErr::InvalidPacket));
+    }
+
+    #[test]
+    fn ipv6_fragments() {
+        // A hop-by-hop options header, then a destination options header, then UDP
+        let mut whole = vec![0u8; 40 + 8 + 8 + 1000];
+        {
+            let mut packet = MutableIpv6Packet::new(&mut whole[..]).unwrap();
+            packet.set_version(6);
+            packet.set_payload_length(1016);
+            packet.set_next_header(IpNextHeaderProtocols::Hopopt);
+            packet.set_hop_limit(64);
+            packet.set_source(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+            packet.set_destination(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2));
+        }
+        whole[40] = IpNextHeaderProtocols::Ipv6Opts.0;
+        whole[48] = IpNextHeaderProtocols::Udp.0;
+        for (i, byte) in whole[56..].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        let packet = Ipv6Packet::new(&whole[..]).unwrap();
+        let fragments = fragment_ipv6(&packet, 500, 0xabcd).unwrap();
+        let lengths: Vec<_> = fragments.iter().map(|f| f.packet().len()).collect();
+        assert_eq!(lengths, vec![496, 496, 184]);
+        for (i, fragment) in fragments.iter().enumerate() {
+            assert_eq!(fragment.get_payload_length() as usize, fragment.packet().len() - 40);
+            assert_eq!(fragment.packet()[40], IpNextHeaderProtocols::Ipv6Frag.0);
+            let header = FragmentPacket::new(&fragment.packet()[48..]).unwrap();
+            // The destination options header is fragmented along with the data
+            assert_eq!(header.get_next_header(), IpNextHeaderProtocols::Ipv6Opts);
+            assert_eq!(header.get_fragment_offset() as usize, i * 55);
+            assert_eq!(header.get_more_fragments(), (i < 2) as u8);
+            assert_eq!(header.get_identification(), 0xabcd);
+        }
+
+        let mut reassembler = FragmentReassembler::new(Config::default());
+        let mut reassembled = None;
+        for fragment in &fragments {
+            reassembled = reassembler.process_ipv6(fragment);
+        }
+        assert_eq!(reassembled.unwrap().packet(), &whole[..]);
+
+        assert_eq!(fragment_ipv6(&packet, 1056, 0).unwrap().len(), 1);
+        assert_eq!(fragment_ipv6(&packet, 63, 0).err(), Some(FragmentErr::MtuTooSmall));
+    }
+}
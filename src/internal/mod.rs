@@ -28,6 +28,20 @@ pub use self::windows::*;
 
 mod native;
 
+/// How often threads receiving in the background check whether they should stop
+pub const POLL_INTERVAL_MS: u64 = 100;
+
+/// Whether a thread receiving in the background should carry on after an error
+pub fn is_transient(err: &io::Error) -> bool {
+    match err.kind() {
+        io::ErrorKind::TimedOut |
+        io::ErrorKind::WouldBlock |
+        io::ErrorKind::Interrupted => true,
+        // Transport channel iterators report packets too short for their header this way
+        io::ErrorKind::InvalidData => true,
+        _ => false,
+    }
+}
 
 // Any file descriptor on unix, only sockets on Windows.
 pub struct FileDesc {
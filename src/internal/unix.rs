@@ -1,6 +1,8 @@
 extern crate libc;
 
 use std::io;
+use std::mem;
+use std::ptr;
 use std::time::Duration;
 
 fn errno() -> i32 {
@@ -20,9 +22,94 @@ pub fn retry<F>(f: &mut F) -> libc::ssize_t
     }
 }
 
+pub fn recv_msg(socket: libc::c_int,
+                msg: &mut libc::msghdr,
+                flags: libc::c_int)
+    -> io::Result<usize> {
+    let len = retry(&mut || unsafe { libc::recvmsg(socket, msg, flags) });
+
+    if len < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(len as usize)
+    }
+}
+
 pub fn duration_to_timespec(dur: Duration) -> libc::timespec {
     libc::timespec {
         tv_sec: dur.as_secs() as libc::time_t,
         tv_nsec: dur.subsec_nanos() as libc::c_long,
     }
 }
+
+/// Block until the socket can be read from, or the timeout expires
+pub fn wait_readable(socket: libc::c_int, timeout: Duration) -> io::Result<()> {
+    select(socket, false, timeout)
+}
+
+/// Block until the socket can be written to, or the timeout expires
+pub fn wait_writable(socket: libc::c_int, timeout: Duration) -> io::Result<()> {
+    select(socket, true, timeout)
+}
+
+/// Block until an error is pending on the socket, or the timeout expires
+pub fn wait_error(socket: libc::c_int, timeout: Option<Duration>) -> io::Result<()> {
+    // Errors are always reported by poll(), so no events need to be requested
+    let mut pollfd = libc::pollfd {
+        fd: socket,
+        events: 0,
+        revents: 0,
+    };
+    let timeout = match timeout {
+        Some(timeout) => {
+            // Round up, so that short timeouts don't become a busy loop
+            let nanos = timeout.subsec_nanos() as u64;
+            let millis = timeout.as_secs()
+                .saturating_mul(1000)
+                .saturating_add((nanos + 999_999) / 1_000_000);
+            if millis > libc::c_int::max_value() as u64 {
+                libc::c_int::max_value()
+            } else {
+                millis as libc::c_int
+            }
+        }
+        None => -1,
+    };
+    let ret = retry(&mut || unsafe { libc::poll(&mut pollfd, 1, timeout) as libc::ssize_t });
+
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else if ret == 0 {
+        Err(io::Error::new(io::ErrorKind::TimedOut, "Timed out"))
+    } else {
+        Ok(())
+    }
+}
+
+fn select(socket: libc::c_int, write: bool, timeout: Duration) -> io::Result<()> {
+    let timeout = duration_to_timespec(timeout);
+    let ret = retry(&mut || unsafe {
+        let mut fd_set: libc::fd_set = mem::zeroed();
+        libc::FD_ZERO(&mut fd_set);
+        libc::FD_SET(socket, &mut fd_set);
+        let (read_fds, write_fds) = if write {
+            (ptr::null_mut(), &mut fd_set as *mut libc::fd_set)
+        } else {
+            (&mut fd_set as *mut libc::fd_set, ptr::null_mut())
+        };
+        libc::pselect(socket + 1,
+                      read_fds,
+                      write_fds,
+                      ptr::null_mut(),
+                      &timeout,
+                      ptr::null()) as libc::ssize_t
+    });
+
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else if ret == 0 {
+        Err(io::Error::new(io::ErrorKind::TimedOut, "Timed out"))
+    } else {
+        Ok(())
+    }
+}
@@ -3,6 +3,8 @@ extern crate winapi;
 extern crate ws2_32;
 
 use std::io;
+use std::mem;
+use std::ptr;
 use std::time::Duration;
 
 fn errno() -> i32 {
@@ -21,3 +23,40 @@ pub fn retry<F>(f: &mut F) -> libc::c_int
         }
     }
 }
+
+/// Block until the socket can be read from, or the timeout expires
+pub fn wait_readable(socket: winapi::SOCKET, timeout: Duration) -> io::Result<()> {
+    select(socket, false, timeout)
+}
+
+/// Block until the socket can be written to, or the timeout expires
+pub fn wait_writable(socket: winapi::SOCKET, timeout: Duration) -> io::Result<()> {
+    select(socket, true, timeout)
+}
+
+fn select(socket: winapi::SOCKET, write: bool, timeout: Duration) -> io::Result<()> {
+    let timeout = winapi::timeval {
+        tv_sec: timeout.as_secs() as libc::c_long,
+        tv_usec: (timeout.subsec_nanos() / 1000) as libc::c_long,
+    };
+    let ret = retry(&mut || unsafe {
+        let mut fd_set: winapi::fd_set = mem::zeroed();
+        fd_set.fd_count = 1;
+        fd_set.fd_array[0] = socket;
+        let (read_fds, write_fds) = if write {
+            (ptr::null_mut(), &mut fd_set as *mut winapi::fd_set)
+        } else {
+            (&mut fd_set as *mut winapi::fd_set, ptr::null_mut())
+        };
+        // The first argument is ignored by winsock
+        ws2_32::select(0, read_fds, write_fds, ptr::null_mut(), &timeout)
+    });
+
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else if ret == 0 {
+        Err(io::Error::new(io::ErrorKind::TimedOut, "Timed out"))
+    } else {
+        Ok(())
+    }
+}
@@ -104,8 +104,6 @@
 
 #![deny(missing_docs)]
 
-#![cfg_attr(feature = "nightly", feature(custom_attribute, plugin))]
-#![cfg_attr(feature = "nightly", plugin(pnet_macros_plugin))]
 #![cfg_attr(feature = "clippy", feature(plugin))]
 #![cfg_attr(feature = "benchmark", feature(test))]
 #![cfg_attr(feature="clippy", plugin(clippy))]
@@ -117,10 +115,21 @@ extern crate test;
 
 extern crate libc;
 extern crate winapi;
+#[macro_use]
+extern crate pnet_macros;
 extern crate pnet_macros_support;
 
+pub mod arp;
+pub mod builder;
 pub mod datalink;
+pub mod dissect;
+pub mod flow;
+pub mod fragment;
 pub mod packet;
+pub mod ping;
+pub mod reassembly;
+pub mod scan;
+pub mod traceroute;
 pub mod transport;
 pub mod util;
 
@@ -1,4 +1,4 @@
-// Copyright (c) 2015 Robert Clipsham <robert@octarineparrot.com>
+// Copyright (c) 2016 Robert Collins <robertc@robertcollins.net>
 //
 // Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
 // http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
@@ -9,8 +9,183 @@
 //! Minimal GRE Packet implementation: suitable for inspection not generation (e.g. checksum not
 //! implemented).
 
-#[cfg(feature = "with-syntex")]
-include!(concat!(env!("OUT_DIR"), "/gre.rs"));
+#[cfg(test)]
+use packet::Packet;
+use packet::PacketError;
 
-#[cfg(not(feature = "with-syntex"))]
-include!("gre.rs.in");
+use pnet_macros_support::types::*;
+
+/// GRE (Generic Routing Encapsulation) Packet.
+///
+/// See RFCs 1701, 2784, 2890, 7676, 2637
+///
+/// Current status of implementation:
+///
+/// - [RFC 1701](https://tools.ietf.org/html/rfc1701) except for source routing and checksums.
+///   Processing a source routed packet will panic. Checksums are able to be inspected, but not
+///   calculated or verified.
+///
+/// - [RFC 2784](https://tools.ietf.org/html/rfc2784) except for checksums (same as 1701 status).
+///   Note that it is possible to generate noncompliant packets by setting any of the reserved bits
+///   (but see 2890).
+///
+/// - [RFC 2890](https://tools.ietf.org/html/rfc2890) implemented
+///
+/// - [RFC 7676](https://tools.ietf.org/html/rfc7676) has no packet changes - compliance is up to
+///   the user
+///
+/// - [RFC 2637](https://tools.ietf.org/html/rfc2637) not implemented.
+///
+/// Note that routing information from RFC 1701 is not implemented, packets
+/// with routing_present true will currently cause a panic.
+#[derive(Clone, Debug, Packet)]
+#[validate_fn = "gre_validate"]
+pub struct Gre {
+    checksum_present: u1,
+    routing_present: u1,
+    key_present: u1,
+    sequence_present: u1,
+    strict_source_route: u1,
+    recursion_control: u3,
+    zero_flags: u5,
+    version: u3,
+    protocol_type: u16be, // 0x800 for ipv4 [basically an ethertype
+    #[present_if = "checksum_present == 1 || routing_present == 1"]
+    checksum: Option<u16be>,
+    #[present_if = "checksum_present == 1 || routing_present == 1"]
+    offset: Option<u16be>,
+    #[present_if = "key_present == 1"]
+    key: Option<u32be>,
+    #[present_if = "sequence_present == 1"]
+    sequence: Option<u32be>,
+    #[length_fn = "gre_routing_length"]
+    routing: Vec<u8>,
+    #[payload]
+    payload: Vec<u8>,
+}
+
+/// Source routed packets aren't supported, so are rejected rather than causing a panic
+fn gre_validate(gre: &GrePacket) -> Result<(), PacketError> {
+    if gre.get_routing_present() != 0 {
+        Err(PacketError::InvalidValue { field: "routing_present" })
+    } else {
+        Ok(())
+    }
+}
+
+fn gre_routing_length(gre: &GrePacket) -> usize {
+    if 0 == gre.get_routing_present() {
+        0
+    } else {
+        panic!("Source routed GRE packets not supported")
+    }
+}
+
+#[test]
+fn gre_packet_test() {
+    let mut packet = [0u8; 4];
+    {
+        let mut gre_packet = MutableGrePacket::new(&mut packet[..]).unwrap();
+        gre_packet.set_protocol_type(0x0800);
+        assert_eq!(gre_packet.payload().len(), 0);
+    }
+
+    let ref_packet = [0x00 /* no flags */,
+                      0x00 /* no flags, version 0 */,
+                      0x08 /* protocol 0x0800 */,
+                      0x00];
+
+    assert_eq!(&ref_packet[..], &packet[..]);
+}
+
+#[test]
+fn gre_new_checked_test() {
+    // Source routing isn't supported, and would otherwise panic
+    assert_eq!(GrePacket::new_checked(&[0x40, 0, 0x08, 0, 0, 0, 0, 0]).err(),
+               Some(PacketError::InvalidValue { field: "routing_present" }));
+    // Checksum present, but no room for it
+    assert_eq!(GrePacket::new_checked(&[0x80, 0, 0x08, 0]).err(),
+               Some(PacketError::Truncated {
+                   field: "checksum",
+                   expected: 6,
+                   available: 4,
+               }));
+    assert!(GrePacket::new_checked(&[0x80, 0, 0x08, 0, 0, 0, 0, 0]).is_ok());
+    // Key present, but only half of it is there
+    assert_eq!(GrePacket::new_checked(&[0x30, 0, 0x08, 0, 1, 2]).err(),
+               Some(PacketError::Truncated {
+                   field: "key",
+                   expected: 8,
+                   available: 6,
+               }));
+}
+
+#[test]
+fn gre_truncated_test() {
+    // Fields which don't fit in the packet are treated as missing, rather than panicking
+    let packet = GrePacket::new(&[0x80, 0, 0x08, 0]).unwrap();
+    assert_eq!(packet.get_checksum(), None);
+    assert_eq!(packet.get_offset(), None);
+    let _ = format!("{:?}", packet);
+    let packet = GrePacket::new(&[0x30, 0, 0x08, 0, 1, 2]).unwrap();
+    assert_eq!(packet.get_key(), None);
+    assert_eq!(packet.get_sequence(), None);
+    let _ = format!("{:?}", packet);
+}
+
+#[test]
+fn gre_checksum_test() {
+    let mut packet = [0u8; 8];
+    {
+        let mut gre_packet = MutableGrePacket::new(&mut packet[..]).unwrap();
+        gre_packet.set_checksum_present(1);
+        assert_eq!(gre_packet.payload().len(), 0);
+        assert_eq!(gre_packet.get_checksum(), Some(0));
+        assert_eq!(gre_packet.get_offset(), Some(0));
+        assert_eq!(gre_packet.get_key(), None);
+    }
+
+    let ref_packet = [0x80 /* checksum on */,
+                      0x00 /* no flags, version 0 */,
+                      0x00 /* protocol 0x0000 */,
+                      0x00,
+                      0x00 /* 16 bits of checksum */,
+                      0x00,
+                      0x00 /* 16 bits of offset */,
+                      0x00];
+
+    assert_eq!(&ref_packet[..], &packet[..]);
+}
+
+#[test]
+fn gre_key_and_sequence_test() {
+    let mut packet = [0u8; 16];
+    {
+        let mut gre_packet = MutableGrePacket::new(&mut packet[..]).unwrap();
+        gre_packet.set_key_present(1);
+        gre_packet.set_sequence_present(1);
+        gre_packet.set_protocol_type(0x6558);
+        gre_packet.set_key(Some(0x01020304));
+        gre_packet.set_sequence(Some(0x05060708));
+        // Absent fields take no space, so the key directly follows the protocol type
+        assert_eq!(gre_packet.get_checksum(), None);
+        assert_eq!(gre_packet.get_key(), Some(0x01020304));
+        assert_eq!(gre_packet.get_sequence(), Some(0x05060708));
+        assert_eq!(gre_packet.payload().len(), 4);
+    }
+
+    let ref_packet = [0x30 /* key and sequence on */,
+                      0x00 /* no flags, version 0 */,
+                      0x65 /* protocol 0x6558 */,
+                      0x58,
+                      0x01 /* 32 bits of key */,
+                      0x02,
+                      0x03,
+                      0x04,
+                      0x05 /* 32 bits of sequence */,
+                      0x06,
+                      0x07,
+                      0x08];
+
+    assert_eq!(&ref_packet[..], &packet[..12]);
+}
@@ -0,0 +1,418 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! ICMPv6 packet abstraction
+
+use packet::PrimitiveValues;
+use pnet_macros_support::types::*;
+use std::net::Ipv6Addr;
+
+/// Represents the "ICMPv6 type" header field.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Icmpv6Type(pub u8);
+
+impl Icmpv6Type {
+    /// Create an ICMPv6 type
+    pub fn new(val: u8) -> Icmpv6Type {
+        Icmpv6Type(val)
+    }
+}
+
+impl PrimitiveValues for Icmpv6Type {
+    type T = (u8,);
+    fn to_primitive_values(&self) -> (u8,) {
+        (self.0,)
+    }
+}
+
+/// Represents the "ICMPv6 code" header field.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Icmpv6Code(pub u8);
+
+impl Icmpv6Code {
+    /// Create an ICMPv6 code
+    pub fn new(val: u8) -> Icmpv6Code {
+        Icmpv6Code(val)
+    }
+}
+
+impl PrimitiveValues for Icmpv6Code {
+    type T = (u8,);
+    fn to_primitive_values(&self) -> (u8,) {
+        (self.0,)
+    }
+}
+
+/// Represents a generic ICMPv6 packet
+#[derive(Clone, Debug, Packet)]
+pub struct Icmpv6 {
+    #[construct_with(u8)]
+    icmpv6_type: Icmpv6Type,
+    #[construct_with(u8)]
+    icmpv6_code: Icmpv6Code,
+    checksum: u16be,
+    // As with ICMP, the rest of the header depends on the type and code, so is treated as part of
+    // the payload.
+    #[payload]
+    payload: Vec<u8>,
+}
+
+/// Calculates the checksum of an ICMPv6 packet. Unlike ICMP, this covers an IPv6 pseudo-header.
+pub fn checksum(packet: &Icmpv6Packet, source: Ipv6Addr, destination: Ipv6Addr) -> u16be {
+    use packet::Packet;
+    use packet::ip::IpNextHeaderProtocols;
+    use util;
+
+    util::ipv6_checksum(packet.packet(),
+                        1,
+                        &[],
+                        source,
+                        destination,
+                        IpNextHeaderProtocols::Ipv6Icmp)
+}
+
+#[test]
+fn icmpv6_checksum_test() {
+    use packet::Packet;
+
+    let mut data = [0u8; 12];
+    let localhost = Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1);
+    {
+        let mut echo = echo_request::MutableEchoRequestPacket::new(&mut data[..]).unwrap();
+        echo.set_icmpv6_type(Icmpv6Types::EchoRequest);
+        echo.set_identifier(0x1234);
+        echo.set_sequence_number(1);
+        echo.set_payload(b"ping");
+    }
+    let mut packet = MutableIcmpv6Packet::new(&mut data[..]).unwrap();
+    assert_eq!(checksum(&packet.to_immutable(), localhost, localhost), 0x8eb1);
+    packet.set_checksum(0x8eb1);
+    assert_eq!(checksum(&packet.to_immutable(), localhost, localhost), 0x8eb1);
+    assert_eq!(packet.packet()[2..4], [0x8e, 0xb1]);
+}
+
+/// Enumeration of the recognized ICMPv6 types
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod Icmpv6Types {
+
+    use packet::icmpv6::Icmpv6Type;
+    /// ICMPv6 type for "destination unreachable" packet
+    pub const DestinationUnreachable: Icmpv6Type = Icmpv6Type(1);
+    /// ICMPv6 type for "packet too big" packet
+    pub const PacketTooBig: Icmpv6Type = Icmpv6Type(2);
+    /// ICMPv6 type for "time exceeded" packet
+    pub const TimeExceeded: Icmpv6Type = Icmpv6Type(3);
+    /// ICMPv6 type for "parameter problem" packet
+    pub const ParameterProblem: Icmpv6Type = Icmpv6Type(4);
+    /// ICMPv6 type for "echo request" packet
+    pub const EchoRequest: Icmpv6Type = Icmpv6Type(128);
+    /// ICMPv6 type for "echo reply" packet
+    pub const EchoReply: Icmpv6Type = Icmpv6Type(129);
+    /// ICMPv6 type for "router solicitation" packet
+    pub const RouterSolicitation: Icmpv6Type = Icmpv6Type(133);
+    /// ICMPv6 type for "router advertisement" packet
+    pub const RouterAdvertisement: Icmpv6Type = Icmpv6Type(134);
+    /// ICMPv6 type for "neighbor solicitation" packet
+    pub const NeighborSolicitation: Icmpv6Type = Icmpv6Type(135);
+    /// ICMPv6 type for "neighbor advertisement" packet
+    pub const NeighborAdvertisement: Icmpv6Type = Icmpv6Type(136);
+    /// ICMPv6 type for "redirect" packet
+    pub const Redirect: Icmpv6Type = Icmpv6Type(137);
+}
+
+/// abstraction for "echo reply" ICMPv6 packets.
+pub mod echo_reply {
+    use packet::icmpv6::{Icmpv6Code, Icmpv6Type};
+    use pnet_macros_support::types::*;
+
+    /// Represents an "echo reply" ICMPv6 packet.
+    #[derive(Clone, Debug, Packet)]
+    pub struct EchoReply {
+        #[construct_with(u8)]
+        icmpv6_type: Icmpv6Type,
+        #[construct_with(u8)]
+        icmpv6_code: Icmpv6Code,
+        checksum: u16be,
+        identifier: u16be,
+        sequence_number: u16be,
+        #[payload]
+        payload: Vec<u8>,
+    }
+}
+
+/// abstraction for "echo request" ICMPv6 packets.
+pub mod echo_request {
+    use packet::icmpv6::{Icmpv6Code, Icmpv6Type};
+    use pnet_macros_support::types::*;
+
+    /// Represents an "echo request" ICMPv6 packet.
+    #[derive(Clone, Debug, Packet)]
+    pub struct EchoRequest {
+        #[construct_with(u8)]
+        icmpv6_type: Icmpv6Type,
+        #[construct_with(u8)]
+        icmpv6_code: Icmpv6Code,
+        checksum: u16be,
+        identifier: u16be,
+        sequence_number: u16be,
+        #[payload]
+        payload: Vec<u8>,
+    }
+}
+
+/// abstraction for "destination unreachable" ICMPv6 packets.
+pub mod destination_unreachable {
+    use packet::icmpv6::{Icmpv6Code, Icmpv6Type};
+    use pnet_macros_support::types::*;
+
+    /// Enumeration of the recognized ICMPv6 codes for "destination unreachable" ICMPv6 packets.
+    #[allow(non_snake_case)]
+    #[allow(non_upper_case_globals)]
+    pub mod Icmpv6Codes {
+        use packet::icmpv6::Icmpv6Code;
+        /// ICMPv6 code for "no route to destination" packet
+        pub const NoRouteToDestination: Icmpv6Code = Icmpv6Code(0);
+        /// ICMPv6 code for "communication with destination administratively prohibited" packet
+        pub const AdministrativelyProhibited: Icmpv6Code = Icmpv6Code(1);
+        /// ICMPv6 code for "beyond scope of source address" packet
+        pub const BeyondScopeOfSourceAddress: Icmpv6Code = Icmpv6Code(2);
+        /// ICMPv6 code for "address unreachable" packet
+        pub const AddressUnreachable: Icmpv6Code = Icmpv6Code(3);
+        /// ICMPv6 code for "port unreachable" packet
+        pub const PortUnreachable: Icmpv6Code = Icmpv6Code(4);
+        /// ICMPv6 code for "source address failed ingress/egress policy" packet
+        pub const SourceAddressFailedPolicy: Icmpv6Code = Icmpv6Code(5);
+        /// ICMPv6 code for "reject route to destination" packet
+        pub const RejectRouteToDestination: Icmpv6Code = Icmpv6Code(6);
+    }
+
+    /// Represents a "destination unreachable" ICMPv6 packet.
+    #[derive(Clone, Debug, Packet)]
+    pub struct DestinationUnreachable {
+        #[construct_with(u8)]
+        icmpv6_type: Icmpv6Type,
+        #[construct_with(u8)]
+        icmpv6_code: Icmpv6Code,
+        checksum: u16be,
+        unused: u32be,
+        #[payload]
+        payload: Vec<u8>,
+    }
+}
+
+/// abstraction for "time exceeded" ICMPv6 packets.
+pub mod time_exceeded {
+    use packet::icmpv6::{Icmpv6Code, Icmpv6Type};
+    use pnet_macros_support::types::*;
+
+    /// Enumeration of the recognized ICMPv6 codes for "time exceeded" ICMPv6 packets.
+    #[allow(non_snake_case)]
+    #[allow(non_upper_case_globals)]
+    pub mod Icmpv6Codes {
+        use packet::icmpv6::Icmpv6Code;
+        /// ICMPv6 code for "hop limit exceeded in transit" packet
+        pub const HopLimitExceededInTransit: Icmpv6Code = Icmpv6Code(0);
+        /// ICMPv6 code for "fragment reassembly time exceeded" packet
+        pub const FragmentReassemblyTimeExceeded: Icmpv6Code = Icmpv6Code(1);
+    }
+
+    /// Represents a "time exceeded" ICMPv6 packet.
+    #[derive(Clone, Debug, Packet)]
+    pub struct TimeExceeded {
+        #[construct_with(u8)]
+        icmpv6_type: Icmpv6Type,
+        #[construct_with(u8)]
+        icmpv6_code: Icmpv6Code,
+        checksum: u16be,
+        unused: u32be,
+        #[payload]
+        payload: Vec<u8>,
+    }
+}
+
+/// abstraction for the Neighbor Discovery Protocol (NDP) ICMPv6 packets used to find the link-layer
+/// addresses of neighbors, as described in RFC 4861.
+pub mod ndp {
+    use packet::{Packet, PrimitiveValues};
+    use packet::icmpv6::{Icmpv6Code, Icmpv6Type};
+    use pnet_macros_support::types::*;
+    use std::net::Ipv6Addr;
+    use util::MacAddr;
+
+    /// Represents the "type" field of an NDP option.
+    #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct NdpOptionType(pub u8);
+
+    impl NdpOptionType {
+        /// Create an NDP option type
+        pub fn new(val: u8) -> NdpOptionType {
+            NdpOptionType(val)
+        }
+    }
+
+    impl PrimitiveValues for NdpOptionType {
+        type T = (u8,);
+        fn to_primitive_values(&self) -> (u8,) {
+            (self.0,)
+        }
+    }
+
+    /// Enumeration of the recognized NDP option types
+    #[allow(non_snake_case)]
+    #[allow(non_upper_case_globals)]
+    pub mod NdpOptionTypes {
+        use super::NdpOptionType;
+        /// NDP option type for the "source link-layer address" option
+        pub const SourceLLAddr: NdpOptionType = NdpOptionType(1);
+        /// NDP option type for the "target link-layer address" option
+        pub const TargetLLAddr: NdpOptionType = NdpOptionType(2);
+        /// NDP option type for the "prefix information" option
+        pub const PrefixInformation: NdpOptionType = NdpOptionType(3);
+        /// NDP option type for the "redirected header" option
+        pub const RedirectedHeader: NdpOptionType = NdpOptionType(4);
+        /// NDP option type for the "MTU" option
+        pub const Mtu: NdpOptionType = NdpOptionType(5);
+    }
+
+    /// Represents an NDP option. The length is in units of 8 octets, and includes the type and
+    /// length fields.
+    #[derive(Clone, Debug, Packet)]
+    pub struct NdpOption {
+        #[construct_with(u8)]
+        option_type: NdpOptionType,
+        length: u8,
+        #[length_fn = "ndp_option_payload_length"]
+        #[payload]
+        data: Vec<u8>,
+    }
+
+    impl NdpOption {
+        /// Source link-layer address: the link-layer address of the sender of a solicitation.
+        pub fn source_ll_addr(mac: MacAddr) -> Self {
+            NdpOption {
+                option_type: NdpOptionTypes::SourceLLAddr,
+                length: 1,
+                data: vec![mac.0, mac.1, mac.2, mac.3, mac.4, mac.5],
+            }
+        }
+
+        /// Target link-layer address: the link-layer address of the target of an advertisement.
+        pub fn target_ll_addr(mac: MacAddr) -> Self {
+            NdpOption {
+                option_type: NdpOptionTypes::TargetLLAddr,
+                length: 1,
+                data: vec![mac.0, mac.1, mac.2, mac.3, mac.4, mac.5],
+            }
+        }
+    }
+
+    fn ndp_option_payload_length(option: &NdpOptionPacket) -> usize {
+        (option.get_length() as usize * 8).saturating_sub(2)
+    }
+
+    /// Represents a "neighbor solicitation" ICMPv6 packet.
+    #[derive(Clone, Debug, Packet)]
+    pub struct NeighborSolicit {
+        #[construct_with(u8)]
+        icmpv6_type: Icmpv6Type,
+        #[construct_with(u8)]
+        icmpv6_code: Icmpv6Code,
+        checksum: u16be,
+        reserved: u32be,
+        #[construct_with(u16, u16, u16, u16, u16, u16, u16, u16)]
+        target_addr: Ipv6Addr,
+        #[length_fn = "neighbor_solicit_options_length"]
+        options: Vec<NdpOption>,
+        #[payload]
+        #[length = "0"]
+        payload: Vec<u8>,
+    }
+
+    /// The options of a neighbor solicitation take up the rest of the packet
+    fn neighbor_solicit_options_length(packet: &NeighborSolicitPacket) -> usize {
+        packet.packet().len().saturating_sub(24)
+    }
+
+    /// The flags of a "neighbor advertisement" ICMPv6 packet
+    #[allow(non_snake_case)]
+    #[allow(non_upper_case_globals)]
+    pub mod NeighborAdvertFlags {
+        /// Set when the sender is a router
+        pub const Router: u8 = 0b1000_0000;
+        /// Set when the advertisement was sent in response to a neighbor solicitation
+        pub const Solicited: u8 = 0b0100_0000;
+        /// Set when the advertisement should override an existing cache entry
+        pub const Override: u8 = 0b0010_0000;
+    }
+
+    /// Represents a "neighbor advertisement" ICMPv6 packet.
+    #[derive(Clone, Debug, Packet)]
+    pub struct NeighborAdvert {
+        #[construct_with(u8)]
+        icmpv6_type: Icmpv6Type,
+        #[construct_with(u8)]
+        icmpv6_code: Icmpv6Code,
+        checksum: u16be,
+        flags: u8,
+        reserved: u24be,
+        #[construct_with(u16, u16, u16, u16, u16, u16, u16, u16)]
+        target_addr: Ipv6Addr,
+        #[length_fn = "neighbor_advert_options_length"]
+        options: Vec<NdpOption>,
+        #[payload]
+        #[length = "0"]
+        payload: Vec<u8>,
+    }
+
+    /// The options of a neighbor advertisement take up the rest of the packet
+    fn neighbor_advert_options_length(packet: &NeighborAdvertPacket) -> usize {
+        packet.packet().len().saturating_sub(24)
+    }
+
+    #[test]
+    fn neighbor_solicit_test() {
+        use packet::icmpv6::Icmpv6Types;
+
+        let mut packet = [0u8; 32];
+        let target = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        {
+            let mut solicit = MutableNeighborSolicitPacket::new(&mut packet[..]).unwrap();
+            solicit.set_icmpv6_type(Icmpv6Types::NeighborSolicitation);
+            solicit.set_target_addr(target);
+            solicit.set_options(&[NdpOption::source_ll_addr(MacAddr::new(1, 2, 3, 4, 5, 6))]);
+        }
+
+        let solicit = NeighborSolicitPacket::new(&packet[..]).unwrap();
+        assert_eq!(solicit.get_target_addr(), target);
+        let options = solicit.get_options();
+        assert_eq!(options.len(), 1);
+        assert_eq!(options[0].option_type, NdpOptionTypes::SourceLLAddr);
+        assert_eq!(options[0].data, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(solicit.payload(), &[]);
+        assert_eq!(&packet[..8], &[135, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&packet[24..], &[1, 1, 1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn neighbor_advert_test() {
+        let packet = [136, 0, 0, 0, 0x60, 0, 0, 0,
+                      0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2,
+                      2, 1, 6, 5, 4, 3, 2, 1];
+        let advert = NeighborAdvertPacket::new(&packet[..]).unwrap();
+        assert_eq!(advert.get_flags(),
+                   NeighborAdvertFlags::Solicited | NeighborAdvertFlags::Override);
+        assert_eq!(advert.get_target_addr(),
+                   Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 2));
+        let options = advert.get_options();
+        assert_eq!(options.len(), 1);
+        assert_eq!(options[0].option_type, NdpOptionTypes::TargetLLAddr);
+        assert_eq!(options[0].length, 1);
+        assert_eq!(options[0].data, vec![6, 5, 4, 3, 2, 1]);
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! IPFIX packet abstraction
+
+use packet::Packet;
+use pnet_macros_support::types::*;
+
+use std::cmp;
+
+/// The version number of IPFIX messages
+pub const VERSION: u16 = 10;
+
+/// The set ID of template sets
+pub const TEMPLATE_SET_ID: u16 = 2;
+
+/// The set ID of options template sets. Data sets have IDs of 256 and above, matching the ID of
+/// their template.
+pub const OPTIONS_TEMPLATE_SET_ID: u16 = 3;
+
+/// The field length used in templates for fields whose length is given in each data record
+pub const VARIABLE_LENGTH: u16 = 65535;
+
+/// The IDs of some IANA assigned information elements, as defined in RFC 7012 and the
+/// [IANA registry](https://www.iana.org/assignments/ipfix/ipfix.xhtml)
+#[allow(non_snake_case)]
+#[allow(non_upper_case_globals)]
+pub mod InformationElements {
+    /// octetDeltaCount
+    pub const OctetDeltaCount: u16 = 1;
+    /// packetDeltaCount
+    pub const PacketDeltaCount: u16 = 2;
+    /// protocolIdentifier
+    pub const ProtocolIdentifier: u16 = 4;
+    /// ipClassOfService
+    pub const IpClassOfService: u16 = 5;
+    /// tcpControlBits
+    pub const TcpControlBits: u16 = 6;
+    /// sourceTransportPort
+    pub const SourceTransportPort: u16 = 7;
+    /// sourceIPv4Address
+    pub const SourceIpv4Address: u16 = 8;
+    /// sourceIPv4PrefixLength
+    pub const SourceIpv4PrefixLength: u16 = 9;
+    /// ingressInterface
+    pub const IngressInterface: u16 = 10;
+    /// destinationTransportPort
+    pub const DestinationTransportPort: u16 = 11;
+    /// destinationIPv4Address
+    pub const DestinationIpv4Address: u16 = 12;
+    /// destinationIPv4PrefixLength
+    pub const DestinationIpv4PrefixLength: u16 = 13;
+    /// egressInterface
+    pub const EgressInterface: u16 = 14;
+    /// ipNextHopIPv4Address
+    pub const IpNextHopIpv4Address: u16 = 15;
+    /// bgpSourceAsNumber
+    pub const BgpSourceAsNumber: u16 = 16;
+    /// bgpDestinationAsNumber
+    pub const BgpDestinationAsNumber: u16 = 17;
+    /// flowEndSysUpTime
+    pub const FlowEndSysUpTime: u16 = 21;
+    /// flowStartSysUpTime
+    pub const FlowStartSysUpTime: u16 = 22;
+    /// sourceIPv6Address
+    pub const SourceIpv6Address: u16 = 27;
+    /// destinationIPv6Address
+    pub const DestinationIpv6Address: u16 = 28;
+    /// flowEndReason
+    pub const FlowEndReason: u16 = 136;
+    /// flowStartMilliseconds
+    pub const FlowStartMilliseconds: u16 = 152;
+    /// flowEndMilliseconds
+    pub const FlowEndMilliseconds: u16 = 153;
+}
+
+/// Represents an IPFIX message, which contains sets
+#[derive(Clone, Debug, Packet)]
+pub struct Ipfix {
+    version: u16be,
+    // The length of the message in bytes, including this header
+    length: u16be,
+    // Seconds since the Unix epoch
+    export_time: u32be,
+    // The number of data records sent in the observation domain before this message
+    sequence_number: u32be,
+    observation_domain_id: u32be,
+    #[length_fn = "ipfix_payload_length"]
+    #[payload]
+    payload: Vec<u8>,
+}
+
+fn ipfix_payload_length(message: &IpfixPacket) -> usize {
+    (message.get_length() as usize).saturating_sub(16)
+}
+
+/// Represents an IPFIX set of template, options template or data records. The length includes
+/// the header and any padding.
+#[derive(Clone, Debug, Packet)]
+pub struct Set {
+    set_id: u16be,
+    length: u16be,
+    #[length_fn = "set_payload_length"]
+    #[payload]
+    payload: Vec<u8>,
+}
+
+fn set_payload_length(set: &SetPacket) -> usize {
+    (set.get_length() as usize).saturating_sub(4)
+}
+
+/// Represents an IPFIX template record, which describes the fields of the data records with its
+/// template ID. A record with no fields withdraws the template.
+#[derive(Clone, Debug, Packet)]
+pub struct TemplateRecord {
+    template_id: u16be,
+    field_count: u16be,
+    #[length_fn = "template_record_fields_length"]
+    fields: Vec<FieldSpecifier>,
+    #[payload]
+    #[length = "0"]
+    payload: Vec<u8>,
+}
+
+fn template_record_fields_length(record: &TemplateRecordPacket) -> usize {
+    field_specifiers_length(&record.packet()[4..], record.get_field_count())
+}
+
+/// Represents an IPFIX options template record. The first `scope_field_count` fields say what the
+/// options apply to.
+#[derive(Clone, Debug, Packet)]
+pub struct OptionsTemplateRecord {
+    template_id: u16be,
+    field_count: u16be,
+    scope_field_count: u16be,
+    #[length_fn = "options_template_record_fields_length"]
+    fields: Vec<FieldSpecifier>,
+    #[payload]
+    #[length = "0"]
+    payload: Vec<u8>,
+}
+
+fn options_template_record_fields_length(record: &OptionsTemplateRecordPacket) -> usize {
+    field_specifiers_length(&record.packet()[6..], record.get_field_count())
+}
+
+/// The number of bytes taken up by `count` field specifiers at the start of `bytes`, which
+/// depends on how many have enterprise numbers
+fn field_specifiers_length(bytes: &[u8], count: u16) -> usize {
+    let mut length = 0;
+    for _ in 0..count {
+        if length >= bytes.len() {
+            break;
+        }
+        length += if bytes[length] & 0x80 != 0 { 8 } else { 4 };
+    }
+
+    cmp::min(length, bytes.len())
+}
+
+/// Represents the information element and length of a field of an IPFIX data record
+#[derive(Clone, Debug, Packet)]
+pub struct FieldSpecifier {
+    // Set if the information element is defined by an enterprise, rather than IANA
+    enterprise: u1,
+    information_element_id: u15be,
+    field_length: u16be,
+    // A big endian u32, present if the enterprise bit is set
+    #[length_fn = "field_specifier_enterprise_length"]
+    enterprise_number: Vec<u8>,
+    #[payload]
+    #[length = "0"]
+    payload: Vec<u8>,
+}
+
+fn field_specifier_enterprise_length(field: &FieldSpecifierPacket) -> usize {
+    field.get_enterprise() as usize * 4
+}
+
+impl FieldSpecifier {
+    /// A field holding an IANA assigned information element
+    pub fn new(information_element_id: u16, field_length: u16) -> FieldSpecifier {
+        FieldSpecifier {
+            enterprise: 0,
+            information_element_id: information_element_id,
+            field_length: field_length,
+            enterprise_number: vec![],
+            payload: vec![],
+        }
+    }
+
+    /// A field holding an information element defined by the given enterprise
+    pub fn enterprise(enterprise_number: u32,
+                      information_element_id: u16,
+                      field_length: u16)
+        -> FieldSpecifier {
+        FieldSpecifier {
+            enterprise: 1,
+            information_element_id: information_element_id,
+            field_length: field_length,
+            enterprise_number: vec![(enterprise_number >> 24) as u8,
+                                    (enterprise_number >> 16) as u8,
+                                    (enterprise_number >> 8) as u8,
+                                    enterprise_number as u8],
+            payload: vec![],
+        }
+    }
+}
+
+#[test]
+fn ipfix_test() {
+    let mut packet = [0u8; 16 + 4 + 4 + 12];
+    {
+        let mut message = MutableIpfixPacket::new(&mut packet[..]).unwrap();
+        message.set_version(VERSION);
+        message.set_length(36);
+        message.set_export_time(1500000000);
+        message.set_observation_domain_id(7);
+    }
+    {
+        let mut set = MutableSetPacket::new(&mut packet[16..]).unwrap();
+        set.set_set_id(TEMPLATE_SET_ID);
+        set.set_length(20);
+    }
+    {
+        let mut record = MutableTemplateRecordPacket::new(&mut packet[20..]).unwrap();
+        record.set_template_id(256);
+        record.set_field_count(2);
+    }
+    // The length of the fields depends on their enterprise bits, so they are set one at a time
+    MutableFieldSpecifierPacket::new(&mut packet[24..])
+        .unwrap()
+        .populate(&FieldSpecifier::new(InformationElements::OctetDeltaCount, 8));
+    MutableFieldSpecifierPacket::new(&mut packet[28..])
+        .unwrap()
+        .populate(&FieldSpecifier::enterprise(29305, 1, 8));
+    assert_eq!(&packet[20..], &[1, 0, 0, 2, 0, 1, 0, 8, 0x80, 1, 0, 8, 0, 0, 0x72, 0x79]);
+
+    let message = IpfixPacket::new(&packet[..]).unwrap();
+    assert_eq!(message.get_observation_domain_id(), 7);
+    let set = SetPacket::new(message.payload()).unwrap();
+    assert_eq!(set.get_set_id(), TEMPLATE_SET_ID);
+    let record = TemplateRecordPacket::new(set.payload()).unwrap();
+    let fields = record.get_fields();
+    assert_eq!(fields.len(), 2);
+    assert_eq!(fields[0].information_element_id, InformationElements::OctetDeltaCount);
+    assert_eq!(fields[0].enterprise_number, vec![]);
+    assert_eq!(fields[1].enterprise, 1);
+    assert_eq!(fields[1].enterprise_number, vec![0, 0, 0x72, 0x79]);
+    assert_eq!(record.payload(), &[]);
+
+    // Truncated records don't read past the end of the packet
+    let record = OptionsTemplateRecordPacket::new(&packet[20..30]).unwrap();
+    assert_eq!(record.get_fields_raw().len(), 4);
+}
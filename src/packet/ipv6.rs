@@ -1,4 +1,4 @@
-// Copyright (c) 2015 Robert Clipsham <robert@octarineparrot.com>
+// Copyright (c) 2014, 2015 Robert Clipsham <robert@octarineparrot.com>
 //
 // Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
 // http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
@@ -8,8 +8,178 @@
 
 //! IPv6 packet abstraction
 
-#[cfg(feature = "with-syntex")]
-include!(concat!(env!("OUT_DIR"), "/ipv6.rs"));
+use packet::Packet;
+use packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
 
-#[cfg(not(feature = "with-syntex"))]
-include!("ipv6.rs.in");
+use pnet_macros_support::types::*;
+
+use std::net::Ipv6Addr;
+
+/// Represents an IPv6 Packet
+#[derive(Clone, Debug, Packet)]
+pub struct Ipv6 {
+    version: u4,
+    traffic_class: u8,
+    flow_label: u20be,
+    payload_length: u16be,
+    #[construct_with(u8)]
+    next_header: IpNextHeaderProtocol,
+    hop_limit: u8,
+    #[construct_with(u16, u16, u16, u16, u16, u16, u16, u16)]
+    source: Ipv6Addr,
+    #[construct_with(u16, u16, u16, u16, u16, u16, u16, u16)]
+    destination: Ipv6Addr,
+    #[payload]
+    payload: Vec<u8>,
+}
+
+#[test]
+fn ipv6_header_test() {
+    let mut packet = [0u8; 40];
+    {
+        let mut ip_header = MutableIpv6Packet::new(&mut packet[..]).unwrap();
+        ip_header.set_version(6);
+        assert_eq!(ip_header.get_version(), 6);
+
+        ip_header.set_traffic_class(17);
+        assert_eq!(ip_header.get_traffic_class(), 17);
+
+        ip_header.set_flow_label(0x10101);
+        assert_eq!(ip_header.get_flow_label(), 0x10101);
+
+        ip_header.set_payload_length(0x0101);
+        assert_eq!(ip_header.get_payload_length(), 0x0101);
+
+        ip_header.set_next_header(IpNextHeaderProtocols::Udp);
+        assert_eq!(ip_header.get_next_header(), IpNextHeaderProtocols::Udp);
+
+        ip_header.set_hop_limit(1);
+        assert_eq!(ip_header.get_hop_limit(), 1);
+
+        let source = Ipv6Addr::new(0x110, 0x1001, 0x110, 0x1001, 0x110, 0x1001, 0x110, 0x1001);
+        ip_header.set_source(source);
+        assert_eq!(ip_header.get_source(), source);
+
+        let dest = Ipv6Addr::new(0x110, 0x1001, 0x110, 0x1001, 0x110, 0x1001, 0x110, 0x1001);
+        ip_header.set_destination(dest);
+        assert_eq!(ip_header.get_destination(), dest);
+    }
+
+    let ref_packet = [0x61,           /* ver/traffic class */
+                      0x11,           /* traffic class/flow label */
+                      0x01, 0x01,     /* flow label */
+                      0x01, 0x01,     /* payload length */
+                      0x11,           /* next header */
+                      0x01,           /* hop limit */
+                      /* source ip */
+                      0x01, 0x10, 0x10, 0x01,
+                      0x01, 0x10, 0x10, 0x01,
+                      0x01, 0x10, 0x10, 0x01,
+                      0x01, 0x10, 0x10, 0x01,
+                      /* dest ip */
+                      0x01, 0x10, 0x10, 0x01,
+                      0x01, 0x10, 0x10, 0x01,
+                      0x01, 0x10, 0x10, 0x01,
+                      0x01, 0x10, 0x10, 0x01];
+    assert_eq!(&ref_packet[..], &packet[..]);
+}
+
+/// Find the header following any Hop-by-Hop Options, Routing, Destination Options and
+/// Authentication headers at the start of an IPv6 packet's payload. Returns its protocol and its
+/// offset within the packet, or `None` if an extension header is truncated.
+///
+/// The walk stops at a Fragment header, since only the first fragment of a packet carries the
+/// upper-layer header.
+pub fn upper_layer(packet: &Ipv6Packet) -> Option<(IpNextHeaderProtocol, usize)> {
+    let buffer = packet.packet();
+    let mut next_header = packet.get_next_header();
+    let mut offset = Ipv6Packet::minimum_packet_size();
+    loop {
+        let header_len = match next_header {
+            IpNextHeaderProtocols::Hopopt |
+            IpNextHeaderProtocols::Ipv6Route |
+            IpNextHeaderProtocols::Ipv6Opts => {
+                if buffer.len() < offset + 2 {
+                    return None;
+                }
+                (buffer[offset + 1] as usize + 1) * 8
+            }
+            IpNextHeaderProtocols::Ah => {
+                if buffer.len() < offset + 2 {
+                    return None;
+                }
+                (buffer[offset + 1] as usize + 2) * 4
+            }
+            _ => return Some((next_header, offset)),
+        };
+        if buffer.len() < offset + header_len {
+            return None;
+        }
+        next_header = IpNextHeaderProtocol(buffer[offset]);
+        offset += header_len;
+    }
+}
+
+#[test]
+fn upper_layer_test() {
+    let mut packet = [0u8; 40 + 8 + 16 + 8 + 8];
+    {
+        let mut ip_header = MutableIpv6Packet::new(&mut packet[..]).unwrap();
+        ip_header.set_version(6);
+        ip_header.set_next_header(IpNextHeaderProtocols::Hopopt);
+    }
+    // Hop-by-Hop Options, then a 16 byte Routing header, then Destination Options
+    packet[40] = IpNextHeaderProtocols::Ipv6Route.0;
+    packet[48] = IpNextHeaderProtocols::Ipv6Opts.0;
+    packet[49] = 1;
+    packet[64] = IpNextHeaderProtocols::Udp.0;
+    assert_eq!(upper_layer(&Ipv6Packet::new(&packet[..]).unwrap()),
+               Some((IpNextHeaderProtocols::Udp, 72)));
+
+    // Fragments are left for the caller
+    packet[64] = IpNextHeaderProtocols::Ipv6Frag.0;
+    assert_eq!(upper_layer(&Ipv6Packet::new(&packet[..]).unwrap()),
+               Some((IpNextHeaderProtocols::Ipv6Frag, 72)));
+
+    // The Routing header runs past the end of the packet
+    assert_eq!(upper_layer(&Ipv6Packet::new(&packet[..60]).unwrap()), None);
+
+    packet[6] = IpNextHeaderProtocols::Tcp.0;
+    assert_eq!(upper_layer(&Ipv6Packet::new(&packet[..]).unwrap()),
+               Some((IpNextHeaderProtocols::Tcp, 40)));
+}
+
+/// Represents the IPv6 Fragment extension header, which follows the IPv6 header (and any other
+/// extension headers which every fragment carries) in each fragment of a packet
+#[derive(Clone, Debug, Packet)]
+pub struct Fragment {
+    #[construct_with(u8)]
+    next_header: IpNextHeaderProtocol,
+    reserved: u8,
+    // The offset of the fragment's data in the original packet, in units of 8 octets
+    fragment_offset: u13be,
+    reserved2: u2,
+    more_fragments: u1,
+    identification: u32be,
+    #[payload]
+    payload: Vec<u8>,
+}
+
+#[test]
+fn fragment_header_test() {
+    let mut packet = [0u8; 12];
+    {
+        let mut fragment = MutableFragmentPacket::new(&mut packet[..]).unwrap();
+        fragment.set_next_header(IpNextHeaderProtocols::Udp);
+        fragment.set_fragment_offset(0x1234);
+        fragment.set_more_fragments(1);
+        fragment.set_identification(0xdeadbeef);
+        fragment.set_payload(&[1, 2, 3, 4]);
+    }
+    assert_eq!(&packet[..8], &[17, 0, 0x91, 0xa1, 0xde, 0xad, 0xbe, 0xef]);
+
+    let fragment = FragmentPacket::new(&packet[..]).unwrap();
+    assert_eq!(fragment.get_fragment_offset(), 0x1234);
+    assert_eq!(fragment.get_more_fragments(), 1);
+    assert_eq!(fragment.payload(), &[1, 2, 3, 4]);
+}
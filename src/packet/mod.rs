@@ -42,15 +42,29 @@ pub trait MutablePacket: Packet {
     }
 }
 
-/// Used to convert on-the-wire packets to their #[packet] equivalent
+/// Used to convert on-the-wire packets to their #[derive(Packet)] equivalent
 pub trait FromPacket: Packet {
     /// The type of the packet to convert from
     type T;
 
-    /// Converts a wire-format packet to #[packet] struct format
+    /// Converts a wire-format packet to #[derive(Packet)] struct format
     fn from_packet(&self) -> Self::T;
 }
 
+/// Used to construct packets which borrow their contents from a byte slice
+///
+/// This is implemented by every #[derive(Packet)] type, for all lifetimes `'p`, with `T` being
+/// the same packet type borrowing for `'p`. This allows code to be generic over packet types, and
+/// still construct them from buffers which live for a shorter time than the type parameter
+/// suggests.
+pub trait FromBytes<'p> {
+    /// The type of packet constructed
+    type T;
+
+    /// Constructs a packet from a byte slice, returning None if it is too short
+    fn from_bytes(bytes: &'p [u8]) -> Option<Self::T>;
+}
+
 /// Used to find the calculated size of the packet. This is used for occasions where the underlying
 /// buffer is not the same length as the packet itself.
 pub trait PacketSize: Packet {
@@ -58,6 +72,26 @@ pub trait PacketSize: Packet {
     fn packet_size(&self) -> usize;
 }
 
+/// Represents an error found by the `new_checked` constructor of a packet
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PacketError {
+    /// The buffer ends before the end of a field
+    Truncated {
+        /// The name of the field which doesn't fit in the buffer
+        field: &'static str,
+        /// The number of bytes needed for the packet, up to the end of the field
+        expected: usize,
+        /// The number of bytes in the buffer
+        available: usize,
+    },
+    /// A field has a value which the packet doesn't allow, eg. an IPv4 header length of less
+    /// than five words
+    InvalidValue {
+        /// The name of the field
+        field: &'static str,
+    },
+}
+
 macro_rules! impl_index {
     ($t:ident, $index_t:ty, $output_t:ty) => {
         impl<'p> Index<$index_t> for $t<'p> {
@@ -200,4 +234,7 @@ pub mod udp;
 pub mod tcp;
 pub mod arp;
 pub mod icmp;
+pub mod icmpv6;
 pub mod vlan;
+pub mod netflow;
+pub mod ipfix;
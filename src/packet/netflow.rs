@@ -0,0 +1,219 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! NetFlow v5 and v9 packet abstraction
+
+use packet::ip::IpNextHeaderProtocol;
+use pnet_macros_support::types::*;
+use std::net::Ipv4Addr;
+
+/// The FlowSet ID of NetFlow v9 template FlowSets
+pub const TEMPLATE_FLOWSET_ID: u16 = 0;
+
+/// The FlowSet ID of NetFlow v9 options template FlowSets. Data FlowSets have IDs of 256 and
+/// above, matching the ID of their template.
+pub const OPTIONS_TEMPLATE_FLOWSET_ID: u16 = 1;
+
+/// Represents a NetFlow v5 export packet, with a fixed format for its flow records
+#[derive(Clone, Debug, Packet)]
+pub struct NetflowV5 {
+    version: u16be,
+    count: u16be,
+    // Milliseconds since the exporting device booted
+    sys_uptime: u32be,
+    unix_secs: u32be,
+    unix_nsecs: u32be,
+    flow_sequence: u32be,
+    engine_type: u8,
+    engine_id: u8,
+    sampling_interval: u16be,
+    #[length_fn = "netflow_v5_records_length"]
+    records: Vec<NetflowV5Record>,
+    #[payload]
+    payload: Vec<u8>,
+}
+
+fn netflow_v5_records_length(packet: &NetflowV5Packet) -> usize {
+    packet.get_count() as usize * 48
+}
+
+/// Represents a NetFlow v5 flow record
+#[derive(Clone, Debug, Packet)]
+pub struct NetflowV5Record {
+    #[construct_with(u8, u8, u8, u8)]
+    source: Ipv4Addr,
+    #[construct_with(u8, u8, u8, u8)]
+    destination: Ipv4Addr,
+    #[construct_with(u8, u8, u8, u8)]
+    next_hop: Ipv4Addr,
+    // SNMP indexes of the input and output interfaces
+    input: u16be,
+    output: u16be,
+    packets: u32be,
+    octets: u32be,
+    // The system uptime when the first and last packets of the flow were seen
+    first: u32be,
+    last: u32be,
+    source_port: u16be,
+    destination_port: u16be,
+    pad1: u8,
+    tcp_flags: u8,
+    #[construct_with(u8)]
+    protocol: IpNextHeaderProtocol,
+    tos: u8,
+    source_as: u16be,
+    destination_as: u16be,
+    source_mask: u8,
+    destination_mask: u8,
+    pad2: u16be,
+    #[payload]
+    #[length = "0"]
+    payload: Vec<u8>,
+}
+
+/// Represents a NetFlow v9 export packet, which contains FlowSets
+#[derive(Clone, Debug, Packet)]
+pub struct NetflowV9 {
+    version: u16be,
+    // The total number of template and data records in the packet
+    count: u16be,
+    sys_uptime: u32be,
+    unix_secs: u32be,
+    sequence_number: u32be,
+    source_id: u32be,
+    #[payload]
+    payload: Vec<u8>,
+}
+
+/// Represents a NetFlow v9 FlowSet, whose length includes its header and any padding
+#[derive(Clone, Debug, Packet)]
+pub struct FlowSet {
+    flowset_id: u16be,
+    length: u16be,
+    #[length_fn = "flowset_payload_length"]
+    #[payload]
+    payload: Vec<u8>,
+}
+
+fn flowset_payload_length(flowset: &FlowSetPacket) -> usize {
+    (flowset.get_length() as usize).saturating_sub(4)
+}
+
+/// Represents a NetFlow v9 template record, which describes the fields of the data records with
+/// its template ID
+#[derive(Clone, Debug, Packet)]
+pub struct TemplateRecord {
+    template_id: u16be,
+    field_count: u16be,
+    #[length_fn = "template_record_fields_length"]
+    fields: Vec<TemplateField>,
+    #[payload]
+    #[length = "0"]
+    payload: Vec<u8>,
+}
+
+fn template_record_fields_length(record: &TemplateRecordPacket) -> usize {
+    record.get_field_count() as usize * 4
+}
+
+/// Represents the type and length of a field of a NetFlow v9 data record
+#[derive(Clone, Debug, Packet)]
+pub struct TemplateField {
+    // Types 1 to 127 are the same as the IPFIX information elements with those IDs
+    field_type: u16be,
+    field_length: u16be,
+    #[payload]
+    #[length = "0"]
+    payload: Vec<u8>,
+}
+
+/// Represents a NetFlow v9 options template record. The scope fields say what the options apply
+/// to, eg. an interface, and their types are a separate set of values from other fields'.
+#[derive(Clone, Debug, Packet)]
+pub struct OptionsTemplateRecord {
+    template_id: u16be,
+    // The lengths, in bytes, of the scope and option fields
+    option_scope_length: u16be,
+    option_length: u16be,
+    #[length_fn = "options_template_scope_fields_length"]
+    scope_fields: Vec<TemplateField>,
+    #[length_fn = "options_template_option_fields_length"]
+    option_fields: Vec<TemplateField>,
+    #[payload]
+    #[length = "0"]
+    payload: Vec<u8>,
+}
+
+fn options_template_scope_fields_length(record: &OptionsTemplateRecordPacket) -> usize {
+    record.get_option_scope_length() as usize
+}
+
+fn options_template_option_fields_length(record: &OptionsTemplateRecordPacket) -> usize {
+    record.get_option_length() as usize
+}
+
+#[test]
+fn netflow_v5_test() {
+    use packet::Packet;
+    use packet::ip::IpNextHeaderProtocols;
+
+    let mut packet = [0u8; 24 + 48];
+    {
+        let mut header = MutableNetflowV5Packet::new(&mut packet[..]).unwrap();
+        header.set_version(5);
+        header.set_count(1);
+        header.set_unix_secs(1500000000);
+        header.set_flow_sequence(42);
+    }
+    {
+        let mut record = MutableNetflowV5RecordPacket::new(&mut packet[24..]).unwrap();
+        record.set_source(Ipv4Addr::new(10, 0, 0, 1));
+        record.set_destination(Ipv4Addr::new(10, 0, 0, 2));
+        record.set_packets(3);
+        record.set_octets(180);
+        record.set_destination_port(53);
+        record.set_protocol(IpNextHeaderProtocols::Udp);
+        record.set_destination_mask(24);
+    }
+
+    let header = NetflowV5Packet::new(&packet[..]).unwrap();
+    assert_eq!(header.get_flow_sequence(), 42);
+    let records = header.get_records();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].source, Ipv4Addr::new(10, 0, 0, 1));
+    assert_eq!(records[0].octets, 180);
+    assert_eq!(records[0].protocol, IpNextHeaderProtocols::Udp);
+    assert_eq!(header.payload(), &[]);
+    assert_eq!(&packet[24..32], &[10, 0, 0, 1, 10, 0, 0, 2]);
+    assert_eq!(&packet[56..72], &[0, 0, 0, 53, 0, 0, 17, 0, 0, 0, 0, 0, 0, 24, 0, 0]);
+}
+
+#[test]
+fn netflow_v9_template_test() {
+    use packet::Packet;
+
+    let packet = [0, 0, 0, 16, // FlowSet ID, length
+                  1, 0, 0, 2, // template ID, field count
+                  0, 8, 0, 4, // source IPv4 address
+                  0, 1, 0, 4]; // octets
+    let flowset = FlowSetPacket::new(&packet[..]).unwrap();
+    assert_eq!(flowset.get_flowset_id(), TEMPLATE_FLOWSET_ID);
+    let template = TemplateRecordPacket::new(flowset.payload()).unwrap();
+    assert_eq!(template.get_template_id(), 256);
+    let fields: Vec<_> = template.get_fields_iter()
+        .map(|field| (field.get_field_type(), field.get_field_length()))
+        .collect();
+    assert_eq!(fields, vec![(8, 4), (1, 4)]);
+
+    let packet = [1, 0, 0, 4, 0, 4, // template ID, scope length, option length
+                  0, 2, 0, 4, // interface scope
+                  0, 34, 0, 4]; // sampling interval
+    let options = OptionsTemplateRecordPacket::new(&packet[..]).unwrap();
+    assert_eq!(options.get_scope_fields()[0].field_type, 2);
+    assert_eq!(options.get_option_fields()[0].field_type, 34);
+}
@@ -0,0 +1,523 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for pinging hosts using ICMP and ICMPv6 echo requests
+//!
+//! A `Pinger` sends echo requests to any number of IPv4 and IPv6 targets at a fixed interval,
+//! matches the echo replies back to the requests which caused them, and reports round trip time
+//! statistics for each target once done.
+//!
+//! ```no_run
+//! use pnet::ping::{self, Config};
+//! use std::net::IpAddr;
+//!
+//! let targets: Vec<IpAddr> = vec!["127.0.0.1".parse().unwrap(), "::1".parse().unwrap()];
+//! for stats in ping::ping(&targets, Config::default()).unwrap() {
+//!     println!("{}: {}/{} received, {:.1}% loss",
+//!              stats.target, stats.received, stats.transmitted, stats.loss() * 100.0);
+//! }
+//! ```
+//!
+//! By default raw sockets are used, which need root privileges. Setting `unprivileged` in the
+//! `Config` uses `PingIpv4` and `PingIpv6` transport channels instead.
+
+use internal::{POLL_INTERVAL_MS, is_transient};
+use packet::Packet;
+use packet::icmp::{self, IcmpPacket, IcmpTypes};
+use packet::icmp::echo_reply::EchoReplyPacket;
+use packet::icmp::echo_request::MutableEchoRequestPacket;
+use packet::icmpv6::Icmpv6Types;
+use packet::icmpv6::echo_reply::EchoReplyPacket as Icmpv6EchoReplyPacket;
+use packet::icmpv6::echo_request::MutableEchoRequestPacket as MutableIcmpv6EchoRequestPacket;
+use packet::ip::IpNextHeaderProtocols;
+use transport::{self, TransportChannelType, TransportReceiver, TransportSender, packet_iter};
+use transport::TransportProtocol::{Ipv4, Ipv6};
+
+use std::collections::HashMap;
+use std::io;
+use std::net::IpAddr;
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The size of an ICMP or ICMPv6 echo header
+const ECHO_HEADER_LEN: usize = 8;
+
+/// Options for a `Pinger`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The number of echo requests to send to each target, which may be at most 65536 so that
+    /// each has its own sequence number. Defaults to 4.
+    pub count: usize,
+
+    /// The time between sending each round of echo requests. Defaults to one second.
+    pub interval: Duration,
+
+    /// How long to wait for replies after the last round of echo requests is sent. Defaults to
+    /// one second.
+    pub timeout: Duration,
+
+    /// The number of bytes of data following the echo header. Defaults to 56.
+    pub payload_size: usize,
+
+    /// The TTL (IPv4) or hop limit (IPv6) of echo requests. Defaults to None, which uses the
+    /// system default.
+    pub ttl: Option<u8>,
+
+    /// The identifier of echo requests. Replies with any other identifier are ignored. Defaults
+    /// to the low 16 bits of the process id. Unprivileged channels always use the identifier
+    /// chosen by the kernel.
+    pub identifier: u16,
+
+    /// Use `PingIpv4` and `PingIpv6` transport channels, which don't need root privileges,
+    /// rather than raw sockets. Defaults to false.
+    pub unprivileged: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            count: 4,
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(1),
+            payload_size: 56,
+            ttl: None,
+            identifier: process::id() as u16,
+            unprivileged: false,
+        }
+    }
+}
+
+/// An echo reply matched to the echo request which caused it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reply {
+    /// The target which replied
+    pub target: IpAddr,
+    /// The sequence number of the echo request
+    pub sequence: u16,
+    /// The size of the echo reply, including its ICMP header
+    pub size: usize,
+    /// The TTL (IPv4) or hop limit (IPv6) the reply arrived with, if known
+    pub ttl: Option<u8>,
+    /// The time between sending the echo request and receiving the reply
+    pub rtt: Duration,
+}
+
+/// Round trip time statistics for a single target
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Statistics {
+    /// The target the statistics are for
+    pub target: IpAddr,
+    /// The number of echo requests sent
+    pub transmitted: usize,
+    /// The number of echo requests which were answered
+    pub received: usize,
+    /// The shortest round trip time, if any replies were received
+    pub min: Option<Duration>,
+    /// The mean round trip time, if any replies were received
+    pub avg: Option<Duration>,
+    /// The longest round trip time, if any replies were received
+    pub max: Option<Duration>,
+    /// The mean deviation of round trip times, as reported by ping(8), if any replies were
+    /// received
+    pub mdev: Option<Duration>,
+}
+
+impl Statistics {
+    fn new(target: IpAddr) -> Statistics {
+        Statistics {
+            target: target,
+            transmitted: 0,
+            received: 0,
+            min: None,
+            avg: None,
+            max: None,
+            mdev: None,
+        }
+    }
+
+    /// The fraction of echo requests which went unanswered, between 0 and 1
+    pub fn loss(&self) -> f64 {
+        if self.transmitted == 0 {
+            0.0
+        } else {
+            1.0 - self.received as f64 / self.transmitted as f64
+        }
+    }
+}
+
+/// Running totals used to calculate `Statistics`
+#[derive(Default)]
+struct Totals {
+    transmitted: usize,
+    rtts: Vec<Duration>,
+}
+
+impl Totals {
+    fn statistics(&self, target: IpAddr) -> Statistics {
+        let mut stats = Statistics::new(target);
+        stats.transmitted = self.transmitted;
+        stats.received = self.rtts.len();
+        if self.rtts.is_empty() {
+            return stats;
+        }
+
+        let secs: Vec<f64> = self.rtts.iter().map(|rtt| duration_to_secs(*rtt)).collect();
+        let n = secs.len() as f64;
+        let mean = secs.iter().sum::<f64>() / n;
+        let mean_sq = secs.iter().map(|s| s * s).sum::<f64>() / n;
+        let variance = mean_sq - mean * mean;
+        stats.min = self.rtts.iter().min().cloned();
+        stats.max = self.rtts.iter().max().cloned();
+        stats.avg = Some(secs_to_duration(mean));
+        stats.mdev = Some(secs_to_duration(if variance > 0.0 { variance.sqrt() } else { 0.0 }));
+
+        stats
+    }
+}
+
+fn duration_to_secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1_000_000_000.0
+}
+
+fn secs_to_duration(secs: f64) -> Duration {
+    let nanos = (secs * 1_000_000_000.0).round() as u64;
+    Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32)
+}
+
+/// An echo reply, as seen by a receiving thread
+struct Echo {
+    source: IpAddr,
+    identifier: u16,
+    sequence: u16,
+    size: usize,
+    ttl: Option<u8>,
+    time: Instant,
+}
+
+/// The sending half of the channel for one address family
+struct Family {
+    tx: TransportSender,
+    ipv6: bool,
+}
+
+/// Sends echo requests to a set of targets and collects the replies
+pub struct Pinger {
+    targets: Vec<IpAddr>,
+    config: Config,
+}
+
+impl Pinger {
+    /// Create a new Pinger for the given targets. Duplicate targets are only pinged once.
+    pub fn new(targets: &[IpAddr], config: Config) -> Pinger {
+        let mut unique = Vec::with_capacity(targets.len());
+        for target in targets {
+            if !unique.contains(target) {
+                unique.push(*target);
+            }
+        }
+
+        Pinger {
+            targets: unique,
+            config: config,
+        }
+    }
+
+    /// Ping every target, returning their statistics in the order the targets were given
+    pub fn run(&self) -> io::Result<Vec<Statistics>> {
+        self.run_with(|_| {})
+    }
+
+    /// Ping every target, calling `on_reply` for each reply as it is received
+    pub fn run_with<F>(&self, mut on_reply: F) -> io::Result<Vec<Statistics>>
+        where F: FnMut(&Reply)
+    {
+        if self.config.count > u16::max_value() as usize + 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      "Too many echo requests for the sequence number"));
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (echo_tx, echo_rx) = mpsc::channel();
+        let mut v4 = None;
+        let mut v6 = None;
+        let mut threads = Vec::new();
+        let result = (|| {
+            for target in &self.targets {
+                let (family, ipv6) = match *target {
+                    IpAddr::V4(..) => (&mut v4, false),
+                    IpAddr::V6(..) => (&mut v6, true),
+                };
+                if family.is_none() {
+                    let (tx, rx) = self.open(ipv6)?;
+                    threads.push(spawn_receiver(rx, ipv6, echo_tx.clone(), stop.clone()));
+                    *family = Some(Family {
+                        tx: tx,
+                        ipv6: ipv6,
+                    });
+                }
+            }
+
+            self.exchange(&mut v4, &mut v6, &echo_rx, &mut on_reply)
+        })();
+
+        stop.store(true, Ordering::SeqCst);
+        for thread in threads {
+            let _ = thread.join();
+        }
+
+        result
+    }
+
+    /// Create the transport channel for one address family
+    fn open(&self, ipv6: bool) -> io::Result<(TransportSender, TransportReceiver)> {
+        let channel_type = match (ipv6, self.config.unprivileged) {
+            (false, false) => TransportChannelType::Layer4(Ipv4(IpNextHeaderProtocols::Icmp)),
+            (true, false) => TransportChannelType::Layer4(Ipv6(IpNextHeaderProtocols::Ipv6Icmp)),
+            (false, true) => TransportChannelType::PingIpv4,
+            (true, true) => TransportChannelType::PingIpv6,
+        };
+        let config = transport::Config {
+            read_buffer_size: 4096 + self.config.payload_size,
+            read_timeout: Some(Duration::from_millis(POLL_INTERVAL_MS)),
+            ..Default::default()
+        };
+        let (tx, rx) = transport::transport_channel_with(channel_type, config)?;
+        if let Some(ttl) = self.config.ttl {
+            tx.set_ttl(ttl)?;
+        }
+
+        Ok((tx, rx))
+    }
+
+    /// Send every round of echo requests, and match up the replies
+    fn exchange<F>(&self,
+                   v4: &mut Option<Family>,
+                   v6: &mut Option<Family>,
+                   echo_rx: &Receiver<io::Result<Echo>>,
+                   on_reply: &mut F)
+        -> io::Result<Vec<Statistics>>
+        where F: FnMut(&Reply)
+    {
+        let mut totals: Vec<Totals> = self.targets.iter().map(|_| Totals::default()).collect();
+        let indices: HashMap<IpAddr, usize> =
+            self.targets.iter().enumerate().map(|(i, target)| (*target, i)).collect();
+        // Echo requests which haven't been answered yet, by target index and sequence number
+        let mut pending: HashMap<(usize, u16), Instant> = HashMap::new();
+
+        let start = Instant::now();
+        let mut next_round = start;
+        for round in 0..self.config.count {
+            self.collect(echo_rx, next_round, &indices, &mut pending, &mut totals, on_reply)?;
+
+            let sequence = round as u16;
+            for (i, target) in self.targets.iter().enumerate() {
+                let family = match *target {
+                    IpAddr::V4(..) => v4.as_mut(),
+                    IpAddr::V6(..) => v6.as_mut(),
+                };
+                let family = family.expect("no channel for target");
+                self.send_echo(family, *target, sequence)?;
+                pending.insert((i, sequence), Instant::now());
+                totals[i].transmitted += 1;
+            }
+            next_round += self.config.interval;
+        }
+
+        let deadline = Instant::now() + self.config.timeout;
+        while !pending.is_empty() && Instant::now() < deadline {
+            self.collect(echo_rx, deadline, &indices, &mut pending, &mut totals, on_reply)?;
+        }
+
+        Ok(self.targets
+            .iter()
+            .zip(totals.iter())
+            .map(|(target, totals)| totals.statistics(*target))
+            .collect())
+    }
+
+    /// Match echo replies to pending echo requests until `until`. Fails if a receiving thread
+    /// has stopped because of an error.
+    fn collect<F>(&self,
+                  echo_rx: &Receiver<io::Result<Echo>>,
+                  until: Instant,
+                  indices: &HashMap<IpAddr, usize>,
+                  pending: &mut HashMap<(usize, u16), Instant>,
+                  totals: &mut [Totals],
+                  on_reply: &mut F)
+        -> io::Result<()>
+        where F: FnMut(&Reply)
+    {
+        loop {
+            let now = Instant::now();
+            if now >= until {
+                return Ok(());
+            }
+            let echo = match echo_rx.recv_timeout(until - now) {
+                Ok(echo) => echo?,
+                Err(RecvTimeoutError::Timeout) |
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            };
+            if !self.config.unprivileged && echo.identifier != self.config.identifier {
+                continue;
+            }
+            let index = match indices.get(&echo.source) {
+                Some(index) => *index,
+                None => continue,
+            };
+            // Duplicate and unsolicited replies have no pending request
+            let sent = match pending.remove(&(index, echo.sequence)) {
+                Some(sent) => sent,
+                None => continue,
+            };
+            let rtt = if echo.time > sent {
+                echo.time - sent
+            } else {
+                Duration::new(0, 0)
+            };
+            totals[index].rtts.push(rtt);
+            on_reply(&Reply {
+                target: echo.source,
+                sequence: echo.sequence,
+                size: echo.size,
+                ttl: echo.ttl,
+                rtt: rtt,
+            });
+        }
+    }
+
+    /// Send a single echo request
+    fn send_echo(&self, family: &mut Family, target: IpAddr, sequence: u16) -> io::Result<()> {
+        let mut buffer = vec![0u8; ECHO_HEADER_LEN + self.config.payload_size];
+        for (i, byte) in buffer[ECHO_HEADER_LEN..].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        if family.ipv6 {
+            // The kernel fills in ICMPv6 checksums, as they depend on the source address
+            let mut echo = MutableIcmpv6EchoRequestPacket::new(&mut buffer[..]).unwrap();
+            echo.set_icmpv6_type(Icmpv6Types::EchoRequest);
+            echo.set_identifier(self.config.identifier);
+            echo.set_sequence_number(sequence);
+        } else {
+            {
+                let mut echo = MutableEchoRequestPacket::new(&mut buffer[..]).unwrap();
+                echo.set_icmp_type(IcmpTypes::EchoRequest);
+                echo.set_identifier(self.config.identifier);
+                echo.set_sequence_number(sequence);
+            }
+            let checksum = icmp::checksum(&IcmpPacket::new(&buffer[..]).unwrap());
+            MutableEchoRequestPacket::new(&mut buffer[..]).unwrap().set_checksum(checksum);
+        }
+
+        family.tx.send_to(IcmpPacket::new(&buffer[..]).unwrap(), target).map(|_| ())
+    }
+}
+
+/// Start a thread which passes echo replies received on `rx` to `echo_tx`, until `stop` is set
+/// or receiving fails, in which case the error is passed on instead
+fn spawn_receiver(mut rx: TransportReceiver,
+                  ipv6: bool,
+                  echo_tx: Sender<io::Result<Echo>>,
+                  stop: Arc<AtomicBool>)
+    -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while !stop.load(Ordering::SeqCst) {
+            let echo = if ipv6 {
+                let mut iter = packet_iter::<Icmpv6EchoReplyPacket>(&mut rx);
+                match iter.next_with_metadata() {
+                    Ok((reply, metadata)) => {
+                        // Raw sockets also see echo requests, including our own on loopback
+                        if reply.get_icmpv6_type() != Icmpv6Types::EchoReply {
+                            continue;
+                        }
+                        Echo {
+                            source: metadata.source,
+                            identifier: reply.get_identifier(),
+                            sequence: reply.get_sequence_number(),
+                            size: reply.packet().len(),
+                            ttl: metadata.ttl,
+                            time: Instant::now(),
+                        }
+                    }
+                    Err(ref e) if is_transient(e) => continue,
+                    Err(e) => {
+                        let _ = echo_tx.send(Err(e));
+                        return;
+                    }
+                }
+            } else {
+                let mut iter = packet_iter::<EchoReplyPacket>(&mut rx);
+                match iter.next_with_metadata() {
+                    Ok((reply, metadata)) => {
+                        if reply.get_icmp_type() != IcmpTypes::EchoReply {
+                            continue;
+                        }
+                        Echo {
+                            source: metadata.source,
+                            identifier: reply.get_identifier(),
+                            sequence: reply.get_sequence_number(),
+                            size: reply.packet().len(),
+                            ttl: metadata.ttl,
+                            time: Instant::now(),
+                        }
+                    }
+                    Err(ref e) if is_transient(e) => continue,
+                    Err(e) => {
+                        let _ = echo_tx.send(Err(e));
+                        return;
+                    }
+                }
+            };
+            if echo_tx.send(Ok(echo)).is_err() {
+                return;
+            }
+        }
+    })
+}
+
+/// Ping the given targets using the given configuration, returning statistics for each
+pub fn ping(targets: &[IpAddr], config: Config) -> io::Result<Vec<Statistics>> {
+    Pinger::new(targets, config).run()
+}
+
+#[test]
+fn statistics_test() {
+    use std::net::Ipv4Addr;
+
+    let target = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
+    let totals = Totals {
+        transmitted: 4,
+        rtts: vec![Duration::from_millis(10), Duration::from_millis(20), Duration::from_millis(30)],
+    };
+    let stats = totals.statistics(target);
+    assert_eq!(stats.target, target);
+    assert_eq!(stats.transmitted, 4);
+    assert_eq!(stats.received, 3);
+    assert_eq!(stats.min, Some(Duration::from_millis(10)));
+    assert_eq!(stats.avg, Some(Duration::from_millis(20)));
+    assert_eq!(stats.max, Some(Duration::from_millis(30)));
+    // sqrt((100 + 400 + 900) / 3 - 400) ms
+    assert_eq!(stats.mdev, Some(Duration::new(0, 8_164_966)));
+    assert_eq!(stats.loss(), 0.25);
+
+    let stats = Totals::default().statistics(target);
+    assert_eq!(stats.received, 0);
+    assert_eq!(stats.avg, None);
+    assert_eq!(stats.loss(), 0.0);
+}
+
+#[test]
+fn too_many_echo_requests_test() {
+    let config = Config { count: 65537, ..Default::default() };
+    let err = ping(&[], config).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+}
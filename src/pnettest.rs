@@ -14,15 +14,15 @@ use packet::Packet;
 use packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
 use packet::ipv4;
 use packet::ipv4::{Ipv4Packet, MutableIpv4Packet};
-use packet::ipv6::MutableIpv6Packet;
+use packet::ipv6::{Ipv6Packet, MutableIpv6Packet};
 use packet::udp;
 use packet::udp::{MutableUdpPacket, UdpPacket};
 use std::iter::Iterator;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::sync::mpsc::channel;
 use std::thread;
-use transport::{TransportChannelType, TransportProtocol, ipv4_packet_iter, transport_channel,
-                udp_packet_iter};
+use transport::{TransportChannelType, TransportProtocol, ipv4_packet_iter, ipv6_packet_iter,
+                transport_channel, udp_packet_iter};
 use transport::TransportProtocol::{Ipv4, Ipv6};
 
 const IPV4_HEADER_LEN: usize = 20;
@@ -226,6 +226,241 @@ fn layer4_ipv6() {
     layer4(IpAddr::V6(ipv6_source()), IPV6_HEADER_LEN);
 }
 
+#[cfg(target_os = "linux")]
+fn layer4_metadata(ip: IpAddr, header_len: usize) {
+    let mut packet = [0u8; IPV6_HEADER_LEN + UDP_HEADER_LEN + TEST_DATA_LEN];
+    let packet_len = header_len + UDP_HEADER_LEN + TEST_DATA_LEN;
+
+    let proto = match ip {
+        IpAddr::V4(..) => {
+            build_udp4_packet(&mut packet[..], 0, "md4t", None);
+            Ipv4(TEST_PROTO)
+        }
+        IpAddr::V6(..) => {
+            build_udp6_packet(&mut packet[..], 0, "md6t");
+            Ipv6(TEST_PROTO)
+        }
+    };
+
+    let tc = transport_channel(128, TransportChannelType::Layer4(proto));
+    let (mut ttx, mut trx) = match tc {
+        Ok((tx, rx)) => (tx, rx),
+        Err(e) => panic!("layer4_metadata: unable to create channel: {}", e),
+    };
+
+    match ttx.send_to(UdpPacket::new(&packet[header_len..packet_len]).unwrap(), ip) {
+        Ok(res) => assert_eq!(res as usize, UDP_HEADER_LEN + TEST_DATA_LEN),
+        Err(e) => panic!("layer4_metadata failed: {}", e),
+    }
+
+    let mut iter = udp_packet_iter(&mut trx);
+    match iter.next_with_metadata() {
+        Ok((header, metadata)) => {
+            assert_eq!(header, UdpPacket::new(&packet[header_len..packet_len]).unwrap());
+            assert_eq!(metadata.source, ip);
+            assert_eq!(metadata.destination, Some(ip));
+            assert!(metadata.interface_index.is_some());
+            assert!(metadata.ttl.is_some());
+        }
+        Err(e) => panic!("receive failed for layer4_metadata(): {}", e),
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn layer4_ipv4_metadata() {
+    layer4_metadata(IpAddr::V4(ipv4_source()), IPV4_HEADER_LEN);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(feature = "travis")))]
+fn layer4_ipv6_metadata() {
+    layer4_metadata(IpAddr::V6(ipv6_source()), IPV6_HEADER_LEN);
+}
+
+#[cfg(target_os = "linux")]
+fn socket_options(ip: IpAddr, header_len: usize) {
+    let mut packet = [0u8; IPV6_HEADER_LEN + UDP_HEADER_LEN + TEST_DATA_LEN];
+    let packet_len = header_len + UDP_HEADER_LEN + TEST_DATA_LEN;
+
+    let proto = match ip {
+        IpAddr::V4(..) => {
+            build_udp4_packet(&mut packet[..], 0, "so4t", None);
+            Ipv4(TEST_PROTO)
+        }
+        IpAddr::V6(..) => {
+            build_udp6_packet(&mut packet[..], 0, "so6t");
+            Ipv6(TEST_PROTO)
+        }
+    };
+
+    let tc = transport_channel(128, TransportChannelType::Layer4(proto));
+    let (mut ttx, mut trx) = match tc {
+        Ok((tx, rx)) => (tx, rx),
+        Err(e) => panic!("socket_options: unable to create channel: {}", e),
+    };
+
+    ttx.set_ttl(17).unwrap();
+    ttx.set_tos(0x10).unwrap();
+    ttx.set_send_buffer_size(65536).unwrap();
+    ttx.set_multicast_ttl(2).unwrap();
+    ttx.set_multicast_loop(false).unwrap();
+    trx.set_recv_buffer_size(65536).unwrap();
+    trx.bind_to_device("lo").unwrap();
+
+    match ttx.send_to(UdpPacket::new(&packet[header_len..packet_len]).unwrap(), ip) {
+        Ok(res) => assert_eq!(res as usize, UDP_HEADER_LEN + TEST_DATA_LEN),
+        Err(e) => panic!("socket_options failed: {}", e),
+    }
+
+    let mut iter = udp_packet_iter(&mut trx);
+    match iter.next_with_metadata() {
+        Ok((_, metadata)) => assert_eq!(metadata.ttl, Some(17)),
+        Err(e) => panic!("receive failed for socket_options(): {}", e),
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn socket_options_ipv4() {
+    socket_options(IpAddr::V4(ipv4_source()), IPV4_HEADER_LEN);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(feature = "travis")))]
+fn socket_options_ipv6() {
+    socket_options(IpAddr::V6(ipv6_source()), IPV6_HEADER_LEN);
+}
+
+#[cfg(target_os = "linux")]
+fn layer4_errors(ip: IpAddr, header_len: usize) {
+    use std::io::ErrorKind;
+    use std::time::Duration;
+    use transport::{self, ErrorOrigin, transport_channel_with};
+
+    // The UDP checksum covers the rest of the buffer, so it must be exactly the packet's size
+    let packet_len = header_len + UDP_HEADER_LEN + TEST_DATA_LEN;
+    let mut packet = vec![0u8; packet_len];
+
+    // Nothing listens on the test port, so the kernel answers with a port unreachable message
+    let (proto, origin, icmp_type, icmp_code) = match ip {
+        IpAddr::V4(..) => {
+            build_udp4_packet(&mut packet[..], 0, "er4t", None);
+            (Ipv4(IpNextHeaderProtocols::Udp), ErrorOrigin::Icmp, 3, 3)
+        }
+        IpAddr::V6(..) => {
+            build_udp6_packet(&mut packet[..], 0, "er6t");
+            (Ipv6(IpNextHeaderProtocols::Udp), ErrorOrigin::Icmpv6, 1, 4)
+        }
+    };
+
+    let config = transport::Config {
+        read_timeout: Some(Duration::from_secs(1)),
+        recv_errors: true,
+        ..Default::default()
+    };
+    let tc = transport_channel_with(TransportChannelType::Layer4(proto), config);
+    let (mut ttx, mut trx) = match tc {
+        Ok((tx, rx)) => (tx, rx),
+        Err(e) => panic!("layer4_errors: unable to create channel: {}", e),
+    };
+
+    match ttx.send_to(UdpPacket::new(&packet[header_len..packet_len]).unwrap(), ip) {
+        Ok(res) => assert_eq!(res as usize, UDP_HEADER_LEN + TEST_DATA_LEN),
+        Err(e) => panic!("layer4_errors failed: {}", e),
+    }
+
+    let mut errors = trx.errors();
+    match errors.next() {
+        Ok((error, offending)) => {
+            assert_eq!(error.error().kind(), ErrorKind::ConnectionRefused);
+            assert_eq!(error.origin, origin);
+            assert_eq!(error.icmp_type, icmp_type);
+            assert_eq!(error.icmp_code, icmp_code);
+            assert_eq!(error.offender, Some(ip));
+            assert_eq!(error.destination, Some(ip));
+            assert_eq!(offending, &packet[header_len..packet_len]);
+        }
+        Err(e) => panic!("receive failed for layer4_errors(): {}", e),
+    }
+
+    match errors.next_with_timeout(Duration::from_millis(100)) {
+        Err(ref e) if e.kind() == ErrorKind::TimedOut => {}
+        Ok((error, _)) => panic!("layer4_errors: unexpected error {:?}", error),
+        Err(e) => panic!("layer4_errors: unexpected failure: {}", e),
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn layer4_ipv4_errors() {
+    layer4_errors(IpAddr::V4(ipv4_source()), IPV4_HEADER_LEN as usize);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(feature = "travis")))]
+fn layer4_ipv6_errors() {
+    layer4_errors(IpAddr::V6(ipv6_source()), IPV6_HEADER_LEN);
+}
+
+#[test]
+#[cfg(not(feature = "appveyor"))]
+fn layer4_timeouts() {
+    use std::io::ErrorKind;
+    use std::time::Duration;
+    use transport::{self, transport_channel_with};
+
+    let config = transport::Config {
+        read_timeout: Some(Duration::from_millis(10)),
+        write_timeout: Some(Duration::from_millis(100)),
+        ..Default::default()
+    };
+    let tc = transport_channel_with(TransportChannelType::Layer4(Ipv4(TEST_PROTO)), config);
+    let (_, mut trx) = match tc {
+        Ok((tx, rx)) => (tx, rx),
+        Err(e) => panic!("layer4_timeouts: unable to create channel: {}", e),
+    };
+
+    let mut iter = udp_packet_iter(&mut trx);
+    match iter.next() {
+        Ok(_) => panic!("layer4_timeouts: should have exceeded timeout"),
+        Err(e) => assert_eq!(e.kind(), ErrorKind::TimedOut),
+    }
+    match iter.next_with_timeout(Duration::new(0, 0)) {
+        Ok(_) => panic!("layer4_timeouts: should not have blocked"),
+        Err(e) => assert_eq!(e.kind(), ErrorKind::TimedOut),
+    }
+}
+
+// Packets too short for the iterator's packet type are reported rather than panicking. Test2 is
+// used so that other tests never see the short packet.
+#[test]
+#[cfg(not(feature = "appveyor"))]
+fn layer4_short_packet() {
+    use packet::icmp::IcmpPacket;
+    use std::io::ErrorKind;
+    use std::time::Duration;
+
+    let proto = Ipv4(IpNextHeaderProtocols::Test2);
+    let tc = transport_channel(128, TransportChannelType::Layer4(proto));
+    let (mut ttx, mut trx) = match tc {
+        Ok((tx, rx)) => (tx, rx),
+        Err(e) => panic!("layer4_short_packet: unable to create channel: {}", e),
+    };
+
+    let data = [0u8; TEST_DATA_LEN];
+    match ttx.send_to(IcmpPacket::new(&data[..]).unwrap(), IpAddr::V4(ipv4_source())) {
+        Ok(res) => assert_eq!(res as usize, TEST_DATA_LEN),
+        Err(e) => panic!("layer4_short_packet failed: {}", e),
+    }
+
+    let mut iter = udp_packet_iter(&mut trx);
+    match iter.next_with_timeout(Duration::from_secs(1)) {
+        Ok(_) => panic!("layer4_short_packet: packet should have been too short"),
+        Err(e) => assert_eq!(e.kind(), ErrorKind::InvalidData),
+    }
+}
+
 #[test]
 #[cfg(not(feature = "appveyor"))]
 fn layer3_ipv4() {
@@ -280,6 +515,395 @@ fn layer3_ipv4() {
     assert!(res.join().is_ok())
 }
 
+// travis does not currently support IPv6
+#[test]
+#[cfg(all(target_os = "linux", not(feature = "travis")))]
+fn layer3_ipv6() {
+    let send_addr = IpAddr::V6(ipv6_source());
+    let mut packet = [0u8; IPV6_HEADER_LEN + UDP_HEADER_LEN + TEST_DATA_LEN];
+
+    build_udp6_packet(&mut packet[..], 0, "l3i6");
+
+    let (tx, rx) = channel();
+
+    let tc = transport_channel(IPV6_HEADER_LEN + UDP_HEADER_LEN + TEST_DATA_LEN,
+                               TransportChannelType::Layer3Ipv6(TEST_PROTO));
+    let (mut ttx, mut trx) = match tc {
+        Ok((tx, rx)) => (tx, rx),
+        Err(e) => panic!("layer3: unable to create channel: {}", e),
+    };
+    trx.bind_to_device("lo").unwrap();
+
+    let res = thread::spawn(move || {
+        tx.send(()).unwrap();
+        let mut iter = ipv6_packet_iter(&mut trx);
+        loop {
+            let next = iter.next();
+            match next {
+                Ok((header, addr)) => {
+                    assert_eq!(addr, send_addr);
+                    assert_eq!(header, Ipv6Packet::new(&packet[..]).unwrap());
+                    let udp_header = UdpPacket::new(&header.packet()[IPV6_HEADER_LEN..]).unwrap();
+                    assert_eq!(udp_header,
+                               UdpPacket::new(&packet[IPV6_HEADER_LEN..]).unwrap());
+                    break;
+                }
+                Err(e) => {
+                    panic!("receive failed for layer3_ipv6_test(): {}", e);
+                }
+            }
+        }
+    });
+
+    rx.recv().unwrap();
+    match ttx.send_to(Ipv6Packet::new(&packet[..]).unwrap(), send_addr) {
+        Ok(res) => assert_eq!(res as usize, packet.len()),
+        Err(e) => panic!("layer3_ipv6_test failed: {}", e),
+    }
+
+    assert!(res.join().is_ok())
+}
+
+// Layer 3 IPv6 channels should strip extension headers, leaving the IPv6 header followed by the
+// upper-layer header
+#[test]
+#[cfg(all(target_os = "linux", not(feature = "travis")))]
+fn layer3_ipv6_extension_headers() {
+    const EXTENSION_LEN: usize = 8;
+
+    let send_addr = IpAddr::V6(ipv6_source());
+    let mut packet = [0u8; IPV6_HEADER_LEN + 2 * EXTENSION_LEN + UDP_HEADER_LEN + TEST_DATA_LEN];
+    let mut udp = [0u8; IPV6_HEADER_LEN + UDP_HEADER_LEN + TEST_DATA_LEN];
+
+    build_udp6_packet(&mut udp[..], 0, "l3eh");
+    build_ipv6_header(&mut packet[..], 0);
+    {
+        let mut ip_header = MutableIpv6Packet::new(&mut packet[..]).unwrap();
+        ip_header.set_payload_length((2 * EXTENSION_LEN + UDP_HEADER_LEN + TEST_DATA_LEN) as u16);
+        ip_header.set_next_header(IpNextHeaderProtocols::Hopopt);
+    }
+    // Hop-by-Hop and Destination Options headers, each containing a single PadN option
+    packet[IPV6_HEADER_LEN] = IpNextHeaderProtocols::Ipv6Opts.0;
+    packet[IPV6_HEADER_LEN + 2] = 1;
+    packet[IPV6_HEADER_LEN + 3] = 4;
+    packet[IPV6_HEADER_LEN + EXTENSION_LEN] = TEST_PROTO.0;
+    packet[IPV6_HEADER_LEN + EXTENSION_LEN + 2] = 1;
+    packet[IPV6_HEADER_LEN + EXTENSION_LEN + 3] = 4;
+    packet[IPV6_HEADER_LEN + 2 * EXTENSION_LEN..].copy_from_slice(&udp[IPV6_HEADER_LEN..]);
+
+    let tc = transport_channel(128, TransportChannelType::Layer3Ipv6(TEST_PROTO));
+    let (mut ttx, mut trx) = match tc {
+        Ok((tx, rx)) => (tx, rx),
+        Err(e) => panic!("layer3_ipv6_extension_headers: unable to create channel: {}", e),
+    };
+    trx.bind_to_device("lo").unwrap();
+
+    match ttx.send_to(Ipv6Packet::new(&packet[..]).unwrap(), send_addr) {
+        Ok(res) => assert_eq!(res as usize, packet.len()),
+        Err(e) => panic!("layer3_ipv6_extension_headers failed: {}", e),
+    }
+
+    let mut iter = ipv6_packet_iter(&mut trx);
+    match iter.next() {
+        Ok((header, addr)) => {
+            assert_eq!(addr, send_addr);
+            assert_eq!(header.get_next_header(), TEST_PROTO);
+            assert_eq!(header.packet(), &udp[..]);
+        }
+        Err(e) => panic!("receive failed for layer3_ipv6_extension_headers(): {}", e),
+    }
+}
+
+// Raw IPv6 sockets should only see the upper-layer header, even when extension headers precede
+// it on the wire
+#[test]
+#[cfg(all(target_os = "linux", not(feature = "travis")))]
+fn layer4_ipv6_extension_headers() {
+    use transport::packet_iter;
+
+    const DEST_OPTS_LEN: usize = 8;
+
+    let send_addr = IpAddr::V6(ipv6_source());
+    let mut packet = [0u8; IPV6_HEADER_LEN + DEST_OPTS_LEN + UDP_HEADER_LEN + TEST_DATA_LEN];
+    let mut udp = [0u8; IPV6_HEADER_LEN + UDP_HEADER_LEN + TEST_DATA_LEN];
+
+    build_udp6_packet(&mut udp[..], 0, "l4eh");
+    build_ipv6_header(&mut packet[..], 0);
+    {
+        let mut ip_header = MutableIpv6Packet::new(&mut packet[..]).unwrap();
+        ip_header.set_payload_length((DEST_OPTS_LEN + UDP_HEADER_LEN + TEST_DATA_LEN) as u16);
+        ip_header.set_next_header(IpNextHeaderProtocols::Ipv6Opts);
+    }
+    // Destination options header containing a single PadN option
+    packet[IPV6_HEADER_LEN] = TEST_PROTO.0;
+    packet[IPV6_HEADER_LEN + 2] = 1;
+    packet[IPV6_HEADER_LEN + 3] = 4;
+    packet[IPV6_HEADER_LEN + DEST_OPTS_LEN..].copy_from_slice(&udp[IPV6_HEADER_LEN..]);
+
+    let tc = transport_channel(128, TransportChannelType::Layer4(Ipv6(TEST_PROTO)));
+    let (_, mut trx) = match tc {
+        Ok((tx, rx)) => (tx, rx),
+        Err(e) => panic!("layer4_ipv6_extension_headers: unable to create channel: {}", e),
+    };
+    let tc = transport_channel(128, TransportChannelType::Layer3Ipv6(TEST_PROTO));
+    let (mut ttx, _) = match tc {
+        Ok((tx, rx)) => (tx, rx),
+        Err(e) => panic!("layer4_ipv6_extension_headers: unable to create channel: {}", e),
+    };
+
+    match ttx.send_to(Ipv6Packet::new(&packet[..]).unwrap(), send_addr) {
+        Ok(res) => assert_eq!(res as usize, packet.len()),
+        Err(e) => panic!("layer4_ipv6_extension_headers failed: {}", e),
+    }
+
+    let mut iter = packet_iter::<UdpPacket>(&mut trx);
+    match iter.next() {
+        Ok((header, addr)) => {
+            assert_eq!(addr, send_addr);
+            assert_eq!(header, UdpPacket::new(&udp[IPV6_HEADER_LEN..]).unwrap());
+        }
+        Err(e) => panic!("receive failed for layer4_ipv6_extension_headers(): {}", e),
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn ping_ipv4() {
+    use packet::icmp::IcmpTypes;
+    use packet::icmp::echo_reply::EchoReplyPacket;
+    use packet::icmp::echo_request::{EchoRequestPacket, MutableEchoRequestPacket};
+    use transport::packet_iter;
+
+    let mut packet = [0u8; 8 + TEST_DATA_LEN];
+    {
+        let mut echo = MutableEchoRequestPacket::new(&mut packet[..]).unwrap();
+        echo.set_icmp_type(IcmpTypes::EchoRequest);
+        echo.set_sequence_number(7);
+        echo.set_payload(b"pi4t");
+    }
+
+    let tc = transport_channel(128, TransportChannelType::PingIpv4);
+    let (mut ttx, mut trx) = match tc {
+        Ok((tx, rx)) => (tx, rx),
+        Err(e) => panic!("ping_ipv4: unable to create channel: {}", e),
+    };
+
+    let addr = IpAddr::V4(ipv4_destination());
+    match ttx.send_to(EchoRequestPacket::new(&packet[..]).unwrap(), addr) {
+        Ok(res) => assert_eq!(res as usize, packet.len()),
+        Err(e) => panic!("ping_ipv4 failed: {}", e),
+    }
+
+    let mut iter = packet_iter::<EchoReplyPacket>(&mut trx);
+    match iter.next() {
+        Ok((reply, from)) => {
+            assert_eq!(from, addr);
+            assert_eq!(reply.get_icmp_type(), IcmpTypes::EchoReply);
+            assert_eq!(reply.get_sequence_number(), 7);
+            assert_eq!(reply.payload(), b"pi4t");
+        }
+        Err(e) => panic!("receive failed for ping_ipv4(): {}", e),
+    }
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(feature = "travis")))]
+fn ping_ipv6() {
+    use packet::icmpv6::Icmpv6Types;
+    use packet::icmpv6::echo_reply::EchoReplyPacket;
+    use packet::icmpv6::echo_request::{EchoRequestPacket, MutableEchoRequestPacket};
+    use transport::packet_iter;
+
+    let mut packet = [0u8; 8 + TEST_DATA_LEN];
+    {
+        let mut echo = MutableEchoRequestPacket::new(&mut packet[..]).unwrap();
+        echo.set_icmpv6_type(Icmpv6Types::EchoRequest);
+        echo.set_sequence_number(7);
+        echo.set_payload(b"pi6t");
+    }
+
+    let tc = transport_channel(128, TransportChannelType::PingIpv6);
+    let (mut ttx, mut trx) = match tc {
+        Ok((tx, rx)) => (tx, rx),
+        Err(e) => panic!("ping_ipv6: unable to create channel: {}", e),
+    };
+
+    let addr = IpAddr::V6(ipv6_destination());
+    match ttx.send_to(EchoRequestPacket::new(&packet[..]).unwrap(), addr) {
+        Ok(res) => assert_eq!(res as usize, packet.len()),
+        Err(e) => panic!("ping_ipv6 failed: {}", e),
+    }
+
+    let mut iter = packet_iter::<EchoReplyPacket>(&mut trx);
+    match iter.next() {
+        Ok((reply, from)) => {
+            assert_eq!(from, addr);
+            assert_eq!(reply.get_icmpv6_type(), Icmpv6Types::EchoReply);
+            assert_eq!(reply.get_sequence_number(), 7);
+            assert_eq!(reply.payload(), b"pi6t");
+        }
+        Err(e) => panic!("receive failed for ping_ipv6(): {}", e),
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pinger(targets: &[IpAddr], unprivileged: bool) {
+    use ping::{Config, Pinger};
+    use std::time::Duration;
+
+    let config = Config {
+        count: 3,
+        interval: Duration::from_millis(50),
+        ttl: Some(32),
+        unprivileged: unprivileged,
+        ..Default::default()
+    };
+    let mut replies = Vec::new();
+    // Duplicate targets are only pinged once
+    let mut doubled = targets.to_vec();
+    doubled.extend_from_slice(targets);
+    let stats = match Pinger::new(&doubled, config).run_with(|reply| replies.push(*reply)) {
+        Ok(stats) => stats,
+        Err(e) => panic!("pinger failed: {}", e),
+    };
+
+    assert_eq!(stats.len(), targets.len());
+    assert_eq!(replies.len(), 3 * targets.len());
+    for (stats, target) in stats.iter().zip(targets.iter()) {
+        assert_eq!(stats.target, *target);
+        assert_eq!(stats.transmitted, 3);
+        assert_eq!(stats.received, 3);
+        assert_eq!(stats.loss(), 0.0);
+        assert!(stats.min.unwrap() <= stats.avg.unwrap());
+        assert!(stats.avg.unwrap() <= stats.max.unwrap());
+        assert!(stats.mdev.is_some());
+
+        let sequences: Vec<u16> = replies.iter()
+            .filter(|reply| reply.target == *target)
+            .map(|reply| reply.sequence)
+            .collect();
+        assert_eq!(sequences, vec![0, 1, 2]);
+    }
+    for reply in &replies {
+        assert_eq!(reply.size, 8 + config.payload_size);
+        assert!(reply.ttl.is_some());
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn pinger_ipv4() {
+    pinger(&[IpAddr::V4(ipv4_destination())], false);
+    pinger(&[IpAddr::V4(ipv4_destination())], true);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(feature = "travis")))]
+fn pinger_ipv6() {
+    pinger(&[IpAddr::V6(ipv6_destination())], false);
+    pinger(&[IpAddr::V6(ipv6_destination())], true);
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(feature = "travis")))]
+fn pinger_multiple_targets() {
+    pinger(&[IpAddr::V4(ipv4_destination()), IpAddr::V6(ipv6_destination())], false);
+}
+
+#[cfg(target_os = "linux")]
+fn traceroute_loopback(target: IpAddr) {
+    use std::net::{SocketAddr, TcpListener};
+    use traceroute::{self, Config, ProbeMethod, ReplyKind};
+
+    // A listening socket makes the destination answer TCP probes with a SYN-ACK
+    let listener = TcpListener::bind(SocketAddr::new(target, 0)).unwrap();
+    let tcp_port = listener.local_addr().unwrap().port();
+
+    for &(method, paris) in &[(ProbeMethod::Udp, false),
+                              (ProbeMethod::Udp, true),
+                              (ProbeMethod::Icmp, false),
+                              (ProbeMethod::Icmp, true),
+                              (ProbeMethod::TcpSyn, false)] {
+        let config = Config {
+            method: method,
+            paris: paris,
+            max_ttl: 4,
+            port: if method == ProbeMethod::TcpSyn {
+                Some(tcp_port)
+            } else {
+                None
+            },
+            ..Default::default()
+        };
+        let hops = match traceroute::traceroute(target, config) {
+            Ok(hops) => hops,
+            Err(e) => panic!("traceroute failed for {:?}: {}", method, e),
+        };
+
+        // The destination is the first hop on loopback
+        assert_eq!(hops.len(), 1, "{:?}: {:?}", method, hops);
+        assert_eq!(hops[0].ttl, 1);
+        assert_eq!(hops[0].addresses(), vec![target]);
+        assert_eq!(hops[0].probes.len(), 3);
+        for probe in &hops[0].probes {
+            assert_eq!(probe.map(|reply| reply.kind), Some(ReplyKind::Reached));
+        }
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn traceroute_ipv4() {
+    traceroute_loopback(IpAddr::V4(ipv4_destination()));
+}
+
+#[test]
+#[cfg(all(target_os = "linux", not(feature = "travis")))]
+fn traceroute_ipv6() {
+    traceroute_loopback(IpAddr::V6(ipv6_destination()));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn syn_scan_ipv4() {
+    use scan::syn::{self, Config, Port, PortState};
+    use std::net::{SocketAddr, TcpListener};
+    use std::time::Duration;
+
+    let target = ipv4_destination();
+    let listener = TcpListener::bind(SocketAddr::new(IpAddr::V4(target), 0)).unwrap();
+    let open = listener.local_addr().unwrap().port();
+    // Nothing listens on a port which was bound and then closed again
+    let closed = TcpListener::bind(SocketAddr::new(IpAddr::V4(target), 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let config = Config {
+        timeout: Duration::from_millis(500),
+        ..Default::default()
+    };
+    let mut answered = Vec::new();
+    let ports = syn::SynScanner::new(&[target], &[closed, open, open], config)
+        .run_with(|port| answered.push(*port))
+        .unwrap();
+
+    assert_eq!(ports,
+               vec![Port {
+                        target: target,
+                        port: closed,
+                        state: PortState::Closed,
+                    },
+                    Port {
+                        target: target,
+                        port: open,
+                        state: PortState::Open,
+                    }]);
+    answered.sort_by_key(|port| port.port != closed);
+    assert_eq!(answered, ports);
+}
+
 #[cfg(windows)]
 fn get_test_interface() -> datalink::NetworkInterface {
     use std::clone::Clone;
@@ -477,6 +1101,27 @@ fn check_test_environment() {
     }
 
     test_iface();
+    test_ping_group_range();
+
+    // Ping sockets are only available to groups within this range
+    #[cfg(target_os = "linux")]
+    fn test_ping_group_range() {
+        use std::fs::File;
+        use std::io::Read;
+
+        let mut range = String::new();
+        File::open("/proc/sys/net/ipv4/ping_group_range")
+            .and_then(|mut f| f.read_to_string(&mut range))
+            .unwrap();
+        let bounds: Vec<u32> = range.split_whitespace().map(|b| b.parse().unwrap()).collect();
+        let gid = unsafe { libc::getgid() };
+        if gid < bounds[0] || gid > bounds[1] {
+            panic!("The sysctl net.ipv4.ping_group_range must include group {}", gid);
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn test_ping_group_range() {}
 
     #[cfg(all(not(windows), not(target_os = "linux")))]
     fn test_iface() {
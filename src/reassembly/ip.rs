@@ -0,0 +1,647 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for reassembling fragmented IPv4 and IPv6 packets
+//!
+//! A `FragmentReassembler` is given every captured IPv4 or IPv6 packet. Fragments are held until
+//! the rest of their packet arrives, and then the whole packet is returned, as if it had never
+//! been fragmented.
+//!
+//! ```
+//! use pnet::packet::ipv4::Ipv4Packet;
+//! use pnet::reassembly::ip::{Config, FragmentReassembler};
+//!
+//! let mut reassembler = FragmentReassembler::new(Config::default());
+//! let handle = |packet: Ipv4Packet| println!("{:?}", packet);
+//! # let captured = vec![0x45, 0, 0, 20, 0, 0, 0, 0, 64, 17, 0, 0, 127, 0, 0, 1, 127, 0, 0, 1];
+//! # let packet = Ipv4Packet::new(&captured[..]).unwrap();
+//! // For each captured packet:
+//! if let Some(packet) = reassembler.process_ipv4(&packet) {
+//!     handle(packet);
+//! }
+//! ```
+//!
+//! Fragments which never complete a packet are dropped by `expire()` after a timeout, or when
+//! they take up more memory than allowed.
+
+use packet::Packet;
+use packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use packet::ipv4::{self, Ipv4Flags, Ipv4Packet, MutableIpv4Packet};
+use packet::ipv6::{FragmentPacket, Ipv6Packet, MutableIpv6Packet};
+
+use std::cmp;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// The largest IPv4 packet, or IPv6 payload
+const MAX_LENGTH: usize = 65535;
+
+/// Which data is kept when fragments overlap
+///
+/// Operating systems disagree about this, so a packet with overlapping fragments can reach
+/// different hosts with different contents. Picking the policy of the host a packet was sent to
+/// reassembles it as that host saw it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OverlapPolicy {
+    /// Data from the fragment which arrived first is kept
+    First,
+    /// Data from the fragment which arrived last is kept
+    Last,
+    /// Data from the fragment which arrived first is kept, unless a later fragment starts at a
+    /// lower offset
+    Bsd,
+    /// Data from the fragment which arrived first is kept, unless a later fragment starts at the
+    /// same or a lower offset
+    Linux,
+}
+
+/// Options for a `FragmentReassembler`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// How overlapping fragments are reassembled. Defaults to `OverlapPolicy::First`.
+    pub policy: OverlapPolicy,
+
+    /// How long after its first fragment arrives a packet can take to complete, before
+    /// `expire()` drops it. Defaults to 30 seconds.
+    pub timeout: Duration,
+
+    /// The most fragment data to hold at once. Once there is more, the oldest incomplete packets
+    /// are dropped. Defaults to 4MiB.
+    pub max_memory: usize,
+
+    /// The most fragments a single packet can be split into. Packets with more are dropped.
+    /// Defaults to 128.
+    pub max_fragments: usize,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            policy: OverlapPolicy::First,
+            timeout: Duration::from_secs(30),
+            max_memory: 4 << 20,
+            max_fragments: 128,
+        }
+    }
+}
+
+/// Identifies the fragments of one packet
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    source: IpAddr,
+    destination: IpAddr,
+    /// Only used for IPv4, as IPv6 fragments of the same packet may give different protocols
+    protocol: Option<u8>,
+    identification: u32,
+}
+
+/// The fragments received so far of one packet
+struct Datagram {
+    /// The headers which come before the fragmented data, from the fragment at offset zero
+    header: Option<Vec<u8>>,
+    /// The fragments' data in the order they arrived, with their offsets
+    fragments: Vec<(usize, Vec<u8>)>,
+    /// The length of the fragmented data, once the last fragment has arrived
+    length: Option<usize>,
+    /// The number of bytes held for the packet
+    memory: usize,
+    first_seen: Instant,
+}
+
+impl Datagram {
+    fn new() -> Datagram {
+        Datagram {
+            header: None,
+            fragments: Vec::new(),
+            length: None,
+            memory: 0,
+            first_seen: Instant::now(),
+        }
+    }
+
+    /// Add a fragment, or return false if it shows that the packet is malformed
+    fn add(&mut self, offset: usize, data: &[u8], more: bool, max_fragments: usize) -> bool {
+        let end = offset + data.len();
+        if !more {
+            if self.length.map_or(false, |length| length != end) ||
+               self.fragments.iter().any(|&(offset, ref data)| offset + data.len() > end) {
+                return false;
+            }
+            self.length = Some(end);
+        } else if self.length.map_or(false, |length| end > length) {
+            return false;
+        }
+        if self.fragments.len() >= max_fragments {
+            return false;
+        }
+
+        self.fragments.push((offset, data.to_vec()));
+        self.memory += data.len();
+
+        true
+    }
+
+    fn is_complete(&self) -> bool {
+        let length = match (self.length, &self.header) {
+            (Some(length), &Some(_)) => length,
+            _ => return false,
+        };
+        let mut ranges: Vec<(usize, usize)> = self.fragments
+            .iter()
+            .map(|&(offset, ref data)| (offset, offset + data.len()))
+            .collect();
+        ranges.sort();
+        let mut covered = 0;
+        for (start, end) in ranges {
+            if start > covered {
+                return false;
+            }
+            covered = cmp::max(covered, end);
+        }
+
+        covered >= length
+    }
+
+    /// Put the fragments' data back together, resolving overlaps using `policy`
+    fn assemble(&self, policy: OverlapPolicy) -> Vec<u8> {
+        let length = self.length.unwrap_or(0);
+        let mut data = vec![0u8; length];
+        // The offset of the fragment each byte was taken from
+        let mut owners: Vec<Option<usize>> = vec![None; length];
+        for &(offset, ref fragment) in &self.fragments {
+            for (i, byte) in fragment.iter().enumerate() {
+                let replace = match (owners[offset + i], policy) {
+                    (None, _) => true,
+                    (Some(_), OverlapPolicy::First) => false,
+                    (Some(_), OverlapPolicy::Last) => true,
+                    (Some(owner), OverlapPolicy::Bsd) => offset < owner,
+                    (Some(owner), OverlapPolicy::Linux) => offset <= owner,
+                };
+                if replace {
+                    data[offset + i] = *byte;
+                    owners[offset + i] = Some(offset);
+                }
+            }
+        }
+
+        data
+    }
+}
+
+/// Reassembles fragmented IPv4 and IPv6 packets
+pub struct FragmentReassembler {
+    datagrams: HashMap<Key, Datagram>,
+    config: Config,
+    /// The number of bytes held for all incomplete packets
+    memory: usize,
+}
+
+impl FragmentReassembler {
+    /// Create a new FragmentReassembler
+    pub fn new(config: Config) -> FragmentReassembler {
+        FragmentReassembler {
+            datagrams: HashMap::new(),
+            config: config,
+            memory: 0,
+        }
+    }
+
+    /// Add a captured IPv4 packet
+    ///
+    /// Returns the packet if it isn't a fragment, the reassembled packet if it was the last
+    /// missing fragment of one, and `None` otherwise. Malformed fragments are ignored.
+    pub fn process_ipv4(&mut self, packet: &Ipv4Packet) -> Option<Ipv4Packet<'static>> {
+        let bytes = packet.packet();
+        let header_length = packet.get_header_length() as usize * 4;
+        let total_length = cmp::min(packet.get_total_length() as usize, bytes.len());
+        let more = packet.get_flags() & Ipv4Flags::MoreFragments != 0;
+        let offset = packet.get_fragment_offset() as usize * 8;
+        if !more && offset == 0 {
+            return Ipv4Packet::owned(bytes.to_vec());
+        }
+        if header_length < 20 || total_length < header_length {
+            return None;
+        }
+
+        let key = Key {
+            source: IpAddr::V4(packet.get_source()),
+            destination: IpAddr::V4(packet.get_destination()),
+            protocol: Some(packet.get_next_level_protocol().0),
+            identification: packet.get_identification() as u32,
+        };
+        let header = if offset == 0 {
+            Some(bytes[..header_length].to_vec())
+        } else {
+            None
+        };
+        let data = &bytes[header_length..total_length];
+        if header_length + offset + data.len() > MAX_LENGTH {
+            return None;
+        }
+
+        let mut buffer = self.add(key, header, offset, data, more)?;
+        let length = buffer.len();
+        {
+            let mut reassembled = MutableIpv4Packet::new(&mut buffer[..]).unwrap();
+            reassembled.set_total_length(length as u16);
+            let flags = reassembled.get_flags() & !Ipv4Flags::MoreFragments;
+            reassembled.set_flags(flags);
+            reassembled.set_fragment_offset(0);
+            let checksum = ipv4::checksum(&reassembled.to_immutable());
+            reassembled.set_checksum(checksum);
+        }
+
+        Ipv4Packet::owned(buffer)
+    }
+
+    /// Add a captured IPv6 packet
+    ///
+    /// Returns the packet if it isn't a fragment, the reassembled packet if it was the last
+    /// missing fragment of one, and `None` otherwise. Malformed fragments are ignored. The
+    /// reassembled packet has no Fragment header.
+    pub fn process_ipv6(&mut self, packet: &Ipv6Packet) -> Option<Ipv6Packet<'static>> {
+        let bytes = packet.packet();
+        let end = cmp::min(40 + packet.get_payload_length() as usize, bytes.len());
+
+        // Every fragment repeats the extension headers before the Fragment header
+        let mut next_header = packet.get_next_header();
+        let mut next_header_position = 6;
+        let mut position = 40;
+        loop {
+            match next_header {
+                IpNextHeaderProtocols::Hopopt |
+                IpNextHeaderProtocols::Ipv6Route |
+                IpNextHeaderProtocols::Ipv6Opts if position + 2 <= end => {
+                    next_header = IpNextHeaderProtocol(bytes[position]);
+                    next_header_position = position;
+                    position += (bytes[position + 1] as usize + 1) * 8;
+                }
+                IpNextHeaderProtocols::Ipv6Frag => break,
+                _ => return Ipv6Packet::owned(bytes.to_vec()),
+            }
+        }
+        if position > end {
+            return None;
+        }
+        let fragment = FragmentPacket::new(&bytes[position..end])?;
+        let offset = fragment.get_fragment_offset() as usize * 8;
+        let more = fragment.get_more_fragments() != 0;
+        let data = &bytes[position + 8..end];
+        if position - 40 + offset + data.len() > MAX_LENGTH {
+            return None;
+        }
+
+        let mut header = bytes[..position].to_vec();
+        // The reassembled packet's data follows the headers directly
+        header[next_header_position] = fragment.get_next_header().0;
+        let mut buffer = if !more && offset == 0 {
+            // An "atomic" fragment is a whole packet, and unrelated to any other fragments
+            let mut buffer = header;
+            buffer.extend_from_slice(data);
+            buffer
+        } else {
+            let key = Key {
+                source: IpAddr::V6(packet.get_source()),
+                destination: IpAddr::V6(packet.get_destination()),
+                protocol: None,
+                identification: fragment.get_identification(),
+            };
+            let header = if offset == 0 { Some(header) } else { None };
+            self.add(key, header, offset, data, more)?
+        };
+        let length = buffer.len();
+        MutableIpv6Packet::new(&mut buffer[..]).unwrap().set_payload_length((length - 40) as u16);
+
+        Ipv6Packet::owned(buffer)
+    }
+
+    /// Add a fragment, returning the headers and data of its packet if it is now complete
+    fn add(&mut self,
+           key: Key,
+           header: Option<Vec<u8>>,
+           offset: usize,
+           data: &[u8],
+           more: bool)
+        -> Option<Vec<u8>> {
+        // Only the last fragment may end part way through a unit of 8 bytes
+        if more && data.len() % 8 != 0 {
+            return None;
+        }
+
+        let (valid, complete) = {
+            let datagram = self.datagrams.entry(key).or_insert_with(Datagram::new);
+            let before = datagram.memory;
+            if let (Some(header), true) = (header, datagram.header.is_none()) {
+                datagram.memory += header.len();
+                datagram.header = Some(header);
+            }
+            let valid = datagram.add(offset, data, more, self.config.max_fragments);
+            self.memory += datagram.memory - before;
+
+            (valid, valid && datagram.is_complete())
+        };
+
+        if !valid {
+            self.remove(&key);
+            None
+        } else if complete {
+            let datagram = self.remove(&key).unwrap();
+            let mut buffer = datagram.header.clone().unwrap();
+            buffer.extend(datagram.assemble(self.config.policy));
+            Some(buffer)
+        } else {
+            self.enforce_memory_limit();
+            None
+        }
+    }
+
+    fn remove(&mut self, key: &Key) -> Option<Datagram> {
+        let datagram = self.datagrams.remove(key);
+        if let Some(ref datagram) = datagram {
+            self.memory -= datagram.memory;
+        }
+
+        datagram
+    }
+
+    /// Drop the oldest incomplete packets until the memory limit is met
+    fn enforce_memory_limit(&mut self) {
+        while self.memory > self.config.max_memory {
+            let oldest = self.datagrams
+                .iter()
+                .min_by_key(|&(_, datagram)| datagram.first_seen)
+                .map(|(key, _)| *key);
+            match oldest {
+                Some(key) => {
+                    self.remove(&key);
+                }
+                None => return,
+            }
+        }
+    }
+
+    /// Drop the incomplete packets whose first fragment arrived longer ago than the timeout,
+    /// returning how many were dropped
+    pub fn expire(&mut self) -> usize {
+        let timeout = self.config.timeout;
+        let expired: Vec<Key> = self.datagrams
+            .iter()
+            .filter(|&(_, datagram)| datagram.first_seen.elapsed() >= timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in &expired {
+            self.remove(key);
+        }
+
+        expired.len()
+    }
+
+    /// The number of incomplete packets being held
+    pub fn pending(&self) -> usize {
+        self.datagrams.len()
+    }
+
+    /// The number of bytes held for incomplete packets
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use packet::Packet;
+    use packet::ip::IpNextHeaderProtocols;
+    use packet::ipv4::{self, Ipv4Flags, Ipv4Packet, MutableIpv4Packet};
+    use packet::ipv6::{Ipv6Packet, MutableFragmentPacket, MutableIpv6Packet};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::thread;
+    use std::time::Duration;
+    use super::{Config, FragmentReassembler, OverlapPolicy};
+
+    /// Build an IPv4 packet, or a fragment of one
+    fn ipv4(identification: u16, offset: usize, more: bool, payload: &[u8]) -> Vec<u8> {
+        let mut buffer = vec![0u8; 20 + payload.len()];
+        {
+            let mut packet = MutableIpv4Packet::new(&mut buffer[..]).unwrap();
+            packet.set_version(4);
+            packet.set_header_length(5);
+            packet.set_total_length(20 + payload.len() as u16);
+            packet.set_identification(identification);
+            packet.set_flags(if more { Ipv4Flags::MoreFragments } else { 0 });
+            packet.set_fragment_offset(offset as u16 / 8);
+            packet.set_ttl(64);
+            packet.set_next_level_protocol(IpNextHeaderProtocols::Udp);
+            packet.set_source(Ipv4Addr::new(10, 0, 0, 1));
+            packet.set_destination(Ipv4Addr::new(10, 0, 0, 2));
+            packet.set_payload(payload);
+            let checksum = ipv4::checksum(&packet.to_immutable());
+            packet.set_checksum(checksum);
+        }
+
+        buffer
+    }
+
+    fn process_ipv4(reassembler: &mut FragmentReassembler, packet: &[u8]) -> Option<Vec<u8>> {
+        reassembler.process_ipv4(&Ipv4Packet::new(packet).unwrap())
+            .map(|packet| packet.packet().to_vec())
+    }
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn unfragmented() {
+        let mut reassembler = FragmentReassembler::new(Config::default());
+        let packet = ipv4(1, 0, false, b"whole");
+        assert_eq!(process_ipv4(&mut reassembler, &packet), Some(packet));
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn ipv4_in_any_order() {
+        let payload = payload(100);
+        let whole = ipv4(7, 0, false, &payload);
+        let fragments = vec![ipv4(7, 0, true, &payload[..32]),
+                             ipv4(7, 32, true, &payload[32..64]),
+                             ipv4(7, 64, false, &payload[64..])];
+        for order in &[[0, 1, 2], [2, 1, 0], [1, 2, 0], [2, 0, 1]] {
+            let mut reassembler = FragmentReassembler::new(Config::default());
+            assert_eq!(process_ipv4(&mut reassembler, &fragments[order[0]]), None);
+            assert_eq!(process_ipv4(&mut reassembler, &fragments[order[1]]), None);
+            assert!(reassembler.memory() > 0);
+            // Duplicates don't matter
+            assert_eq!(process_ipv4(&mut reassembler, &fragments[order[1]]), None);
+            assert_eq!(process_ipv4(&mut reassembler, &fragments[order[2]]),
+                       Some(whole.clone()));
+            assert_eq!(reassembler.pending(), 0);
+            assert_eq!(reassembler.memory(), 0);
+        }
+    }
+
+    #[test]
+    fn separate_packets() {
+        let mut reassembler = FragmentReassembler::new(Config::default());
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(1, 0, true, &[1; 8])), None);
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(2, 8, false, &[2; 8])), None);
+        assert_eq!(reassembler.pending(), 2);
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(2, 0, true, &[2; 8])),
+                   Some(ipv4(2, 0, false, &[2; 16])));
+        assert_eq!(reassembler.pending(), 1);
+    }
+
+    #[test]
+    fn overlap_policies() {
+        // Units of 8 bytes each: the fragment at offset 0 arrives twice, overlapping the
+        // second time with another fragment which started later, and the last fragment
+        // overlaps one which started earlier
+        let fragments = vec![ipv4(3, 0, true, &[1; 8]),
+                             ipv4(3, 8, true, &[2; 16]),
+                             ipv4(3, 0, true, &[3; 16]),
+                             ipv4(3, 16, false, &[4; 16])];
+        for &(policy, units) in &[(OverlapPolicy::First, [1, 2, 2, 4]),
+                                  (OverlapPolicy::Last, [3, 3, 4, 4]),
+                                  (OverlapPolicy::Bsd, [1, 3, 2, 4]),
+                                  (OverlapPolicy::Linux, [3, 3, 2, 4])] {
+            let mut reassembler = FragmentReassembler::new(Config {
+                policy: policy,
+                ..Default::default()
+            });
+            let mut result = None;
+            for fragment in &fragments {
+                result = process_ipv4(&mut reassembler, fragment);
+            }
+            let expected: Vec<u8> = units.iter().flat_map(|unit| vec![*unit; 8]).collect();
+            assert_eq!(result, Some(ipv4(3, 0, false, &expected)), "{:?}", policy);
+        }
+    }
+
+    #[test]
+    fn malformed_fragments() {
+        let mut reassembler = FragmentReassembler::new(Config::default());
+        // Fragments other than the last must be a multiple of 8 bytes long
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(1, 0, true, &[0; 7])), None);
+        assert_eq!(reassembler.pending(), 0);
+
+        // Fragments past the end of the packet drop it
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(1, 8, false, &[0; 8])), None);
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(1, 16, true, &[0; 8])), None);
+        assert_eq!(reassembler.pending(), 0);
+
+        // As do conflicting last fragments
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(1, 8, false, &[0; 8])), None);
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(1, 8, false, &[0; 16])), None);
+        assert_eq!(reassembler.pending(), 0);
+        assert_eq!(reassembler.memory(), 0);
+
+        // And fragments which would make the packet too long are ignored
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(1, 65520, false, &[0; 16])), None);
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn limits() {
+        let mut reassembler = FragmentReassembler::new(Config {
+            max_memory: 40,
+            ..Default::default()
+        });
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(1, 8, true, &[0; 8])), None);
+        thread::sleep(Duration::from_millis(1));
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(2, 8, true, &[0; 8])), None);
+        assert_eq!(reassembler.memory(), 16);
+        // The oldest packet is dropped to make room for the header
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(2, 0, true, &[0; 8])), None);
+        assert_eq!(reassembler.memory(), 20 + 8 + 8);
+        assert_eq!(reassembler.pending(), 1);
+
+        let mut reassembler = FragmentReassembler::new(Config {
+            max_fragments: 2,
+            ..Default::default()
+        });
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(1, 0, true, &[0; 8])), None);
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(1, 8, true, &[0; 8])), None);
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(1, 16, false, &[0; 8])), None);
+        assert_eq!(reassembler.pending(), 0);
+        assert_eq!(reassembler.memory(), 0);
+    }
+
+    #[test]
+    fn expire() {
+        let mut reassembler = FragmentReassembler::new(Config {
+            timeout: Duration::from_millis(20),
+            ..Default::default()
+        });
+        assert_eq!(process_ipv4(&mut reassembler, &ipv4(1, 0, true, &[0; 8])), None);
+        assert_eq!(reassembler.expire(), 0);
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(reassembler.expire(), 1);
+        assert_eq!(reassembler.pending(), 0);
+        assert_eq!(reassembler.memory(), 0);
+    }
+
+    /// Build an IPv6 packet with a hop-by-hop options header, and a Fragment header if
+    /// `fragment` is given
+    fn ipv6(fragment: Option<(usize, bool)>, payload: &[u8]) -> Vec<u8> {
+        let fragment_length = if fragment.is_some() { 8 } else { 0 };
+        let mut buffer = vec![0u8; 40 + 8 + fragment_length + payload.len()];
+        {
+            let mut packet = MutableIpv6Packet::new(&mut buffer[..]).unwrap();
+            packet.set_version(6);
+            packet.set_payload_length((8 + fragment_length + payload.len()) as u16);
+            packet.set_next_header(IpNextHeaderProtocols::Hopopt);
+            packet.set_hop_limit(64);
+            packet.set_source(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+            packet.set_destination(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 2));
+        }
+        // A hop-by-hop options header containing only padding
+        buffer[40] = match fragment {
+            Some(_) => IpNextHeaderProtocols::Ipv6Frag.0,
+            None => IpNextHeaderProtocols::Udp.0,
+        };
+        buffer[42] = 1;
+        buffer[43] = 4;
+        if let Some((offset, more)) = fragment {
+            let mut header = MutableFragmentPacket::new(&mut buffer[48..]).unwrap();
+            header.set_next_header(IpNextHeaderProtocols::Udp);
+            header.set_fragment_offset(offset as u16 / 8);
+            header.set_more_fragments(more as u8);
+            header.set_identification(0x12345678);
+        }
+        let start = buffer.len() - payload.len();
+        buffer[start..].copy_from_slice(payload);
+
+        buffer
+    }
+
+    fn process_ipv6(reassembler: &mut FragmentReassembler, packet: &[u8]) -> Option<Vec<u8>> {
+        reassembler.process_ipv6(&Ipv6Packet::new(packet).unwrap())
+            .map(|packet| packet.packet().to_vec())
+    }
+
+    #[test]
+    fn ipv6_fragments() {
+        let payload = payload(40);
+        let whole = ipv6(None, &payload);
+        let mut reassembler = FragmentReassembler::new(Config::default());
+        assert_eq!(process_ipv6(&mut reassembler, &whole), Some(whole.clone()));
+
+        assert_eq!(process_ipv6(&mut reassembler, &ipv6(Some((24, false)), &payload[24..])),
+                   None);
+        assert_eq!(process_ipv6(&mut reassembler, &ipv6(Some((0, true)), &payload[..24])),
+                   Some(whole.clone()));
+        assert_eq!(reassembler.pending(), 0);
+
+        // Atomic fragments are returned straight away
+        assert_eq!(process_ipv6(&mut reassembler, &ipv6(Some((24, true)), &payload[24..32])),
+                   None);
+        assert_eq!(process_ipv6(&mut reassembler, &ipv6(Some((0, false)), &payload)),
+                   Some(whole));
+        assert_eq!(reassembler.pending(), 1);
+    }
+}
@@ -0,0 +1,15 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for reassembling data which was split across several packets
+//!
+//! These work on captured packets, for example from a datalink channel, and put back together
+//! what the receiving host's network stack would have seen.
+
+pub mod ip;
+pub mod tcp;
@@ -0,0 +1,701 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for reassembling the byte streams of TCP connections
+//!
+//! A `TcpReassembler` is given every captured TCP segment, along with the addresses from its IP
+//! header. It tracks each connection from its SYN to its FINs or RST, and passes the bytes sent in
+//! each direction to a `StreamHandler` in order, exactly once: retransmitted and overlapping data
+//! is only delivered the first time, and segments which arrive early are held back until the
+//! data before them turns up.
+//!
+//! ```
+//! use pnet::reassembly::tcp::{Config, Connection, Direction, StreamHandler, TcpReassembler};
+//!
+//! struct Printer;
+//!
+//! impl StreamHandler for Printer {
+//!     fn data(&mut self, connection: &Connection, direction: Direction, data: &[u8]) {
+//!         println!("{} -> {} ({:?}): {} bytes",
+//!                  connection.client, connection.server, direction, data.len());
+//!     }
+//! }
+//!
+//! let mut reassembler = TcpReassembler::new(Printer, Config::default());
+//! // For each captured segment:
+//! // reassembler.process(ipv4.get_source().into(), ipv4.get_destination().into(), &tcp);
+//! ```
+
+use packet::Packet;
+use packet::tcp::{TcpFlags, TcpPacket};
+
+use std::cmp;
+use std::collections::{BTreeMap, HashMap};
+use std::collections::hash_map::Entry;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// A TCP connection, identified by the addresses and ports of its two ends
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Connection {
+    /// The end which opened the connection. For connections which were already open when first
+    /// seen, this is the sender of the first segment seen.
+    pub client: SocketAddr,
+    /// The other end of the connection
+    pub server: SocketAddr,
+}
+
+/// One of the two byte streams of a connection
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// The data sent by the client
+    ClientToServer,
+    /// The data sent by the server
+    ServerToClient,
+}
+
+/// Why a connection is no longer tracked
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CloseReason {
+    /// Both ends sent a FIN, and all the data before them was seen
+    Finished,
+    /// One of the ends sent a RST
+    Reset,
+    /// No segments were seen for longer than the idle timeout
+    TimedOut,
+    /// The connection was still open when `TcpReassembler::close_all()` was called
+    Flushed,
+}
+
+/// A change in the state of a connection
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// The first segment of the connection was seen
+    Opened,
+    /// All the data one end will send has been delivered, ending with a FIN
+    Finished(Direction),
+    /// The connection is no longer tracked, and nothing more will be delivered for it
+    Closed(CloseReason),
+}
+
+/// Receives the reassembled streams of connections from a `TcpReassembler`
+pub trait StreamHandler {
+    /// Called with the next bytes of one direction of a connection, in order
+    fn data(&mut self, connection: &Connection, direction: Direction, data: &[u8]);
+
+    /// Called when `len` bytes of one direction of a connection were missed, and will never be
+    /// passed to `data`
+    fn gap(&mut self, _connection: &Connection, _direction: Direction, _len: u64) {}
+
+    /// Called when a connection is opened, finished or closed
+    fn event(&mut self, _connection: &Connection, _event: Event) {}
+}
+
+/// Options for a `TcpReassembler`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The most out of order data to hold back for each direction of a connection. Once there is
+    /// more, the missing data before it is given up on and reported as a gap. Defaults to 1MiB.
+    pub max_buffered: usize,
+
+    /// How long a connection can go without any segments before `expire()` closes it. Defaults to
+    /// five minutes.
+    pub idle_timeout: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            max_buffered: 1 << 20,
+            idle_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+/// The reassembly state of one direction of a connection
+#[derive(Debug, Default)]
+struct HalfStream {
+    /// The sequence number of the next byte to deliver, once the start of the stream is known
+    next_sequence: Option<u32>,
+    /// The number of bytes delivered or skipped so far
+    offset: u64,
+    /// Data which arrived before the data preceding it, by stream offset
+    pending: BTreeMap<u64, Vec<u8>>,
+    /// The total length of the pending data
+    buffered: usize,
+    /// The stream offset of the FIN, once seen
+    fin: Option<u64>,
+    finished: bool,
+}
+
+impl HalfStream {
+    /// Add a segment sent in this direction, delivering whatever data is now in order
+    fn receive<H>(&mut self,
+                  segment: &TcpPacket,
+                  connection: &Connection,
+                  direction: Direction,
+                  max_buffered: usize,
+                  handler: &mut H)
+        where H: StreamHandler
+    {
+        let flags = segment.get_flags();
+        let mut sequence = segment.get_sequence();
+        if flags & TcpFlags::SYN != 0 {
+            // The SYN takes up the first sequence number, before any data
+            sequence = sequence.wrapping_add(1);
+            if self.next_sequence.is_none() {
+                self.next_sequence = Some(sequence);
+            }
+        }
+        let next_sequence = *self.next_sequence.get_or_insert(sequence);
+
+        // Sequence numbers wrap around, so their distance from the next expected one is used
+        let start = self.offset as i64 + sequence.wrapping_sub(next_sequence) as i32 as i64;
+        let payload = segment.payload();
+        let end = start + payload.len() as i64;
+        if flags & TcpFlags::FIN != 0 && self.fin.is_none() && end >= self.offset as i64 {
+            self.fin = Some(end as u64);
+        }
+
+        if end > self.offset as i64 {
+            // Only the part which hasn't already been delivered is new
+            let skip = if start < self.offset as i64 {
+                (self.offset as i64 - start) as usize
+            } else {
+                0
+            };
+            let start = (start + skip as i64) as u64;
+            let data = &payload[skip..];
+            if start == self.offset {
+                handler.data(connection, direction, data);
+                self.advance(data.len() as u64);
+            } else {
+                self.hold(start, data);
+            }
+        }
+        self.deliver_pending(connection, direction, max_buffered, handler);
+
+        if !self.finished && self.fin.is_some() && self.fin <= Some(self.offset) {
+            self.finished = true;
+            handler.event(connection, Event::Finished(direction));
+        }
+    }
+
+    /// Hold back data which arrived out of order. Only the parts which aren't already pending are
+    /// kept, so that overlapping segments are only counted once towards the limit.
+    fn hold(&mut self, start: u64, data: &[u8]) {
+        let end = start + data.len() as u64;
+        let mut position = start;
+        if let Some((&before, existing)) = self.pending.range(..start).next_back() {
+            position = cmp::max(position, before + existing.len() as u64);
+        }
+        let mut uncovered = Vec::new();
+        for (&other, existing) in self.pending.range(start..end) {
+            if other > position {
+                uncovered.push((position, other));
+            }
+            position = cmp::max(position, other + existing.len() as u64);
+        }
+        if position < end {
+            uncovered.push((position, end));
+        }
+
+        for (from, to) in uncovered {
+            let piece = &data[(from - start) as usize..(to - start) as usize];
+            self.pending.insert(from, piece.to_vec());
+            self.buffered += piece.len();
+        }
+    }
+
+    /// Deliver the pending data which is now in order, skipping over missing data while more than
+    /// `max_buffered` bytes are pending
+    fn deliver_pending<H>(&mut self,
+                          connection: &Connection,
+                          direction: Direction,
+                          max_buffered: usize,
+                          handler: &mut H)
+        where H: StreamHandler
+    {
+        loop {
+            let start = match self.pending.keys().next() {
+                Some(start) => *start,
+                None => return,
+            };
+            if start > self.offset {
+                if self.buffered <= max_buffered {
+                    return;
+                }
+                handler.gap(connection, direction, start - self.offset);
+                let missing = start - self.offset;
+                self.advance(missing);
+            }
+
+            let data = self.pending.remove(&start).unwrap();
+            self.buffered -= data.len();
+            // Pending data never overlaps, but it may have been delivered as part of a later
+            // segment which arrived in order
+            let end = start + data.len() as u64;
+            if end > self.offset {
+                let data = &data[(self.offset - start) as usize..];
+                handler.data(connection, direction, data);
+                self.advance(data.len() as u64);
+            }
+        }
+    }
+
+    fn advance(&mut self, len: u64) {
+        self.offset += len;
+        self.next_sequence = self.next_sequence.map(|sequence| sequence.wrapping_add(len as u32));
+    }
+}
+
+/// A tracked connection
+struct State {
+    connection: Connection,
+    /// Indexed by direction, client to server first
+    halves: [HalfStream; 2],
+    last_seen: Instant,
+}
+
+impl State {
+    /// Deliver everything still pending, then report the connection closed
+    fn close<H>(mut self, reason: CloseReason, handler: &mut H)
+        where H: StreamHandler
+    {
+        for &direction in &[Direction::ClientToServer, Direction::ServerToClient] {
+            let half = &mut self.halves[index(direction)];
+            half.deliver_pending(&self.connection, direction, 0, handler);
+        }
+        handler.event(&self.connection, Event::Closed(reason));
+    }
+}
+
+fn index(direction: Direction) -> usize {
+    match direction {
+        Direction::ClientToServer => 0,
+        Direction::ServerToClient => 1,
+    }
+}
+
+/// Reassembles the byte streams of the TCP connections in captured traffic
+pub struct TcpReassembler<H: StreamHandler> {
+    /// Keyed by the two ends of each connection, lowest first
+    connections: HashMap<(SocketAddr, SocketAddr), State>,
+    handler: H,
+    config: Config,
+}
+
+impl<H: StreamHandler> TcpReassembler<H> {
+    /// Create a new TcpReassembler, which passes the streams it reassembles to `handler`
+    pub fn new(handler: H, config: Config) -> TcpReassembler<H> {
+        TcpReassembler {
+            connections: HashMap::new(),
+            handler: handler,
+            config: config,
+        }
+    }
+
+    /// Add a captured segment, sent from `source` to `destination`
+    ///
+    /// Segments which carry neither a SYN nor any data don't open a connection, so late ACKs for
+    /// closed connections are ignored.
+    pub fn process(&mut self, source: IpAddr, destination: IpAddr, segment: &TcpPacket) {
+        let from = SocketAddr::new(source, segment.get_source());
+        let to = SocketAddr::new(destination, segment.get_destination());
+        let key = if from < to { (from, to) } else { (to, from) };
+        let flags = segment.get_flags();
+
+        let done = {
+            let state = match self.connections.entry(key) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    if flags & TcpFlags::RST != 0 ||
+                       (flags & TcpFlags::SYN == 0 && segment.payload().is_empty()) {
+                        return;
+                    }
+                    // Only a SYN-ACK is sent by the server
+                    let connection = if flags & (TcpFlags::SYN | TcpFlags::ACK) ==
+                                        TcpFlags::SYN | TcpFlags::ACK {
+                        Connection {
+                            client: to,
+                            server: from,
+                        }
+                    } else {
+                        Connection {
+                            client: from,
+                            server: to,
+                        }
+                    };
+                    self.handler.event(&connection, Event::Opened);
+                    entry.insert(State {
+                        connection: connection,
+                        halves: [HalfStream::default(), HalfStream::default()],
+                        last_seen: Instant::now(),
+                    })
+                }
+            };
+            state.last_seen = Instant::now();
+            let direction = if from == state.connection.client {
+                Direction::ClientToServer
+            } else {
+                Direction::ServerToClient
+            };
+
+            if flags & TcpFlags::RST != 0 {
+                Some(CloseReason::Reset)
+            } else {
+                state.halves[index(direction)].receive(segment,
+                                                       &state.connection,
+                                                       direction,
+                                                       self.config.max_buffered,
+                                                       &mut self.handler);
+                if state.halves.iter().all(|half| half.finished) {
+                    Some(CloseReason::Finished)
+                } else {
+                    None
+                }
+            }
+        };
+
+        if let Some(reason) = done {
+            let state = self.connections.remove(&key).unwrap();
+            state.close(reason, &mut self.handler);
+        }
+    }
+
+    /// Close the connections which have been idle for longer than the idle timeout
+    pub fn expire(&mut self) {
+        let timeout = self.config.idle_timeout;
+        let expired: Vec<(SocketAddr, SocketAddr)> = self.connections
+            .iter()
+            .filter(|&(_, state)| state.last_seen.elapsed() >= timeout)
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired {
+            let state = self.connections.remove(&key).unwrap();
+            state.close(CloseReason::TimedOut, &mut self.handler);
+        }
+    }
+
+    /// Close every connection, delivering any data still held back, for example at the end of a
+    /// capture
+    pub fn close_all(&mut self) {
+        for (_, state) in self.connections.drain() {
+            state.close(CloseReason::Flushed, &mut self.handler);
+        }
+    }
+
+    /// The connections currently being tracked
+    pub fn connections(&self) -> Vec<Connection> {
+        self.connections.values().map(|state| state.connection).collect()
+    }
+
+    /// Get a reference to the handler
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    /// Get a mutable reference to the handler
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Stop reassembling, and get back the handler. Connections which are still open are dropped
+    /// without being closed; call `close_all()` first to deliver their pending data.
+    pub fn into_handler(self) -> H {
+        self.handler
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use packet::tcp::{MutableTcpPacket, TcpFlags, TcpPacket};
+    use pnet_macros_support::types::*;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::thread;
+    use std::time::Duration;
+    use super::{CloseReason, Config, Connection, Direction, Event, StreamHandler,
+                TcpReassembler};
+
+    #[derive(Debug, PartialEq)]
+    enum Record {
+        Data(Direction, Vec<u8>),
+        Gap(Direction, u64),
+        Event(Event),
+    }
+
+    #[derive(Default)]
+    struct Recorder {
+        records: Vec<(Connection, Record)>,
+    }
+
+    impl StreamHandler for Recorder {
+        fn data(&mut self, connection: &Connection, direction: Direction, data: &[u8]) {
+            self.records.push((*connection, Record::Data(direction, data.to_vec())));
+        }
+
+        fn gap(&mut self, connection: &Connection, direction: Direction, len: u64) {
+            self.records.push((*connection, Record::Gap(direction, len)));
+        }
+
+        fn event(&mut self, connection: &Connection, event: Event) {
+            self.records.push((*connection, Record::Event(event)));
+        }
+    }
+
+    const CLIENT: u16 = 40000;
+    const SERVER: u16 = 80;
+
+    fn client_ip() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))
+    }
+
+    fn server_ip() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))
+    }
+
+    fn connection() -> Connection {
+        Connection {
+            client: SocketAddr::new(client_ip(), CLIENT),
+            server: SocketAddr::new(server_ip(), SERVER),
+        }
+    }
+
+    fn reassembler(config: Config) -> TcpReassembler<Recorder> {
+        TcpReassembler::new(Recorder::default(), config)
+    }
+
+    /// Feed a segment from the client, or from the server if `to_client` is set
+    fn send(reassembler: &mut TcpReassembler<Recorder>,
+            to_client: bool,
+            sequence: u32,
+            flags: u9be,
+            payload: &[u8]) {
+        let mut buffer = vec![0u8; 20 + payload.len()];
+        {
+            let mut segment = MutableTcpPacket::new(&mut buffer[..]).unwrap();
+            let (source, destination) = if to_client {
+                (SERVER, CLIENT)
+            } else {
+                (CLIENT, SERVER)
+            };
+            segment.set_source(source);
+            segment.set_destination(destination);
+            segment.set_sequence(sequence);
+            segment.set_data_offset(5);
+            segment.set_flags(flags);
+            segment.set_payload(payload);
+        }
+        let segment = TcpPacket::new(&buffer[..]).unwrap();
+        if to_client {
+            reassembler.process(server_ip(), client_ip(), &segment);
+        } else {
+            reassembler.process(client_ip(), server_ip(), &segment);
+        }
+    }
+
+    fn records(reassembler: &mut TcpReassembler<Recorder>) -> Vec<Record> {
+        reassembler.handler_mut()
+            .records
+            .drain(..)
+            .map(|(connection, record)| {
+                assert_eq!(connection, self::connection());
+                record
+            })
+            .collect()
+    }
+
+    /// The stream sent in one direction, with gaps as '?'s
+    fn stream(records: &[Record], direction: Direction) -> Vec<u8> {
+        let mut stream = Vec::new();
+        for record in records {
+            match *record {
+                Record::Data(d, ref data) if d == direction => stream.extend_from_slice(data),
+                Record::Gap(d, len) if d == direction => {
+                    stream.extend(vec![b'?'; len as usize])
+                }
+                _ => {}
+            }
+        }
+
+        stream
+    }
+
+    #[test]
+    fn whole_connection() {
+        let mut r = reassembler(Config::default());
+        send(&mut r, false, 1000, TcpFlags::SYN, b"");
+        send(&mut r, true, 5000, TcpFlags::SYN | TcpFlags::ACK, b"");
+        send(&mut r, false, 1001, TcpFlags::ACK, b"");
+        send(&mut r, false, 1001, TcpFlags::ACK | TcpFlags::PSH, b"GET / HTTP/1.0\r\n\r\n");
+        send(&mut r, true, 5001, TcpFlags::ACK | TcpFlags::PSH, b"HTTP/1.0 200 OK\r\n");
+        assert_eq!(r.connections(), vec![connection()]);
+        send(&mut r, true, 5018, TcpFlags::ACK | TcpFlags::FIN, b"\r\n");
+        send(&mut r, false, 1019, TcpFlags::ACK | TcpFlags::FIN, b"");
+        // The final ACK doesn't open a new connection
+        send(&mut r, true, 5021, TcpFlags::ACK, b"");
+
+        assert_eq!(records(&mut r),
+                   vec![Record::Event(Event::Opened),
+                        Record::Data(Direction::ClientToServer, b"GET / HTTP/1.0\r\n\r\n".to_vec()),
+                        Record::Data(Direction::ServerToClient, b"HTTP/1.0 200 OK\r\n".to_vec()),
+                        Record::Data(Direction::ServerToClient, b"\r\n".to_vec()),
+                        Record::Event(Event::Finished(Direction::ServerToClient)),
+                        Record::Event(Event::Finished(Direction::ClientToServer)),
+                        Record::Event(Event::Closed(CloseReason::Finished))]);
+        assert!(r.connections().is_empty());
+    }
+
+    #[test]
+    fn syn_ack_seen_first() {
+        let mut r = reassembler(Config::default());
+        send(&mut r, true, 5000, TcpFlags::SYN | TcpFlags::ACK, b"");
+        send(&mut r, false, 1001, TcpFlags::ACK, b"hi");
+        assert_eq!(r.connections(), vec![connection()]);
+        assert_eq!(stream(&records(&mut r), Direction::ClientToServer), b"hi");
+    }
+
+    #[test]
+    fn out_of_order_and_retransmitted() {
+        let mut r = reassembler(Config::default());
+        send(&mut r, false, 100, TcpFlags::SYN, b"");
+        send(&mut r, false, 107, TcpFlags::ACK, b"ghi");
+        send(&mut r, false, 104, TcpFlags::ACK, b"def");
+        send(&mut r, false, 101, TcpFlags::ACK, b"abc");
+        // A retransmission of delivered data
+        send(&mut r, false, 101, TcpFlags::ACK, b"abcdef");
+        // An overlapping retransmission, only partly new
+        send(&mut r, false, 106, TcpFlags::ACK, b"fghijk");
+        // Overlapping out of order segments
+        send(&mut r, false, 115, TcpFlags::ACK, b"opq");
+        send(&mut r, false, 113, TcpFlags::ACK, b"mnop");
+        send(&mut r, false, 112, TcpFlags::ACK, b"l");
+
+        let records = records(&mut r);
+        assert_eq!(stream(&records, Direction::ClientToServer), b"abcdefghijklmnopq");
+        assert_eq!(records[1], Record::Data(Direction::ClientToServer, b"abc".to_vec()));
+        assert_eq!(records[2], Record::Data(Direction::ClientToServer, b"def".to_vec()));
+    }
+
+    #[test]
+    fn sequence_wraparound() {
+        let mut r = reassembler(Config::default());
+        let isn = u32::max_value() - 3;
+        send(&mut r, false, isn, TcpFlags::SYN, b"");
+        send(&mut r, false, 1, TcpFlags::ACK, b"efgh");
+        send(&mut r, false, isn + 1, TcpFlags::ACK, b"abc");
+        send(&mut r, false, u32::max_value(), TcpFlags::ACK, b"cd");
+        send(&mut r, false, 5, TcpFlags::ACK | TcpFlags::FIN, b"");
+
+        let records = records(&mut r);
+        assert_eq!(stream(&records, Direction::ClientToServer), b"abcdefgh");
+        assert_eq!(records.last(),
+                   Some(&Record::Event(Event::Finished(Direction::ClientToServer))));
+    }
+
+    #[test]
+    fn reset() {
+        let mut r = reassembler(Config::default());
+        send(&mut r, false, 100, TcpFlags::SYN, b"");
+        send(&mut r, false, 101, TcpFlags::ACK, b"abc");
+        send(&mut r, false, 107, TcpFlags::ACK, b"ghi");
+        send(&mut r, true, 0, TcpFlags::RST, b"");
+        // Segments after the reset don't reopen the connection
+        send(&mut r, true, 0, TcpFlags::RST, b"");
+
+        assert_eq!(records(&mut r),
+                   vec![Record::Event(Event::Opened),
+                        Record::Data(Direction::ClientToServer, b"abc".to_vec()),
+                        Record::Gap(Direction::ClientToServer, 3),
+                        Record::Data(Direction::ClientToServer, b"ghi".to_vec()),
+                        Record::Event(Event::Closed(CloseReason::Reset))]);
+        assert!(r.connections().is_empty());
+    }
+
+    #[test]
+    fn memory_limit() {
+        let mut r = reassembler(Config {
+            max_buffered: 8,
+            ..Default::default()
+        });
+        send(&mut r, false, 100, TcpFlags::SYN, b"");
+        send(&mut r, false, 111, TcpFlags::ACK, b"klmn");
+        send(&mut r, false, 105, TcpFlags::ACK, b"efgh");
+        assert_eq!(stream(&records(&mut r), Direction::ClientToServer), b"");
+
+        // Going over the limit gives up on the first missing data, but not the second
+        send(&mut r, false, 117, TcpFlags::ACK, b"qr");
+        assert_eq!(stream(&records(&mut r), Direction::ClientToServer), b"????efgh");
+        send(&mut r, false, 109, TcpFlags::ACK, b"ij");
+        assert_eq!(stream(&records(&mut r), Direction::ClientToServer), b"ijklmn");
+    }
+
+    #[test]
+    fn overlapping_segments_counted_once() {
+        let mut r = reassembler(Config {
+            max_buffered: 8,
+            ..Default::default()
+        });
+        send(&mut r, false, 100, TcpFlags::SYN, b"");
+        send(&mut r, false, 106, TcpFlags::ACK, b"fg");
+        // Overlaps both ends of the pending data, adding "de" and "hi"
+        send(&mut r, false, 104, TcpFlags::ACK, b"defghi");
+        // Only "jk" is new, which brings the pending data up to the limit but not over it
+        send(&mut r, false, 108, TcpFlags::ACK, b"hijk");
+        assert_eq!(stream(&records(&mut r), Direction::ClientToServer), b"");
+
+        send(&mut r, false, 101, TcpFlags::ACK, b"abc");
+        let records = records(&mut r);
+        assert!(!records.iter().any(|record| match *record {
+            Record::Gap(..) => true,
+            _ => false,
+        }));
+        assert_eq!(stream(&records, Direction::ClientToServer), b"abcdefghijk");
+    }
+
+    #[test]
+    fn midstream() {
+        let mut r = reassembler(Config::default());
+        // Without a SYN, the first segment with data starts the stream
+        send(&mut r, true, 7000, TcpFlags::ACK, b"");
+        assert!(r.connections().is_empty());
+        send(&mut r, false, 3000, TcpFlags::ACK, b"abc");
+        send(&mut r, true, 7000, TcpFlags::ACK, b"xyz");
+        send(&mut r, false, 3003, TcpFlags::ACK, b"def");
+
+        let records = records(&mut r);
+        assert_eq!(stream(&records, Direction::ClientToServer), b"abcdef");
+        assert_eq!(stream(&records, Direction::ServerToClient), b"xyz");
+    }
+
+    #[test]
+    fn expire_and_close_all() {
+        let mut r = reassembler(Config {
+            idle_timeout: Duration::from_millis(20),
+            ..Default::default()
+        });
+        send(&mut r, false, 100, TcpFlags::SYN, b"");
+        r.expire();
+        assert_eq!(r.connections().len(), 1);
+        thread::sleep(Duration::from_millis(30));
+        r.expire();
+        assert!(r.connections().is_empty());
+        assert_eq!(records(&mut r),
+                   vec![Record::Event(Event::Opened),
+                        Record::Event(Event::Closed(CloseReason::TimedOut))]);
+
+        send(&mut r, false, 100, TcpFlags::SYN, b"");
+        send(&mut r, false, 104, TcpFlags::ACK, b"def");
+        r.close_all();
+        assert_eq!(records(&mut r),
+                   vec![Record::Event(Event::Opened),
+                        Record::Gap(Direction::ClientToServer, 3),
+                        Record::Data(Direction::ClientToServer, b"def".to_vec()),
+                        Record::Event(Event::Closed(CloseReason::Flushed))]);
+    }
+}
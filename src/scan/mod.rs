@@ -0,0 +1,309 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for scanning networks for hosts
+//!
+//! The scanners send probes to many addresses at a limited rate, and report how each address
+//! answered: `neighbor` finds the hosts of a `Subnet` on the local network, and `syn` finds the
+//! open TCP ports of any IPv4 hosts.
+
+use std::cmp;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub mod neighbor;
+pub mod syn;
+
+/// An IPv4 or IPv6 network, given by an address and a prefix length
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Subnet {
+    address: IpAddr,
+    prefix: u8,
+}
+
+/// Represents an error which occurred whilst parsing a subnet
+#[derive(Copy, Debug, PartialEq, Eq, Clone)]
+pub enum ParseSubnetErr {
+    /// The subnet has no prefix length, eg. 10.0.0.0
+    MissingPrefix,
+    /// The address is not a valid IPv4 or IPv6 address, eg. 10.0.0/8
+    InvalidAddress,
+    /// The prefix length is not a number, or is too long for the address, eg. 10.0.0.0/33
+    InvalidPrefix,
+}
+
+impl Subnet {
+    /// Create the subnet containing `address` with the given prefix length
+    ///
+    /// Any bits of `address` beyond the prefix are ignored. Returns `None` if the prefix is longer
+    /// than the address.
+    pub fn new(address: IpAddr, prefix: u8) -> Option<Subnet> {
+        if prefix > max_prefix(address) {
+            return None;
+        }
+        let subnet = Subnet {
+            address: address,
+            prefix: prefix,
+        };
+
+        Some(Subnet {
+            address: subnet.nth(0),
+            prefix: prefix,
+        })
+    }
+
+    /// The first address of the subnet
+    pub fn address(&self) -> IpAddr {
+        self.address
+    }
+
+    /// The prefix length of the subnet
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+
+    /// Whether `address` is part of the subnet
+    pub fn contains(&self, address: IpAddr) -> bool {
+        match (self.address, address) {
+            (IpAddr::V4(..), IpAddr::V4(..)) |
+            (IpAddr::V6(..), IpAddr::V6(..)) => {
+                to_bits(address) & self.mask() == to_bits(self.address)
+            }
+            _ => false,
+        }
+    }
+
+    /// Iterate over the addresses of the subnet which may belong to hosts
+    ///
+    /// For IPv4 this excludes the network and broadcast addresses, and for IPv6 the subnet-router
+    /// anycast address, except in subnets too small to have them.
+    pub fn hosts(&self) -> Hosts {
+        let last = !self.mask() & width_mask(self.address);
+        let reserved = match self.address {
+            IpAddr::V4(..) => self.prefix < 31,
+            IpAddr::V6(..) => self.prefix < 127,
+        };
+        let (first, last) = match (reserved, self.address) {
+            (true, IpAddr::V4(..)) => (1, last - 1),
+            (true, IpAddr::V6(..)) => (1, last),
+            (false, _) => (0, last),
+        };
+
+        Hosts {
+            subnet: *self,
+            next: Some(first),
+            last: last,
+        }
+    }
+
+    /// The address at offset `n` from the start of the subnet
+    fn nth(&self, n: u128) -> IpAddr {
+        from_bits(self.address, (to_bits(self.address) & self.mask()) | n)
+    }
+
+    /// The bits of an address which are part of the prefix
+    fn mask(&self) -> u128 {
+        let host_bits = max_prefix(self.address) - self.prefix;
+        if host_bits == 128 {
+            0
+        } else {
+            width_mask(self.address) & !((1u128 << host_bits) - 1)
+        }
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}/{}", self.address, self.prefix)
+    }
+}
+
+impl FromStr for Subnet {
+    type Err = ParseSubnetErr;
+    fn from_str(s: &str) -> Result<Subnet, ParseSubnetErr> {
+        let mut parts = s.splitn(2, '/');
+        let address = match parts.next().unwrap().parse() {
+            Ok(address) => address,
+            Err(_) => return Err(ParseSubnetErr::InvalidAddress),
+        };
+        let prefix = match parts.next() {
+            Some(prefix) => prefix,
+            None => return Err(ParseSubnetErr::MissingPrefix),
+        };
+        match prefix.parse() {
+            Ok(prefix) => Subnet::new(address, prefix).ok_or(ParseSubnetErr::InvalidPrefix),
+            Err(_) => Err(ParseSubnetErr::InvalidPrefix),
+        }
+    }
+}
+
+/// An iterator over the host addresses of a `Subnet`, in order
+#[derive(Clone, Debug)]
+pub struct Hosts {
+    subnet: Subnet,
+    next: Option<u128>,
+    last: u128,
+}
+
+impl Iterator for Hosts {
+    type Item = IpAddr;
+
+    fn next(&mut self) -> Option<IpAddr> {
+        match self.next {
+            Some(n) if n <= self.last => {
+                self.next = n.checked_add(1);
+                Some(self.subnet.nth(n))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn max_prefix(address: IpAddr) -> u8 {
+    match address {
+        IpAddr::V4(..) => 32,
+        IpAddr::V6(..) => 128,
+    }
+}
+
+/// All the bits used by addresses of the same family as `address`
+fn width_mask(address: IpAddr) -> u128 {
+    match address {
+        IpAddr::V4(..) => u32::max_value() as u128,
+        IpAddr::V6(..) => u128::max_value(),
+    }
+}
+
+fn to_bits(address: IpAddr) -> u128 {
+    match address {
+        IpAddr::V4(ip) => u32::from(ip) as u128,
+        IpAddr::V6(ip) => u128::from(ip),
+    }
+}
+
+/// Convert `bits` to an address of the same family as `family`
+fn from_bits(family: IpAddr, bits: u128) -> IpAddr {
+    match family {
+        IpAddr::V4(..) => IpAddr::V4(Ipv4Addr::from(bits as u32)),
+        IpAddr::V6(..) => IpAddr::V6(Ipv6Addr::from(bits)),
+    }
+}
+
+/// Spaces out probes so that no more than a given number are sent each second
+struct RateLimiter {
+    interval: Option<Duration>,
+    next: Instant,
+}
+
+impl RateLimiter {
+    /// A rate of zero means there is no limit
+    fn new(rate: u32) -> RateLimiter {
+        let interval = match rate {
+            0 => None,
+            rate => Some(Duration::new(0, 1_000_000_000 / rate)),
+        };
+
+        RateLimiter {
+            interval: interval,
+            next: Instant::now(),
+        }
+    }
+
+    /// Wait until the next probe may be sent
+    ///
+    /// After falling behind, probes are only sent at the normal rate rather than in a burst.
+    fn wait(&mut self) {
+        if let Some(interval) = self.interval {
+            let now = Instant::now();
+            if self.next > now {
+                thread::sleep(self.next - now);
+            }
+            self.next = cmp::max(self.next, now) + interval;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::IpAddr;
+    use std::time::{Duration, Instant};
+    use super::{ParseSubnetErr, RateLimiter, Subnet};
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn subnet_from_str() {
+        let subnet: Subnet = "192.168.1.77/24".parse().unwrap();
+        assert_eq!(subnet.address(), ip("192.168.1.0"));
+        assert_eq!(subnet.prefix(), 24);
+        assert_eq!(subnet.to_string(), "192.168.1.0/24");
+
+        let subnet: Subnet = "fe80::1:2/112".parse().unwrap();
+        assert_eq!(subnet.address(), ip("fe80::1:0"));
+        assert_eq!("0.0.0.0/0".parse::<Subnet>().unwrap().address(), ip("0.0.0.0"));
+        assert_eq!("::/0".parse::<Subnet>().unwrap().address(), ip("::"));
+
+        assert_eq!("10.0.0.0".parse::<Subnet>(), Err(ParseSubnetErr::MissingPrefix));
+        assert_eq!("10.0.0/8".parse::<Subnet>(), Err(ParseSubnetErr::InvalidAddress));
+        assert_eq!("10.0.0.0/33".parse::<Subnet>(), Err(ParseSubnetErr::InvalidPrefix));
+        assert_eq!("::/129".parse::<Subnet>(), Err(ParseSubnetErr::InvalidPrefix));
+        assert_eq!("10.0.0.0/x".parse::<Subnet>(), Err(ParseSubnetErr::InvalidPrefix));
+    }
+
+    #[test]
+    fn subnet_contains() {
+        let subnet: Subnet = "10.1.0.0/16".parse().unwrap();
+        assert!(subnet.contains(ip("10.1.0.0")));
+        assert!(subnet.contains(ip("10.1.255.255")));
+        assert!(!subnet.contains(ip("10.2.0.0")));
+        assert!(!subnet.contains(ip("::a01:0")));
+        assert!("::/0".parse::<Subnet>().unwrap().contains(ip("2001:db8::1")));
+    }
+
+    #[test]
+    fn subnet_hosts() {
+        let hosts = |s: &str| s.parse::<Subnet>().unwrap().hosts().collect::<Vec<_>>();
+        assert_eq!(hosts("10.0.0.0/30"), vec![ip("10.0.0.1"), ip("10.0.0.2")]);
+        assert_eq!(hosts("10.0.0.0/31"), vec![ip("10.0.0.0"), ip("10.0.0.1")]);
+        assert_eq!(hosts("10.0.0.7/32"), vec![ip("10.0.0.7")]);
+        assert_eq!(hosts("192.168.0.0/24").len(), 254);
+        assert_eq!(hosts("2001:db8::/126"),
+                   vec![ip("2001:db8::1"), ip("2001:db8::2"), ip("2001:db8::3")]);
+        assert_eq!(hosts("2001:db8::/127"), vec![ip("2001:db8::"), ip("2001:db8::1")]);
+
+        let mut all = "::/0".parse::<Subnet>().unwrap().hosts();
+        assert_eq!(all.next(), Some(ip("::1")));
+        let mut last = "ffff:ffff:ffff:ffff:ffff:ffff:ffff:fffe/127".parse::<Subnet>()
+            .unwrap()
+            .hosts();
+        assert_eq!(last.nth(1), Some(ip("ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff")));
+        assert_eq!(last.next(), None);
+    }
+
+    #[test]
+    fn rate_limiter() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(100);
+        for _ in 0..6 {
+            limiter.wait();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let start = Instant::now();
+        let mut unlimited = RateLimiter::new(0);
+        for _ in 0..1000 {
+            unlimited.wait();
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,654 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for finding the hosts on the local network segment
+//!
+//! A `Scanner` sends an ARP request (for IPv4) or an ICMPv6 neighbor solicitation (for IPv6) to
+//! every address in a subnet, directly on a network interface, and reports the MAC address of
+//! each host which answers.
+//!
+//! ```no_run
+//! use pnet::datalink;
+//! use pnet::scan::neighbor::{self, Config};
+//!
+//! let interface = datalink::interfaces().into_iter().find(|i| i.name == "eth0").unwrap();
+//! let subnet = "192.168.1.0/24".parse().unwrap();
+//! for host in neighbor::scan(&interface, &subnet, Config::default()).unwrap() {
+//!     println!("{} is at {} ({:?})", host.ip, host.mac, host.rtt);
+//! }
+//! ```
+//!
+//! As with other datalink channels, this needs root privileges on most platforms.
+
+use arp::ArpFrame;
+use datalink::{self, EthernetDataLinkReceiver, EthernetDataLinkSender, NetworkInterface};
+use datalink::Channel::Ethernet;
+use internal::{POLL_INTERVAL_MS, is_transient};
+use packet::{MutablePacket, Packet};
+use packet::arp::{ArpHardwareTypes, ArpOperations, ArpPacket};
+use packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use packet::icmpv6::{self, Icmpv6Packet, Icmpv6Types};
+use packet::icmpv6::ndp::{MutableNeighborSolicitPacket, NdpOption, NdpOptionTypes,
+                          NeighborAdvertPacket};
+use packet::ip::IpNextHeaderProtocols;
+use packet::ipv6::{Ipv6Packet, MutableIpv6Packet};
+use scan::{RateLimiter, Subnet, max_prefix};
+use util::MacAddr;
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv6Addr};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The length of an Ethernet frame carrying a neighbor solicitation with a source link-layer
+/// address option
+const SOLICIT_FRAME_LEN: usize = 14 + 40 + 24 + 8;
+
+/// The most host bits a subnet may have to be scanned, so that scans of, say, an IPv6 /64 are
+/// refused rather than running forever
+const MAX_HOST_BITS: u8 = 16;
+
+/// Options for a `Scanner`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The maximum number of requests to send each second, or 0 for no limit. Defaults to 1000.
+    pub rate: u32,
+
+    /// How long to keep waiting for answers after the last request is sent. Defaults to one
+    /// second.
+    pub timeout: Duration,
+
+    /// The address to send requests from. Defaults to an address of the interface, preferring one
+    /// in the subnet being scanned and, for IPv6, then a link-local one.
+    pub source: Option<IpAddr>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            rate: 1000,
+            timeout: Duration::from_secs(1),
+            source: None,
+        }
+    }
+}
+
+/// A host which answered a scan
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Neighbor {
+    /// The address which was scanned
+    pub ip: IpAddr,
+    /// The MAC address the host gave for it
+    pub mac: MacAddr,
+    /// The time between sending the request and receiving the answer
+    pub rtt: Duration,
+}
+
+/// An answer seen by the receiving thread
+struct Answer {
+    ip: IpAddr,
+    mac: MacAddr,
+    time: Instant,
+}
+
+/// Scans the hosts of a subnet attached to a network interface
+pub struct Scanner {
+    interface: NetworkInterface,
+    config: Config,
+}
+
+impl Scanner {
+    /// Create a new Scanner, which sends requests on `interface`
+    pub fn new(interface: &NetworkInterface, config: Config) -> Scanner {
+        Scanner {
+            interface: interface.clone(),
+            config: config,
+        }
+    }
+
+    /// Scan every host address of `subnet`, returning the hosts which answered in the order their
+    /// answers were received
+    ///
+    /// Subnets of more than 65536 addresses are rejected with `ErrorKind::InvalidInput`.
+    pub fn scan(&self, subnet: &Subnet) -> io::Result<Vec<Neighbor>> {
+        self.scan_with(subnet, |_| {})
+    }
+
+    /// Scan every host address of `subnet`, calling `on_answer` for each host as it answers
+    pub fn scan_with<F>(&self, subnet: &Subnet, on_answer: F) -> io::Result<Vec<Neighbor>>
+        where F: FnMut(&Neighbor)
+    {
+        let config = datalink::Config {
+            read_timeout: Some(Duration::from_millis(POLL_INTERVAL_MS)),
+            ..Default::default()
+        };
+        match datalink::channel(&self.interface, config)? {
+            Ethernet(tx, rx) => self.scan_channel(subnet, tx, rx, on_answer),
+            _ => Err(io::Error::new(io::ErrorKind::Other, "Unsupported datalink channel type")),
+        }
+    }
+
+    /// Scan every host address of `subnet` over an existing datalink channel for the interface
+    ///
+    /// The receiving half of the channel is read by a background thread, which finishes once the
+    /// next packet or read timeout arrives after the scan.
+    pub fn scan_channel<F>(&self,
+                           subnet: &Subnet,
+                           mut tx: Box<EthernetDataLinkSender>,
+                           rx: Box<EthernetDataLinkReceiver>,
+                           mut on_answer: F)
+        -> io::Result<Vec<Neighbor>>
+        where F: FnMut(&Neighbor)
+    {
+        if max_prefix(subnet.address()) - subnet.prefix() > MAX_HOST_BITS {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Subnet is too large to scan"));
+        }
+        let mac = match self.interface.mac {
+            Some(mac) => mac,
+            None => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                          "Interface has no MAC address"))
+            }
+        };
+        let source = self.source(subnet)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (answer_tx, answer_rx) = mpsc::channel();
+        spawn_receiver(rx, answer_tx, stop.clone());
+
+        let result = (|| {
+            let mut sent = HashMap::new();
+            let mut found = Vec::new();
+            let mut limiter = RateLimiter::new(self.config.rate);
+            for target in subnet.hosts().filter(|target| *target != source) {
+                limiter.wait();
+                // Recorded first, so that the answer can't arrive before the request is known
+                sent.insert(target, Instant::now());
+                send_request(&mut tx, mac, source, target)?;
+                while let Ok(answer) = answer_rx.try_recv() {
+                    handle_answer(answer, &mut sent, &mut found, &mut on_answer);
+                }
+            }
+
+            let deadline = Instant::now() + self.config.timeout;
+            loop {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                match answer_rx.recv_timeout(deadline - now) {
+                    Ok(answer) => handle_answer(answer, &mut sent, &mut found, &mut on_answer),
+                    Err(RecvTimeoutError::Timeout) => break,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+
+            Ok(found)
+        })();
+
+        stop.store(true, Ordering::SeqCst);
+
+        result
+    }
+
+    /// Choose the address to send requests for `subnet` from
+    fn source(&self, subnet: &Subnet) -> io::Result<IpAddr> {
+        let ipv6 = subnet.address().is_ipv6();
+        if let Some(source) = self.config.source {
+            return if source.is_ipv6() == ipv6 {
+                Ok(source)
+            } else {
+                Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                   "Source address is from a different family to the subnet"))
+            };
+        }
+
+        let candidates: Vec<IpAddr> = match self.interface.ips {
+            Some(ref ips) => ips.iter().cloned().filter(|ip| ip.is_ipv6() == ipv6).collect(),
+            None => Vec::new(),
+        };
+        let preferred = candidates.iter()
+            .find(|ip| subnet.contains(**ip))
+            .or_else(|| candidates.iter().find(|ip| is_link_local(**ip)))
+            .or_else(|| candidates.first());
+
+        match preferred {
+            Some(ip) => Ok(*ip),
+            None => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                   "Interface has no address to scan the subnet from"))
+            }
+        }
+    }
+}
+
+/// Scan the hosts of `subnet` on `interface` using the given configuration
+pub fn scan(interface: &NetworkInterface,
+            subnet: &Subnet,
+            config: Config)
+    -> io::Result<Vec<Neighbor>> {
+    Scanner::new(interface, config).scan(subnet)
+}
+
+fn is_link_local(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.octets()[0] == 169 && ip.octets()[1] == 254,
+        IpAddr::V6(ip) => ip.segments()[0] & 0xffc0 == 0xfe80,
+    }
+}
+
+/// Record an answer to one of our requests, ignoring unsolicited and repeated answers
+fn handle_answer<F>(answer: Answer,
+                    sent: &mut HashMap<IpAddr, Instant>,
+                    found: &mut Vec<Neighbor>,
+                    on_answer: &mut F)
+    where F: FnMut(&Neighbor)
+{
+    // Gratuitous ARP and unsolicited advertisements may arrive before the request is sent, in
+    // which case the host is still waited for
+    let time = match sent.get(&answer.ip) {
+        Some(&time) if answer.time >= time => time,
+        _ => return,
+    };
+    sent.remove(&answer.ip);
+    let neighbor = Neighbor {
+        ip: answer.ip,
+        mac: answer.mac,
+        rtt: answer.time.duration_since(time),
+    };
+    on_answer(&neighbor);
+    found.push(neighbor);
+}
+
+fn send_request(tx: &mut Box<EthernetDataLinkSender>,
+                mac: MacAddr,
+                source: IpAddr,
+                target: IpAddr)
+    -> io::Result<()> {
+    match (source, target) {
+        (IpAddr::V4(source), IpAddr::V4(target)) => {
+            ArpFrame::request(mac, source, target).send(tx)
+        }
+        (IpAddr::V6(source), IpAddr::V6(target)) => {
+            let result = tx.build_and_send(1, SOLICIT_FRAME_LEN, &mut |mut ethernet| {
+                fill_neighbor_solicit(&mut ethernet, mac, source, target);
+            });
+
+            match result {
+                Some(result) => result,
+                None => Err(io::Error::new(io::ErrorKind::Other, "Unable to build request")),
+            }
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// The solicited-node multicast address which neighbor solicitations for `target` are sent to
+fn solicited_node(target: Ipv6Addr) -> Ipv6Addr {
+    let segments = target.segments();
+    Ipv6Addr::new(0xff02, 0, 0, 0, 0, 1, 0xff00 | (segments[6] & 0xff), segments[7])
+}
+
+fn fill_neighbor_solicit(ethernet: &mut MutableEthernetPacket,
+                         mac: MacAddr,
+                         source: Ipv6Addr,
+                         target: Ipv6Addr) {
+    let destination = solicited_node(target);
+    let octets = destination.octets();
+    ethernet.set_destination(MacAddr(0x33, 0x33, octets[12], octets[13], octets[14], octets[15]));
+    ethernet.set_source(mac);
+    ethernet.set_ethertype(EtherTypes::Ipv6);
+
+    let mut ipv6 = MutableIpv6Packet::new(ethernet.payload_mut()).unwrap();
+    ipv6.set_version(6);
+    ipv6.set_payload_length(24 + 8);
+    ipv6.set_next_header(IpNextHeaderProtocols::Ipv6Icmp);
+    // Neighbor discovery packets are only accepted if they can't have been forwarded
+    ipv6.set_hop_limit(255);
+    ipv6.set_source(source);
+    ipv6.set_destination(destination);
+
+    let checksum = {
+        let mut solicit = MutableNeighborSolicitPacket::new(ipv6.payload_mut()).unwrap();
+        solicit.set_icmpv6_type(Icmpv6Types::NeighborSolicitation);
+        solicit.set_target_addr(target);
+        solicit.set_options(&[NdpOption::source_ll_addr(mac)]);
+
+        icmpv6::checksum(&Icmpv6Packet::new(solicit.packet()).unwrap(), source, destination)
+    };
+    MutableNeighborSolicitPacket::new(ipv6.payload_mut()).unwrap().set_checksum(checksum);
+}
+
+/// Start a thread which passes the answers received on `rx` to `answer_tx`, until `stop` is set
+fn spawn_receiver(mut rx: Box<EthernetDataLinkReceiver>,
+                  answer_tx: Sender<Answer>,
+                  stop: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        let mut iter = rx.iter();
+        while !stop.load(Ordering::SeqCst) {
+            match iter.next() {
+                Ok(packet) => {
+                    if let Some((ip, mac)) = parse_answer(&packet) {
+                        let answer = Answer {
+                            ip: ip,
+                            mac: mac,
+                            time: Instant::now(),
+                        };
+                        if answer_tx.send(answer).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(ref e) if is_transient(e) => {}
+                Err(_) => return,
+            }
+        }
+    });
+}
+
+/// Extract the address and MAC address from an ARP reply or neighbor advertisement
+fn parse_answer(ethernet: &EthernetPacket) -> Option<(IpAddr, MacAddr)> {
+    match ethernet.get_ethertype() {
+        EtherTypes::Arp => {
+            let arp = ArpPacket::new(ethernet.payload())?;
+            if arp.get_operation() != ArpOperations::Reply ||
+               arp.get_hardware_type() != ArpHardwareTypes::Ethernet ||
+               arp.get_protocol_type() != EtherTypes::Ipv4 {
+                return None;
+            }
+
+            Some((IpAddr::V4(arp.get_sender_proto_addr()), arp.get_sender_hw_addr()))
+        }
+        EtherTypes::Ipv6 => {
+            let ipv6 = Ipv6Packet::new(ethernet.payload())?;
+            if ipv6.get_next_header() != IpNextHeaderProtocols::Ipv6Icmp ||
+               ipv6.get_hop_limit() != 255 {
+                return None;
+            }
+            let advert = NeighborAdvertPacket::new(ipv6.payload())?;
+            if advert.get_icmpv6_type() != Icmpv6Types::NeighborAdvertisement {
+                return None;
+            }
+            // The target link-layer address option is only left out when the sender knows we
+            // have its address already, in which case it's also the Ethernet source
+            let mac = advert.get_options_iter()
+                .find(|option| option.get_option_type() == NdpOptionTypes::TargetLLAddr)
+                .and_then(|option| {
+                    let data = option.payload();
+                    if data.len() >= 6 {
+                        Some(MacAddr(data[0], data[1], data[2], data[3], data[4], data[5]))
+                    } else {
+                        None
+                    }
+                })
+                .unwrap_or_else(|| ethernet.get_source());
+
+            Some((IpAddr::V6(advert.get_target_addr()), mac))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arp::ARP_FRAME_LEN;
+    use datalink::Channel::Ethernet;
+    use datalink::{NetworkInterface, dummy};
+    use packet::{MutablePacket, Packet};
+    use packet::arp::{ArpOperations, ArpPacket, MutableArpPacket};
+    use packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+    use packet::icmpv6::{self, Icmpv6Packet, Icmpv6Types};
+    use packet::icmpv6::ndp::{MutableNeighborAdvertPacket, NdpOption, NdpOptionTypes,
+                              NeighborAdvertFlags, NeighborSolicitPacket};
+    use packet::ip::IpNextHeaderProtocols;
+    use packet::ipv6::{Ipv6Packet, MutableIpv6Packet};
+    use scan::Subnet;
+    use std::collections::HashMap;
+    use std::io;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::sync::mpsc::{Receiver, Sender};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use super::{Answer, Config, Scanner, SOLICIT_FRAME_LEN, handle_answer,
+                solicited_node};
+    use util::MacAddr;
+
+    fn peer_mac(i: u8) -> MacAddr {
+        MacAddr::new(2, 0, 0, 0, 0, i)
+    }
+
+    fn interface(ips: Vec<IpAddr>) -> NetworkInterface {
+        NetworkInterface { ips: Some(ips), ..dummy::dummy_interface(0) }
+    }
+
+    fn channel() -> (dummy::Config, Sender<io::Result<Box<[u8]>>>, Receiver<Box<[u8]>>) {
+        let mut config = dummy::Config::default();
+        let inject_handle = config.inject_handle().unwrap();
+        let read_handle = config.read_handle().unwrap();
+
+        (config, inject_handle, read_handle)
+    }
+
+    /// Scan `subnet` while `peer` plays the part of the hosts on the network
+    fn scan<F>(interface: NetworkInterface, subnet: &str, peer: F) -> Vec<super::Neighbor>
+        where F: FnOnce(Sender<io::Result<Box<[u8]>>>, Receiver<Box<[u8]>>) + Send + 'static
+    {
+        let (dummy_config, inject_handle, read_handle) = channel();
+        let (tx, rx) = match dummy::channel(&interface, dummy_config) {
+            Ok(Ethernet(tx, rx)) => (tx, rx),
+            _ => panic!("Not a valid channel returned"),
+        };
+        let peer = thread::spawn(move || peer(inject_handle, read_handle));
+
+        let config = Config {
+            timeout: Duration::from_millis(200),
+            ..Default::default()
+        };
+        let subnet: Subnet = subnet.parse().unwrap();
+        let scanner = Scanner::new(&interface, config);
+        let mut answered = Vec::new();
+        let found = scanner.scan_channel(&subnet, tx, rx, |neighbor| answered.push(*neighbor))
+            .unwrap();
+        peer.join().unwrap();
+        assert_eq!(found, answered);
+
+        found
+    }
+
+    fn arp_reply(ip: Ipv4Addr, mac: MacAddr, request: &ArpPacket) -> Box<[u8]> {
+        let mut buffer = vec![0u8; ARP_FRAME_LEN];
+        {
+            let mut ethernet = MutableEthernetPacket::new(&mut buffer[..]).unwrap();
+            ethernet.set_destination(request.get_sender_hw_addr());
+            ethernet.set_source(mac);
+            ethernet.set_ethertype(EtherTypes::Arp);
+            let mut arp = MutableArpPacket::new(ethernet.payload_mut()).unwrap();
+            arp.clone_from(request);
+            arp.set_operation(ArpOperations::Reply);
+            arp.set_sender_hw_addr(mac);
+            arp.set_sender_proto_addr(ip);
+            arp.set_target_hw_addr(request.get_sender_hw_addr());
+            arp.set_target_proto_addr(request.get_sender_proto_addr());
+        }
+
+        buffer.into_boxed_slice()
+    }
+
+    fn neighbor_advert(target: Ipv6Addr, mac: MacAddr, solicitation: &Ipv6Packet) -> Box<[u8]> {
+        let mut buffer = vec![0u8; SOLICIT_FRAME_LEN];
+        {
+            let mut ethernet = MutableEthernetPacket::new(&mut buffer[..]).unwrap();
+            ethernet.set_source(mac);
+            ethernet.set_ethertype(EtherTypes::Ipv6);
+            let mut ipv6 = MutableIpv6Packet::new(ethernet.payload_mut()).unwrap();
+            ipv6.set_version(6);
+            ipv6.set_payload_length(32);
+            ipv6.set_next_header(IpNextHeaderProtocols::Ipv6Icmp);
+            ipv6.set_hop_limit(255);
+            ipv6.set_source(target);
+            ipv6.set_destination(solicitation.get_source());
+            let mut advert = MutableNeighborAdvertPacket::new(ipv6.payload_mut()).unwrap();
+            advert.set_icmpv6_type(Icmpv6Types::NeighborAdvertisement);
+            advert.set_flags(NeighborAdvertFlags::Solicited);
+            advert.set_target_addr(target);
+            advert.set_options(&[NdpOption::target_ll_addr(mac)]);
+        }
+
+        buffer.into_boxed_slice()
+    }
+
+    #[test]
+    fn arp_scan() {
+        let ips = vec![IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)),
+                       IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))];
+        let found = scan(interface(ips), "10.0.0.0/29", |inject_handle, read_handle| {
+            // Every host address except our own is asked for
+            let mut targets = Vec::new();
+            for _ in 0..5 {
+                let buffer = read_handle.recv_timeout(Duration::from_secs(1)).unwrap();
+                let ethernet = EthernetPacket::new(&buffer[..]).unwrap();
+                assert_eq!(ethernet.get_destination(),
+                           MacAddr::new(0xff, 0xff, 0xff, 0xff, 0xff, 0xff));
+                assert_eq!(ethernet.get_ethertype(), EtherTypes::Arp);
+                let request = ArpPacket::new(ethernet.payload()).unwrap();
+                assert_eq!(request.get_operation(), ArpOperations::Request);
+                assert_eq!(request.get_sender_hw_addr(), MacAddr::new(1, 2, 3, 4, 5, 0));
+                assert_eq!(request.get_sender_proto_addr(), Ipv4Addr::new(10, 0, 0, 2));
+                let target = request.get_target_proto_addr();
+                targets.push(target.octets()[3]);
+
+                if target.octets()[3] % 2 == 1 {
+                    let mac = peer_mac(target.octets()[3]);
+                    inject_handle.send(Ok(arp_reply(target, mac, &request))).unwrap();
+                    // Repeated answers are ignored
+                    inject_handle.send(Ok(arp_reply(target, mac, &request))).unwrap();
+                }
+            }
+            assert_eq!(targets, vec![1, 3, 4, 5, 6]);
+            assert!(read_handle.try_recv().is_err());
+        });
+
+        let found: Vec<(IpAddr, MacAddr)> = found.iter().map(|n| (n.ip, n.mac)).collect();
+        assert_eq!(found,
+                   vec![(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)), peer_mac(1)),
+                        (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3)), peer_mac(3)),
+                        (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 5)), peer_mac(5))]);
+    }
+
+    #[test]
+    fn ndp_scan() {
+        let source = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0xabcd);
+        let ips = vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                       IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+                       IpAddr::V6(source)];
+        let found = scan(interface(ips), "fe80::1:0/126", move |inject_handle, read_handle| {
+            for i in 1..4 {
+                let target = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 1, i);
+                let buffer = read_handle.recv_timeout(Duration::from_secs(1)).unwrap();
+                let ethernet = EthernetPacket::new(&buffer[..]).unwrap();
+                assert_eq!(ethernet.get_destination(),
+                           MacAddr::new(0x33, 0x33, 0xff, 1, 0, i as u8));
+                assert_eq!(ethernet.get_ethertype(), EtherTypes::Ipv6);
+                let ipv6 = Ipv6Packet::new(ethernet.payload()).unwrap();
+                assert_eq!(ipv6.get_hop_limit(), 255);
+                assert_eq!(ipv6.get_source(), source);
+                assert_eq!(ipv6.get_destination(), solicited_node(target));
+                let icmpv6 = Icmpv6Packet::new(ipv6.payload()).unwrap();
+                assert_eq!(icmpv6.get_checksum(),
+                           icmpv6::checksum(&icmpv6, source, ipv6.get_destination()));
+                let solicit = NeighborSolicitPacket::new(ipv6.payload()).unwrap();
+                assert_eq!(solicit.get_icmpv6_type(), Icmpv6Types::NeighborSolicitation);
+                assert_eq!(solicit.get_target_addr(), target);
+                let option = solicit.get_options_iter().next().unwrap();
+                assert_eq!(option.get_option_type(), NdpOptionTypes::SourceLLAddr);
+                assert_eq!(option.payload(), &[1, 2, 3, 4, 5, 0]);
+
+                if i != 2 {
+                    let advert = neighbor_advert(target, peer_mac(i as u8), &ipv6);
+                    inject_handle.send(Ok(advert)).unwrap();
+                }
+            }
+        });
+
+        let found: Vec<(IpAddr, MacAddr)> = found.iter().map(|n| (n.ip, n.mac)).collect();
+        assert_eq!(found,
+                   vec![(IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 1, 1)), peer_mac(1)),
+                        (IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 1, 3)), peer_mac(3))]);
+    }
+
+    #[test]
+    fn solicited_node_address() {
+        assert_eq!(solicited_node("2001:db8::12:3456:789a".parse().unwrap()),
+                   "ff02::1:ff56:789a".parse::<Ipv6Addr>().unwrap());
+    }
+
+    #[test]
+    fn missing_source() {
+        let (dummy_config, _inject_handle, _read_handle) = channel();
+        let interface = interface(vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2))]);
+        let (tx, rx) = match dummy::channel(&interface, dummy_config) {
+            Ok(Ethernet(tx, rx)) => (tx, rx),
+            _ => panic!("Not a valid channel returned"),
+        };
+
+        let scanner = Scanner::new(&interface, Config::default());
+        let err = scanner.scan_channel(&"fe80::/120".parse().unwrap(), tx, rx, |_| {})
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn subnet_too_large() {
+        let interface = interface(vec![IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+                                       IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1))]);
+        for subnet in &["10.0.0.0/15", "fe80::/64"] {
+            let (dummy_config, _inject_handle, read_handle) = channel();
+            let (tx, rx) = match dummy::channel(&interface, dummy_config) {
+                Ok(Ethernet(tx, rx)) => (tx, rx),
+                _ => panic!("Not a valid channel returned"),
+            };
+
+            let scanner = Scanner::new(&interface, Config::default());
+            let err = scanner.scan_channel(&subnet.parse().unwrap(), tx, rx, |_| {}).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(read_handle.try_recv().is_err());
+        }
+    }
+
+    #[test]
+    fn answer_before_request() {
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let answered = Instant::now();
+        let mut sent = HashMap::new();
+        sent.insert(ip, answered + Duration::from_millis(10));
+        let mut found = Vec::new();
+
+        let answer = Answer {
+            ip: ip,
+            mac: peer_mac(1),
+            time: answered,
+        };
+        handle_answer(answer, &mut sent, &mut found, &mut |_| {});
+        assert!(found.is_empty());
+        assert!(sent.contains_key(&ip));
+
+        let answer = Answer {
+            ip: ip,
+            mac: peer_mac(1),
+            time: answered + Duration::from_millis(30),
+        };
+        handle_answer(answer, &mut sent, &mut found, &mut |_| {});
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].rtt, Duration::from_millis(20));
+        assert!(sent.is_empty());
+    }
+}
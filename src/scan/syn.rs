@@ -0,0 +1,583 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for finding open TCP ports by sending SYN segments
+//!
+//! A `SynScanner` sends a single SYN to every port of every target, in a random order, and
+//! classifies each port by its answer: a SYN-ACK means it is open, a RST that it is closed, and no
+//! answer at all that it is filtered.
+//!
+//! The scan is stateless: the initial sequence number of each SYN is a keyed hash of its
+//! addresses and ports, so an answer is recognised by its acknowledgement number alone, rather
+//! than by looking up the probe which caused it.
+//!
+//! ```no_run
+//! use pnet::scan::syn::{self, Config, PortState};
+//! use std::net::Ipv4Addr;
+//!
+//! let targets = [Ipv4Addr::new(192, 168, 1, 1)];
+//! for port in syn::scan(&targets, &[22, 80, 443], Config::default()).unwrap() {
+//!     if port.state == PortState::Open {
+//!         println!("{}:{} is open", port.target, port.port);
+//!     }
+//! }
+//! ```
+//!
+//! The SYNs are sent on a raw socket, which needs root privileges. The operating system doesn't
+//! know about the connections being opened, so it answers each SYN-ACK with a RST.
+
+use internal::{POLL_INTERVAL_MS, is_transient};
+use packet::ip::IpNextHeaderProtocols;
+use packet::tcp::{self, MutableTcpPacket, TcpFlags, TcpPacket};
+use scan::RateLimiter;
+use transport::{self, TransportChannelType, TransportReceiver, TransportSender, packet_iter};
+use transport::TransportProtocol::Ipv4;
+
+use std::collections::HashMap;
+use std::collections::hash_map::{Entry, RandomState};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The size of a TCP header without options
+const TCP_HEADER_LEN: usize = 20;
+
+/// Options for a `SynScanner`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The maximum number of SYNs to send each second, or 0 for no limit. Defaults to 1000.
+    pub rate: u32,
+
+    /// How long to keep waiting for answers after the last SYN is sent. Defaults to one second.
+    pub timeout: Duration,
+
+    /// The number of times to send another SYN to ports which haven't answered. Defaults to 0.
+    pub retries: usize,
+
+    /// The address to send from. Defaults to the address the operating system would use to
+    /// connect to each target.
+    pub source: Option<Ipv4Addr>,
+
+    /// The port to send from. Defaults to a random port in the dynamic range.
+    pub source_port: Option<u16>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            rate: 1000,
+            timeout: Duration::from_secs(1),
+            retries: 0,
+            source: None,
+            source_port: None,
+        }
+    }
+}
+
+/// The state of a scanned port
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PortState {
+    /// The target answered with a SYN-ACK, so is listening on the port
+    Open,
+    /// The target answered with a RST, so isn't listening on the port
+    Closed,
+    /// There was no answer, so the SYNs or their answers were probably dropped by a firewall
+    Filtered,
+}
+
+/// The result of scanning one port of one target
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Port {
+    /// The address of the target
+    pub target: Ipv4Addr,
+    /// The port scanned
+    pub port: u16,
+    /// What the answer, or lack of one, says about the port
+    pub state: PortState,
+}
+
+/// A TCP segment seen by the receiving thread, which may answer one of our SYNs
+struct Answer {
+    source: Ipv4Addr,
+    source_port: u16,
+    destination_port: u16,
+    acknowledgement: u32,
+    flags: u16,
+}
+
+/// Derives the initial sequence number of each SYN from its addresses and ports
+///
+/// The hash is keyed with a random key chosen for each scan, so only the targets which really
+/// received a SYN can answer it.
+#[derive(Clone)]
+struct Cookies {
+    keys: RandomState,
+}
+
+impl Cookies {
+    fn new() -> Cookies {
+        Cookies { keys: RandomState::new() }
+    }
+
+    fn sequence(&self, source: Ipv4Addr, source_port: u16, target: Ipv4Addr, port: u16) -> u32 {
+        let mut hasher = self.keys.build_hasher();
+        (source, source_port, target, port).hash(&mut hasher);
+
+        hasher.finish() as u32
+    }
+}
+
+/// Visits each number below `count` exactly once, in an order chosen by a seed
+///
+/// The numbers come from a full period linear congruential generator modulo the next power of two,
+/// passed through an invertible mixing function; those which aren't below `count` are skipped.
+/// This needs no memory however many numbers there are, and skips fewer than half of them.
+struct Permutation {
+    count: u64,
+    mask: u64,
+    shift: u32,
+    multiplier: u64,
+    increment: u64,
+    scramble: u64,
+    state: u64,
+    remaining: u64,
+}
+
+impl Permutation {
+    fn new(count: u64, seed: u64) -> Permutation {
+        let modulus = count.next_power_of_two();
+        let mut seed = seed;
+
+        Permutation {
+            count: count,
+            mask: modulus.wrapping_sub(1),
+            shift: (modulus.trailing_zeros() / 2).max(1),
+            // A multiplier one more than a multiple of four and an odd increment give the
+            // generator a full period
+            multiplier: splitmix64(&mut seed) << 2 | 1,
+            increment: splitmix64(&mut seed) | 1,
+            scramble: splitmix64(&mut seed) | 1,
+            state: splitmix64(&mut seed),
+            remaining: if count == 0 { 0 } else { modulus },
+        }
+    }
+
+    /// An invertible function of the numbers below the modulus
+    fn mix(&self, value: u64) -> u64 {
+        let value = value ^ (value >> self.shift);
+        let value = value.wrapping_mul(self.scramble) & self.mask;
+
+        value ^ (value >> self.shift)
+    }
+}
+
+impl Iterator for Permutation {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        while self.remaining > 0 {
+            self.remaining -= 1;
+            self.state = self.state.wrapping_mul(self.multiplier).wrapping_add(self.increment) &
+                         self.mask;
+            let value = self.mix(self.state);
+            if value < self.count {
+                return Some(value);
+            }
+        }
+
+        None
+    }
+}
+
+/// The SplitMix64 generator, used to turn one random seed into several
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+    z ^ (z >> 31)
+}
+
+/// A random number, taken from the randomly keyed hashers in the standard library
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// What an answer says about a port, if it answers a SYN at all
+fn classify(flags: u16) -> Option<PortState> {
+    if flags & TcpFlags::RST != 0 {
+        Some(PortState::Closed)
+    } else if flags & (TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::SYN | TcpFlags::ACK {
+        Some(PortState::Open)
+    } else {
+        None
+    }
+}
+
+/// Scans the TCP ports of a set of IPv4 targets
+pub struct SynScanner {
+    targets: Vec<Ipv4Addr>,
+    ports: Vec<u16>,
+    config: Config,
+}
+
+impl SynScanner {
+    /// Create a new SynScanner for every port in `ports` of every target in `targets`. Duplicate
+    /// targets and ports are only scanned once.
+    pub fn new(targets: &[Ipv4Addr], ports: &[u16], config: Config) -> SynScanner {
+        let mut unique_targets = Vec::with_capacity(targets.len());
+        for target in targets {
+            if !unique_targets.contains(target) {
+                unique_targets.push(*target);
+            }
+        }
+        let mut unique_ports = Vec::with_capacity(ports.len());
+        for port in ports {
+            if !unique_ports.contains(port) {
+                unique_ports.push(*port);
+            }
+        }
+
+        SynScanner {
+            targets: unique_targets,
+            ports: unique_ports,
+            config: config,
+        }
+    }
+
+    /// Scan every port, returning the results ordered by target and then by port, in the order
+    /// they were given
+    pub fn run(&self) -> io::Result<Vec<Port>> {
+        self.run_with(|_| {})
+    }
+
+    /// Scan every port, calling `on_answer` for each open or closed port as its answer is received
+    pub fn run_with<F>(&self, mut on_answer: F) -> io::Result<Vec<Port>>
+        where F: FnMut(&Port)
+    {
+        let sources = self.sources()?;
+        let source_port = match self.config.source_port {
+            Some(port) => port,
+            None => 49152 + (random() % 16384) as u16,
+        };
+        let cookies = Cookies::new();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (answer_tx, answer_rx) = mpsc::channel();
+        let mut thread = None;
+        let result = (|| {
+            let config = transport::Config {
+                read_timeout: Some(Duration::from_millis(POLL_INTERVAL_MS)),
+                ..Default::default()
+            };
+            let channel_type = TransportChannelType::Layer4(Ipv4(IpNextHeaderProtocols::Tcp));
+            let (mut tx, rx) = transport::transport_channel_with(channel_type, config)?;
+            thread = Some(spawn_receiver(rx, answer_tx, stop.clone()));
+
+            let mut scan = Scan {
+                sources: &sources,
+                source_port: source_port,
+                cookies: &cookies,
+                states: HashMap::new(),
+                on_answer: &mut on_answer,
+            };
+            let mut limiter = RateLimiter::new(self.config.rate);
+            let count = self.targets.len() as u64 * self.ports.len() as u64;
+            for _ in 0..self.config.retries + 1 {
+                for index in Permutation::new(count, random()) {
+                    // Neighbouring indexes are different targets, so that no target gets a
+                    // burst of SYNs
+                    let target = self.targets[(index % self.targets.len() as u64) as usize];
+                    let port = self.ports[(index / self.targets.len() as u64) as usize];
+                    if scan.states.contains_key(&(target, port)) {
+                        continue;
+                    }
+                    limiter.wait();
+                    scan.send(&mut tx, target, port)?;
+                    while let Ok(answer) = answer_rx.try_recv() {
+                        scan.handle_answer(answer?);
+                    }
+                }
+                scan.wait(&answer_rx, self.config.timeout)?;
+            }
+
+            let mut ports = Vec::with_capacity(count as usize);
+            for target in &self.targets {
+                for port in &self.ports {
+                    ports.push(Port {
+                        target: *target,
+                        port: *port,
+                        state: scan.states
+                            .get(&(*target, *port))
+                            .cloned()
+                            .unwrap_or(PortState::Filtered),
+                    });
+                }
+            }
+
+            Ok(ports)
+        })();
+
+        stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+
+        result
+    }
+
+    /// The address to send SYNs to each target from
+    fn sources(&self) -> io::Result<HashMap<Ipv4Addr, Ipv4Addr>> {
+        let mut sources = HashMap::new();
+        for target in &self.targets {
+            let source = match self.config.source {
+                Some(source) => source,
+                None => source_address(*target)?,
+            };
+            sources.insert(*target, source);
+        }
+
+        Ok(sources)
+    }
+}
+
+/// Scan every port in `ports` of every target in `targets` using the given configuration
+pub fn scan(targets: &[Ipv4Addr], ports: &[u16], config: Config) -> io::Result<Vec<Port>> {
+    SynScanner::new(targets, ports, config).run()
+}
+
+/// The state of a scan in progress
+struct Scan<'a, F: 'a> {
+    sources: &'a HashMap<Ipv4Addr, Ipv4Addr>,
+    source_port: u16,
+    cookies: &'a Cookies,
+    /// The states of the ports which have answered
+    states: HashMap<(Ipv4Addr, u16), PortState>,
+    on_answer: &'a mut F,
+}
+
+impl<'a, F> Scan<'a, F>
+    where F: FnMut(&Port)
+{
+    fn send(&self, tx: &mut TransportSender, target: Ipv4Addr, port: u16) -> io::Result<()> {
+        let source = self.sources[&target];
+        let mut buffer = [0u8; TCP_HEADER_LEN];
+        {
+            let mut syn = MutableTcpPacket::new(&mut buffer[..]).unwrap();
+            syn.set_source(self.source_port);
+            syn.set_destination(port);
+            syn.set_sequence(self.cookies.sequence(source, self.source_port, target, port));
+            syn.set_data_offset(5);
+            syn.set_flags(TcpFlags::SYN);
+            syn.set_window(1024);
+        }
+        let checksum = tcp::ipv4_checksum(&TcpPacket::new(&buffer[..]).unwrap(), source, target);
+        MutableTcpPacket::new(&mut buffer[..]).unwrap().set_checksum(checksum);
+
+        tx.send_to(TcpPacket::new(&buffer[..]).unwrap(), IpAddr::V4(target)).map(|_| ())
+    }
+
+    /// Record the state of a port, if `answer` really answers a SYN we sent it
+    fn handle_answer(&mut self, answer: Answer) {
+        let source = match self.sources.get(&answer.source) {
+            Some(source) => *source,
+            None => return,
+        };
+        let sequence = self.cookies
+            .sequence(source, self.source_port, answer.source, answer.source_port);
+        if answer.destination_port != self.source_port ||
+           answer.acknowledgement != sequence.wrapping_add(1) {
+            return;
+        }
+        // Raw sockets see every TCP segment, including our own SYNs on loopback, which are
+        // ignored here
+        let state = match classify(answer.flags) {
+            Some(state) => state,
+            None => return,
+        };
+
+        if let Entry::Vacant(entry) = self.states.entry((answer.source, answer.source_port)) {
+            entry.insert(state);
+            (self.on_answer)(&Port {
+                target: answer.source,
+                port: answer.source_port,
+                state: state,
+            });
+        }
+    }
+
+    /// Handle answers until `timeout` has passed. Fails if the receiving thread has stopped
+    /// because of an error.
+    fn wait(&mut self, answer_rx: &Receiver<io::Result<Answer>>, timeout: Duration)
+        -> io::Result<()> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            match answer_rx.recv_timeout(deadline - now) {
+                Ok(answer) => self.handle_answer(answer?),
+                Err(RecvTimeoutError::Timeout) |
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Find the address the operating system would send packets to `target` from
+fn source_address(target: Ipv4Addr) -> io::Result<Ipv4Addr> {
+    // Connecting a UDP socket sends nothing, but picks a route and so a source address
+    let socket = UdpSocket::bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0))?;
+    socket.connect(SocketAddr::new(IpAddr::V4(target), 9))?;
+
+    match socket.local_addr()?.ip() {
+        IpAddr::V4(source) => Ok(source),
+        IpAddr::V6(..) => unreachable!(),
+    }
+}
+
+/// Start a thread which passes the TCP segments received on `rx` to `answer_tx`, until `stop` is
+/// set or receiving fails, in which case the error is passed on instead
+fn spawn_receiver(mut rx: TransportReceiver,
+                  answer_tx: Sender<io::Result<Answer>>,
+                  stop: Arc<AtomicBool>)
+    -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while !stop.load(Ordering::SeqCst) {
+            let mut iter = packet_iter::<TcpPacket>(&mut rx);
+            let answer = match iter.next_with_metadata() {
+                Ok((segment, metadata)) => {
+                    let source = match metadata.source {
+                        IpAddr::V4(source) => source,
+                        IpAddr::V6(..) => continue,
+                    };
+                    Answer {
+                        source: source,
+                        source_port: segment.get_source(),
+                        destination_port: segment.get_destination(),
+                        acknowledgement: segment.get_acknowledgement(),
+                        flags: segment.get_flags(),
+                    }
+                }
+                Err(ref e) if is_transient(e) => continue,
+                Err(e) => {
+                    let _ = answer_tx.send(Err(e));
+                    return;
+                }
+            };
+            if answer_tx.send(Ok(answer)).is_err() {
+                return;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use packet::tcp::TcpFlags;
+    use std::collections::HashMap;
+    use std::net::Ipv4Addr;
+    use super::{Answer, Cookies, Permutation, PortState, Port, Scan, classify};
+
+    #[test]
+    fn permutation_visits_everything_once() {
+        for &count in &[0u64, 1, 2, 3, 7, 8, 9, 1000, 4097] {
+            for seed in 0..4 {
+                let mut seen: Vec<u64> = Permutation::new(count, seed).collect();
+                assert_eq!(seen.len() as u64, count);
+                seen.sort();
+                assert!(seen.iter().cloned().eq(0..count));
+            }
+        }
+    }
+
+    #[test]
+    fn permutation_depends_on_seed() {
+        let first: Vec<u64> = Permutation::new(1000, 1).collect();
+        let second: Vec<u64> = Permutation::new(1000, 2).collect();
+        assert!(first != second);
+        // Far from the identity
+        let in_place = first.iter().enumerate().filter(|&(i, value)| i as u64 == *value).count();
+        assert!(in_place < 50);
+    }
+
+    #[test]
+    fn classify_flags() {
+        assert_eq!(classify(TcpFlags::SYN | TcpFlags::ACK), Some(PortState::Open));
+        assert_eq!(classify(TcpFlags::RST | TcpFlags::ACK), Some(PortState::Closed));
+        assert_eq!(classify(TcpFlags::RST), Some(PortState::Closed));
+        assert_eq!(classify(TcpFlags::SYN), None);
+        assert_eq!(classify(TcpFlags::ACK), None);
+    }
+
+    #[test]
+    fn answers_are_checked() {
+        let source = Ipv4Addr::new(10, 0, 0, 1);
+        let target = Ipv4Addr::new(10, 0, 0, 2);
+        let mut sources = HashMap::new();
+        sources.insert(target, source);
+        let cookies = Cookies::new();
+        let mut answered = Vec::new();
+        {
+            let mut on_answer = |port: &Port| answered.push(*port);
+            let mut scan = Scan {
+                sources: &sources,
+                source_port: 50000,
+                cookies: &cookies,
+                states: HashMap::new(),
+                on_answer: &mut on_answer,
+            };
+            let answer = |port: u16, acknowledgement: u32, flags: u16| {
+                Answer {
+                    source: target,
+                    source_port: port,
+                    destination_port: 50000,
+                    acknowledgement: acknowledgement,
+                    flags: flags,
+                }
+            };
+            let cookie = |port: u16| cookies.sequence(source, 50000, target, port).wrapping_add(1);
+
+            scan.handle_answer(answer(22, cookie(22), TcpFlags::SYN | TcpFlags::ACK));
+            scan.handle_answer(answer(23, cookie(23), TcpFlags::RST | TcpFlags::ACK));
+            // Wrong acknowledgement numbers, and answers to another port's SYN, are ignored
+            scan.handle_answer(answer(80, cookie(80).wrapping_add(1), TcpFlags::RST));
+            scan.handle_answer(answer(81, cookie(82), TcpFlags::SYN | TcpFlags::ACK));
+            // As are segments which don't answer a SYN, and repeated answers
+            scan.handle_answer(answer(443, cookie(443), TcpFlags::ACK));
+            scan.handle_answer(answer(22, cookie(22), TcpFlags::RST));
+            // And answers from addresses we never scanned
+            let mut stranger = answer(22, cookie(22), TcpFlags::SYN | TcpFlags::ACK);
+            stranger.source = Ipv4Addr::new(10, 0, 0, 3);
+            scan.handle_answer(stranger);
+
+            assert_eq!(scan.states.len(), 2);
+        }
+
+        assert_eq!(answered,
+                   vec![Port {
+                            target: target,
+                            port: 22,
+                            state: PortState::Open,
+                        },
+                        Port {
+                            target: target,
+                            port: 23,
+                            state: PortState::Closed,
+                        }]);
+    }
+}
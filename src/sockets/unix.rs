@@ -14,13 +14,43 @@ pub type SockAddrFamily = libc::sa_family_t;
 pub type SockAddrFamily6 = libc::sa_family_t;
 pub type InAddr = libc::in_addr;
 pub type In6Addr = libc::in6_addr;
+pub type IpMreq = libc::ip_mreq;
+pub type Ipv6Mreq = libc::ipv6_mreq;
 
 pub const AF_INET: libc::c_int = libc::AF_INET;
 pub const AF_INET6: libc::c_int = libc::AF_INET6;
+pub const SOCK_DGRAM: libc::c_int = libc::SOCK_DGRAM;
 pub const SOCK_RAW: libc::c_int = libc::SOCK_RAW;
 
+pub const SOL_SOCKET: libc::c_int = libc::SOL_SOCKET;
+pub const SO_RCVBUF: libc::c_int = libc::SO_RCVBUF;
+pub const SO_SNDBUF: libc::c_int = libc::SO_SNDBUF;
+
 pub const IPPROTO_IP: libc::c_int = libc::IPPROTO_IP;
 pub const IP_HDRINCL: libc::c_int = libc::IP_HDRINCL;
+pub const IP_TTL: libc::c_int = libc::IP_TTL;
+pub const IP_TOS: libc::c_int = libc::IP_TOS;
+pub const IP_MULTICAST_IF: libc::c_int = libc::IP_MULTICAST_IF;
+pub const IP_MULTICAST_TTL: libc::c_int = libc::IP_MULTICAST_TTL;
+pub const IP_MULTICAST_LOOP: libc::c_int = libc::IP_MULTICAST_LOOP;
+pub const IP_ADD_MEMBERSHIP: libc::c_int = libc::IP_ADD_MEMBERSHIP;
+pub const IP_DROP_MEMBERSHIP: libc::c_int = libc::IP_DROP_MEMBERSHIP;
+
+pub const IPPROTO_IPV6: libc::c_int = libc::IPPROTO_IPV6;
+pub const IPV6_UNICAST_HOPS: libc::c_int = libc::IPV6_UNICAST_HOPS;
+pub const IPV6_TCLASS: libc::c_int = libc::IPV6_TCLASS;
+pub const IPV6_MULTICAST_IF: libc::c_int = libc::IPV6_MULTICAST_IF;
+pub const IPV6_MULTICAST_HOPS: libc::c_int = libc::IPV6_MULTICAST_HOPS;
+pub const IPV6_MULTICAST_LOOP: libc::c_int = libc::IPV6_MULTICAST_LOOP;
+#[cfg(target_os = "linux")]
+pub const IPV6_JOIN_GROUP: libc::c_int = libc::IPV6_ADD_MEMBERSHIP;
+#[cfg(target_os = "linux")]
+pub const IPV6_LEAVE_GROUP: libc::c_int = libc::IPV6_DROP_MEMBERSHIP;
+#[cfg(not(target_os = "linux"))]
+pub const IPV6_JOIN_GROUP: libc::c_int = libc::IPV6_JOIN_GROUP;
+#[cfg(not(target_os = "linux"))]
+pub const IPV6_LEAVE_GROUP: libc::c_int = libc::IPV6_LEAVE_GROUP;
+pub const IPV6_CHECKSUM: libc::c_int = libc::IPV6_CHECKSUM;
 
 pub const IFF_LOOPBACK: libc::c_int = libc::IFF_LOOPBACK;
 
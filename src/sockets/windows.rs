@@ -16,13 +16,37 @@ pub type SockAddrFamily = winapi::ADDRESS_FAMILY;
 pub type SockAddrFamily6 = libc::c_short;
 pub type InAddr = winapi::in_addr;
 pub type In6Addr = winapi::in6_addr;
+pub type IpMreq = winapi::ip_mreq;
+pub type Ipv6Mreq = winapi::ipv6_mreq;
 
 pub const AF_INET: libc::c_int = winapi::AF_INET;
 pub const AF_INET6: libc::c_int = winapi::AF_INET6;
+pub const SOCK_DGRAM: libc::c_int = winapi::SOCK_DGRAM;
 pub const SOCK_RAW: libc::c_int = winapi::SOCK_RAW;
 
+pub const SOL_SOCKET: libc::c_int = winapi::SOL_SOCKET;
+pub const SO_RCVBUF: libc::c_int = winapi::SO_RCVBUF;
+pub const SO_SNDBUF: libc::c_int = winapi::SO_SNDBUF;
+
 pub const IPPROTO_IP: libc::c_int = winapi::IPPROTO_IP;
 pub const IP_HDRINCL: libc::c_int = winapi::IP_HDRINCL;
+pub const IP_TTL: libc::c_int = winapi::IP_TTL;
+pub const IP_TOS: libc::c_int = winapi::IP_TOS;
+pub const IP_MULTICAST_IF: libc::c_int = winapi::IP_MULTICAST_IF;
+pub const IP_MULTICAST_TTL: libc::c_int = winapi::IP_MULTICAST_TTL;
+pub const IP_MULTICAST_LOOP: libc::c_int = winapi::IP_MULTICAST_LOOP;
+pub const IP_ADD_MEMBERSHIP: libc::c_int = winapi::IP_ADD_MEMBERSHIP;
+pub const IP_DROP_MEMBERSHIP: libc::c_int = winapi::IP_DROP_MEMBERSHIP;
+
+pub const IPPROTO_IPV6: libc::c_int = winapi::IPPROTO_IPV6.0 as libc::c_int;
+pub const IPV6_UNICAST_HOPS: libc::c_int = winapi::IPV6_UNICAST_HOPS;
+pub const IPV6_TCLASS: libc::c_int = winapi::IPV6_TCLASS;
+pub const IPV6_MULTICAST_IF: libc::c_int = winapi::IPV6_MULTICAST_IF;
+pub const IPV6_MULTICAST_HOPS: libc::c_int = winapi::IPV6_MULTICAST_HOPS;
+pub const IPV6_MULTICAST_LOOP: libc::c_int = winapi::IPV6_MULTICAST_LOOP;
+pub const IPV6_JOIN_GROUP: libc::c_int = winapi::IPV6_JOIN_GROUP;
+pub const IPV6_LEAVE_GROUP: libc::c_int = winapi::IPV6_LEAVE_GROUP;
+pub const IPV6_CHECKSUM: libc::c_int = winapi::IPV6_CHECKSUM;
 
 pub const IFF_LOOPBACK: libc::c_int = 0x00000004;
 
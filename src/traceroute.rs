@@ -0,0 +1,897 @@
+// Copyright (c) 2017 Robert Clipsham <robert@octarineparrot.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Support for tracing the route packets take to a host
+//!
+//! Probes are sent with increasing TTLs (IPv4) or hop limits (IPv6). Each router which drops a
+//! probe because its TTL ran out answers with an ICMP time exceeded message, which quotes the
+//! start of the probe; this is used to match replies to the probes which caused them. Tracing
+//! stops once the destination answers, or a destination unreachable message is received.
+//!
+//! ```no_run
+//! use pnet::traceroute::{self, Config, ProbeMethod};
+//!
+//! let config = Config { method: ProbeMethod::Icmp, ..Default::default() };
+//! for hop in traceroute::traceroute("192.0.2.1".parse().unwrap(), config).unwrap() {
+//!     println!("{} {:?}", hop.ttl, hop.addresses());
+//! }
+//! ```
+//!
+//! Raw sockets are used, so root privileges are needed.
+
+use internal::{POLL_INTERVAL_MS, is_transient};
+use packet::Packet;
+use packet::icmp::{self, IcmpPacket, IcmpTypes};
+use packet::icmp::destination_unreachable::DestinationUnreachablePacket;
+use packet::icmp::echo_reply::EchoReplyPacket;
+use packet::icmp::echo_request::MutableEchoRequestPacket;
+use packet::icmp::time_exceeded::TimeExceededPacket;
+use packet::icmpv6::{Icmpv6Packet, Icmpv6Types};
+use packet::icmpv6::destination_unreachable as icmpv6_destination_unreachable;
+use packet::icmpv6::echo_reply::EchoReplyPacket as Icmpv6EchoReplyPacket;
+use packet::icmpv6::echo_request::MutableEchoRequestPacket as MutableIcmpv6EchoRequestPacket;
+use packet::icmpv6::time_exceeded::TimeExceededPacket as Icmpv6TimeExceededPacket;
+use packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use packet::ipv4::Ipv4Packet;
+use packet::ipv6::Ipv6Packet;
+use packet::tcp::{self, MutableTcpPacket, TcpFlags, TcpPacket};
+use packet::udp::{self, MutableUdpPacket, UdpPacket};
+use transport::{self, TransportChannelType, TransportReceiver, TransportSender, packet_iter};
+use transport::TransportProtocol::{Ipv4, Ipv6};
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The destination port of the first UDP probe, as used by traceroute(8)
+const DEFAULT_UDP_PORT: u16 = 33434;
+
+/// The destination port of TCP SYN probes
+const DEFAULT_TCP_PORT: u16 = 80;
+
+/// The number of bytes of data in UDP and ICMP probes. The first two bytes are adjusted to keep
+/// checksums as needed for Paris traceroute.
+const PROBE_PAYLOAD_LEN: usize = 12;
+
+/// The kind of packet sent as a probe
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ProbeMethod {
+    /// UDP datagrams to an unused port, which the destination answers with a port unreachable
+    /// message
+    Udp,
+    /// ICMP or ICMPv6 echo requests, which the destination answers with an echo reply
+    Icmp,
+    /// TCP SYN segments, which the destination answers with a SYN-ACK or a RST
+    TcpSyn,
+}
+
+/// Options for `traceroute()`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    /// The kind of probes to send. Defaults to `ProbeMethod::Udp`.
+    pub method: ProbeMethod,
+
+    /// The TTL or hop limit of the first probes. Defaults to 1.
+    pub first_ttl: u8,
+
+    /// The largest TTL or hop limit to probe with. Defaults to 30.
+    pub max_ttl: u8,
+
+    /// The number of probes sent for each TTL. Defaults to 3.
+    pub probes_per_hop: usize,
+
+    /// How long to wait for replies to the probes for each TTL. Defaults to one second.
+    pub timeout: Duration,
+
+    /// The destination port of UDP and TCP probes. Defaults to None, which uses 33434 for UDP
+    /// probes and 80 for TCP probes. Classic UDP probes use consecutive ports from this one.
+    pub port: Option<u16>,
+
+    /// Keep the fields routers use to balance load across paths the same in every probe, as
+    /// Paris traceroute does, so that all probes follow the same path. UDP probes are then told
+    /// apart by their checksum rather than their destination port, and the payload of ICMP
+    /// probes is adjusted so that their checksum doesn't change. TCP probes always keep their
+    /// ports, so are unaffected. Defaults to false.
+    pub paris: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            method: ProbeMethod::Udp,
+            first_ttl: 1,
+            max_ttl: 30,
+            probes_per_hop: 3,
+            timeout: Duration::from_secs(1),
+            port: None,
+            paris: false,
+        }
+    }
+}
+
+/// What a reply to a probe reported
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ReplyKind {
+    /// A router on the path ran out of TTL or hop limit for the probe
+    TimeExceeded,
+    /// The destination answered the probe
+    Reached,
+    /// The probe could not be delivered, other than because the destination's UDP port was
+    /// closed. Holds the ICMP or ICMPv6 destination unreachable code.
+    Unreachable(u8),
+}
+
+/// A reply to a single probe
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Reply {
+    /// The address the reply came from
+    pub address: IpAddr,
+    /// What the reply reported
+    pub kind: ReplyKind,
+    /// The time between sending the probe and receiving the reply
+    pub rtt: Duration,
+}
+
+/// The replies to the probes sent with a single TTL
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hop {
+    /// The TTL or hop limit of the probes
+    pub ttl: u8,
+    /// The reply to each probe, in the order they were sent, or None if the probe wasn't
+    /// answered in time
+    pub probes: Vec<Option<Reply>>,
+}
+
+impl Hop {
+    /// The distinct addresses which replied to probes with this TTL
+    pub fn addresses(&self) -> Vec<IpAddr> {
+        let mut addresses = Vec::new();
+        for reply in self.probes.iter().filter_map(|probe| probe.as_ref()) {
+            if !addresses.contains(&reply.address) {
+                addresses.push(reply.address);
+            }
+        }
+
+        addresses
+    }
+}
+
+/// Trace the route to `target`, returning a hop for each TTL probed
+///
+/// The last hop is the one where the destination answered or a destination unreachable message
+/// was received, unless `max_ttl` was reached first.
+pub fn traceroute(target: IpAddr, config: Config) -> io::Result<Vec<Hop>> {
+    let port = config.port.unwrap_or(match config.method {
+        ProbeMethod::TcpSyn => DEFAULT_TCP_PORT,
+        _ => DEFAULT_UDP_PORT,
+    });
+    let source = source_address(target, port)?;
+    let matcher = Matcher {
+        method: config.method,
+        paris: config.paris,
+        target: target,
+        identifier: process::id() as u16,
+        source_port: (process::id() as u16) | 0x8000,
+        port: port,
+    };
+    let tracer = Tracer {
+        config: config,
+        matcher: matcher,
+        source: source,
+    };
+
+    tracer.run()
+}
+
+/// Find the address probes to `target` will be sent from, which is needed for UDP and TCP
+/// checksums
+fn source_address(target: IpAddr, port: u16) -> io::Result<IpAddr> {
+    let unspecified = match target {
+        IpAddr::V4(..) => IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+        IpAddr::V6(..) => IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)),
+    };
+    // Connecting a UDP socket sends nothing, but picks a route and so a source address
+    let socket = UdpSocket::bind(SocketAddr::new(unspecified, 0))?;
+    socket.connect(SocketAddr::new(target, port))?;
+
+    Ok(socket.local_addr()?.ip())
+}
+
+/// Add two numbers using one's complement arithmetic, as used by internet checksums
+fn ones_complement_add(a: u16, b: u16) -> u16 {
+    let sum = a as u32 + b as u32;
+    ((sum & 0xffff) + (sum >> 16)) as u16
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    (bytes[offset] as u16) << 8 | bytes[offset + 1] as u16
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    (read_u16(bytes, offset) as u32) << 16 | read_u16(bytes, offset + 2) as u32
+}
+
+/// A reply matched to the id of a probe
+struct Event {
+    id: u16,
+    address: IpAddr,
+    kind: ReplyKind,
+    time: Instant,
+}
+
+/// Everything needed to build probes and recognise replies to them
+#[derive(Clone, Copy, Debug)]
+struct Matcher {
+    method: ProbeMethod,
+    paris: bool,
+    target: IpAddr,
+    identifier: u16,
+    source_port: u16,
+    port: u16,
+}
+
+impl Matcher {
+    fn is_ipv6(&self) -> bool {
+        match self.target {
+            IpAddr::V4(..) => false,
+            IpAddr::V6(..) => true,
+        }
+    }
+
+    fn protocol(&self) -> IpNextHeaderProtocol {
+        match self.method {
+            ProbeMethod::Udp => IpNextHeaderProtocols::Udp,
+            ProbeMethod::TcpSyn => IpNextHeaderProtocols::Tcp,
+            ProbeMethod::Icmp if self.is_ipv6() => IpNextHeaderProtocols::Ipv6Icmp,
+            ProbeMethod::Icmp => IpNextHeaderProtocols::Icmp,
+        }
+    }
+
+    /// The TCP sequence number of the probe with the given id
+    fn tcp_sequence(&self, id: u16) -> u32 {
+        (self.identifier as u32) << 16 | id as u32
+    }
+
+    /// Build the probe with the given id, starting at its transport header
+    fn build_probe(&self, id: u16, source: IpAddr) -> Vec<u8> {
+        match self.method {
+            ProbeMethod::Udp => self.build_udp_probe(id, source),
+            ProbeMethod::Icmp => self.build_icmp_probe(id),
+            ProbeMethod::TcpSyn => self.build_tcp_probe(id, source),
+        }
+    }
+
+    fn build_udp_probe(&self, id: u16, source: IpAddr) -> Vec<u8> {
+        let mut buffer = vec![0u8; 8 + PROBE_PAYLOAD_LEN];
+        let length = buffer.len() as u16;
+        {
+            let mut probe = MutableUdpPacket::new(&mut buffer[..]).unwrap();
+            probe.set_source(self.source_port);
+            probe.set_destination(if self.paris {
+                self.port
+            } else {
+                self.port.wrapping_add(id - 1)
+            });
+            probe.set_length(length);
+        }
+        let checksum = udp_checksum(&buffer[..], source, self.target);
+        if self.paris {
+            // Make the checksum the probe id, by choosing the payload word to make up the
+            // difference: ~(sum + word) == id
+            let word = ones_complement_add(!id, checksum);
+            buffer[8] = (word >> 8) as u8;
+            buffer[9] = word as u8;
+            MutableUdpPacket::new(&mut buffer[..]).unwrap().set_checksum(id);
+        } else {
+            MutableUdpPacket::new(&mut buffer[..]).unwrap().set_checksum(checksum);
+        }
+
+        buffer
+    }
+
+    fn build_icmp_probe(&self, id: u16) -> Vec<u8> {
+        let mut buffer = vec![0u8; 8 + PROBE_PAYLOAD_LEN];
+        if self.paris {
+            // The sequence number and this word always add up to 0xffff
+            buffer[8] = (!id >> 8) as u8;
+            buffer[9] = !id as u8;
+        }
+        if self.is_ipv6() {
+            // The kernel fills in the checksum of ICMPv6 packets
+            let mut probe = MutableIcmpv6EchoRequestPacket::new(&mut buffer[..]).unwrap();
+            probe.set_icmpv6_type(Icmpv6Types::EchoRequest);
+            probe.set_identifier(self.identifier);
+            probe.set_sequence_number(id);
+        } else {
+            {
+                let mut probe = MutableEchoRequestPacket::new(&mut buffer[..]).unwrap();
+                probe.set_icmp_type(IcmpTypes::EchoRequest);
+                probe.set_identifier(self.identifier);
+                probe.set_sequence_number(id);
+            }
+            let checksum = icmp::checksum(&IcmpPacket::new(&buffer[..]).unwrap());
+            MutableEchoRequestPacket::new(&mut buffer[..]).unwrap().set_checksum(checksum);
+        }
+
+        buffer
+    }
+
+    fn build_tcp_probe(&self, id: u16, source: IpAddr) -> Vec<u8> {
+        let mut buffer = vec![0u8; 20];
+        {
+            let mut probe = MutableTcpPacket::new(&mut buffer[..]).unwrap();
+            probe.set_source(self.source_port);
+            probe.set_destination(self.port);
+            probe.set_sequence(self.tcp_sequence(id));
+            probe.set_data_offset(5);
+            probe.set_flags(TcpFlags::SYN);
+            probe.set_window(5840);
+        }
+        let checksum = {
+            let probe = TcpPacket::new(&buffer[..]).unwrap();
+            match (source, self.target) {
+                (IpAddr::V4(source), IpAddr::V4(target)) => {
+                    tcp::ipv4_checksum(&probe, source, target)
+                }
+                (IpAddr::V6(source), IpAddr::V6(target)) => {
+                    tcp::ipv6_checksum(&probe, source, target)
+                }
+                _ => unreachable!(),
+            }
+        };
+        MutableTcpPacket::new(&mut buffer[..]).unwrap().set_checksum(checksum);
+
+        buffer
+    }
+
+    /// Find the id of the probe quoted in an ICMP or ICMPv6 error message
+    fn quoted_probe_id(&self, quoted: &[u8]) -> Option<u16> {
+        let transport = if self.is_ipv6() {
+            let header = match Ipv6Packet::new(quoted) {
+                Some(header) => header,
+                None => return None,
+            };
+            if header.get_next_header() != self.protocol() ||
+               IpAddr::V6(header.get_destination()) != self.target {
+                return None;
+            }
+            &quoted[40..]
+        } else {
+            let header = match Ipv4Packet::new(quoted) {
+                Some(header) => header,
+                None => return None,
+            };
+            let header_len = header.get_header_length() as usize * 4;
+            if header.get_next_level_protocol() != self.protocol() ||
+               IpAddr::V4(header.get_destination()) != self.target ||
+               quoted.len() < header_len {
+                return None;
+            }
+            &quoted[header_len..]
+        };
+        // Only the first eight bytes of the probe are sure to be quoted
+        if transport.len() < 8 {
+            return None;
+        }
+
+        match self.method {
+            ProbeMethod::Udp => {
+                if read_u16(transport, 0) != self.source_port {
+                    None
+                } else if self.paris {
+                    if read_u16(transport, 2) == self.port {
+                        Some(read_u16(transport, 6))
+                    } else {
+                        None
+                    }
+                } else {
+                    Some(read_u16(transport, 2).wrapping_sub(self.port).wrapping_add(1))
+                }
+            }
+            ProbeMethod::Icmp => {
+                let echo_request = if self.is_ipv6() {
+                    Icmpv6Types::EchoRequest.0
+                } else {
+                    IcmpTypes::EchoRequest.0
+                };
+                if transport[0] == echo_request && read_u16(transport, 4) == self.identifier {
+                    Some(read_u16(transport, 6))
+                } else {
+                    None
+                }
+            }
+            ProbeMethod::TcpSyn => {
+                let sequence = read_u32(transport, 4);
+                if read_u16(transport, 0) == self.source_port &&
+                   read_u16(transport, 2) == self.port &&
+                   sequence >> 16 == self.identifier as u32 {
+                    Some(sequence as u16)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// Match a received ICMP packet to a probe
+    fn match_icmp(&self, packet: &[u8], address: IpAddr) -> Option<(u16, ReplyKind)> {
+        use packet::icmp::destination_unreachable::IcmpCodes;
+
+        let icmp = match IcmpPacket::new(packet) {
+            Some(icmp) => icmp,
+            None => return None,
+        };
+        match icmp.get_icmp_type() {
+            IcmpTypes::TimeExceeded => {
+                TimeExceededPacket::new(packet)
+                    .and_then(|reply| self.quoted_probe_id(reply.payload()))
+                    .map(|id| (id, ReplyKind::TimeExceeded))
+            }
+            IcmpTypes::DestinationUnreachable => {
+                let code = icmp.get_icmp_code();
+                let kind = if self.method == ProbeMethod::Udp &&
+                              code == IcmpCodes::DestinationPortUnreachable {
+                    ReplyKind::Reached
+                } else {
+                    ReplyKind::Unreachable(code.0)
+                };
+                DestinationUnreachablePacket::new(packet)
+                    .and_then(|reply| self.quoted_probe_id(reply.payload()))
+                    .map(|id| (id, kind))
+            }
+            IcmpTypes::EchoReply if self.method == ProbeMethod::Icmp && address == self.target => {
+                EchoReplyPacket::new(packet)
+                    .and_then(|reply| if reply.get_identifier() == self.identifier {
+                        Some(reply.get_sequence_number())
+                    } else {
+                        None
+                    })
+                    .map(|id| (id, ReplyKind::Reached))
+            }
+            _ => None,
+        }
+    }
+
+    /// Match a received ICMPv6 packet to a probe
+    fn match_icmpv6(&self, packet: &[u8], address: IpAddr) -> Option<(u16, ReplyKind)> {
+        use self::icmpv6_destination_unreachable::{DestinationUnreachablePacket, Icmpv6Codes};
+
+        let icmpv6 = match Icmpv6Packet::new(packet) {
+            Some(icmpv6) => icmpv6,
+            None => return None,
+        };
+        match icmpv6.get_icmpv6_type() {
+            Icmpv6Types::TimeExceeded => {
+                Icmpv6TimeExceededPacket::new(packet)
+                    .and_then(|reply| self.quoted_probe_id(reply.payload()))
+                    .map(|id| (id, ReplyKind::TimeExceeded))
+            }
+            Icmpv6Types::DestinationUnreachable => {
+                let code = icmpv6.get_icmpv6_code();
+                let kind = if self.method == ProbeMethod::Udp &&
+                              code == Icmpv6Codes::PortUnreachable {
+                    ReplyKind::Reached
+                } else {
+                    ReplyKind::Unreachable(code.0)
+                };
+                DestinationUnreachablePacket::new(packet)
+                    .and_then(|reply| self.quoted_probe_id(reply.payload()))
+                    .map(|id| (id, kind))
+            }
+            Icmpv6Types::EchoReply if self.method == ProbeMethod::Icmp &&
+                                      address == self.target => {
+                Icmpv6EchoReplyPacket::new(packet)
+                    .and_then(|reply| if reply.get_identifier() == self.identifier {
+                        Some(reply.get_sequence_number())
+                    } else {
+                        None
+                    })
+                    .map(|id| (id, ReplyKind::Reached))
+            }
+            _ => None,
+        }
+    }
+
+    /// Match a received TCP segment to a probe
+    fn match_tcp(&self, packet: &[u8], address: IpAddr) -> Option<(u16, ReplyKind)> {
+        let segment = match TcpPacket::new(packet) {
+            Some(segment) => segment,
+            None => return None,
+        };
+        let flags = segment.get_flags();
+        let answered = flags & (TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::SYN | TcpFlags::ACK ||
+                       flags & TcpFlags::RST != 0;
+        let sequence = segment.get_acknowledgement().wrapping_sub(1);
+        if address == self.target && answered && segment.get_source() == self.port &&
+           segment.get_destination() == self.source_port &&
+           sequence >> 16 == self.identifier as u32 {
+            Some((sequence as u16, ReplyKind::Reached))
+        } else {
+            None
+        }
+    }
+}
+
+fn udp_checksum(buffer: &[u8], source: IpAddr, target: IpAddr) -> u16 {
+    let packet = UdpPacket::new(buffer).unwrap();
+    match (source, target) {
+        (IpAddr::V4(source), IpAddr::V4(target)) => udp::ipv4_checksum(&packet, source, target),
+        (IpAddr::V6(source), IpAddr::V6(target)) => udp::ipv6_checksum(&packet, source, target),
+        _ => unreachable!(),
+    }
+}
+
+/// The protocol a listening thread receives
+#[derive(Clone, Copy)]
+enum Listener {
+    Icmp,
+    Icmpv6,
+    Tcp,
+    /// UDP, which never carries replies but has to be read so that it doesn't queue up
+    Udp,
+}
+
+struct Tracer {
+    config: Config,
+    matcher: Matcher,
+    source: IpAddr,
+}
+
+impl Tracer {
+    fn run(&self) -> io::Result<Vec<Hop>> {
+        let stop = Arc::new(AtomicBool::new(false));
+        let (event_tx, event_rx) = mpsc::channel();
+        let mut threads = Vec::new();
+        let result = (|| {
+            let ipv6 = self.matcher.is_ipv6();
+            let (icmp_tx, icmp_rx) = if ipv6 {
+                open(Ipv6(IpNextHeaderProtocols::Ipv6Icmp))?
+            } else {
+                open(Ipv4(IpNextHeaderProtocols::Icmp))?
+            };
+            let listener = if ipv6 { Listener::Icmpv6 } else { Listener::Icmp };
+            threads.push(self.listen(icmp_rx, listener, event_tx.clone(), stop.clone()));
+
+            let mut tx = match self.config.method {
+                ProbeMethod::Icmp => icmp_tx,
+                ProbeMethod::Udp | ProbeMethod::TcpSyn => {
+                    let protocol = self.matcher.protocol();
+                    let (tx, rx) = if ipv6 {
+                        open(Ipv6(protocol))?
+                    } else {
+                        open(Ipv4(protocol))?
+                    };
+                    // The raw socket receives every packet of the protocol on the host
+                    let listener = match self.config.method {
+                        ProbeMethod::TcpSyn => Listener::Tcp,
+                        _ => Listener::Udp,
+                    };
+                    threads.push(self.listen(rx, listener, event_tx.clone(), stop.clone()));
+                    tx
+                }
+            };
+
+            self.probe(&mut tx, &event_rx)
+        })();
+
+        stop.store(true, Ordering::SeqCst);
+        for thread in threads {
+            let _ = thread.join();
+        }
+
+        result
+    }
+
+    /// Send the probes for each TTL in turn, until the destination is reached
+    fn probe(&self,
+             tx: &mut TransportSender,
+             event_rx: &Receiver<io::Result<Event>>)
+        -> io::Result<Vec<Hop>> {
+        let mut hops = Vec::new();
+        let mut next_id: u16 = 1;
+        for ttl in self.config.first_ttl as u16..self.config.max_ttl as u16 + 1 {
+            let ttl = ttl as u8;
+            tx.set_ttl(ttl)?;
+
+            let mut sent = Vec::with_capacity(self.config.probes_per_hop);
+            for _ in 0..self.config.probes_per_hop {
+                let id = next_id;
+                // Probe ids are never 0, so that they can be used as UDP checksums
+                next_id = if next_id == u16::max_value() { 1 } else { next_id + 1 };
+                let probe = self.matcher.build_probe(id, self.source);
+                let target = self.matcher.target;
+                match self.config.method {
+                    ProbeMethod::Udp => tx.send_to(UdpPacket::new(&probe[..]).unwrap(), target),
+                    ProbeMethod::Icmp => tx.send_to(IcmpPacket::new(&probe[..]).unwrap(), target),
+                    ProbeMethod::TcpSyn => tx.send_to(TcpPacket::new(&probe[..]).unwrap(), target),
+                }?;
+                sent.push((id, Instant::now()));
+            }
+
+            let mut probes: Vec<Option<Reply>> = sent.iter().map(|_| None).collect();
+            let deadline = Instant::now() + self.config.timeout;
+            while probes.iter().any(|probe| probe.is_none()) {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                let event = match event_rx.recv_timeout(deadline - now) {
+                    Ok(event) => event?,
+                    Err(RecvTimeoutError::Timeout) |
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                // Late replies to probes for earlier TTLs are ignored
+                if let Some(i) = sent.iter().position(|&(id, _)| id == event.id) {
+                    if probes[i].is_none() {
+                        let sent_at = sent[i].1;
+                        probes[i] = Some(Reply {
+                            address: event.address,
+                            kind: event.kind,
+                            rtt: if event.time > sent_at {
+                                event.time - sent_at
+                            } else {
+                                Duration::new(0, 0)
+                            },
+                        });
+                    }
+                }
+            }
+
+            let done = probes.iter()
+                .filter_map(|probe| probe.as_ref())
+                .any(|reply| reply.kind != ReplyKind::TimeExceeded);
+            hops.push(Hop {
+                ttl: ttl,
+                probes: probes,
+            });
+            if done {
+                break;
+            }
+        }
+
+        Ok(hops)
+    }
+
+    /// Start a thread which passes replies received on `rx` to `event_tx`, until `stop` is set
+    /// or receiving fails, in which case the error is passed on instead. UDP packets are read and
+    /// thrown away.
+    fn listen(&self,
+              mut rx: TransportReceiver,
+              listener: Listener,
+              event_tx: Sender<io::Result<Event>>,
+              stop: Arc<AtomicBool>)
+        -> thread::JoinHandle<()> {
+        let matcher = self.matcher;
+        thread::spawn(move || {
+            while !stop.load(Ordering::SeqCst) {
+                let matched = match listener {
+                    Listener::Icmp | Listener::Icmpv6 => {
+                        let mut iter = packet_iter::<IcmpPacket>(&mut rx);
+                        match iter.next_with_metadata() {
+                            Ok((packet, metadata)) => {
+                                let address = metadata.source;
+                                match listener {
+                                    Listener::Icmp => matcher.match_icmp(packet.packet(), address),
+                                    _ => matcher.match_icmpv6(packet.packet(), address),
+                                }
+                                .map(|(id, kind)| (id, kind, address))
+                            }
+                            Err(ref e) if is_transient(e) => continue,
+                            Err(e) => {
+                                let _ = event_tx.send(Err(e));
+                                return;
+                            }
+                        }
+                    }
+                    Listener::Tcp => {
+                        let mut iter = packet_iter::<TcpPacket>(&mut rx);
+                        match iter.next_with_metadata() {
+                            Ok((packet, metadata)) => {
+                                let address = metadata.source;
+                                matcher.match_tcp(packet.packet(), address)
+                                    .map(|(id, kind)| (id, kind, address))
+                            }
+                            Err(ref e) if is_transient(e) => continue,
+                            Err(e) => {
+                                let _ = event_tx.send(Err(e));
+                                return;
+                            }
+                        }
+                    }
+                    Listener::Udp => {
+                        let mut iter = packet_iter::<UdpPacket>(&mut rx);
+                        match iter.next() {
+                            Ok(_) => None,
+                            Err(ref e) if is_transient(e) => continue,
+                            Err(e) => {
+                                let _ = event_tx.send(Err(e));
+                                return;
+                            }
+                        }
+                    }
+                };
+                if let Some((id, kind, address)) = matched {
+                    let event = Event {
+                        id: id,
+                        address: address,
+                        kind: kind,
+                        time: Instant::now(),
+                    };
+                    if event_tx.send(Ok(event)).is_err() {
+                        return;
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Create a layer 4 transport channel for the given protocol
+fn open(protocol: transport::TransportProtocol)
+    -> io::Result<(TransportSender, TransportReceiver)> {
+    let config = transport::Config {
+        read_timeout: Some(Duration::from_millis(POLL_INTERVAL_MS)),
+        ..Default::default()
+    };
+
+    transport::transport_channel_with(TransportChannelType::Layer4(protocol), config)
+}
+
+#[cfg(test)]
+fn test_matcher(method: ProbeMethod, paris: bool) -> Matcher {
+    Matcher {
+        method: method,
+        paris: paris,
+        target: IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+        identifier: 0x1234,
+        source_port: 0x9234,
+        port: DEFAULT_UDP_PORT,
+    }
+}
+
+/// Wrap a probe in an IPv4 or IPv6 header, then in an ICMP or ICMPv6 error message, as a router
+/// would
+#[cfg(test)]
+fn quote_probe(matcher: &Matcher, probe: &[u8], icmp_type: u8, icmp_code: u8) -> Vec<u8> {
+    use packet::ipv4::MutableIpv4Packet;
+    use packet::ipv6::MutableIpv6Packet;
+
+    let header_len = if matcher.is_ipv6() { 40 } else { 20 };
+    let mut reply = vec![0u8; 8 + header_len + 8];
+    reply[0] = icmp_type;
+    reply[1] = icmp_code;
+    match matcher.target {
+        IpAddr::V4(target) => {
+            let mut header = MutableIpv4Packet::new(&mut reply[8..28]).unwrap();
+            header.set_version(4);
+            header.set_header_length(5);
+            header.set_total_length((20 + probe.len()) as u16);
+            header.set_ttl(1);
+            header.set_next_level_protocol(matcher.protocol());
+            header.set_source(Ipv4Addr::new(198, 51, 100, 1));
+            header.set_destination(target);
+        }
+        IpAddr::V6(target) => {
+            let mut header = MutableIpv6Packet::new(&mut reply[8..48]).unwrap();
+            header.set_version(6);
+            header.set_payload_length(probe.len() as u16);
+            header.set_next_header(matcher.protocol());
+            header.set_hop_limit(1);
+            header.set_source(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+            header.set_destination(target);
+        }
+    }
+    reply[8 + header_len..].copy_from_slice(&probe[..8]);
+
+    reply
+}
+
+#[test]
+fn paris_udp_probe_test() {
+    let source = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7));
+    let matcher = test_matcher(ProbeMethod::Udp, true);
+    for id in 1..100 {
+        let probe = matcher.build_probe(id, source);
+        let packet = UdpPacket::new(&probe[..]).unwrap();
+        assert_eq!(packet.get_destination(), DEFAULT_UDP_PORT);
+        assert_eq!(packet.get_checksum(), id);
+        // The checksum must still be valid
+        assert_eq!(udp_checksum(&probe[..], source, matcher.target), id);
+    }
+}
+
+#[test]
+fn paris_icmp_probe_test() {
+    let source = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7));
+    let matcher = test_matcher(ProbeMethod::Icmp, true);
+    let checksums: Vec<u16> = (1..10)
+        .map(|id| {
+            let probe = matcher.build_probe(id, source);
+            let checksum = IcmpPacket::new(&probe[..]).unwrap().get_checksum();
+            checksum
+        })
+        .collect();
+    assert!(checksums.iter().all(|checksum| *checksum == checksums[0]));
+}
+
+#[test]
+fn match_time_exceeded_test() {
+    use packet::icmp::destination_unreachable;
+
+    let source = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7));
+    let router = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1));
+    for &(method, paris) in &[(ProbeMethod::Udp, false),
+                              (ProbeMethod::Udp, true),
+                              (ProbeMethod::Icmp, true),
+                              (ProbeMethod::TcpSyn, false)] {
+        let matcher = test_matcher(method, paris);
+        let probe = matcher.build_probe(42, source);
+
+        let reply = quote_probe(&matcher, &probe[..], IcmpTypes::TimeExceeded.0, 0);
+        assert_eq!(matcher.match_icmp(&reply[..], router), Some((42, ReplyKind::TimeExceeded)));
+
+        let code = destination_unreachable::IcmpCodes::DestinationHostUnreachable.0;
+        let reply = quote_probe(&matcher, &probe[..], IcmpTypes::DestinationUnreachable.0, code);
+        assert_eq!(matcher.match_icmp(&reply[..], router),
+                   Some((42, ReplyKind::Unreachable(code))));
+
+        // Probes from other processes are ignored
+        let other = Matcher { identifier: 1, source_port: 1, ..matcher };
+        let reply = quote_probe(&other, &other.build_probe(42, source)[..], 11, 0);
+        assert_eq!(matcher.match_icmp(&reply[..], router), None);
+    }
+
+    let matcher = test_matcher(ProbeMethod::Udp, false);
+    let probe = matcher.build_probe(7, source);
+    let code = destination_unreachable::IcmpCodes::DestinationPortUnreachable.0;
+    let reply = quote_probe(&matcher, &probe[..], IcmpTypes::DestinationUnreachable.0, code);
+    assert_eq!(matcher.match_icmp(&reply[..], matcher.target), Some((7, ReplyKind::Reached)));
+}
+
+#[test]
+fn match_icmpv6_test() {
+    use self::icmpv6_destination_unreachable::Icmpv6Codes;
+
+    let source = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 7));
+    let router = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1));
+    let target = IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 1, 0, 0, 0, 0, 1));
+    for &(method, paris) in &[(ProbeMethod::Udp, false),
+                              (ProbeMethod::Udp, true),
+                              (ProbeMethod::Icmp, true),
+                              (ProbeMethod::TcpSyn, false)] {
+        let matcher = Matcher { target: target, ..test_matcher(method, paris) };
+        let probe = matcher.build_probe(42, source);
+
+        let reply = quote_probe(&matcher, &probe[..], Icmpv6Types::TimeExceeded.0, 0);
+        assert_eq!(matcher.match_icmpv6(&reply[..], router), Some((42, ReplyKind::TimeExceeded)));
+
+        let code = Icmpv6Codes::AddressUnreachable.0;
+        let reply = quote_probe(&matcher, &probe[..], Icmpv6Types::DestinationUnreachable.0, code);
+        assert_eq!(matcher.match_icmpv6(&reply[..], router),
+                   Some((42, ReplyKind::Unreachable(code))));
+
+        // Probes from other processes are ignored
+        let other = Matcher { identifier: 1, source_port: 1, ..matcher };
+        let reply = quote_probe(&other,
+                                &other.build_probe(42, source)[..],
+                                Icmpv6Types::TimeExceeded.0,
+                                0);
+        assert_eq!(matcher.match_icmpv6(&reply[..], router), None);
+    }
+
+    let matcher = Matcher { target: target, ..test_matcher(ProbeMethod::Udp, false) };
+    let probe = matcher.build_probe(7, source);
+    let code = Icmpv6Codes::PortUnreachable.0;
+    let reply = quote_probe(&matcher, &probe[..], Icmpv6Types::DestinationUnreachable.0, code);
+    assert_eq!(matcher.match_icmpv6(&reply[..], target), Some((7, ReplyKind::Reached)));
+}
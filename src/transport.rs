@@ -10,7 +10,9 @@
 //!
 //! The transport module provides the ability to send and receive packets at
 //! the transport layer using IPv4 or IPv6. It also enables layer 3 networking
-//! for specific transport protocols, using IPv4 only.
+//! for specific transport protocols, using IPv4, or IPv6 on Linux. On Linux and OS X, ICMP echo
+//! packets can also be sent and received without root privileges, using `PingIpv4` or `PingIpv6`
+//! channels.
 //!
 //! Note that this is limited by operating system support - for example, on OS
 //! X and FreeBSD, it is impossible to implement protocols which are already
@@ -23,22 +25,25 @@ extern crate libc;
 
 use internal;
 
-use packet::Packet;
-use packet::ip::IpNextHeaderProtocol;
+use packet::{FromBytes, Packet};
+use packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
 use packet::ipv4::Ipv4Packet;
+use packet::ipv6::Ipv6Packet;
 use packet::udp::UdpPacket;
 use packet::icmp::IcmpPacket;
 use packet::tcp::TcpPacket;
-use self::TransportChannelType::{Layer3, Layer4};
+use self::TransportChannelType::{Layer3, Layer3Ipv6, Layer4, PingIpv4, PingIpv6};
 use self::TransportProtocol::{Ipv4, Ipv6};
 use sockets;
 
 use std::io;
 use std::io::Error;
 use std::iter::repeat;
+use std::marker::PhantomData;
 use std::mem;
 use std::net::{self, IpAddr};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// Represents a transport layer protocol
 #[derive(Clone, Copy)]
@@ -56,12 +61,105 @@ pub enum TransportChannelType {
     Layer4(TransportProtocol),
     /// The application will send and receive IPv4 packets, with the specified transport protocol
     Layer3(IpNextHeaderProtocol),
+    /// The application will send and receive IPv6 packets, with the specified transport protocol.
+    /// Only supported on Linux
+    ///
+    /// Packets are received from every interface, unless `bind_to_device()` is called on the
+    /// `TransportReceiver`. Any extension headers before the transport header are removed from
+    /// received packets, with the IPv6 header's next header and payload length updated to match.
+    Layer3Ipv6(IpNextHeaderProtocol),
+    /// The application will send ICMP echo requests and receive the matching echo replies, using
+    /// an unprivileged "ping socket". Supported on Linux, where the user's group must fall within
+    /// the `net.ipv4.ping_group_range` sysctl, and OS X.
+    ///
+    /// Unlike a `Layer4(Ipv4(IpNextHeaderProtocols::Icmp))` channel, only echo requests may be
+    /// sent. The kernel replaces their identifier with one of its own choosing and calculates
+    /// their checksum, so both fields may be left as zero. Only echo replies carrying that
+    /// identifier are received, rather than every ICMP packet arriving at the host.
+    PingIpv4,
+    /// As `PingIpv4`, but sending ICMPv6 echo requests. The `net.ipv4.ping_group_range` sysctl
+    /// also applies to IPv6 on Linux.
+    PingIpv6,
+}
+
+/// Information about a received packet beyond its contents
+///
+/// Only `source` is available on every platform; the other fields are filled in from ancillary
+/// data on Linux, and are `None` elsewhere.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PacketMetadata {
+    /// The address the packet was sent from
+    pub source: IpAddr,
+    /// The local address the packet was sent to
+    pub destination: Option<IpAddr>,
+    /// The index of the interface the packet arrived on
+    pub interface_index: Option<u32>,
+    /// The TTL (IPv4) or hop limit (IPv6) of the packet
+    pub ttl: Option<u8>,
+}
+
+impl PacketMetadata {
+    fn new(source: IpAddr) -> PacketMetadata {
+        PacketMetadata {
+            source: source,
+            destination: None,
+            interface_index: None,
+            ttl: None,
+        }
+    }
+}
+
+/// Where an error read from the error queue of a transport channel came from
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorOrigin {
+    /// The error was raised by the local host, for example when a packet exceeds the known path
+    /// MTU
+    Local,
+    /// The error was reported by an ICMP message
+    Icmp,
+    /// The error was reported by an ICMPv6 message
+    Icmpv6,
+    /// Any other origin, as the kernel's `SO_EE_ORIGIN_*` value
+    Unknown(u8),
+}
+
+/// An error queued by the kernel for a packet sent on a transport channel
+///
+/// This carries the details of Linux's `struct sock_extended_err`.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExtendedError {
+    /// The error number, such as `ECONNREFUSED` for an ICMP port unreachable message
+    pub errno: i32,
+    /// Where the error came from
+    pub origin: ErrorOrigin,
+    /// The ICMP or ICMPv6 type of the error message, or 0 for local errors
+    pub icmp_type: u8,
+    /// The ICMP or ICMPv6 code of the error message, or 0 for local errors
+    pub icmp_code: u8,
+    /// Extra information about the error. For "fragmentation needed" and "packet too big"
+    /// messages, and local errors about oversized packets, this is the path MTU
+    pub info: u32,
+    /// The address of the host which reported the error, if known
+    pub offender: Option<IpAddr>,
+    /// The destination of the packet which caused the error, if known
+    pub destination: Option<IpAddr>,
+}
+
+#[cfg(target_os = "linux")]
+impl ExtendedError {
+    /// The error as an `io::Error`
+    pub fn error(&self) -> io::Error {
+        io::Error::from_raw_os_error(self.errno)
+    }
 }
 
 /// Structure used for sending at the transport layer. Should be created with transport_channel()
 pub struct TransportSender {
     socket: Arc<internal::FileDesc>,
-    _channel_type: TransportChannelType,
+    channel_type: TransportChannelType,
+    write_timeout: Option<Duration>,
 }
 
 /// Structure used for receiving at the transport layer. Should be created with transport_channel()
@@ -69,6 +167,36 @@ pub struct TransportReceiver {
     socket: Arc<internal::FileDesc>,
     buffer: Vec<u8>,
     channel_type: TransportChannelType,
+    read_timeout: Option<Duration>,
+}
+
+/// Options for creating a transport channel with `transport_channel_with()`
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Config {
+    /// The size of buffer to use when reading packets. Defaults to 4096
+    pub read_buffer_size: usize,
+
+    /// The read timeout, applied to every call to `next()` on a packet iterator. Defaults to None.
+    pub read_timeout: Option<Duration>,
+
+    /// The write timeout. Defaults to None.
+    pub write_timeout: Option<Duration>,
+
+    /// Linux only: queue the errors reported for sent packets, such as ICMP destination
+    /// unreachable messages, so they can be read with `TransportReceiver::errors()`. Not
+    /// supported for `Layer3Ipv6` channels. Defaults to false.
+    pub recv_errors: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            read_buffer_size: 4096,
+            read_timeout: None,
+            write_timeout: None,
+            recv_errors: false,
+        }
+    }
 }
 
 /// Create a new (TransportSender, TransportReceiver) pair
@@ -80,10 +208,22 @@ pub struct TransportReceiver {
 /// protocol you wish to implement. For example, `Layer4(Ipv4(IpNextHeaderProtocols::Udp))` would
 /// allow sending and receiving UDP packets using IPv4; whereas Layer3(IpNextHeaderProtocols::Udp)
 /// would include the IPv4 Header in received values, and require manual construction of an IP
-/// header when sending.
+/// header when sending. `Layer3Ipv6(IpNextHeaderProtocols::Udp)` does the same using IPv6.
 pub fn transport_channel(buffer_size: usize,
                          channel_type: TransportChannelType)
     -> io::Result<(TransportSender, TransportReceiver)> {
+    let config = Config { read_buffer_size: buffer_size, ..Default::default() };
+
+    transport_channel_with(channel_type, config)
+}
+
+/// Create a new (TransportSender, TransportReceiver) pair using the given configuration
+///
+/// This behaves like `transport_channel()`, but additionally allows read and write timeouts to
+/// be set. Operations which time out fail with `ErrorKind::TimedOut`.
+pub fn transport_channel_with(channel_type: TransportChannelType,
+                              config: Config)
+    -> io::Result<(TransportSender, TransportReceiver)> {
     use std::net;
 
     // This hack makes sure that winsock is initialised
@@ -103,6 +243,15 @@ pub fn transport_channel(buffer_size: usize,
             Layer4(Ipv6(IpNextHeaderProtocol(proto))) => {
                 sockets::socket(sockets::AF_INET6, sockets::SOCK_RAW, proto as libc::c_int)
             }
+            Layer3Ipv6(_) => return layer3_ipv6_channel(channel_type, config),
+            PingIpv4 => {
+                let IpNextHeaderProtocol(proto) = IpNextHeaderProtocols::Icmp;
+                sockets::socket(sockets::AF_INET, sockets::SOCK_DGRAM, proto as libc::c_int)
+            }
+            PingIpv6 => {
+                let IpNextHeaderProtocol(proto) = IpNextHeaderProtocols::Ipv6Icmp;
+                sockets::socket(sockets::AF_INET6, sockets::SOCK_DGRAM, proto as libc::c_int)
+            }
         }
     };
     if socket == sockets::INVALID_SOCKET {
@@ -134,19 +283,670 @@ pub fn transport_channel(buffer_size: usize,
     }
 
     let sock = Arc::new(internal::FileDesc { fd: socket });
+    enable_metadata(sock.fd, channel_type)?;
+    if config.recv_errors {
+        enable_recv_errors(sock.fd, channel_type)?;
+    }
+
     let sender = TransportSender {
         socket: sock.clone(),
-        _channel_type: channel_type,
+        channel_type: channel_type,
+        write_timeout: config.write_timeout,
     };
     let receiver = TransportReceiver {
         socket: sock,
-        buffer: repeat(0u8).take(buffer_size).collect(),
+        buffer: repeat(0u8).take(config.read_buffer_size).collect(),
         channel_type: channel_type,
+        read_timeout: config.read_timeout,
     };
 
     Ok((sender, receiver))
 }
 
+fn set_socket_option<T>(socket: sockets::CSocket,
+                        level: libc::c_int,
+                        name: libc::c_int,
+                        value: T)
+    -> io::Result<()> {
+    let res = unsafe {
+        sockets::setsockopt(socket,
+                            level,
+                            name,
+                            (&value as *const T) as sockets::Buf,
+                            mem::size_of::<T>() as sockets::SockLen)
+    };
+    if res == -1 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Ask the kernel to deliver the destination address, interface and TTL of received packets
+#[cfg(target_os = "linux")]
+fn enable_metadata(socket: sockets::CSocket, channel_type: TransportChannelType) -> io::Result<()> {
+    match channel_type {
+        Layer4(Ipv4(_)) | Layer3(_) | PingIpv4 => {
+            set_socket_option(socket, libc::IPPROTO_IP, libc::IP_PKTINFO, 1 as libc::c_int)?;
+            set_socket_option(socket, libc::IPPROTO_IP, libc::IP_RECVTTL, 1 as libc::c_int)
+        }
+        Layer4(Ipv6(_)) | PingIpv6 => {
+            set_socket_option(socket, libc::IPPROTO_IPV6, libc::IPV6_RECVPKTINFO, 1 as libc::c_int)?;
+            set_socket_option(socket, libc::IPPROTO_IPV6, libc::IPV6_RECVHOPLIMIT, 1 as libc::c_int)
+        }
+        // Taken from the IPv6 header and the AF_PACKET address instead
+        Layer3Ipv6(_) => Ok(()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn enable_metadata(_socket: sockets::CSocket,
+                   _channel_type: TransportChannelType)
+    -> io::Result<()> {
+    Ok(())
+}
+
+/// Ask the kernel to queue errors for sent packets, to be read using MSG_ERRQUEUE
+#[cfg(target_os = "linux")]
+fn enable_recv_errors(socket: sockets::CSocket,
+                      channel_type: TransportChannelType)
+    -> io::Result<()> {
+    if is_ipv6(channel_type) {
+        set_socket_option(socket, libc::IPPROTO_IPV6, libc::IPV6_RECVERR, 1 as libc::c_int)
+    } else {
+        set_socket_option(socket, libc::IPPROTO_IP, libc::IP_RECVERR, 1 as libc::c_int)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn enable_recv_errors(_socket: sockets::CSocket,
+                      _channel_type: TransportChannelType)
+    -> io::Result<()> {
+    Err(Error::new(io::ErrorKind::Other,
+                   "Error queues are only supported on Linux"))
+}
+
+#[cfg(target_os = "linux")]
+fn layer3_ipv6_channel(channel_type: TransportChannelType,
+                       config: Config)
+    -> io::Result<(TransportSender, TransportReceiver)> {
+    use bindings::linux;
+
+    // Errors would be queued on the send socket, which only ever sees IPPROTO_RAW
+    if config.recv_errors {
+        return Err(Error::new(io::ErrorKind::Other,
+                              "Error queues are not supported for Layer3Ipv6 channels"));
+    }
+
+    // Raw IPv6 sockets never pass the IPv6 header up to the application, even with
+    // IPV6_HDRINCL set, so whole packets are read from an AF_PACKET socket instead. An
+    // IPPROTO_RAW socket implies IPV6_HDRINCL on kernels which predate the option.
+    let send_socket = unsafe {
+        sockets::socket(sockets::AF_INET6, sockets::SOCK_RAW, libc::IPPROTO_RAW)
+    };
+    if send_socket == sockets::INVALID_SOCKET {
+        return Err(Error::last_os_error());
+    }
+    let send_socket = internal::FileDesc { fd: send_socket };
+
+    let hincl: libc::c_int = 1;
+    let res = unsafe {
+        sockets::setsockopt(send_socket.fd,
+                            libc::IPPROTO_IPV6,
+                            linux::IPV6_HDRINCL,
+                            (&hincl as *const libc::c_int) as sockets::Buf,
+                            mem::size_of::<libc::c_int>() as sockets::SockLen)
+    };
+    if res == -1 {
+        let err = Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ENOPROTOOPT) {
+            return Err(err);
+        }
+    }
+
+    let eth_p_ipv6 = (libc::ETH_P_IPV6 as u16).to_be() as libc::c_int;
+    let recv_socket = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_DGRAM, eth_p_ipv6) };
+    if recv_socket == -1 {
+        return Err(Error::last_os_error());
+    }
+
+    let sender = TransportSender {
+        socket: Arc::new(send_socket),
+        channel_type: channel_type,
+        write_timeout: config.write_timeout,
+    };
+    let receiver = TransportReceiver {
+        socket: Arc::new(internal::FileDesc { fd: recv_socket }),
+        buffer: repeat(0u8).take(config.read_buffer_size).collect(),
+        channel_type: channel_type,
+        read_timeout: config.read_timeout,
+    };
+
+    Ok((sender, receiver))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn layer3_ipv6_channel(_channel_type: TransportChannelType,
+                       _config: Config)
+    -> io::Result<(TransportSender, TransportReceiver)> {
+    Err(Error::new(io::ErrorKind::Other,
+                   "IPv6 layer 3 transport channels are only supported on Linux"))
+}
+
+impl TransportReceiver {
+    /// Receive the next packet into the buffer, waiting at most `timeout` if one is given.
+    /// Returns the range of the buffer holding the packet, after removing any header the channel
+    /// type does not include, and its metadata.
+    fn recv(&mut self, timeout: Option<Duration>) -> io::Result<(usize, usize, PacketMetadata)> {
+        if let Layer3Ipv6(proto) = self.channel_type {
+            return self.recv_layer3_ipv6(proto, timeout);
+        }
+
+        if let Some(timeout) = timeout {
+            internal::wait_readable(self.socket.fd, timeout)?;
+        }
+        let (len, metadata) = self.recv_with_metadata()?;
+        let offset = match self.channel_type {
+            Layer4(Ipv4(_)) => ipv4_header_len(&self.buffer[..len]),
+            // OS X includes the IPv4 header on ping sockets, Linux does not
+            #[cfg(target_os = "macos")]
+            PingIpv4 => ipv4_header_len(&self.buffer[..len]),
+            Layer3(_) => {
+                fixup_packet(&mut self.buffer[..]);
+
+                0
+            }
+            _ => 0,
+        };
+
+        return Ok((offset, len, metadata));
+
+        fn ipv4_header_len(buffer: &[u8]) -> usize {
+            match Ipv4Packet::new(buffer) {
+                Some(ip_header) => ip_header.get_header_length() as usize * 4usize,
+                None => 0,
+            }
+        }
+
+        #[cfg(any(target_os = "freebsd", target_os = "macos"))]
+        fn fixup_packet(buffer: &mut [u8]) {
+            use packet::ipv4::MutableIpv4Packet;
+
+            let buflen = buffer.len();
+            let mut new_packet = MutableIpv4Packet::new(buffer).unwrap();
+
+            let length = u16::from_be(new_packet.get_total_length());
+            new_packet.set_total_length(length);
+
+            // OS X does this awesome thing where it removes the header length
+            // from the total length sometimes.
+            let length = new_packet.get_total_length() as usize +
+                         (new_packet.get_header_length() as usize * 4usize);
+            if length == buflen {
+                new_packet.set_total_length(length as u16)
+            }
+
+            let offset = u16::from_be(new_packet.get_fragment_offset());
+            new_packet.set_fragment_offset(offset);
+        }
+
+        #[cfg(all(not(target_os = "freebsd"), not(target_os = "macos")))]
+        fn fixup_packet(_buffer: &mut [u8]) {}
+    }
+
+    #[cfg(target_os = "linux")]
+    fn recv_with_metadata(&mut self) -> io::Result<(usize, PacketMetadata)> {
+        use std::ptr;
+
+        let mut caddr: sockets::SockAddrStorage = unsafe { mem::zeroed() };
+        // u64s to keep the control buffer suitably aligned for cmsghdr
+        let mut control = [0u64; 16];
+        let mut iov = libc::iovec {
+            iov_base: self.buffer.as_mut_ptr() as *mut libc::c_void,
+            iov_len: self.buffer.len(),
+        };
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_name = (&mut caddr as *mut sockets::SockAddrStorage) as *mut libc::c_void;
+        msg.msg_namelen = mem::size_of::<sockets::SockAddrStorage>() as sockets::SockLen;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+
+        let len = internal::recv_msg(self.socket.fd, &mut msg, 0)?;
+        let mut metadata = PacketMetadata::new(sockaddr_to_ip(&caddr)?);
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                let data = libc::CMSG_DATA(cmsg);
+                match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+                    (libc::IPPROTO_IP, libc::IP_PKTINFO) => {
+                        let info = ptr::read_unaligned(data as *const libc::in_pktinfo);
+                        let addr = u32::from_be(info.ipi_addr.s_addr);
+                        metadata.destination = Some(IpAddr::V4(net::Ipv4Addr::from(addr)));
+                        metadata.interface_index = Some(info.ipi_ifindex as u32);
+                    }
+                    (libc::IPPROTO_IP, libc::IP_TTL) => {
+                        let ttl = ptr::read_unaligned(data as *const libc::c_int);
+                        metadata.ttl = Some(ttl as u8);
+                    }
+                    (libc::IPPROTO_IPV6, libc::IPV6_PKTINFO) => {
+                        let info = ptr::read_unaligned(data as *const libc::in6_pktinfo);
+                        let addr = net::Ipv6Addr::from(info.ipi6_addr.s6_addr);
+                        metadata.destination = Some(IpAddr::V6(addr));
+                        metadata.interface_index = Some(info.ipi6_ifindex as u32);
+                    }
+                    (libc::IPPROTO_IPV6, libc::IPV6_HOPLIMIT) => {
+                        let hop_limit = ptr::read_unaligned(data as *const libc::c_int);
+                        metadata.ttl = Some(hop_limit as u8);
+                    }
+                    _ => {}
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+
+        Ok((len, metadata))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn recv_with_metadata(&mut self) -> io::Result<(usize, PacketMetadata)> {
+        let mut caddr: sockets::SockAddrStorage = unsafe { mem::zeroed() };
+        let len = internal::recv_from(self.socket.fd, &mut self.buffer[..], &mut caddr)?;
+
+        Ok((len, PacketMetadata::new(sockaddr_to_ip(&caddr)?)))
+    }
+
+    /// Receive the next IPv6 packet carrying the given protocol into the buffer
+    #[cfg(target_os = "linux")]
+    fn recv_layer3_ipv6(&mut self,
+                        proto: IpNextHeaderProtocol,
+                        timeout: Option<Duration>)
+        -> io::Result<(usize, usize, PacketMetadata)> {
+        use bindings::linux;
+
+        // Packets for other protocols don't restart the timeout
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(deadline) = deadline {
+                let now = Instant::now();
+                let remaining = if now < deadline {
+                    deadline - now
+                } else {
+                    Duration::new(0, 0)
+                };
+                internal::wait_readable(self.socket.fd, remaining)?;
+            }
+            let mut caddr: sockets::SockAddrStorage = unsafe { mem::zeroed() };
+            let len = internal::recv_from(self.socket.fd, &mut self.buffer[..], &mut caddr)?;
+            let sll = unsafe { &*((&caddr as *const _) as *const libc::sockaddr_ll) };
+            // Packets sent from this host are seen here too
+            if sll.sll_pkttype == linux::PACKET_OUTGOING {
+                continue;
+            }
+            let (next, offset, metadata) = match Ipv6Packet::new(&self.buffer[..len]) {
+                Some(packet) => {
+                    let (next, offset) = match ipv6_protocol_header(&packet, proto) {
+                        Some(header) => header,
+                        None => continue,
+                    };
+                    let mut metadata = PacketMetadata::new(IpAddr::V6(packet.get_source()));
+                    metadata.destination = Some(IpAddr::V6(packet.get_destination()));
+                    metadata.interface_index = Some(sll.sll_ifindex as u32);
+                    metadata.ttl = Some(packet.get_hop_limit());
+                    (next, offset, metadata)
+                }
+                None => continue,
+            };
+            let start = strip_ipv6_extension_headers(&mut self.buffer[..len], next, offset);
+
+            return Ok((start, len, metadata));
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn recv_layer3_ipv6(&mut self,
+                        _proto: IpNextHeaderProtocol,
+                        _timeout: Option<Duration>)
+        -> io::Result<(usize, usize, PacketMetadata)> {
+        unreachable!()
+    }
+}
+
+/// Find the header carrying `proto` in an IPv6 packet, returning its protocol and offset. Fragments
+/// of packets carrying the protocol are found too, as their Fragment header.
+#[cfg(target_os = "linux")]
+fn ipv6_protocol_header(packet: &Ipv6Packet,
+                        proto: IpNextHeaderProtocol)
+    -> Option<(IpNextHeaderProtocol, usize)> {
+    use packet::ipv6::{self, FragmentPacket};
+
+    if packet.get_next_header() == proto {
+        return Some((proto, Ipv6Packet::minimum_packet_size()));
+    }
+    match ipv6::upper_layer(packet) {
+        Some((next, offset)) if next == proto => Some((next, offset)),
+        Some((IpNextHeaderProtocols::Ipv6Frag, offset)) => {
+            match FragmentPacket::new(&packet.packet()[offset..]) {
+                Some(ref fragment) if fragment.get_next_header() == proto => {
+                    Some((IpNextHeaderProtocols::Ipv6Frag, offset))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn ipv6_protocol_header_test() {
+    use packet::ipv6::MutableIpv6Packet;
+
+    let mut packet = [0u8; 40 + 8 + 8];
+    {
+        let mut ip_header = MutableIpv6Packet::new(&mut packet[..]).unwrap();
+        ip_header.set_version(6);
+        ip_header.set_next_header(IpNextHeaderProtocols::Ipv6Opts);
+    }
+    // Destination Options, then a Fragment header carrying UDP
+    packet[40] = IpNextHeaderProtocols::Ipv6Frag.0;
+    packet[48] = IpNextHeaderProtocols::Udp.0;
+    let ip = Ipv6Packet::new(&packet[..]).unwrap();
+    assert_eq!(ipv6_protocol_header(&ip, IpNextHeaderProtocols::Ipv6Opts),
+               Some((IpNextHeaderProtocols::Ipv6Opts, 40)));
+    assert_eq!(ipv6_protocol_header(&ip, IpNextHeaderProtocols::Udp),
+               Some((IpNextHeaderProtocols::Ipv6Frag, 48)));
+    assert_eq!(ipv6_protocol_header(&ip, IpNextHeaderProtocols::Tcp), None);
+
+    // A packet which ends at the Fragment header, with the rest of the buffer left over from an
+    // earlier packet
+    let ip = Ipv6Packet::new(&packet[..48]).unwrap();
+    assert_eq!(ipv6_protocol_header(&ip, IpNextHeaderProtocols::Udp), None);
+    let ip = Ipv6Packet::new(&packet[..52]).unwrap();
+    assert_eq!(ipv6_protocol_header(&ip, IpNextHeaderProtocols::Udp), None);
+}
+
+/// Remove the extension headers between the fixed IPv6 header and the `next` header at `offset`,
+/// by moving the fixed header up to meet it. Returns the new start of the packet.
+#[cfg(target_os = "linux")]
+fn strip_ipv6_extension_headers(buffer: &mut [u8],
+                                next: IpNextHeaderProtocol,
+                                offset: usize)
+    -> usize {
+    use packet::ipv6::MutableIpv6Packet;
+
+    let header_len = Ipv6Packet::minimum_packet_size();
+    let stripped = offset - header_len;
+    if stripped > 0 {
+        buffer.copy_within(0..header_len, stripped);
+        let mut header = MutableIpv6Packet::new(&mut buffer[stripped..]).unwrap();
+        header.set_next_header(next);
+        // Zero for jumbograms, whose length is in a Hop-by-Hop option
+        let payload_length = header.get_payload_length();
+        header.set_payload_length(payload_length.saturating_sub(stripped as u16));
+    }
+
+    stripped
+}
+
+fn sockaddr_to_ip(caddr: &sockets::SockAddrStorage) -> io::Result<IpAddr> {
+    let addr = internal::sockaddr_to_addr(caddr, mem::size_of::<sockets::SockAddrStorage>())?;
+    Ok(match addr {
+        net::SocketAddr::V4(sa) => IpAddr::V4(*sa.ip()),
+        net::SocketAddr::V6(sa) => IpAddr::V6(*sa.ip()),
+    })
+}
+
+// The IPv4 multicast TTL and loopback options take a u_char on the BSDs, which Linux also
+// accepts; Windows insists on a DWORD.
+#[cfg(not(windows))]
+type MulticastOption = u8;
+#[cfg(windows)]
+type MulticastOption = libc::c_int;
+
+fn is_ipv6(channel_type: TransportChannelType) -> bool {
+    match channel_type {
+        Layer4(Ipv6(_)) | Layer3Ipv6(_) | PingIpv6 => true,
+        Layer4(Ipv4(_)) | Layer3(_) | PingIpv4 => false,
+    }
+}
+
+fn ipv4_mreq(multiaddr: &net::Ipv4Addr, interface: &net::Ipv4Addr) -> sockets::IpMreq {
+    sockets::IpMreq {
+        imr_multiaddr: sockets::mk_inaddr(u32::from(*multiaddr).to_be()),
+        imr_interface: sockets::mk_inaddr(u32::from(*interface).to_be()),
+    }
+}
+
+fn ipv6_mreq(multiaddr: &net::Ipv6Addr, interface: u32) -> sockets::Ipv6Mreq {
+    let mut mreq: sockets::Ipv6Mreq = unsafe { mem::zeroed() };
+    mreq.ipv6mr_multiaddr.s6_addr = multiaddr.octets();
+    mreq.ipv6mr_interface = interface as _;
+
+    mreq
+}
+
+macro_rules! socket_options {
+    ($ty:ident) => (
+        impl $ty {
+            /// Set the TTL (IPv4) or hop limit (IPv6) of outgoing unicast packets. Packets sent
+            /// on layer 3 channels keep the value from their own header.
+            pub fn set_ttl(&self, ttl: u8) -> io::Result<()> {
+                let (level, name) = if is_ipv6(self.channel_type) {
+                    (sockets::IPPROTO_IPV6, sockets::IPV6_UNICAST_HOPS)
+                } else {
+                    (sockets::IPPROTO_IP, sockets::IP_TTL)
+                };
+                set_socket_option(self.socket.fd, level, name, ttl as libc::c_int)
+            }
+
+            /// Set the type of service (IPv4) or traffic class (IPv6) of outgoing packets
+            pub fn set_tos(&self, tos: u8) -> io::Result<()> {
+                let (level, name) = if is_ipv6(self.channel_type) {
+                    (sockets::IPPROTO_IPV6, sockets::IPV6_TCLASS)
+                } else {
+                    (sockets::IPPROTO_IP, sockets::IP_TOS)
+                };
+                set_socket_option(self.socket.fd, level, name, tos as libc::c_int)
+            }
+
+            /// Only send and receive packets using the named interface (`SO_BINDTODEVICE`)
+            ///
+            /// On the receiver of a `Layer3Ipv6` channel, which reads from an `AF_PACKET` socket,
+            /// this binds the socket to the interface instead.
+            #[cfg(target_os = "linux")]
+            pub fn bind_to_device(&self, interface: &str) -> io::Result<()> {
+                if self.is_packet_socket() {
+                    return bind_packet_socket(self.socket.fd, interface);
+                }
+                let res = unsafe {
+                    libc::setsockopt(self.socket.fd,
+                                     libc::SOL_SOCKET,
+                                     libc::SO_BINDTODEVICE,
+                                     interface.as_ptr() as *const libc::c_void,
+                                     interface.len() as libc::socklen_t)
+                };
+                if res == -1 {
+                    Err(Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            }
+
+            /// Set the size of the kernel's receive buffer for the socket (`SO_RCVBUF`)
+            pub fn set_recv_buffer_size(&self, size: usize) -> io::Result<()> {
+                set_socket_option(self.socket.fd,
+                                  sockets::SOL_SOCKET,
+                                  sockets::SO_RCVBUF,
+                                  size as libc::c_int)
+            }
+
+            /// Set the size of the kernel's send buffer for the socket (`SO_SNDBUF`)
+            pub fn set_send_buffer_size(&self, size: usize) -> io::Result<()> {
+                set_socket_option(self.socket.fd,
+                                  sockets::SOL_SOCKET,
+                                  sockets::SO_SNDBUF,
+                                  size as libc::c_int)
+            }
+
+            /// Send IPv4 multicast packets from the interface with the given address
+            pub fn set_multicast_if_v4(&self, interface: &net::Ipv4Addr) -> io::Result<()> {
+                set_socket_option(self.socket.fd,
+                                  sockets::IPPROTO_IP,
+                                  sockets::IP_MULTICAST_IF,
+                                  sockets::mk_inaddr(u32::from(*interface).to_be()))
+            }
+
+            /// Send IPv6 multicast packets from the interface with the given index
+            pub fn set_multicast_if_v6(&self, interface: u32) -> io::Result<()> {
+                set_socket_option(self.socket.fd,
+                                  sockets::IPPROTO_IPV6,
+                                  sockets::IPV6_MULTICAST_IF,
+                                  interface as libc::c_uint)
+            }
+
+            /// Set the TTL (IPv4) or hop limit (IPv6) of outgoing multicast packets
+            pub fn set_multicast_ttl(&self, ttl: u8) -> io::Result<()> {
+                if is_ipv6(self.channel_type) {
+                    set_socket_option(self.socket.fd,
+                                      sockets::IPPROTO_IPV6,
+                                      sockets::IPV6_MULTICAST_HOPS,
+                                      ttl as libc::c_int)
+                } else {
+                    set_socket_option(self.socket.fd,
+                                      sockets::IPPROTO_IP,
+                                      sockets::IP_MULTICAST_TTL,
+                                      ttl as MulticastOption)
+                }
+            }
+
+            /// Set whether outgoing multicast packets are looped back to local sockets
+            pub fn set_multicast_loop(&self, enabled: bool) -> io::Result<()> {
+                if is_ipv6(self.channel_type) {
+                    set_socket_option(self.socket.fd,
+                                      sockets::IPPROTO_IPV6,
+                                      sockets::IPV6_MULTICAST_LOOP,
+                                      enabled as libc::c_uint)
+                } else {
+                    set_socket_option(self.socket.fd,
+                                      sockets::IPPROTO_IP,
+                                      sockets::IP_MULTICAST_LOOP,
+                                      enabled as MulticastOption)
+                }
+            }
+
+            /// Join an IPv4 multicast group on the interface with the given address
+            pub fn join_multicast_v4(&self,
+                                     multiaddr: &net::Ipv4Addr,
+                                     interface: &net::Ipv4Addr)
+                -> io::Result<()> {
+                set_socket_option(self.socket.fd,
+                                  sockets::IPPROTO_IP,
+                                  sockets::IP_ADD_MEMBERSHIP,
+                                  ipv4_mreq(multiaddr, interface))
+            }
+
+            /// Leave an IPv4 multicast group joined with `join_multicast_v4`
+            pub fn leave_multicast_v4(&self,
+                                      multiaddr: &net::Ipv4Addr,
+                                      interface: &net::Ipv4Addr)
+                -> io::Result<()> {
+                set_socket_option(self.socket.fd,
+                                  sockets::IPPROTO_IP,
+                                  sockets::IP_DROP_MEMBERSHIP,
+                                  ipv4_mreq(multiaddr, interface))
+            }
+
+            /// Join an IPv6 multicast group on the interface with the given index. An index of 0
+            /// lets the kernel choose.
+            pub fn join_multicast_v6(&self,
+                                     multiaddr: &net::Ipv6Addr,
+                                     interface: u32)
+                -> io::Result<()> {
+                set_socket_option(self.socket.fd,
+                                  sockets::IPPROTO_IPV6,
+                                  sockets::IPV6_JOIN_GROUP,
+                                  ipv6_mreq(multiaddr, interface))
+            }
+
+            /// Leave an IPv6 multicast group joined with `join_multicast_v6`
+            pub fn leave_multicast_v6(&self,
+                                      multiaddr: &net::Ipv6Addr,
+                                      interface: u32)
+                -> io::Result<()> {
+                set_socket_option(self.socket.fd,
+                                  sockets::IPPROTO_IPV6,
+                                  sockets::IPV6_LEAVE_GROUP,
+                                  ipv6_mreq(multiaddr, interface))
+            }
+
+            /// Have the kernel compute and verify the checksum of IPv6 raw packets, found at
+            /// the given byte offset into the transport header (`IPV6_CHECKSUM`). `None`
+            /// disables checksumming.
+            pub fn set_ipv6_checksum_offset(&self, offset: Option<u32>) -> io::Result<()> {
+                let offset = match offset {
+                    Some(offset) => offset as libc::c_int,
+                    None => -1,
+                };
+                set_socket_option(self.socket.fd,
+                                  sockets::IPPROTO_IPV6,
+                                  sockets::IPV6_CHECKSUM,
+                                  offset)
+            }
+        }
+    )
+}
+
+socket_options!(TransportSender);
+
+socket_options!(TransportReceiver);
+
+impl TransportSender {
+    #[cfg(target_os = "linux")]
+    fn is_packet_socket(&self) -> bool {
+        false
+    }
+}
+
+impl TransportReceiver {
+    #[cfg(target_os = "linux")]
+    fn is_packet_socket(&self) -> bool {
+        match self.channel_type {
+            Layer3Ipv6(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Bind an `AF_PACKET` socket receiving IPv6 packets to the named interface
+#[cfg(target_os = "linux")]
+fn bind_packet_socket(socket: sockets::CSocket, interface: &str) -> io::Result<()> {
+    use std::ffi::CString;
+
+    let name = CString::new(interface)
+        .map_err(|_| Error::new(io::ErrorKind::InvalidInput, "Invalid interface name"))?;
+    let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    if index == 0 {
+        return Err(Error::last_os_error());
+    }
+
+    let mut addr: libc::sockaddr_ll = unsafe { mem::zeroed() };
+    addr.sll_family = libc::AF_PACKET as libc::sa_family_t;
+    addr.sll_protocol = (libc::ETH_P_IPV6 as u16).to_be();
+    addr.sll_ifindex = index as libc::c_int;
+    let res = unsafe {
+        libc::bind(socket,
+                   (&addr as *const libc::sockaddr_ll) as *const libc::sockaddr,
+                   mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t)
+    };
+    if res == -1 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
 impl TransportSender {
     fn send<T: Packet>(&mut self, packet: T, dst: IpAddr) -> io::Result<usize> {
         let mut caddr = unsafe { mem::zeroed() };
@@ -157,6 +957,9 @@ impl TransportSender {
         let slen = internal::addr_to_sockaddr(sockaddr, &mut caddr);
         let caddr_ptr = (&caddr as *const sockets::SockAddrStorage) as *const sockets::SockAddr;
 
+        if let Some(timeout) = self.write_timeout {
+            internal::wait_writable(self.socket.fd, timeout)?;
+        }
         internal::send_to(self.socket.fd, packet.packet(), caddr_ptr, slen)
     }
 
@@ -180,7 +983,7 @@ impl TransportSender {
         // packets to be in host byte order rather than network byte order. Fragment offset is the
         // ip_off field in the ip struct and contains both the offset and the three flag bits.
         // See `man 4 ip`/Raw IP Sockets)
-        if let Layer3(_) = self._channel_type {
+        if let Layer3(_) = self.channel_type {
             let mut mut_slice: Vec<u8> = repeat(0u8).take(packet.packet().len()).collect();
 
             let mut new_packet = MutableIpv4Packet::new(&mut mut_slice[..]).unwrap();
@@ -201,8 +1004,199 @@ impl TransportSender {
     }
 }
 
+/// An iterator over packets of type `T` received by a `TransportReceiver`
+///
+/// `T` can be any type implementing `FromBytes`, which includes every #[derive(Packet)] type,
+/// whether it is defined in this crate or not. On layer 4 channels each packet starts at the
+/// transport header; the kernel includes the IPv4 header on raw sockets, so it is skipped, but
+/// never passes up IPv6 headers or extension headers. On layer 3 channels each packet is the whole
+/// IP packet, except that received IPv6 packets have any extension headers before the transport
+/// header (or a Fragment header) removed, as they would be on layer 4.
+pub struct TransportChannelIterator<'a, T> {
+    tr: &'a mut TransportReceiver,
+    _packet: PhantomData<T>,
+}
+
+/// Return an iterator over packets of type `T` for some transport receiver
+///
+/// For example, `packet_iter::<UdpPacket>(&mut rx)` iterates over UDP packets.
+pub fn packet_iter<T>(tr: &mut TransportReceiver) -> TransportChannelIterator<T> {
+    TransportChannelIterator {
+        tr: tr,
+        _packet: PhantomData,
+    }
+}
+
+impl<'a, T> TransportChannelIterator<'a, T> {
+    /// Get the next packet for the given channel, along with the address it was sent from
+    pub fn next<'p>(&'p mut self) -> io::Result<(T::T, IpAddr)>
+        where T: FromBytes<'p>
+    {
+        self.next_with_metadata().map(|(packet, metadata)| (packet, metadata.source))
+    }
+
+    /// Get the next packet for the given channel, failing with `ErrorKind::TimedOut` if none
+    /// arrives within `timeout`. This overrides any read timeout the channel was created with.
+    pub fn next_with_timeout<'p>(&'p mut self, timeout: Duration) -> io::Result<(T::T, IpAddr)>
+        where T: FromBytes<'p>
+    {
+        let (packet, metadata) = self.recv(Some(timeout))?;
+
+        Ok((packet, metadata.source))
+    }
+
+    /// Get the next packet for the given channel, along with the addresses, interface and TTL it
+    /// was received with
+    pub fn next_with_metadata<'p>(&'p mut self) -> io::Result<(T::T, PacketMetadata)>
+        where T: FromBytes<'p>
+    {
+        let timeout = self.tr.read_timeout;
+        self.recv(timeout)
+    }
+
+    fn recv<'p>(&'p mut self, timeout: Option<Duration>) -> io::Result<(T::T, PacketMetadata)>
+        where T: FromBytes<'p>
+    {
+        let (start, end, metadata) = self.tr.recv(timeout)?;
+        match T::from_bytes(&self.tr.buffer[start..end]) {
+            Some(packet) => Ok((packet, metadata)),
+            None => Err(Error::new(io::ErrorKind::InvalidData, "Received packet too short")),
+        }
+    }
+}
+
+/// An iterator over the errors queued for packets sent on a transport channel. Created with
+/// `TransportReceiver::errors()`
+///
+/// Each error is returned with the packet which caused it, as far as it was included in the error
+/// message. The packet starts at the same layer as packets received on the channel: at the
+/// transport header on layer 4 and ping channels, and at the IPv4 header on layer 3 channels.
+#[cfg(target_os = "linux")]
+pub struct TransportErrorIterator<'a> {
+    tr: &'a mut TransportReceiver,
+}
+
+#[cfg(target_os = "linux")]
+impl TransportReceiver {
+    /// Return an iterator over the errors queued for packets sent on this channel
+    ///
+    /// The channel must have been created with `recv_errors` set in its `Config`. Reading an
+    /// error removes it from the queue; until then, it may also be reported once by a call to
+    /// `next()` on a packet iterator.
+    pub fn errors(&mut self) -> TransportErrorIterator {
+        TransportErrorIterator { tr: self }
+    }
+
+    /// Read the next error from the error queue into the buffer, without blocking
+    fn recv_error(&mut self) -> io::Result<(usize, ExtendedError)> {
+        use std::ptr;
+
+        let mut caddr: sockets::SockAddrStorage = unsafe { mem::zeroed() };
+        // Ancillary data for the packet, such as IP_PKTINFO, may come along with the error
+        let mut control = [0u64; 32];
+        let mut iov = libc::iovec {
+            iov_base: self.buffer.as_mut_ptr() as *mut libc::c_void,
+            iov_len: self.buffer.len(),
+        };
+        let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+        msg.msg_name = (&mut caddr as *mut sockets::SockAddrStorage) as *mut libc::c_void;
+        msg.msg_namelen = mem::size_of::<sockets::SockAddrStorage>() as sockets::SockLen;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = mem::size_of_val(&control) as _;
+
+        let len = internal::recv_msg(self.socket.fd, &mut msg, libc::MSG_ERRQUEUE)?;
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                match ((*cmsg).cmsg_level, (*cmsg).cmsg_type) {
+                    (libc::IPPROTO_IP, libc::IP_RECVERR) |
+                    (libc::IPPROTO_IPV6, libc::IPV6_RECVERR) => {
+                        let data = libc::CMSG_DATA(cmsg);
+                        let data_len = (*cmsg).cmsg_len as usize -
+                                       (data as usize - cmsg as usize);
+                        let ee = ptr::read_unaligned(data as *const libc::sock_extended_err);
+
+                        // The offending host's address follows the sock_extended_err
+                        let ee_len = mem::size_of::<libc::sock_extended_err>();
+                        let mut offender: sockets::SockAddrStorage = mem::zeroed();
+                        if data_len > ee_len {
+                            let addr_len = ::std::cmp::min(data_len - ee_len,
+                                                           mem::size_of_val(&offender));
+                            ptr::copy_nonoverlapping(data.add(ee_len),
+                                                     (&mut offender as *mut _) as *mut u8,
+                                                     addr_len);
+                        }
+
+                        let error = ExtendedError {
+                            errno: ee.ee_errno as i32,
+                            origin: match ee.ee_origin {
+                                libc::SO_EE_ORIGIN_LOCAL => ErrorOrigin::Local,
+                                libc::SO_EE_ORIGIN_ICMP => ErrorOrigin::Icmp,
+                                libc::SO_EE_ORIGIN_ICMP6 => ErrorOrigin::Icmpv6,
+                                origin => ErrorOrigin::Unknown(origin),
+                            },
+                            icmp_type: ee.ee_type,
+                            icmp_code: ee.ee_code,
+                            info: ee.ee_info,
+                            offender: sockaddr_to_ip(&offender).ok(),
+                            destination: sockaddr_to_ip(&caddr).ok(),
+                        };
+                        return Ok((len, error));
+                    }
+                    _ => {}
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+        }
+
+        Err(Error::new(io::ErrorKind::InvalidData, "Error queue entry without error details"))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl<'a> TransportErrorIterator<'a> {
+    /// Get the next queued error and the packet which caused it, waiting for the channel's read
+    /// timeout if the queue is empty
+    pub fn next(&mut self) -> io::Result<(ExtendedError, &[u8])> {
+        let timeout = self.tr.read_timeout;
+        self.recv(timeout)
+    }
+
+    /// Get the next queued error and the packet which caused it, failing with
+    /// `ErrorKind::TimedOut` if none is queued within `timeout`. This overrides any read timeout
+    /// the channel was created with.
+    pub fn next_with_timeout(&mut self, timeout: Duration) -> io::Result<(ExtendedError, &[u8])> {
+        self.recv(Some(timeout))
+    }
+
+    fn recv(&mut self, timeout: Option<Duration>) -> io::Result<(ExtendedError, &[u8])> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let (len, error) = loop {
+            match self.tr.recv_error() {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                result => break result?,
+            }
+            let remaining = deadline.map(|deadline| {
+                let now = Instant::now();
+                if now < deadline {
+                    deadline - now
+                } else {
+                    Duration::new(0, 0)
+                }
+            });
+            internal::wait_error(self.tr.socket.fd, remaining)?;
+        };
+
+        Ok((error, &self.tr.buffer[..len]))
+    }
+}
+
 /// Create an iterator for some packet type.
 ///
+/// New code should prefer the generic `packet_iter()`, which works for any packet type.
+///
 /// Usage:
 /// ```
 /// transport_channel_iterator!(Ipv4Packet, // Type to iterate over
@@ -225,64 +1219,31 @@ macro_rules! transport_channel_iterator {
         impl<'a> $iter<'a> {
             /// Get the next ($ty, IpAddr) pair for the given channel
             pub fn next(&mut self) -> io::Result<($ty, IpAddr)> {
-                let mut caddr: sockets::SockAddrStorage = unsafe { mem::zeroed() };
-                let res = internal::recv_from(self.tr.socket.fd,
-                                              &mut self.tr.buffer[..],
-                                              &mut caddr);
-
-                let offset = match self.tr.channel_type {
-                    Layer4(Ipv4(_)) => {
-                        let ip_header = Ipv4Packet::new(&self.tr.buffer[..]).unwrap();
-
-                        ip_header.get_header_length() as usize * 4usize
-                    },
-                    Layer3(_) => {
-                        fixup_packet(&mut self.tr.buffer[..]);
-
-                        0
-                    },
-                    _ => 0
-                };
-                return match res {
-                    Ok(len) => {
-                        let packet = $ty::new(&self.tr.buffer[offset..len]).unwrap();
-                        let addr = internal::sockaddr_to_addr(
-                                        &caddr,
-                                        mem::size_of::<sockets::SockAddrStorage>()
-                                   );
-                        let ip = match addr.unwrap() {
-                            net::SocketAddr::V4(sa) => IpAddr::V4(*sa.ip()),
-                            net::SocketAddr::V6(sa) => IpAddr::V6(*sa.ip()),
-                        };
-                        Ok((packet, ip))
-                    },
-                    Err(e) => Err(e),
-                };
-
-                #[cfg(any(target_os = "freebsd", target_os = "macos"))]
-                fn fixup_packet(buffer: &mut [u8]) {
-                    use packet::ipv4::MutableIpv4Packet;
-
-                    let buflen = buffer.len();
-                    let mut new_packet = MutableIpv4Packet::new(buffer).unwrap();
-
-                    let length = u16::from_be(new_packet.get_total_length());
-                    new_packet.set_total_length(length);
-
-                    // OS X does this awesome thing where it removes the header length
-                    // from the total length sometimes.
-                    let length = new_packet.get_total_length() as usize +
-                                 (new_packet.get_header_length() as usize * 4usize);
-                    if length == buflen {
-                        new_packet.set_total_length(length as u16)
-                    }
+                self.next_with_metadata().map(|(packet, metadata)| (packet, metadata.source))
+            }
 
-                    let offset = u16::from_be(new_packet.get_fragment_offset());
-                    new_packet.set_fragment_offset(offset);
+            /// Get the next ($ty, IpAddr) pair for the given channel, failing with
+            /// `ErrorKind::TimedOut` if none arrives within `timeout`. A zero timeout polls
+            /// without blocking. This overrides any read timeout the channel was created with.
+            pub fn next_with_timeout(&mut self, timeout: Duration) -> io::Result<($ty, IpAddr)> {
+                let (start, end, metadata) = self.tr.recv(Some(timeout))?;
+                match $ty::new(&self.tr.buffer[start..end]) {
+                    Some(packet) => Ok((packet, metadata.source)),
+                    None => Err(Error::new(io::ErrorKind::InvalidData,
+                                           "Received packet too short")),
                 }
+            }
 
-                #[cfg(all(not(target_os = "freebsd"), not(target_os = "macos")))]
-                fn fixup_packet(_buffer: &mut [u8]) {}
+            /// Get the next packet for the given channel, along with the addresses, interface
+            /// and TTL it was received with
+            pub fn next_with_metadata(&mut self) -> io::Result<($ty, PacketMetadata)> {
+                let timeout = self.tr.read_timeout;
+                let (start, end, metadata) = self.tr.recv(timeout)?;
+                match $ty::new(&self.tr.buffer[start..end]) {
+                    Some(packet) => Ok((packet, metadata)),
+                    None => Err(Error::new(io::ErrorKind::InvalidData,
+                                           "Received packet too short")),
+                }
             }
         }
     )
@@ -290,6 +1251,8 @@ macro_rules! transport_channel_iterator {
 
 transport_channel_iterator!(Ipv4Packet, Ipv4TransportChannelIterator, ipv4_packet_iter);
 
+transport_channel_iterator!(Ipv6Packet, Ipv6TransportChannelIterator, ipv6_packet_iter);
+
 transport_channel_iterator!(UdpPacket, UdpTransportChannelIterator, udp_packet_iter);
 
 transport_channel_iterator!(IcmpPacket, IcmpTransportChannelIterator, icmp_packet_iter);
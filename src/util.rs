@@ -16,7 +16,6 @@ use pnet_macros_support::types::u16be;
 
 use std::fmt;
 use std::net::{Ipv4Addr, Ipv6Addr};
-use std::slice;
 use std::str::FromStr;
 use std::u8;
 
@@ -261,24 +260,19 @@ fn ipv6_word_sum(ip: Ipv6Addr) -> u32 {
 /// Sum all words (16 bit chunks) in the given data. The word at word offset
 /// `skipword` will be skipped. Each word is treated as big endian.
 fn sum_be_words(data: &[u8], skipword: usize) -> u32 {
-    let len = data.len();
-    let wdata: &[u16] = unsafe { slice::from_raw_parts(data.as_ptr() as *const u16, len / 2) };
-    assert!(skipword <= wdata.len());
+    assert!(skipword <= data.len() / 2);
 
+    // The data isn't necessarily aligned for u16 reads, so each word is built from its bytes
     let mut sum = 0u32;
-    let mut i = 0;
-    while i < skipword {
-        sum += u16::from_be(unsafe { *wdata.get_unchecked(i) }) as u32;
-        i += 1;
-    }
-    i += 1;
-    while i < wdata.len() {
-        sum += u16::from_be(unsafe { *wdata.get_unchecked(i) }) as u32;
-        i += 1;
-    }
-    // If the length is odd, make sure to checksum the final byte
-    if len & 1 != 0 {
-        sum += (unsafe { *data.get_unchecked(len - 1) } as u32) << 8;
+    for (i, word) in data.chunks(2).enumerate() {
+        if word.len() == 2 {
+            if i != skipword {
+                sum += ((word[0] as u32) << 8) | word[1] as u32;
+            }
+        } else {
+            // If the length is odd, make sure to checksum the final byte
+            sum += (word[0] as u32) << 8;
+        }
     }
 
     sum